@@ -0,0 +1,434 @@
+//! Mastery card drafts generated from highlights.
+//!
+//! The LLM proposes question/answer pairs and cloze sentences along with the
+//! words to hide. Deletion offsets are worked out here by finding those words
+//! in the sentence, so they always cover the text they blank out.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::path::Path;
+
+use super::providers::FallbackChain;
+use crate::highlights::{Highlight, HighlightStore};
+use crate::reading::ReadingStore;
+use crate::rpc::{MasteryGenerateRequest, MasteryGenerateResponse};
+use crate::spaced_repetition::mastery::{ClozeDeletion, MasteryCardType};
+use crate::spaced_repetition::{CreateMasteryCardRequest, MasteryCard, SpacedRepetitionStore};
+use crate::utils::text;
+
+/// Bytes of article text either side of the highlight given as context
+const CONTEXT_BYTES: usize = 1500;
+const DEFAULT_MAX_CARDS: usize = 4;
+const MAX_CARDS: usize = 10;
+const CARD_TOKENS: u32 = 1024;
+
+/// Drafts cards for the highlight `request` names, creating them as mastery
+/// cards when `create` is set. No more than `card_limit` cards are drafted or
+/// created.
+pub async fn generate(
+    data_dir: &Path,
+    request: &MasteryGenerateRequest,
+    card_limit: Option<usize>,
+) -> Result<MasteryGenerateResponse> {
+    let highlight = HighlightStore::new(data_dir.to_path_buf())?
+        .get(&request.highlight_id)
+        .cloned()
+        .with_context(|| format!("Highlight not found: {}", request.highlight_id))?;
+    let article = ReadingStore::new(data_dir.to_path_buf())?
+        .get(&highlight.article_id)
+        .cloned();
+    let source = source_text(
+        &highlight,
+        article
+            .as_ref()
+            .map(|a| (a.title.as_str(), a.content.as_str())),
+    );
+
+    let max_cards = request
+        .max_cards
+        .unwrap_or(DEFAULT_MAX_CARDS)
+        .clamp(1, MAX_CARDS)
+        .min(card_limit.unwrap_or(MAX_CARDS));
+    let chain = super::provider_chain(&request.llm)?.unwrap_or_else(super::local_chain);
+    let (drafts, provider) = draft_cards(&chain, &highlight, &source, max_cards).await?;
+
+    let (cards, skipped) = if request.create && !drafts.is_empty() {
+        let mut store = SpacedRepetitionStore::new(data_dir.to_path_buf())?;
+        create_drafts(&mut store, &drafts, card_limit)?
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
+    Ok(MasteryGenerateResponse {
+        drafts,
+        cards,
+        skipped,
+        provider,
+    })
+}
+
+/// Creates the drafts that fit in `card_limit` cards, returning the cards
+/// and the indices of the drafts left out. Every draft is checked before
+/// any is created, so an invalid one fails the whole batch.
+fn create_drafts(
+    store: &mut SpacedRepetitionStore,
+    drafts: &[CreateMasteryCardRequest],
+    card_limit: Option<usize>,
+) -> Result<(Vec<MasteryCard>, Vec<usize>)> {
+    // A cloze draft can make several sibling cards
+    let counts = drafts
+        .iter()
+        .map(|draft| draft.card_count())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut budget = card_limit.unwrap_or(usize::MAX);
+    let mut cards = Vec::new();
+    let mut skipped = Vec::new();
+    for (i, (draft, count)) in drafts.iter().zip(counts).enumerate() {
+        if count > budget {
+            skipped.push(i);
+            continue;
+        }
+        budget -= count;
+        cards.extend(store.create_mastery_card(draft.clone())?);
+    }
+    Ok((cards, skipped))
+}
+
+/// The highlight, the reader's note on it, and the article text around it.
+fn source_text(highlight: &Highlight, article: Option<(&str, &str)>) -> String {
+    let mut source = format!("Highlight:\n{}", highlight.text.trim());
+    if let Some(note) = highlight.note.as_deref().filter(|n| !n.trim().is_empty()) {
+        source.push_str(&format!("\n\nReader's note:\n{}", note.trim()));
+    }
+    if let Some((title, content)) = article {
+        let context = surrounding(content, &highlight.text);
+        source.push_str(&format!("\n\nFrom \"{}\":\n{}", title, context.trim()));
+    }
+    source
+}
+
+/// Article text around the first occurrence of `passage`, or the article's
+/// opening if the passage can't be found.
+fn surrounding<'a>(content: &'a str, passage: &str) -> &'a str {
+    let passage = passage.trim();
+    let Some(start) = content.find(passage) else {
+        return text::prefix(content, 2 * CONTEXT_BYTES);
+    };
+    let end = start + passage.len();
+
+    let mut from = start.saturating_sub(CONTEXT_BYTES);
+    while !content.is_char_boundary(from) {
+        from -= 1;
+    }
+    let to = end + text::prefix(&content[end..], CONTEXT_BYTES).len();
+    &content[from..to]
+}
+
+#[derive(Deserialize)]
+struct Proposals {
+    #[serde(default)]
+    qa: Vec<QaProposal>,
+    #[serde(default)]
+    cloze: Vec<ClozeProposal>,
+}
+
+#[derive(Deserialize)]
+struct QaProposal {
+    question: String,
+    answer: String,
+}
+
+#[derive(Deserialize)]
+struct ClozeProposal {
+    text: String,
+    deletions: Vec<DeletionProposal>,
+}
+
+#[derive(Deserialize)]
+struct DeletionProposal {
+    answer: String,
+    hint: Option<String>,
+}
+
+async fn draft_cards(
+    chain: &FallbackChain,
+    highlight: &Highlight,
+    source: &str,
+    max_cards: usize,
+) -> Result<(Vec<CreateMasteryCardRequest>, String)> {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "qa": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "question": {"type": "string", "minLength": 1},
+                        "answer": {"type": "string", "minLength": 1}
+                    },
+                    "required": ["question", "answer"]
+                }
+            },
+            "cloze": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "text": {"type": "string", "minLength": 1},
+                        "deletions": {
+                            "type": "array",
+                            "minItems": 1,
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "answer": {"type": "string", "minLength": 1},
+                                    "hint": {"type": "string"}
+                                },
+                                "required": ["answer"]
+                            }
+                        }
+                    },
+                    "required": ["text", "deletions"]
+                }
+            }
+        },
+        "required": ["qa", "cloze"]
+    });
+    let instructions = format!(
+        "Write up to {} flashcards that test the key ideas of the highlight, using the \
+         article text only as context. Mix question/answer pairs with cloze cards. For a \
+         cloze card, give a sentence from the highlight as `text` and, in `deletions`, the \
+         key terms to hide, each copied exactly as it appears in the sentence, with an \
+         optional short hint. Skip trivia.",
+        max_cards
+    );
+
+    let extraction =
+        super::extract::extract(chain, source, &schema, Some(&instructions), CARD_TOKENS).await?;
+    let proposals: Proposals = serde_json::from_value(extraction.data)?;
+    Ok((
+        drafts(&highlight.id, proposals, max_cards),
+        extraction.provider,
+    ))
+}
+
+/// Cards from the LLM's proposals, alternating kinds, leaving out blank
+/// pairs and cloze sentences none of whose deletions could be found.
+fn drafts(
+    highlight_id: &str,
+    proposals: Proposals,
+    max_cards: usize,
+) -> Vec<CreateMasteryCardRequest> {
+    let mut qa = proposals.qa.into_iter().filter_map(|p| {
+        let (question, answer) = (p.question.trim(), p.answer.trim());
+        (!question.is_empty() && !answer.is_empty()).then(|| CreateMasteryCardRequest {
+            highlight_id: highlight_id.to_string(),
+            card_type: MasteryCardType::QA,
+            question: Some(question.to_string()),
+            answer: Some(answer.to_string()),
+            cloze_text: None,
+            cloze_deletions: None,
+        })
+    });
+    let mut cloze = proposals.cloze.into_iter().filter_map(|p| {
+        let text = p.text.trim();
+        let deletions = locate_deletions(text, &p.deletions);
+        (!deletions.is_empty()).then(|| CreateMasteryCardRequest {
+            highlight_id: highlight_id.to_string(),
+            card_type: MasteryCardType::Cloze,
+            question: None,
+            answer: None,
+            cloze_text: Some(text.to_string()),
+            cloze_deletions: Some(deletions),
+        })
+    });
+
+    let mut cards = Vec::new();
+    while cards.len() < max_cards {
+        let before = cards.len();
+        cards.extend(qa.next());
+        if cards.len() < max_cards {
+            cards.extend(cloze.next());
+        }
+        if cards.len() == before {
+            break;
+        }
+    }
+    cards
+}
+
+/// Byte ranges of each answer in `text`, in order and not overlapping.
+/// Answers that don't appear, or only where an earlier one already is, are
+/// dropped, as is one that would hide the whole text.
+fn locate_deletions(text: &str, proposals: &[DeletionProposal]) -> Vec<ClozeDeletion> {
+    let mut deletions: Vec<ClozeDeletion> = Vec::new();
+    for proposal in proposals {
+        let answer = proposal.answer.trim();
+        if answer.is_empty() || answer.len() == text.len() {
+            continue;
+        }
+        let free = text
+            .match_indices(answer)
+            .map(|(start, _)| (start, start + answer.len()))
+            .find(|&(start, end)| deletions.iter().all(|d| end <= d.start || start >= d.end));
+        if let Some((start, end)) = free {
+            deletions.push(ClozeDeletion {
+                start,
+                end,
+                hint: proposal
+                    .hint
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|h| !h.is_empty())
+                    .map(String::from),
+            });
+        }
+    }
+    deletions.sort_by_key(|d| d.start);
+    deletions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spaced_repetition::AlgorithmType;
+
+    fn deletion(answer: &str, hint: Option<&str>) -> DeletionProposal {
+        DeletionProposal {
+            answer: answer.to_string(),
+            hint: hint.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_locate_deletions_finds_byte_offsets() {
+        let text = "Rust prevents data races at compile time, not at run time.";
+        let deletions = locate_deletions(
+            text,
+            &[
+                deletion("compile time", Some(" when ")),
+                deletion("data races", None),
+                deletion("garbage collection", None),
+                deletion("time", None),
+            ],
+        );
+
+        let hidden: Vec<&str> = deletions.iter().map(|d| &text[d.start..d.end]).collect();
+        // The first "time" is inside "compile time", so the later one is used
+        assert_eq!(hidden, vec!["data races", "compile time", "time"]);
+        assert_eq!(deletions[0].start, 14);
+        assert_eq!(deletions[1].hint.as_deref(), Some("when"));
+        assert_eq!(deletions[2].start, text.rfind("time").unwrap());
+    }
+
+    #[test]
+    fn test_locate_deletions_multibyte_text() {
+        let text = "서울은 한국의 수도이다.";
+        let deletions = locate_deletions(text, &[deletion("수도", None), deletion(text, None)]);
+
+        assert_eq!(deletions.len(), 1);
+        assert_eq!(&text[deletions[0].start..deletions[0].end], "수도");
+    }
+
+    #[test]
+    fn test_create_drafts_within_the_card_limit() {
+        let qa = |question: &str| CreateMasteryCardRequest {
+            highlight_id: "h1".to_string(),
+            card_type: MasteryCardType::QA,
+            question: Some(question.to_string()),
+            answer: Some("A skill".to_string()),
+            cloze_text: None,
+            cloze_deletions: None,
+        };
+        let cloze = |text: &str| CreateMasteryCardRequest {
+            highlight_id: "h1".to_string(),
+            card_type: MasteryCardType::Cloze,
+            question: None,
+            answer: None,
+            cloze_text: Some(text.to_string()),
+            cloze_deletions: None,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = SpacedRepetitionStore::new(dir.path().to_path_buf()).unwrap();
+
+        // The two-card cloze draft doesn't fit, the QA draft after it does
+        let drafts = vec![
+            qa("What is focus?"),
+            cloze("{{c1::Paris}} is in {{c2::France}}."),
+            qa("What is rest?"),
+        ];
+        let (cards, skipped) = create_drafts(&mut store, &drafts, Some(2)).unwrap();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(skipped, vec![1]);
+
+        let (cards, skipped) = create_drafts(&mut store, &drafts, None).unwrap();
+        assert_eq!(cards.len(), 4);
+        assert!(skipped.is_empty());
+
+        // An invalid draft saves none of the batch
+        let drafts = vec![qa("What is sleep?"), cloze("{{c1::open")];
+        assert!(create_drafts(&mut store, &drafts, None).is_err());
+        assert_eq!(store.get_due_counts().1, 6);
+    }
+
+    #[test]
+    fn test_drafts_alternate_and_render() {
+        let proposals = Proposals {
+            qa: vec![
+                QaProposal {
+                    question: "What does Rust prevent at compile time?".to_string(),
+                    answer: "Data races".to_string(),
+                },
+                QaProposal {
+                    question: " ".to_string(),
+                    answer: "blank".to_string(),
+                },
+                QaProposal {
+                    question: "What checks borrows?".to_string(),
+                    answer: "The borrow checker".to_string(),
+                },
+            ],
+            cloze: vec![
+                ClozeProposal {
+                    text: "Missing words are dropped.".to_string(),
+                    deletions: vec![deletion("absent", None)],
+                },
+                ClozeProposal {
+                    text: "The capital of France is Paris.".to_string(),
+                    deletions: vec![deletion("Paris", Some("city"))],
+                },
+            ],
+        };
+
+        let cards = drafts("h1", proposals, 3);
+        let types: Vec<&MasteryCardType> = cards.iter().map(|c| &c.card_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                &MasteryCardType::QA,
+                &MasteryCardType::Cloze,
+                &MasteryCardType::QA
+            ]
+        );
+        assert!(cards.iter().all(|c| c.highlight_id == "h1"));
+
+        let card = MasteryCard::new(cards[1].clone(), AlgorithmType::SM2);
+        assert_eq!(
+            card.get_display_text(),
+            "The capital of France is [...city...]."
+        );
+    }
+
+    #[test]
+    fn test_surrounding_centres_on_passage() {
+        let content = format!("{}needle{}", "é".repeat(1000), "a".repeat(2000));
+        let context = surrounding(&content, " needle ");
+
+        assert!(context.contains("needle"));
+        assert_eq!(context.len(), 1500 + "needle".len() + 1500);
+        assert_eq!(surrounding("short text", "absent"), "short text");
+    }
+}
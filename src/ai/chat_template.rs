@@ -0,0 +1,228 @@
+//! Prompt formats for the local engine's chat models.
+//!
+//! Instruction-tuned models only answer sensibly when the conversation is
+//! laid out in the markup they were trained on, and they end their turn with
+//! a family-specific token rather than the tokenizer's generic EOS. GGUF
+//! files usually carry their Jinja chat template; rather than evaluate it,
+//! the family is recognised from its markers and rendered from a built-in
+//! table, falling back to the architecture and model name.
+
+use super::providers::ChatMessage;
+
+const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful assistant.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatTemplate {
+    /// TinyLlama and other Zephyr-style models: `<|user|>` ... `</s>`
+    Zephyr,
+    /// Llama 3: `<|start_header_id|>` ... `<|eot_id|>`
+    Llama3,
+    /// Qwen and other ChatML models: `<|im_start|>` ... `<|im_end|>`
+    ChatMl,
+    /// Phi-3: `<|user|>` ... `<|end|>`
+    Phi3,
+    /// Mistral and Llama 2: `[INST]` ... `[/INST]`
+    Mistral,
+}
+
+impl ChatTemplate {
+    /// Picks the template for a model from its GGUF `tokenizer.chat_template`,
+    /// `general.architecture` and a name (GGUF `general.name`, repo and file),
+    /// in that order of trust. Unknown models get the Zephyr format the
+    /// default model uses.
+    pub fn detect(jinja: Option<&str>, architecture: Option<&str>, name: &str) -> Self {
+        if let Some(template) = jinja.and_then(Self::from_jinja) {
+            return template;
+        }
+        match architecture {
+            Some("qwen2") | Some("qwen2moe") => return Self::ChatMl,
+            Some("phi3") => return Self::Phi3,
+            _ => {}
+        }
+
+        let name = name.to_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|n| name.contains(n));
+        if has(&["llama-3", "llama3", "llama_3"]) {
+            Self::Llama3
+        } else if has(&["qwen"]) {
+            Self::ChatMl
+        } else if has(&["phi-3", "phi3"]) {
+            Self::Phi3
+        } else if has(&["mistral", "mixtral", "llama-2", "llama2"]) {
+            Self::Mistral
+        } else {
+            Self::Zephyr
+        }
+    }
+
+    fn from_jinja(template: &str) -> Option<Self> {
+        if template.contains("<|start_header_id|>") {
+            Some(Self::Llama3)
+        } else if template.contains("<|im_start|>") {
+            Some(Self::ChatMl)
+        } else if template.contains("<|end|>") && template.contains("<|assistant|>") {
+            Some(Self::Phi3)
+        } else if template.contains("<|assistant|>") {
+            Some(Self::Zephyr)
+        } else if template.contains("[INST]") {
+            Some(Self::Mistral)
+        } else {
+            None
+        }
+    }
+
+    /// Tokens that end the assistant's turn, besides the GGUF EOS token.
+    pub fn stop_tokens(&self) -> &'static [&'static str] {
+        match self {
+            Self::Zephyr | Self::Mistral => &["</s>"],
+            Self::Llama3 => &["<|eot_id|>", "<|end_of_text|>"],
+            Self::ChatMl => &["<|im_end|>", "<|endoftext|>"],
+            Self::Phi3 => &["<|end|>", "<|endoftext|>"],
+        }
+    }
+
+    /// Lays out `messages` and opens the assistant's turn. A conversation
+    /// without a system message gets a default one. The BOS token is left to
+    /// the tokenizer.
+    pub fn render(&self, messages: &[ChatMessage]) -> String {
+        let has_system = messages.iter().any(|m| m.role == "system");
+        let default_system = ChatMessage {
+            role: "system".to_string(),
+            content: DEFAULT_SYSTEM_PROMPT.to_string(),
+        };
+        let messages: Vec<&ChatMessage> = (!has_system)
+            .then_some(&default_system)
+            .into_iter()
+            .chain(messages)
+            .collect();
+
+        let mut out = String::new();
+        match self {
+            Self::Zephyr => {
+                for m in &messages {
+                    out.push_str(&format!("<|{}|>\n{}</s>\n", m.role, m.content));
+                }
+                out.push_str("<|assistant|>\n");
+            }
+            Self::Phi3 => {
+                for m in &messages {
+                    out.push_str(&format!("<|{}|>\n{}<|end|>\n", m.role, m.content));
+                }
+                out.push_str("<|assistant|>\n");
+            }
+            Self::Llama3 => {
+                for m in &messages {
+                    out.push_str(&format!(
+                        "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+                        m.role, m.content
+                    ));
+                }
+                out.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+            }
+            Self::ChatMl => {
+                for m in &messages {
+                    out.push_str(&format!(
+                        "<|im_start|>{}\n{}<|im_end|>\n",
+                        m.role, m.content
+                    ));
+                }
+                out.push_str("<|im_start|>assistant\n");
+            }
+            Self::Mistral => {
+                // No system role: it is folded into the first user turn
+                let mut system = None;
+                for m in &messages {
+                    match m.role.as_str() {
+                        "system" => system = Some(m.content.as_str()),
+                        "assistant" => out.push_str(&format!(" {}</s>", m.content)),
+                        _ => {
+                            let content = match system.take() {
+                                Some(system) => format!("{}\n\n{}", system, m.content),
+                                None => m.content.clone(),
+                            };
+                            out.push_str(&format!("[INST] {} [/INST]", content));
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_detect_prefers_metadata_over_name() {
+        let llama3 = "{% for m in messages %}<|start_header_id|>{{ m.role }}<|end_header_id|>";
+        assert_eq!(
+            ChatTemplate::detect(Some(llama3), Some("llama"), "tinyllama"),
+            ChatTemplate::Llama3
+        );
+        assert_eq!(
+            ChatTemplate::detect(None, Some("qwen2"), "model"),
+            ChatTemplate::ChatMl
+        );
+        assert_eq!(
+            ChatTemplate::detect(
+                Some("{{ bos_token }}"),
+                Some("llama"),
+                "Mistral-7B-Instruct"
+            ),
+            ChatTemplate::Mistral
+        );
+        assert_eq!(
+            ChatTemplate::detect(None, Some("llama"), "Meta-Llama-3.1-8B-Instruct"),
+            ChatTemplate::Llama3
+        );
+        assert_eq!(
+            ChatTemplate::detect(None, None, "TinyLlama-1.1B-Chat"),
+            ChatTemplate::Zephyr
+        );
+    }
+
+    #[test]
+    fn test_render_multi_turn() {
+        let messages = [
+            message("user", "Hi"),
+            message("assistant", "Hello!"),
+            message("user", "Bye"),
+        ];
+        assert_eq!(
+            ChatTemplate::ChatMl.render(&messages),
+            "<|im_start|>system\nYou are a helpful assistant.<|im_end|>\n\
+             <|im_start|>user\nHi<|im_end|>\n\
+             <|im_start|>assistant\nHello!<|im_end|>\n\
+             <|im_start|>user\nBye<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+        assert_eq!(
+            ChatTemplate::Mistral.render(&messages),
+            "[INST] You are a helpful assistant.\n\nHi [/INST] Hello!</s>[INST] Bye [/INST]"
+        );
+    }
+
+    #[test]
+    fn test_render_keeps_given_system_prompt() {
+        let messages = [message("system", "Be brief."), message("user", "Hi")];
+        assert_eq!(
+            ChatTemplate::Llama3.render(&messages),
+            "<|start_header_id|>system<|end_header_id|>\n\nBe brief.<|eot_id|>\
+             <|start_header_id|>user<|end_header_id|>\n\nHi<|eot_id|>\
+             <|start_header_id|>assistant<|end_header_id|>\n\n"
+        );
+        assert_eq!(
+            ChatTemplate::Zephyr.render(&messages[1..]),
+            "<|system|>\nYou are a helpful assistant.</s>\n<|user|>\nHi</s>\n<|assistant|>\n"
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Splits Markdown notes into overlapping chunks for embedding.
+//!
+//! The embedding model only sees the first few hundred tokens of its input,
+//! so a long note embedded whole is mostly invisible to semantic search.
+//! Notes are instead split along headings and paragraphs; a chunk never spans
+//! two sections and remembers the headings it sits under, so a match can point
+//! at the right part of the note.
+
+use serde::{Deserialize, Serialize};
+
+/// Identifies the splitting rules below. Stored with the index; changing the
+/// rules or sizes must change this so stored chunk vectors are rebuilt.
+pub const CHUNKER_ID: &str = "markdown-v1-1000-150";
+
+const MAX_CHUNK_CHARS: usize = 1000;
+/// Text repeated from the end of the previous chunk in the same section
+const OVERLAP_CHARS: usize = 150;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chunk {
+    /// Headings enclosing the chunk, outermost first
+    pub heading_path: Vec<String>,
+    pub text: String,
+    /// 1-based, inclusive line range in the note
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Splits `content` into chunks. Always returns at least one chunk, so every
+/// note gets a vector even when it is empty.
+pub fn split_markdown(content: &str) -> Vec<Chunk> {
+    split_with(content, MAX_CHUNK_CHARS, OVERLAP_CHARS)
+}
+
+/// A piece of text that is never split further when packing chunks.
+#[derive(Clone)]
+struct Unit {
+    text: String,
+    chars: usize,
+    /// Joins the unit to the one before it in the same chunk
+    separator: &'static str,
+    start_line: usize,
+    end_line: usize,
+}
+
+struct Section {
+    heading_path: Vec<String>,
+    /// Blocks of (line number, line); a block is a paragraph, a heading or
+    /// a fenced code block
+    blocks: Vec<Vec<(usize, String)>>,
+}
+
+fn split_with(content: &str, max_chars: usize, overlap: usize) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    for section in sections(content) {
+        let units = section
+            .blocks
+            .iter()
+            .flat_map(|block| block_units(block, max_chars));
+        pack(
+            units,
+            &section.heading_path,
+            max_chars,
+            overlap,
+            &mut chunks,
+        );
+    }
+
+    if chunks.is_empty() {
+        chunks.push(Chunk {
+            heading_path: Vec::new(),
+            text: String::new(),
+            start_line: 1,
+            end_line: 1,
+        });
+    }
+    chunks
+}
+
+fn sections(content: &str) -> Vec<Section> {
+    let mut sections = vec![Section {
+        heading_path: Vec::new(),
+        blocks: Vec::new(),
+    }];
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut block: Vec<(usize, String)> = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut lines = content.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+    // Frontmatter is metadata, not note text
+    if content.starts_with("---") {
+        let mut probe = lines.clone();
+        probe.next();
+        if probe.any(|(_, line)| line.trim_end() == "---") {
+            lines = probe;
+        }
+    }
+
+    for (number, line) in lines {
+        let section = sections.last_mut().unwrap();
+        if let Some(marker) = fence {
+            block.push((number, line.to_string()));
+            if line.trim_start().starts_with(marker) {
+                fence = None;
+                section.blocks.push(std::mem::take(&mut block));
+            }
+            continue;
+        }
+
+        let trimmed = line.trim_start();
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            if !block.is_empty() {
+                section.blocks.push(std::mem::take(&mut block));
+            }
+            fence = Some(marker);
+            block.push((number, line.to_string()));
+        } else if let Some((level, title)) = parse_heading(line) {
+            if !block.is_empty() {
+                section.blocks.push(std::mem::take(&mut block));
+            }
+            while headings.last().is_some_and(|(l, _)| *l >= level) {
+                headings.pop();
+            }
+            headings.push((level, title));
+            sections.push(Section {
+                heading_path: headings.iter().map(|(_, t)| t.clone()).collect(),
+                blocks: vec![vec![(number, line.to_string())]],
+            });
+        } else if trimmed.is_empty() {
+            if !block.is_empty() {
+                section.blocks.push(std::mem::take(&mut block));
+            }
+        } else {
+            block.push((number, line.to_string()));
+        }
+    }
+    if !block.is_empty() {
+        sections.last_mut().unwrap().blocks.push(block);
+    }
+
+    sections.retain(|s| !s.blocks.is_empty());
+    sections
+}
+
+/// Parses an ATX heading (`## Title`) into its level and text.
+fn parse_heading(line: &str) -> Option<(usize, String)> {
+    let level = line.bytes().take_while(|&b| b == b'#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let title = rest.trim().trim_end_matches('#').trim_end();
+    Some((level, title.to_string()))
+}
+
+/// Keeps a block whole when it fits, otherwise falls back to its lines, and
+/// cuts lines that are longer than a chunk on character boundaries.
+fn block_units(block: &[(usize, String)], max_chars: usize) -> Vec<Unit> {
+    let text = block
+        .iter()
+        .map(|(_, line)| line.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let chars = text.chars().count();
+    let (first, last) = (block[0].0, block[block.len() - 1].0);
+    if chars <= max_chars {
+        return vec![Unit {
+            text,
+            chars,
+            separator: "\n\n",
+            start_line: first,
+            end_line: last,
+        }];
+    }
+
+    let mut units = Vec::new();
+    for (i, (number, line)) in block.iter().enumerate() {
+        let separator = if i == 0 { "\n\n" } else { "\n" };
+        let line_chars: Vec<char> = line.chars().collect();
+        if line_chars.is_empty() {
+            units.push(Unit {
+                text: String::new(),
+                chars: 0,
+                separator,
+                start_line: *number,
+                end_line: *number,
+            });
+        }
+        for (j, piece) in line_chars.chunks(max_chars).enumerate() {
+            units.push(Unit {
+                text: piece.iter().collect(),
+                chars: piece.len(),
+                separator: if j == 0 { separator } else { "" },
+                start_line: *number,
+                end_line: *number,
+            });
+        }
+    }
+    units
+}
+
+/// Greedily packs units into chunks of at most `max_chars`, starting each
+/// new chunk with the trailing units of the previous one up to `overlap`.
+fn pack(
+    units: impl Iterator<Item = Unit>,
+    heading_path: &[String],
+    max_chars: usize,
+    overlap: usize,
+    chunks: &mut Vec<Chunk>,
+) {
+    let mut current: Vec<Unit> = Vec::new();
+    let mut length = 0;
+    // Units added since the last flush; a chunk of only overlap is a repeat
+    let mut fresh = 0;
+
+    for unit in units {
+        let added = unit.chars + unit.separator.len();
+        if fresh > 0 && length + added > max_chars {
+            chunks.push(build_chunk(&current, heading_path));
+
+            let mut kept = 0;
+            let mut keep_from = current.len();
+            while keep_from > 0 && kept + current[keep_from - 1].chars <= overlap {
+                keep_from -= 1;
+                kept += current[keep_from].chars + current[keep_from].separator.len();
+            }
+            current.drain(..keep_from);
+            length = kept;
+            if length + added > max_chars {
+                current.clear();
+                length = 0;
+            }
+            fresh = 0;
+        }
+        length += added;
+        fresh += 1;
+        current.push(unit);
+    }
+    if fresh > 0 {
+        chunks.push(build_chunk(&current, heading_path));
+    }
+}
+
+fn build_chunk(units: &[Unit], heading_path: &[String]) -> Chunk {
+    let mut text = String::new();
+    for (i, unit) in units.iter().enumerate() {
+        if i > 0 {
+            text.push_str(unit.separator);
+        }
+        text.push_str(&unit.text);
+    }
+    Chunk {
+        heading_path: heading_path.to_vec(),
+        text,
+        start_line: units[0].start_line,
+        end_line: units[units.len() - 1].end_line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections_follow_headings() {
+        let content =
+            "---\ntags: [a]\n---\nIntro line\n\n# Top\nBody\n\n## Sub ##\nDeep\n\n# Other\nMore";
+        let chunks = split_markdown(content);
+
+        let paths: Vec<Vec<&str>> = chunks
+            .iter()
+            .map(|c| c.heading_path.iter().map(String::as_str).collect())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![vec![], vec!["Top"], vec!["Top", "Sub"], vec!["Other"]]
+        );
+        assert_eq!(chunks[0].text, "Intro line");
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (4, 4));
+        assert_eq!(chunks[2].text, "## Sub ##\n\nDeep");
+        assert_eq!((chunks[2].start_line, chunks[2].end_line), (9, 10));
+    }
+
+    #[test]
+    fn test_headings_inside_code_fences_are_text() {
+        let content = "# Real\n```sh\n# not a heading\n\necho hi\n```";
+        let chunks = split_markdown(content);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].heading_path, vec!["Real".to_string()]);
+        assert!(chunks[0].text.contains("# not a heading\n\necho hi"));
+        assert_eq!(chunks[0].end_line, 6);
+    }
+
+    #[test]
+    fn test_long_sections_overlap() {
+        let paragraphs: Vec<String> = (0..6)
+            .map(|i| format!("p{} {}", i, "x".repeat(20)))
+            .collect();
+        let content = paragraphs.join("\n\n");
+        let chunks = split_with(&content, 60, 30);
+
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.text.chars().count() <= 60));
+        // Each chunk after the first repeats the previous chunk's last paragraph
+        for pair in chunks.windows(2) {
+            let last = pair[0].text.rsplit("\n\n").next().unwrap();
+            assert!(pair[1].text.starts_with(last));
+            assert!(pair[1].start_line <= pair[0].end_line);
+        }
+        assert!(chunks.last().unwrap().text.ends_with(&paragraphs[5]));
+    }
+
+    #[test]
+    fn test_oversized_lines_split_on_char_boundaries() {
+        let content = "한".repeat(25);
+        let chunks = split_with(&content, 10, 0);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].text.chars().count(), 10);
+        assert!(chunks.iter().all(|c| c.start_line == 1 && c.end_line == 1));
+    }
+
+    #[test]
+    fn test_empty_note_has_one_chunk() {
+        assert_eq!(split_markdown("").len(), 1);
+        assert_eq!(split_markdown("---\na: 1\n---\n").len(), 1);
+    }
+}
@@ -0,0 +1,287 @@
+//! Persisted settings for the AI features, stored as `ai_config.json` in the
+//! data directory.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use super::llm::SamplingParams;
+use super::providers::RetryPolicy;
+use crate::rpc::LlmSelection;
+
+const CONFIG_FILE: &str = "ai_config.json";
+/// Stands in for a secret in the config sent to clients. Sending it back
+/// unchanged keeps the saved value.
+pub const REDACTED: &str = "********";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AiConfig {
+    /// Embedding model for the search index; `None` uses the default model
+    pub embedding_model: Option<String>,
+    /// Cross-encoder that reorders search and RAG results; `None` disables
+    /// reranking
+    pub reranker: Option<String>,
+    /// Defaults for requests that use the `ollama` provider
+    pub ollama: OllamaSettings,
+    /// Defaults for requests that use the `custom` provider
+    pub custom: CustomProviderSettings,
+    /// Providers tried in order after the one a request names, or instead
+    /// of the local engine when it names none
+    pub fallback: Vec<LlmSelection>,
+    /// Retries of each provider in the chain on rate limits, timeouts and
+    /// server errors
+    pub retry: RetryPolicy,
+    /// Token sampling for the local engine
+    pub sampling: SamplingParams,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OllamaSettings {
+    /// Server URL; `http://localhost:11434` when unset
+    pub host: Option<String>,
+    /// Model used when a request names none
+    pub model: Option<String>,
+    /// Ollama model options such as `num_ctx` or `temperature`
+    pub options: Option<serde_json::Value>,
+}
+
+/// An OpenAI-compatible server such as LM Studio, vLLM or llama.cpp.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CustomProviderSettings {
+    /// API root, e.g. `http://192.168.1.10:1234/v1`
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+    /// Extra headers sent with every request
+    pub headers: HashMap<String, String>,
+}
+
+impl AiConfig {
+    /// The config with API keys and auth headers masked, for sending to
+    /// clients.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        config.custom.redact();
+        config.fallback.iter_mut().for_each(redact_selection);
+        config
+    }
+
+    /// Puts back the secrets of `saved` that a client returned as
+    /// [`REDACTED`].
+    pub fn restore_secrets(&mut self, saved: &AiConfig) {
+        self.custom.restore_secrets(&saved.custom);
+        restore_fallback_secrets(&mut self.fallback, &saved.fallback);
+    }
+}
+
+impl CustomProviderSettings {
+    fn redact(&mut self) {
+        redact_secrets(&mut self.api_key, &mut self.headers);
+    }
+
+    fn restore_secrets(&mut self, saved: &Self) {
+        restore_secrets(
+            (&mut self.api_key, &mut self.headers),
+            (&saved.api_key, &saved.headers),
+        );
+    }
+}
+
+fn redact_selection(selection: &mut LlmSelection) {
+    redact_secrets(&mut selection.api_key, &mut selection.headers);
+    selection.fallback.iter_mut().for_each(redact_selection);
+}
+
+/// Fallback entries are matched to the saved ones by position and provider.
+fn restore_fallback_secrets(fallback: &mut [LlmSelection], saved: &[LlmSelection]) {
+    let empty = LlmSelection::default();
+    for (i, entry) in fallback.iter_mut().enumerate() {
+        let saved = saved
+            .get(i)
+            .filter(|s| s.provider == entry.provider)
+            .unwrap_or(&empty);
+        restore_secrets(
+            (&mut entry.api_key, &mut entry.headers),
+            (&saved.api_key, &saved.headers),
+        );
+        restore_fallback_secrets(&mut entry.fallback, &saved.fallback);
+    }
+}
+
+/// Whether a header carries credentials, like `Authorization` or `X-Api-Key`.
+fn is_secret_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["auth", "key", "token", "secret", "cookie"]
+        .iter()
+        .any(|word| name.contains(word))
+}
+
+fn redact_secrets(api_key: &mut Option<String>, headers: &mut HashMap<String, String>) {
+    if let Some(key) = api_key.as_mut() {
+        *key = REDACTED.to_string();
+    }
+    for (name, value) in headers.iter_mut() {
+        if is_secret_header(name) {
+            *value = REDACTED.to_string();
+        }
+    }
+}
+
+fn restore_secrets(
+    (api_key, headers): (&mut Option<String>, &mut HashMap<String, String>),
+    (saved_key, saved_headers): (&Option<String>, &HashMap<String, String>),
+) {
+    if api_key.as_deref() == Some(REDACTED) {
+        api_key.clone_from(saved_key);
+    }
+    headers.retain(|name, value| {
+        if value != REDACTED {
+            return true;
+        }
+        match saved_headers.get(name) {
+            Some(saved) => {
+                value.clone_from(saved);
+                true
+            }
+            None => false,
+        }
+    });
+}
+
+pub fn load(data_dir: &Path) -> Result<AiConfig> {
+    let path = data_dir.join(CONFIG_FILE);
+    if !path.exists() {
+        return Ok(AiConfig::default());
+    }
+    let data = fs::read_to_string(&path).context("Failed to read AI config")?;
+    serde_json::from_str(&data).context("Failed to parse AI config")
+}
+
+pub fn save(data_dir: &Path, config: &AiConfig) -> Result<()> {
+    fs::create_dir_all(data_dir).context("Failed to create data directory")?;
+    let data = serde_json::to_string_pretty(config)?;
+    fs::write(data_dir.join(CONFIG_FILE), data).context("Failed to write AI config")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(load(dir.path()).unwrap(), AiConfig::default());
+
+        let config = AiConfig {
+            embedding_model: Some("intfloat/multilingual-e5-small".to_string()),
+            reranker: Some("BAAI/bge-reranker-base".to_string()),
+            ollama: OllamaSettings {
+                host: Some("http://10.0.0.2:11434".to_string()),
+                model: Some("qwen2.5:7b".to_string()),
+                options: Some(serde_json::json!({"num_ctx": 8192})),
+            },
+            custom: CustomProviderSettings {
+                base_url: Some("http://10.0.0.3:1234/v1".to_string()),
+                model: Some("qwen2.5-7b-instruct".to_string()),
+                api_key: None,
+                headers: HashMap::from([("X-Client".to_string(), "naidis".to_string())]),
+            },
+            fallback: vec![LlmSelection {
+                provider: Some("ollama".to_string()),
+                ..Default::default()
+            }],
+            retry: RetryPolicy {
+                max_retries: 4,
+                ..Default::default()
+            },
+            sampling: SamplingParams {
+                temperature: 0.2,
+                seed: Some(7),
+                ..Default::default()
+            },
+        };
+        save(dir.path(), &config).unwrap();
+        assert_eq!(load(dir.path()).unwrap(), config);
+    }
+
+    #[test]
+    fn test_redacted_config_hides_secrets() {
+        let config = AiConfig {
+            custom: CustomProviderSettings {
+                base_url: Some("http://10.0.0.3:1234/v1".to_string()),
+                api_key: Some("sk-secret".to_string()),
+                headers: HashMap::from([
+                    ("Authorization".to_string(), "Bearer tok-secret".to_string()),
+                    ("X-Client".to_string(), "naidis".to_string()),
+                ]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let redacted = config.redacted();
+        let payload = serde_json::to_string(&redacted).unwrap();
+        assert!(!payload.contains("sk-secret"));
+        assert!(!payload.contains("tok-secret"));
+        assert_eq!(redacted.custom.api_key.as_deref(), Some(REDACTED));
+        assert_eq!(redacted.custom.headers["X-Client"], "naidis");
+
+        // Sending the redacted config back keeps the saved secrets
+        let mut update = redacted;
+        update.restore_secrets(&config);
+        assert_eq!(update, config);
+
+        // New values replace them, and a placeholder with nothing saved is
+        // dropped
+        let mut update = config.redacted();
+        update.custom.api_key = Some("sk-new".to_string());
+        update
+            .custom
+            .headers
+            .insert("X-Api-Key".to_string(), REDACTED.to_string());
+        update.restore_secrets(&config);
+        assert_eq!(update.custom.api_key.as_deref(), Some("sk-new"));
+        assert!(!update.custom.headers.contains_key("X-Api-Key"));
+        assert_eq!(update.custom.headers["Authorization"], "Bearer tok-secret");
+    }
+
+    #[test]
+    fn test_redacted_config_hides_fallback_keys() {
+        let config = AiConfig {
+            fallback: vec![
+                LlmSelection {
+                    provider: Some("openai".to_string()),
+                    api_key: Some("sk-openai".to_string()),
+                    ..Default::default()
+                },
+                LlmSelection {
+                    provider: Some("anthropic".to_string()),
+                    api_key: Some("sk-ant".to_string()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let redacted = config.redacted();
+        let payload = serde_json::to_string(&redacted).unwrap();
+        assert!(!payload.contains("sk-openai"));
+        assert!(!payload.contains("sk-ant"));
+
+        let mut update = redacted.clone();
+        update.restore_secrets(&config);
+        assert_eq!(update, config);
+
+        // A reordered chain doesn't hand one provider's key to another
+        let mut update = redacted;
+        update.fallback.reverse();
+        update.restore_secrets(&config);
+        assert!(update.fallback.iter().all(|e| e.api_key.is_none()));
+    }
+}
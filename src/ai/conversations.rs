@@ -0,0 +1,465 @@
+//! Chat conversations kept across requests.
+//!
+//! Each conversation is a JSON file under `conversations/` in the data
+//! directory. A turn sends the conversation's recent history to the model;
+//! when that outgrows the context budget, the oldest turns are folded into a
+//! running summary that rides along in the system message instead. Context
+//! passed with a turn, and notes retrieved for it, only go into that turn's
+//! prompt.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::providers::{ChatMessage, TokenSink};
+use super::search::{SearchOptions, SearchResult};
+use crate::rpc::{ConversationCreateRequest, ConversationMessageRequest, LlmSelection};
+
+const CONVERSATIONS_DIR: &str = "conversations";
+const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful assistant.";
+/// Characters of prompt sent with each turn. When the history outgrows it,
+/// old turns are summarised until the rest fit in half of it, so a summary
+/// is written every few turns rather than on every one.
+const CONTEXT_CHARS: usize = 12_000;
+const PASSAGE_CHARS: usize = 1000;
+const TITLE_CHARS: usize = 60;
+const RAG_RESULTS: usize = 5;
+const REPLY_TOKENS: u32 = 1024;
+const SUMMARY_TOKENS: u32 = 400;
+
+/// Serialises read-modify-write cycles on conversation files.
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    pub system_prompt: Option<String>,
+    /// Ground turns in the indexed notes by default
+    #[serde(default)]
+    pub rag: bool,
+    pub messages: Vec<ConversationMessage>,
+    /// Summary of the turns before `summarized`, sent in their place
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// Number of leading messages covered by `summary`
+    #[serde(default)]
+    pub summarized: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationMessage {
+    pub role: String,
+    pub content: String,
+    pub created_at: i64,
+    /// Notes the reply was grounded in, as `title (path)`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub id: String,
+    pub title: String,
+    pub message_count: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationReply {
+    pub conversation_id: String,
+    pub message: ConversationMessage,
+    /// Provider that produced the reply; `local` for the in-process engine
+    pub provider: String,
+    /// Whether older turns were folded into the summary on this turn
+    pub summarized: bool,
+}
+
+impl ConversationMessage {
+    fn new(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+            created_at: chrono::Utc::now().timestamp(),
+            sources: Vec::new(),
+        }
+    }
+}
+
+fn conversation_path(data_dir: &Path, id: &str) -> Result<PathBuf> {
+    // Ids become file names, so only accept the ones we hand out
+    if uuid::Uuid::parse_str(id).is_err() {
+        bail!("Invalid conversation id: {}", id);
+    }
+    Ok(data_dir
+        .join(CONVERSATIONS_DIR)
+        .join(format!("{}.json", id)))
+}
+
+fn load(data_dir: &Path, id: &str) -> Result<Option<Conversation>> {
+    let path = conversation_path(data_dir, id)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = fs::read_to_string(&path).context("Failed to read conversation")?;
+    Ok(Some(
+        serde_json::from_str(&data).context("Failed to parse conversation")?,
+    ))
+}
+
+fn save(data_dir: &Path, conversation: &Conversation) -> Result<()> {
+    let path = conversation_path(data_dir, &conversation.id)?;
+    fs::create_dir_all(data_dir.join(CONVERSATIONS_DIR))
+        .context("Failed to create conversations directory")?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(conversation)?)
+        .context("Failed to write conversation")?;
+    fs::rename(&tmp, &path).context("Failed to write conversation")?;
+    Ok(())
+}
+
+/// Loads, changes and saves a conversation under the store lock.
+fn update<T>(data_dir: &Path, id: &str, change: impl FnOnce(&mut Conversation) -> T) -> Result<T> {
+    let _guard = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(mut conversation) = load(data_dir, id)? else {
+        bail!("Conversation not found: {}", id);
+    };
+    conversation.updated_at = chrono::Utc::now().timestamp();
+    let result = change(&mut conversation);
+    save(data_dir, &conversation)?;
+    Ok(result)
+}
+
+pub fn create(data_dir: &Path, request: &ConversationCreateRequest) -> Result<Conversation> {
+    let now = chrono::Utc::now().timestamp();
+    let conversation = Conversation {
+        id: uuid::Uuid::new_v4().to_string(),
+        title: request.title.clone().unwrap_or_default(),
+        system_prompt: request.system_prompt.clone(),
+        rag: request.rag,
+        messages: Vec::new(),
+        summary: None,
+        summarized: 0,
+        created_at: now,
+        updated_at: now,
+    };
+    save(data_dir, &conversation)?;
+    Ok(conversation)
+}
+
+/// All conversations, most recently active first.
+pub fn list(data_dir: &Path) -> Result<Vec<ConversationSummary>> {
+    let dir = data_dir.join(CONVERSATIONS_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut conversations = Vec::new();
+    for entry in fs::read_dir(&dir).context("Failed to read conversations directory")? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let parsed = fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(serde_json::from_str::<Conversation>(&data)?));
+        match parsed {
+            Ok(c) => conversations.push(ConversationSummary {
+                id: c.id,
+                title: c.title,
+                message_count: c.messages.len(),
+                created_at: c.created_at,
+                updated_at: c.updated_at,
+            }),
+            Err(e) => tracing::warn!("Skipping conversation {:?}: {}", path, e),
+        }
+    }
+    conversations.sort_by_key(|c| std::cmp::Reverse(c.updated_at));
+    Ok(conversations)
+}
+
+pub fn get(data_dir: &Path, id: &str) -> Result<Option<Conversation>> {
+    load(data_dir, id)
+}
+
+pub fn rename(data_dir: &Path, id: &str, title: &str) -> Result<Conversation> {
+    update(data_dir, id, |conversation| {
+        conversation.title = title.trim().to_string();
+        conversation.clone()
+    })
+}
+
+pub fn delete(data_dir: &Path, id: &str) -> Result<bool> {
+    let path = conversation_path(data_dir, id)?;
+    let _guard = STORE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if !path.exists() {
+        return Ok(false);
+    }
+    fs::remove_file(&path).context("Failed to delete conversation")?;
+    Ok(true)
+}
+
+/// Adds a user turn to the conversation and generates the reply, streaming
+/// it to `tokens` when given. Both turns are saved once the reply is done.
+pub async fn send(
+    data_dir: &Path,
+    request: &ConversationMessageRequest,
+    tokens: Option<TokenSink>,
+) -> Result<ConversationReply> {
+    let Some(conversation) = load(data_dir, &request.id)? else {
+        bail!("Conversation not found: {}", request.id);
+    };
+    let user_message = ConversationMessage::new("user", &request.message);
+
+    let mut passages: Vec<(String, String)> = request
+        .context
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(i, text)| (format!("Context {}", i + 1), text.clone()))
+        .collect();
+    let mut sources = Vec::new();
+    if request.rag.unwrap_or(conversation.rag) {
+        for result in retrieve(&request.message).await? {
+            sources.push(format!("{} ({})", result.title, result.path));
+            passages.push((result.title.clone(), result.passage().to_string()));
+        }
+    }
+
+    let summarized_before = conversation.summarized.min(conversation.messages.len());
+    let mut history: Vec<ConversationMessage> = conversation.messages[summarized_before..].to_vec();
+    history.push(user_message.clone());
+    let mut summary = conversation.summary.clone();
+    let mut summarized = summarized_before;
+
+    let system_len = system_message(&conversation, summary.as_deref(), &passages).len();
+    let budget = CONTEXT_CHARS.saturating_sub(system_len);
+    if window_start(&history, budget) > 0 {
+        let cut = window_start(&history, budget / 2);
+        match summarize(&request.llm, summary.as_deref(), &history[..cut]).await {
+            Ok(text) => summary = Some(text),
+            Err(e) => tracing::warn!("Failed to summarize conversation, trimming it: {}", e),
+        }
+        history.drain(..cut);
+        summarized += cut;
+    }
+
+    let mut messages = vec![ChatMessage {
+        role: "system".to_string(),
+        content: system_message(&conversation, summary.as_deref(), &passages),
+    }];
+    messages.extend(history.iter().map(|m| ChatMessage {
+        role: m.role.clone(),
+        content: m.content.clone(),
+    }));
+    let (text, provider) = complete(&request.llm, messages, REPLY_TOKENS, tokens).await?;
+
+    let mut reply = ConversationMessage::new("assistant", text.trim());
+    reply.sources = sources;
+    let saved_reply = reply.clone();
+    update(data_dir, &request.id, move |conversation| {
+        if conversation.title.is_empty() {
+            conversation.title = title_from(&user_message.content);
+        }
+        conversation.messages.push(user_message);
+        conversation.messages.push(saved_reply);
+        if summarized > conversation.summarized {
+            conversation.summary = summary;
+            conversation.summarized = summarized;
+        }
+    })?;
+
+    Ok(ConversationReply {
+        conversation_id: request.id.clone(),
+        message: reply,
+        provider,
+        summarized: summarized > summarized_before,
+    })
+}
+
+/// Index of the oldest message that still fits in `budget` characters,
+/// counting back from the newest, which is always kept. The window opens on
+/// a user turn, as some chat templates require.
+fn window_start(messages: &[ConversationMessage], budget: usize) -> usize {
+    let mut used = 0;
+    let mut start = messages.len();
+    while start > 0 {
+        let len = messages[start - 1].content.len();
+        if used + len > budget && start < messages.len() {
+            break;
+        }
+        used += len;
+        start -= 1;
+    }
+    while start + 1 < messages.len() && messages[start].role != "user" {
+        start += 1;
+    }
+    start
+}
+
+fn system_message(
+    conversation: &Conversation,
+    summary: Option<&str>,
+    passages: &[(String, String)],
+) -> String {
+    let mut system = conversation
+        .system_prompt
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string());
+    if let Some(summary) = summary {
+        system.push_str("\n\nSummary of the earlier conversation:\n");
+        system.push_str(summary);
+    }
+    if !passages.is_empty() {
+        system.push_str(
+            "\n\nUse the following context from the user's notes when it is relevant, \
+             citing passages by number, e.g. [1]:",
+        );
+        for (i, (title, text)) in passages.iter().enumerate() {
+            let text: String = text.chars().take(PASSAGE_CHARS).collect();
+            system.push_str(&format!("\n\n[{}] {}\n{}", i + 1, title, text));
+        }
+    }
+    system
+}
+
+fn title_from(message: &str) -> String {
+    let line = message.lines().next().unwrap_or_default().trim();
+    if line.chars().count() <= TITLE_CHARS {
+        line.to_string()
+    } else {
+        let title: String = line.chars().take(TITLE_CHARS).collect();
+        format!("{}...", title.trim_end())
+    }
+}
+
+fn transcript(messages: &[ConversationMessage]) -> String {
+    messages
+        .iter()
+        .map(|m| {
+            let speaker = if m.role == "assistant" {
+                "Assistant"
+            } else {
+                "User"
+            };
+            format!("{}: {}", speaker, m.content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Folds `messages` into the running summary.
+async fn summarize(
+    llm: &LlmSelection,
+    previous: Option<&str>,
+    messages: &[ConversationMessage],
+) -> Result<String> {
+    let previous = previous
+        .map(|s| format!("Summary so far:\n{}\n\n", s))
+        .unwrap_or_default();
+    let prompt = format!(
+        "Summarize the conversation below so it can stand in for it later. Keep \
+         names, facts, decisions and open questions. Write at most 200 words.\n\n\
+         {}Conversation:\n{}\n\nSummary:",
+        previous,
+        transcript(messages)
+    );
+    let messages = vec![ChatMessage {
+        role: "user".to_string(),
+        content: prompt,
+    }];
+    let (text, _) = complete(llm, messages, SUMMARY_TOKENS, None).await?;
+    Ok(text.trim().to_string())
+}
+
+async fn retrieve(query: &str) -> Result<Vec<SearchResult>> {
+    super::ensure_pipeline().await?;
+    let guard = super::get_pipeline_lock().read().await;
+    let pipeline = guard.as_ref().unwrap();
+    pipeline
+        .search_with(query, RAG_RESULTS, &SearchOptions::default())
+        .await
+}
+
+/// Runs `messages` on the request's providers, or the local engine. Returns
+/// the text and the provider that wrote it.
+async fn complete(
+    llm: &LlmSelection,
+    messages: Vec<ChatMessage>,
+    max_tokens: u32,
+    tokens: Option<TokenSink>,
+) -> Result<(String, String)> {
+    if let Some(chain) = super::provider_chain(llm)? {
+        let answer = chain.chat(messages, max_tokens, tokens).await?;
+        return Ok((answer.text, answer.provider));
+    }
+    super::ensure_pipeline().await?;
+    let guard = super::get_pipeline_lock().read().await;
+    let pipeline = guard.as_ref().unwrap();
+    let text = pipeline.chat(messages, max_tokens, tokens).await?;
+    Ok((text, super::LOCAL_PROVIDER.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ConversationMessage {
+        ConversationMessage::new(role, content)
+    }
+
+    #[test]
+    fn test_window_keeps_recent_turns_from_a_user_message() {
+        let messages = vec![
+            message("user", "aaaa"),
+            message("assistant", "bbbb"),
+            message("user", "cccc"),
+            message("assistant", "dddd"),
+            message("user", "eeee"),
+        ];
+        assert_eq!(window_start(&messages, 100), 0);
+        assert_eq!(window_start(&messages, 12), 2);
+        // Room for four messages, but the window can't open on a reply
+        assert_eq!(window_start(&messages, 16), 2);
+        assert_eq!(window_start(&messages, 9), 4);
+        // The newest message is kept even when it alone is too long
+        assert_eq!(window_start(&messages, 1), 4);
+    }
+
+    #[test]
+    fn test_create_rename_list_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let request = ConversationCreateRequest {
+            system_prompt: Some("Be brief.".to_string()),
+            ..Default::default()
+        };
+        let first = create(dir.path(), &request).unwrap();
+        let second = create(dir.path(), &request).unwrap();
+
+        let renamed = rename(dir.path(), &first.id, " Trip plans ").unwrap();
+        assert_eq!(renamed.title, "Trip plans");
+        let listed = list(dir.path()).unwrap();
+        assert_eq!(listed.len(), 2);
+        assert!(listed.iter().any(|c| c.title == "Trip plans"));
+
+        assert!(delete(dir.path(), &second.id).unwrap());
+        assert!(!delete(dir.path(), &second.id).unwrap());
+        assert!(get(dir.path(), &second.id).unwrap().is_none());
+        assert_eq!(
+            get(dir.path(), &first.id).unwrap().unwrap().system_prompt,
+            Some("Be brief.".to_string())
+        );
+        assert!(get(dir.path(), "../ai_config").is_err());
+    }
+
+    #[test]
+    fn test_title_from_first_line() {
+        assert_eq!(title_from("Short question\nmore"), "Short question");
+        let long = "é".repeat(80);
+        assert_eq!(title_from(&long), format!("{}...", "é".repeat(60)));
+    }
+}
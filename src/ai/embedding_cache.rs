@@ -0,0 +1,209 @@
+//! On-disk cache of embedding vectors, keyed by a hash of the embedded text.
+//!
+//! Each model gets its own append-only file of fixed-size records: the
+//! SHA-256 of the text followed by the vector as little-endian `f32`s.
+//! Re-indexing unchanged chunks, or switching to a model used before, then
+//! reads vectors back instead of running the model again.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const KEY_LEN: usize = 32;
+/// Vectors kept when the file is compacted on open; the oldest go first.
+const MAX_ENTRIES: usize = 50_000;
+
+type Key = [u8; KEY_LEN];
+
+pub struct EmbeddingCache {
+    path: PathBuf,
+    dimension: usize,
+    vectors: HashMap<Key, Vec<f32>>,
+    /// Keys of `vectors` in the order they were first added
+    order: Vec<Key>,
+}
+
+fn key(text: &str) -> Key {
+    Sha256::digest(text.as_bytes()).into()
+}
+
+impl EmbeddingCache {
+    /// Opens the cache for `model_id` in `dir`. A missing or damaged file
+    /// leaves the cache empty rather than failing.
+    pub fn open(dir: &Path, model_id: &str, dimension: usize) -> Self {
+        let file_name = format!("{}.bin", model_id.replace(['/', '\\', ':'], "__"));
+        let mut cache = Self {
+            path: dir.join(file_name),
+            dimension,
+            vectors: HashMap::new(),
+            order: Vec::new(),
+        };
+        if let Err(e) = cache.load() {
+            tracing::warn!("Ignoring embedding cache {:?}: {}", cache.path, e);
+            cache.vectors.clear();
+            cache.order.clear();
+        }
+        cache
+    }
+
+    fn record_len(&self) -> usize {
+        KEY_LEN + self.dimension * 4
+    }
+
+    fn load(&mut self) -> Result<()> {
+        if self.dimension == 0 || !self.path.exists() {
+            return Ok(());
+        }
+        let bytes = fs::read(&self.path).context("Failed to read embedding cache")?;
+        // A torn final record from an interrupted write is skipped
+        let records: Vec<&[u8]> = bytes.chunks_exact(self.record_len()).collect();
+        let skip = records.len().saturating_sub(MAX_ENTRIES);
+        for record in &records[skip..] {
+            let (key, vector) = record.split_at(KEY_LEN);
+            let key: Key = key.try_into()?;
+            if self.vectors.contains_key(&key) {
+                continue;
+            }
+            let vector = vector
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            self.vectors.insert(key, vector);
+            self.order.push(key);
+        }
+
+        let whole = records.len() * self.record_len();
+        if skip > 0 || whole != bytes.len() || self.vectors.len() < records.len() {
+            self.rewrite()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the live entries back in the order they were added, dropping
+    /// duplicates and trimmed ones.
+    fn rewrite(&self) -> Result<()> {
+        let mut bytes = Vec::with_capacity(self.vectors.len() * self.record_len());
+        for key in &self.order {
+            bytes.extend_from_slice(key);
+            let vector = &self.vectors[key];
+            for value in vector {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, bytes).context("Failed to write embedding cache")?;
+        fs::rename(&tmp, &self.path).context("Failed to write embedding cache")?;
+        Ok(())
+    }
+
+    pub fn get(&self, text: &str) -> Option<&Vec<f32>> {
+        self.vectors.get(&key(text))
+    }
+
+    /// Adds `(text, vector)` entries, appending new ones to the file. Vectors
+    /// of the wrong dimension are skipped.
+    pub fn insert(&mut self, entries: &[(&str, &Vec<f32>)]) -> Result<()> {
+        let mut bytes = Vec::new();
+        for (text, vector) in entries {
+            if vector.len() != self.dimension {
+                continue;
+            }
+            let key = key(text);
+            if self.vectors.contains_key(&key) {
+                continue;
+            }
+            bytes.extend_from_slice(&key);
+            for value in vector.iter() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            self.vectors.insert(key, (*vector).clone());
+            self.order.push(key);
+        }
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).context("Failed to create embedding cache directory")?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("Failed to open embedding cache")?;
+        file.write_all(&bytes)
+            .context("Failed to write embedding cache")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vectors_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = EmbeddingCache::open(dir.path(), "org/model", 2);
+        assert!(cache.get("hello").is_none());
+
+        cache
+            .insert(&[("hello", &vec![1.0, 2.0]), ("bad", &vec![1.0])])
+            .unwrap();
+        cache.insert(&[("world", &vec![3.0, 4.0])]).unwrap();
+        assert!(dir.path().join("org__model.bin").exists());
+
+        let reopened = EmbeddingCache::open(dir.path(), "org/model", 2);
+        assert_eq!(reopened.get("hello"), Some(&vec![1.0, 2.0]));
+        assert_eq!(reopened.get("world"), Some(&vec![3.0, 4.0]));
+        assert!(reopened.get("bad").is_none());
+
+        // Other models don't share vectors
+        assert!(EmbeddingCache::open(dir.path(), "other", 2)
+            .get("hello")
+            .is_none());
+    }
+
+    #[test]
+    fn test_torn_record_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = EmbeddingCache::open(dir.path(), "m", 1);
+        cache.insert(&[("a", &vec![0.5])]).unwrap();
+        let path = dir.path().join("m.bin");
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.extend_from_slice(&[1, 2, 3]);
+        fs::write(&path, &bytes).unwrap();
+
+        let reopened = EmbeddingCache::open(dir.path(), "m", 1);
+        assert_eq!(reopened.get("a"), Some(&vec![0.5]));
+        assert_eq!(fs::read(&path).unwrap().len(), KEY_LEN + 4);
+    }
+
+    #[test]
+    fn test_compaction_keeps_insertion_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let texts = ["e", "d", "c", "b", "a", "f", "g", "h"];
+        let mut cache = EmbeddingCache::open(dir.path(), "m", 1);
+        for (i, text) in texts.iter().enumerate() {
+            cache.insert(&[(*text, &vec![i as f32])]).unwrap();
+        }
+        // A duplicate record makes the next open rewrite the file
+        let path = dir.path().join("m.bin");
+        let mut bytes = fs::read(&path).unwrap();
+        let first = bytes[..KEY_LEN + 4].to_vec();
+        bytes.extend_from_slice(&first);
+        fs::write(&path, &bytes).unwrap();
+
+        let reopened = EmbeddingCache::open(dir.path(), "m", 1);
+        assert_eq!(reopened.get("e"), Some(&vec![0.0]));
+        let keys: Vec<Key> = fs::read(&path)
+            .unwrap()
+            .chunks_exact(KEY_LEN + 4)
+            .map(|record| record[..KEY_LEN].try_into().unwrap())
+            .collect();
+        assert_eq!(keys, texts.map(key).to_vec());
+    }
+}
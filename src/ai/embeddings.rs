@@ -1,64 +1,240 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use serde::Serialize;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+use super::embedding_cache::EmbeddingCache;
+
+/// Model used when none is configured. Model ids are stored with the index so
+/// a model change is detected on load.
+pub const DEFAULT_EMBEDDING_MODEL_ID: &str = "sentence-transformers/all-MiniLM-L6-v2";
+
+/// An embedding model the engine can load.
+pub struct ModelSpec {
+    pub id: &'static str,
+    model: EmbeddingModel,
+    /// Prepended to search queries and to indexed text; some models are
+    /// trained to expect these markers
+    query_prefix: &'static str,
+    passage_prefix: &'static str,
+}
+
+const MODELS: &[ModelSpec] = &[
+    ModelSpec {
+        id: DEFAULT_EMBEDDING_MODEL_ID,
+        model: EmbeddingModel::AllMiniLML6V2,
+        query_prefix: "",
+        passage_prefix: "",
+    },
+    ModelSpec {
+        id: "sentence-transformers/paraphrase-multilingual-MiniLM-L12-v2",
+        model: EmbeddingModel::ParaphraseMLMiniLML12V2,
+        query_prefix: "",
+        passage_prefix: "",
+    },
+    ModelSpec {
+        id: "intfloat/multilingual-e5-small",
+        model: EmbeddingModel::MultilingualE5Small,
+        query_prefix: "query: ",
+        passage_prefix: "passage: ",
+    },
+    ModelSpec {
+        id: "intfloat/multilingual-e5-base",
+        model: EmbeddingModel::MultilingualE5Base,
+        query_prefix: "query: ",
+        passage_prefix: "passage: ",
+    },
+    ModelSpec {
+        id: "intfloat/multilingual-e5-large",
+        model: EmbeddingModel::MultilingualE5Large,
+        query_prefix: "query: ",
+        passage_prefix: "passage: ",
+    },
+    ModelSpec {
+        id: "BAAI/bge-small-en-v1.5",
+        model: EmbeddingModel::BGESmallENV15,
+        query_prefix: "Represent this sentence for searching relevant passages: ",
+        passage_prefix: "",
+    },
+    ModelSpec {
+        id: "BAAI/bge-base-en-v1.5",
+        model: EmbeddingModel::BGEBaseENV15,
+        query_prefix: "Represent this sentence for searching relevant passages: ",
+        passage_prefix: "",
+    },
+    ModelSpec {
+        id: "BAAI/bge-small-zh-v1.5",
+        model: EmbeddingModel::BGESmallZHV15,
+        query_prefix: "为这个句子生成表示以用于检索相关文章：",
+        passage_prefix: "",
+    },
+];
+
+/// Finds a model by its full id (`intfloat/multilingual-e5-small`) or by the
+/// name after the organisation (`multilingual-e5-small`), ignoring case.
+pub fn resolve_model(name: &str) -> Option<&'static ModelSpec> {
+    let name = name.trim();
+    MODELS.iter().find(|spec| {
+        let short = spec.id.rsplit('/').next().unwrap_or(spec.id);
+        spec.id.eq_ignore_ascii_case(name) || short.eq_ignore_ascii_case(name)
+    })
+}
+
+impl ModelSpec {
+    pub fn dimension(&self) -> usize {
+        TextEmbedding::get_model_info(&self.model).map_or(0, |info| info.dim)
+    }
+
+    fn description(&self) -> String {
+        TextEmbedding::get_model_info(&self.model)
+            .map(|info| info.description.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbeddingModelInfo {
+    pub id: String,
+    pub dimension: usize,
+    pub description: String,
+}
+
+pub fn supported_models() -> Vec<EmbeddingModelInfo> {
+    MODELS
+        .iter()
+        .map(|spec| EmbeddingModelInfo {
+            id: spec.id.to_string(),
+            dimension: spec.dimension(),
+            description: spec.description(),
+        })
+        .collect()
+}
+
 pub struct EmbeddingEngine {
     model: TextEmbedding,
+    spec: &'static ModelSpec,
+    dimension: usize,
+    cache: Option<EmbeddingCache>,
 }
 
 impl EmbeddingEngine {
-    pub fn new() -> Result<Self> {
-        let model = TextEmbedding::try_new(InitOptions::new(EmbeddingModel::AllMiniLML6V2))
+    /// Loads `model_id` (see [`resolve_model`]). With `cache_dir`, vectors are
+    /// cached on disk by the text they were computed from.
+    pub fn new(model_id: &str, cache_dir: Option<&Path>) -> Result<Self> {
+        let Some(spec) = resolve_model(model_id) else {
+            bail!("Unknown embedding model: {}", model_id);
+        };
+        let model = TextEmbedding::try_new(InitOptions::new(spec.model.clone()))
             .context("Failed to initialize embedding model")?;
+        let dimension = spec.dimension();
+        let cache = cache_dir.map(|dir| EmbeddingCache::open(dir, spec.id, dimension));
 
-        Ok(Self { model })
+        Ok(Self {
+            model,
+            spec,
+            dimension,
+            cache,
+        })
     }
 
-    pub fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
-        let embeddings = self
-            .model
-            .embed(texts.to_vec(), None)
-            .context("Failed to generate embeddings")?;
-        Ok(embeddings)
+    /// Embeds indexed text. Texts seen before are served from the cache.
+    pub fn embed(&mut self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let inputs: Vec<String> = texts
+            .iter()
+            .map(|t| format!("{}{}", self.spec.passage_prefix, t))
+            .collect();
+
+        let mut vectors: Vec<Option<Vec<f32>>> = inputs
+            .iter()
+            .map(|input| self.cache.as_ref().and_then(|c| c.get(input).cloned()))
+            .collect();
+        let missing: Vec<usize> = (0..inputs.len())
+            .filter(|&i| vectors[i].is_none())
+            .collect();
+
+        if !missing.is_empty() {
+            let batch: Vec<&str> = missing.iter().map(|&i| inputs[i].as_str()).collect();
+            let computed = self
+                .model
+                .embed(batch, None)
+                .context("Failed to generate embeddings")?;
+            if let Some(ref mut cache) = self.cache {
+                let entries: Vec<(&str, &Vec<f32>)> = missing
+                    .iter()
+                    .map(|&i| inputs[i].as_str())
+                    .zip(computed.iter())
+                    .collect();
+                if let Err(e) = cache.insert(&entries) {
+                    tracing::warn!("Failed to update embedding cache: {}", e);
+                }
+            }
+            for (i, vector) in missing.into_iter().zip(computed) {
+                vectors[i] = Some(vector);
+            }
+        }
+
+        vectors
+            .into_iter()
+            .map(|v| v.context("No embedding generated"))
+            .collect()
     }
 
-    pub fn embed_single(&self, text: &str) -> Result<Vec<f32>> {
-        let embeddings = self.embed(&[text])?;
-        embeddings
+    /// Embeds a search query, which some models mark differently from
+    /// indexed text. Queries are not cached.
+    pub fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
+        let input = format!("{}{}", self.spec.query_prefix, query);
+        self.model
+            .embed(vec![input], None)
+            .context("Failed to generate embeddings")?
             .into_iter()
             .next()
             .context("No embedding generated")
     }
 
     pub fn dimension(&self) -> usize {
-        384
+        self.dimension
+    }
+
+    pub fn model_id(&self) -> &'static str {
+        self.spec.id
     }
 }
 
+#[derive(Clone)]
 pub struct AsyncEmbeddingEngine {
     inner: Arc<Mutex<EmbeddingEngine>>,
+    model_id: &'static str,
+    dimension: usize,
 }
 
 impl AsyncEmbeddingEngine {
-    pub fn new() -> Result<Self> {
+    pub fn new(model_id: &str, cache_dir: Option<&Path>) -> Result<Self> {
+        let engine = EmbeddingEngine::new(model_id, cache_dir)?;
         Ok(Self {
-            inner: Arc::new(Mutex::new(EmbeddingEngine::new()?)),
+            model_id: engine.model_id(),
+            dimension: engine.dimension(),
+            inner: Arc::new(Mutex::new(engine)),
         })
     }
 
     pub async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
-        let engine = self.inner.lock().await;
+        let mut engine = self.inner.lock().await;
         engine.embed(texts)
     }
 
-    pub async fn embed_single(&self, text: &str) -> Result<Vec<f32>> {
+    pub async fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
         let engine = self.inner.lock().await;
-        engine.embed_single(text)
+        engine.embed_query(query)
     }
 
     pub fn dimension(&self) -> usize {
-        384
+        self.dimension
+    }
+
+    pub fn model_id(&self) -> &'static str {
+        self.model_id
     }
 }
 
@@ -73,3 +249,29 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
         dot / (norm_a * norm_b)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_model_by_full_or_short_name() {
+        let spec = resolve_model("intfloat/multilingual-e5-small").unwrap();
+        assert_eq!(spec.id, "intfloat/multilingual-e5-small");
+        assert_eq!(resolve_model("Multilingual-E5-Small").unwrap().id, spec.id);
+        assert_eq!(
+            resolve_model("bge-small-en-v1.5").unwrap().id,
+            "BAAI/bge-small-en-v1.5"
+        );
+        assert!(resolve_model("no-such-model").is_none());
+    }
+
+    #[test]
+    fn test_dimensions_come_from_the_model() {
+        let dimension = |id| resolve_model(id).unwrap().dimension();
+        assert_eq!(dimension(DEFAULT_EMBEDDING_MODEL_ID), 384);
+        assert_eq!(dimension("multilingual-e5-base"), 768);
+        assert_eq!(dimension("multilingual-e5-large"), 1024);
+        assert!(supported_models().iter().all(|m| m.dimension > 0));
+    }
+}
@@ -0,0 +1,438 @@
+//! Structured extraction: text in, JSON matching a caller's JSON Schema out.
+//!
+//! Providers are asked for JSON in their native way (JSON mode for
+//! OpenAI-compatible servers, schema-constrained output for Ollama, a forced
+//! tool call for Anthropic); the local engine only has the prompt to go on.
+//! Every reply is validated against the schema, and one that fails is sent
+//! back with the problems found for the model to repair.
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+use super::providers::{ChatMessage, FallbackChain};
+
+/// Replies asked for before giving up: the first and two repairs
+const MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_INSTRUCTIONS: &str = "Extract the requested information from the text.";
+const TYPES: &[&str] = &[
+    "object", "array", "string", "number", "integer", "boolean", "null",
+];
+
+/// Validated data and where it came from.
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    pub data: Value,
+    pub provider: String,
+    /// Replies it took, including the valid one
+    pub attempts: u32,
+}
+
+/// Extracts data matching `schema` from `text` with the providers in
+/// `chain`, repairing invalid replies up to [`MAX_ATTEMPTS`] times.
+pub async fn extract(
+    chain: &FallbackChain,
+    text: &str,
+    schema: &Value,
+    instructions: Option<&str>,
+    max_tokens: u32,
+) -> Result<Extraction> {
+    check_schema(schema)?;
+
+    let mut messages = vec![ChatMessage {
+        role: "user".to_string(),
+        content: extraction_prompt(text, schema, instructions)?,
+    }];
+    let mut problems = Vec::new();
+    for attempt in 1..=MAX_ATTEMPTS {
+        let reply = chain
+            .chat_json(messages.clone(), max_tokens, schema)
+            .await?;
+        let data = parse_json(&reply.text);
+        problems = match data {
+            Ok(ref data) => validate(data, schema),
+            Err(ref e) => vec![format!("{:#}", e)],
+        };
+        if problems.is_empty() {
+            return Ok(Extraction {
+                data: data?,
+                provider: reply.provider,
+                attempts: attempt,
+            });
+        }
+
+        tracing::debug!(
+            "Extraction attempt {} was invalid: {}",
+            attempt,
+            problems.join("; ")
+        );
+        messages.push(ChatMessage {
+            role: "assistant".to_string(),
+            content: reply.text,
+        });
+        messages.push(ChatMessage {
+            role: "user".to_string(),
+            content: format!(
+                "That reply does not match the schema:\n- {}\n\nReply with only the corrected JSON object.",
+                problems.join("\n- ")
+            ),
+        });
+    }
+    bail!(
+        "No valid JSON after {} attempts: {}",
+        MAX_ATTEMPTS,
+        problems.join("; ")
+    )
+}
+
+fn extraction_prompt(text: &str, schema: &Value, instructions: Option<&str>) -> Result<String> {
+    Ok(format!(
+        r#"{}
+
+Respond with a single JSON object that matches this JSON Schema:
+{}
+
+Text:
+{}
+
+Respond ONLY with the JSON object."#,
+        instructions.unwrap_or(DEFAULT_INSTRUCTIONS),
+        serde_json::to_string_pretty(schema)?,
+        text
+    ))
+}
+
+/// Checks that `schema` has an object at its root, which JSON modes and
+/// tool inputs require, and only uses JSON Schema's type names.
+pub fn check_schema(schema: &Value) -> Result<()> {
+    if schema.get("type").and_then(Value::as_str) != Some("object") {
+        bail!("Schema root must be {{\"type\": \"object\"}}");
+    }
+    check_types(schema, "$")
+}
+
+fn check_types(schema: &Value, path: &str) -> Result<()> {
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+    let names: Vec<&Value> = match schema.get("type") {
+        Some(Value::Array(names)) => names.iter().collect(),
+        Some(name) => vec![name],
+        None => Vec::new(),
+    };
+    for name in names {
+        if !name.as_str().is_some_and(|n| TYPES.contains(&n)) {
+            bail!("{}: unknown type {}", path, name);
+        }
+    }
+
+    if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+        for (name, property) in properties {
+            check_types(property, &format!("{}.{}", path, name))?;
+        }
+    }
+    for key in ["items", "additionalProperties"] {
+        if let Some(nested) = schema.get(key) {
+            check_types(nested, &format!("{}.{}", path, key))?;
+        }
+    }
+    for key in ["anyOf", "oneOf"] {
+        for (i, option) in schema
+            .get(key)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            check_types(option, &format!("{}.{}[{}]", path, key, i))?;
+        }
+    }
+    Ok(())
+}
+
+/// The JSON value in a model's reply, which may sit in a Markdown code
+/// fence or between sentences.
+pub fn parse_json(reply: &str) -> Result<Value> {
+    let reply = reply.trim();
+    if let Ok(value) = serde_json::from_str(reply) {
+        return Ok(value);
+    }
+    let start = reply.find(['{', '[']).context("Reply contains no JSON")?;
+    // Parses the first value and ignores whatever follows it
+    serde_json::Deserializer::from_str(&reply[start..])
+        .into_iter::<Value>()
+        .next()
+        .context("Reply contains no JSON")?
+        .context("Reply is not valid JSON")
+}
+
+/// Problems with `value` against `schema`, each prefixed with the path to
+/// the offending value. Covers the commonly used keywords: `type`, `enum`,
+/// `const`, `anyOf`/`oneOf`, `properties`, `required`,
+/// `additionalProperties`, `items`, `minItems`/`maxItems`,
+/// `minLength`/`maxLength` and `minimum`/`maximum`.
+pub fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    validate_at(value, schema, "$", &mut problems);
+    problems
+}
+
+fn validate_at(value: &Value, schema: &Value, path: &str, problems: &mut Vec<String>) {
+    // `true` and `{}` accept anything
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(name)) => vec![name.as_str()],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+        problems.push(format!(
+            "{}: expected {}, got {}",
+            path,
+            types.join(" or "),
+            type_name(value)
+        ));
+        return;
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            problems.push(format!(
+                "{}: must be one of {}",
+                path,
+                Value::Array(allowed.clone())
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            problems.push(format!("{}: must be {}", path, expected));
+        }
+    }
+    let options = schema.get("anyOf").or_else(|| schema.get("oneOf"));
+    if let Some(options) = options.and_then(Value::as_array) {
+        if !options.iter().any(|o| validate(value, o).is_empty()) {
+            problems.push(format!("{}: matches none of the allowed schemas", path));
+        }
+    }
+
+    let limit = |key: &str| schema.get(key).and_then(Value::as_f64);
+    match value {
+        Value::Object(map) => {
+            let required = schema.get("required").and_then(Value::as_array);
+            for name in required.into_iter().flatten().filter_map(Value::as_str) {
+                if !map.contains_key(name) {
+                    problems.push(format!("{}: missing required property \"{}\"", path, name));
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, item) in map {
+                let child = format!("{}.{}", path, key);
+                match (
+                    properties.and_then(|p| p.get(key)),
+                    schema.get("additionalProperties"),
+                ) {
+                    (Some(property), _) => validate_at(item, property, &child, problems),
+                    (None, Some(Value::Bool(false))) => {
+                        problems.push(format!("{}: unexpected property", child))
+                    }
+                    (None, Some(extra)) => validate_at(item, extra, &child, problems),
+                    (None, None) => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            let len = items.len() as f64;
+            if let Some(min) = limit("minItems").filter(|min| len < *min) {
+                problems.push(format!("{}: fewer than {} items", path, min));
+            }
+            if let Some(max) = limit("maxItems").filter(|max| len > *max) {
+                problems.push(format!("{}: more than {} items", path, max));
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(item, item_schema, &format!("{}[{}]", path, i), problems);
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as f64;
+            if let Some(min) = limit("minLength").filter(|min| len < *min) {
+                problems.push(format!("{}: shorter than {} characters", path, min));
+            }
+            if let Some(max) = limit("maxLength").filter(|max| len > *max) {
+                problems.push(format!("{}: longer than {} characters", path, max));
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = limit("minimum").filter(|min| n < *min) {
+                problems.push(format!("{}: less than {}", path, min));
+            }
+            if let Some(max) = limit("maximum").filter(|max| n > *max) {
+                problems.push(format!("{}: greater than {}", path, max));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Object(_) => "object",
+        Value::Array(_) => "array",
+        Value::String(_) => "string",
+        Value::Number(_) => "number",
+        Value::Bool(_) => "boolean",
+        Value::Null => "null",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::providers::{LlmProviderTrait, RetryPolicy};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    fn tags_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "title": {"type": "string", "minLength": 1},
+                "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 3},
+                "rating": {"type": "integer", "minimum": 1, "maximum": 5},
+                "kind": {"enum": ["article", "video"]}
+            },
+            "required": ["title", "tags"],
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn test_validate_reports_paths() {
+        let schema = tags_schema();
+        let valid = json!({"title": "Rust", "tags": ["lang"], "rating": 4, "kind": "article"});
+        assert!(validate(&valid, &schema).is_empty());
+
+        let invalid = json!({
+            "tags": ["a", 2, "c", "d"],
+            "rating": 4.5,
+            "kind": "podcast",
+            "extra": true
+        });
+        let problems = validate(&invalid, &schema);
+        assert!(problems.contains(&"$: missing required property \"title\"".to_string()));
+        assert!(problems.contains(&"$.tags: more than 3 items".to_string()));
+        assert!(problems.contains(&"$.tags[1]: expected string, got number".to_string()));
+        assert!(problems.contains(&"$.rating: expected integer, got number".to_string()));
+        assert!(problems.contains(&"$.extra: unexpected property".to_string()));
+        assert!(problems
+            .iter()
+            .any(|p| p.starts_with("$.kind: must be one of")));
+    }
+
+    #[test]
+    fn test_check_schema() {
+        assert!(check_schema(&tags_schema()).is_ok());
+        assert!(check_schema(&json!({"type": "array"})).is_err());
+        let unknown = json!({"type": "object", "properties": {"n": {"type": "float"}}});
+        assert_eq!(
+            check_schema(&unknown).unwrap_err().to_string(),
+            "$.n: unknown type \"float\""
+        );
+    }
+
+    #[test]
+    fn test_parse_json_from_prose_and_fences() {
+        assert_eq!(parse_json(r#" {"a": 1} "#).unwrap(), json!({"a": 1}));
+        let fenced = "Here you go:\n```json\n{\"a\": [1, 2]}\n```\nAnything else?";
+        assert_eq!(parse_json(fenced).unwrap(), json!({"a": [1, 2]}));
+        assert!(parse_json("I can't help with that.").is_err());
+        assert!(parse_json("{\"a\": ").is_err());
+    }
+
+    /// Answers with `replies` in turn, recording the conversations it sees.
+    struct Scripted {
+        replies: Mutex<Vec<&'static str>>,
+        seen: Seen,
+    }
+
+    #[async_trait::async_trait]
+    impl LlmProviderTrait for Scripted {
+        async fn generate(&self, _prompt: &str, _max_tokens: u32) -> Result<String> {
+            unreachable!()
+        }
+
+        async fn chat(&self, messages: Vec<ChatMessage>, _max_tokens: u32) -> Result<String> {
+            self.seen.lock().unwrap().push(messages);
+            Ok(self.replies.lock().unwrap().remove(0).to_string())
+        }
+
+        fn name(&self) -> &'static str {
+            "Scripted"
+        }
+    }
+
+    type Seen = Arc<Mutex<Vec<Vec<ChatMessage>>>>;
+
+    fn scripted_chain(replies: Vec<&'static str>) -> (FallbackChain, Seen) {
+        let seen = Seen::default();
+        let mut chain = FallbackChain::new(RetryPolicy::default());
+        chain.push(
+            "local",
+            Box::new(Scripted {
+                replies: Mutex::new(replies),
+                seen: seen.clone(),
+            }),
+        );
+        (chain, seen)
+    }
+
+    #[tokio::test]
+    async fn test_extract_repairs_invalid_replies() {
+        let (chain, seen) = scripted_chain(vec![
+            "Sure! {\"title\": \"Rust\"}",
+            "```json\n{\"title\": \"Rust\", \"tags\": [\"lang\"]}\n```",
+        ]);
+        let extraction = extract(&chain, "A post about Rust", &tags_schema(), None, 256)
+            .await
+            .unwrap();
+        assert_eq!(extraction.data, json!({"title": "Rust", "tags": ["lang"]}));
+        assert_eq!(extraction.provider, "local");
+        assert_eq!(extraction.attempts, 2);
+
+        let seen = seen.lock().unwrap();
+        let repair = &seen[1];
+        assert_eq!(repair.len(), 3);
+        assert_eq!(repair[1].role, "assistant");
+        assert!(repair[2]
+            .content
+            .contains("missing required property \"tags\""));
+    }
+
+    #[tokio::test]
+    async fn test_extract_gives_up() {
+        let (chain, _) = scripted_chain(vec!["no", "still no", "never"]);
+        let err = extract(&chain, "text", &tags_schema(), None, 256)
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("No valid JSON after 3 attempts"));
+    }
+}
@@ -0,0 +1,204 @@
+//! On-disk format for the AI search index.
+//!
+//! An index directory holds `index.json`, with the format version, the
+//! embedding model, and the documents, plus `embeddings.bin`, which packs
+//! the vectors as little-endian `f32`s in the order of `embedding_ids`.
+//! Vectors are kept out of the JSON because they make up most of the data.
+//! Each vector belongs to one chunk of a note; its id is the chunk key.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use super::search::NoteDocument;
+
+/// Bump when the layout changes; older indexes are then discarded.
+pub const INDEX_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "index.json";
+const EMBEDDINGS_FILE: &str = "embeddings.bin";
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    model_id: Option<String>,
+    dimension: usize,
+    #[serde(default)]
+    chunker: Option<String>,
+    documents: Vec<NoteDocument>,
+    embedding_ids: Vec<String>,
+}
+
+pub struct StoredIndex {
+    /// Embedding model that produced `embeddings`
+    pub model_id: Option<String>,
+    pub dimension: usize,
+    /// Chunking the vectors were computed for, see `chunking::CHUNKER_ID`
+    pub chunker: Option<String>,
+    pub documents: Vec<NoteDocument>,
+    pub embeddings: Vec<(String, Vec<f32>)>,
+}
+
+pub fn exists(dir: &Path) -> bool {
+    dir.join(MANIFEST_FILE).exists()
+}
+
+/// Reads the index in `dir`. Returns `None` when there is none or it was
+/// written in another format version. Vectors that don't match the manifest
+/// are dropped, so callers re-embed whatever is missing.
+pub fn load(dir: &Path) -> Result<Option<StoredIndex>> {
+    let manifest_path = dir.join(MANIFEST_FILE);
+    if !manifest_path.exists() {
+        return Ok(None);
+    }
+
+    let data = fs::read(&manifest_path).context("Failed to read index manifest")?;
+    let manifest: Manifest = match serde_json::from_slice(&data) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            tracing::warn!("Discarding unreadable AI index: {}", e);
+            return Ok(None);
+        }
+    };
+    if manifest.version != INDEX_FORMAT_VERSION {
+        tracing::info!(
+            "Discarding AI index format v{} (current v{})",
+            manifest.version,
+            INDEX_FORMAT_VERSION
+        );
+        return Ok(None);
+    }
+
+    let bytes = fs::read(dir.join(EMBEDDINGS_FILE)).unwrap_or_default();
+    let expected = manifest.embedding_ids.len() * manifest.dimension * 4;
+    let embeddings = if manifest.dimension > 0 && bytes.len() == expected {
+        let vectors = bytes.chunks_exact(manifest.dimension * 4).map(|vector| {
+            vector
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        });
+        manifest.embedding_ids.into_iter().zip(vectors).collect()
+    } else {
+        if !manifest.embedding_ids.is_empty() {
+            tracing::warn!("AI index vectors do not match the manifest; they will be rebuilt");
+        }
+        Vec::new()
+    };
+
+    Ok(Some(StoredIndex {
+        model_id: manifest.model_id,
+        dimension: manifest.dimension,
+        chunker: manifest.chunker,
+        documents: manifest.documents,
+        embeddings,
+    }))
+}
+
+pub fn save(dir: &Path, index: &StoredIndex) -> Result<()> {
+    fs::create_dir_all(dir).context("Failed to create index directory")?;
+
+    let mut bytes = Vec::with_capacity(index.embeddings.len() * index.dimension * 4);
+    let mut embedding_ids = Vec::with_capacity(index.embeddings.len());
+    for (id, vector) in &index.embeddings {
+        if vector.len() != index.dimension {
+            continue;
+        }
+        embedding_ids.push(id.clone());
+        for value in vector {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    let manifest = Manifest {
+        version: INDEX_FORMAT_VERSION,
+        model_id: index.model_id.clone(),
+        dimension: index.dimension,
+        chunker: index.chunker.clone(),
+        documents: index.documents.clone(),
+        embedding_ids,
+    };
+
+    write_atomic(&dir.join(EMBEDDINGS_FILE), &bytes)?;
+    write_atomic(&dir.join(MANIFEST_FILE), &serde_json::to_vec(&manifest)?)?;
+    Ok(())
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes).with_context(|| format!("Failed to write {:?}", tmp))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(id: &str) -> NoteDocument {
+        NoteDocument {
+            id: id.to_string(),
+            title: format!("Title {}", id),
+            content: "content".to_string(),
+            path: format!("{}.md", id),
+            embedding: None,
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load(dir.path()).unwrap().is_none());
+
+        let index = StoredIndex {
+            model_id: Some("model".to_string()),
+            dimension: 3,
+            chunker: Some("chunker".to_string()),
+            documents: vec![doc("a"), doc("b")],
+            embeddings: vec![
+                ("a".to_string(), vec![0.1, 0.2, 0.3]),
+                ("b".to_string(), vec![-1.0, 0.0, 1.5]),
+            ],
+        };
+        save(dir.path(), &index).unwrap();
+        assert!(exists(dir.path()));
+
+        let loaded = load(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.model_id.as_deref(), Some("model"));
+        assert_eq!(loaded.dimension, 3);
+        assert_eq!(loaded.chunker.as_deref(), Some("chunker"));
+        assert_eq!(loaded.documents.len(), 2);
+        assert_eq!(loaded.documents[1].path, "b.md");
+        assert_eq!(loaded.embeddings, index.embeddings);
+    }
+
+    #[test]
+    fn test_mismatched_vectors_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = StoredIndex {
+            model_id: None,
+            dimension: 2,
+            chunker: None,
+            documents: vec![doc("a")],
+            embeddings: vec![("a".to_string(), vec![1.0, 2.0])],
+        };
+        save(dir.path(), &index).unwrap();
+        fs::write(dir.path().join(EMBEDDINGS_FILE), [0u8; 3]).unwrap();
+
+        let loaded = load(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.documents.len(), 1);
+        assert!(loaded.embeddings.is_empty());
+    }
+
+    #[test]
+    fn test_other_format_version_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(MANIFEST_FILE),
+            r#"{"version":0,"model_id":null,"dimension":0,"documents":[],"embedding_ids":[]}"#,
+        )
+        .unwrap();
+        assert!(load(dir.path()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,499 @@
+//! Keyword search: an inverted index ranked with BM25.
+//!
+//! Text is split on Unicode word boundaries and lowercased. Scripts written
+//! without spaces between words (Hangul, Han, kana) are indexed as overlapping
+//! character bigrams, so "노트" matches inside "노트를" without a dictionary.
+//! Title, tags and body are indexed as separate fields and their scores are
+//! weighted, so a query word in a title counts for more than one in passing.
+//!
+//! Queries support `"quoted phrases"`, which must match, `-exclusions` (words
+//! or phrases) and `prefix*` terms; the remaining words are ranked, with no
+//! need for a note to contain all of them.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+const FIELD_COUNT: usize = 3;
+const TITLE: usize = 0;
+const TAGS: usize = 1;
+const BODY: usize = 2;
+/// Weight of a match in each field, by field index
+const FIELD_BOOSTS: [f32; FIELD_COUNT] = [3.0, 2.0, 1.0];
+
+/// Indexed terms a `prefix*` query term may expand to
+const MAX_PREFIX_EXPANSIONS: usize = 64;
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x11FF // Hangul Jamo
+        | 0x3040..=0x30FF // Hiragana, Katakana
+        | 0x3130..=0x318F // Hangul Compatibility Jamo
+        | 0x3400..=0x4DBF // CJK Extension A
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF // Hangul Syllables
+        | 0xF900..=0xFAFF // CJK Compatibility Ideographs
+    )
+}
+
+/// Splits `text` into lowercase terms, with CJK runs as bigrams.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut cjk: Vec<char> = Vec::new();
+
+    fn flush_word(word: &mut String, tokens: &mut Vec<String>) {
+        if !word.is_empty() {
+            tokens.push(std::mem::take(word));
+        }
+    }
+    fn flush_cjk(cjk: &mut Vec<char>, tokens: &mut Vec<String>) {
+        match cjk.len() {
+            0 => {}
+            1 => tokens.push(cjk[0].to_string()),
+            _ => tokens.extend(cjk.windows(2).map(|pair| pair.iter().collect())),
+        }
+        cjk.clear();
+    }
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            flush_word(&mut word, &mut tokens);
+            cjk.push(c);
+        } else if c.is_alphanumeric() {
+            flush_cjk(&mut cjk, &mut tokens);
+            word.extend(c.to_lowercase());
+        } else {
+            flush_word(&mut word, &mut tokens);
+            flush_cjk(&mut cjk, &mut tokens);
+        }
+    }
+    flush_word(&mut word, &mut tokens);
+    flush_cjk(&mut cjk, &mut tokens);
+    tokens
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Exact(String),
+    Prefix(String),
+}
+
+impl Term {
+    fn matches(&self, token: &str) -> bool {
+        match self {
+            Term::Exact(term) => token == term,
+            Term::Prefix(prefix) => token.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// A parsed search query.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Query {
+    /// Ranked terms; a note needs only one of them
+    terms: Vec<Term>,
+    /// Token sequences a note must contain
+    phrases: Vec<Vec<String>>,
+    /// Token sequences a note must not contain
+    excluded: Vec<Vec<String>>,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Self {
+        let mut query = Query::default();
+        let mut chars = input.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+            let negated = c == '-';
+            if negated {
+                chars.next();
+            }
+
+            let (text, quoted) = if chars.peek() == Some(&'"') {
+                chars.next();
+                let text: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                (text, true)
+            } else {
+                let mut text = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    text.push(c);
+                    chars.next();
+                }
+                (text, false)
+            };
+
+            let prefix = !quoted && text.len() > 1 && text.ends_with('*');
+            let tokens = tokenize(&text);
+            if tokens.is_empty() {
+                continue;
+            }
+            if negated {
+                query.excluded.push(tokens);
+            } else if quoted {
+                query.phrases.push(tokens);
+            } else {
+                let last = tokens.len() - 1;
+                query
+                    .terms
+                    .extend(tokens.into_iter().enumerate().map(|(i, token)| {
+                        if prefix && i == last {
+                            Term::Prefix(token)
+                        } else {
+                            Term::Exact(token)
+                        }
+                    }));
+            }
+        }
+        query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty() && self.phrases.is_empty()
+    }
+
+    /// How many of the query's terms and phrase words occur in `text`; used
+    /// to pick the passage of a note that best matches.
+    pub fn matched_terms(&self, text: &str) -> usize {
+        let tokens: HashSet<String> = tokenize(text).into_iter().collect();
+        let phrase_terms = self.phrases.iter().flatten().cloned().map(Term::Exact);
+        self.terms
+            .iter()
+            .cloned()
+            .chain(phrase_terms)
+            .filter(|term| tokens.iter().any(|token| term.matches(token)))
+            .count()
+    }
+}
+
+/// Positions of one term in one document, per field.
+#[derive(Default)]
+struct Postings {
+    positions: [Vec<u32>; FIELD_COUNT],
+}
+
+struct DocEntry {
+    id: String,
+    lengths: [u32; FIELD_COUNT],
+    /// Distinct terms, for removing the document from `postings`
+    terms: Vec<String>,
+}
+
+/// Inverted index over note title, tags and body.
+#[derive(Default)]
+pub struct KeywordIndex {
+    postings: BTreeMap<String, HashMap<u32, Postings>>,
+    docs: Vec<Option<DocEntry>>,
+    slots: HashMap<String, u32>,
+    free: Vec<u32>,
+    total_lengths: [u64; FIELD_COUNT],
+}
+
+impl KeywordIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes a document, replacing any earlier version with the same id.
+    pub fn insert(&mut self, id: &str, title: &str, tags: &[String], body: &str) {
+        self.remove(id);
+
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => {
+                self.docs.push(None);
+                (self.docs.len() - 1) as u32
+            }
+        };
+
+        let mut lengths = [0u32; FIELD_COUNT];
+        let mut terms = HashSet::new();
+        let fields = [tokenize(title), tokenize(&tags.join(" ")), tokenize(body)];
+        for (field, tokens) in fields.into_iter().enumerate() {
+            lengths[field] = tokens.len() as u32;
+            self.total_lengths[field] += tokens.len() as u64;
+            for (position, token) in tokens.into_iter().enumerate() {
+                let postings = self.postings.entry(token.clone()).or_default();
+                postings.entry(slot).or_default().positions[field].push(position as u32);
+                terms.insert(token);
+            }
+        }
+
+        self.docs[slot as usize] = Some(DocEntry {
+            id: id.to_string(),
+            lengths,
+            terms: terms.into_iter().collect(),
+        });
+        self.slots.insert(id.to_string(), slot);
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        let Some(slot) = self.slots.remove(id) else {
+            return;
+        };
+        let Some(entry) = self.docs[slot as usize].take() else {
+            return;
+        };
+        for term in &entry.terms {
+            if let Some(postings) = self.postings.get_mut(term) {
+                postings.remove(&slot);
+                if postings.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        for (field, length) in entry.lengths.iter().enumerate() {
+            self.total_lengths[field] -= *length as u64;
+        }
+        self.free.push(slot);
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Ranks documents for `query`, returning ids and BM25 scores, best first.
+    pub fn search(&self, query: &Query, limit: usize) -> Vec<(String, f32)> {
+        if query.is_empty() || self.slots.is_empty() {
+            return Vec::new();
+        }
+
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+        for term in &query.terms {
+            match term {
+                Term::Exact(term) => self.add_term_scores(term, &mut scores),
+                Term::Prefix(prefix) => {
+                    let expansions = self
+                        .postings
+                        .range(prefix.clone()..)
+                        .map(|(term, _)| term)
+                        .take_while(|term| term.starts_with(prefix.as_str()))
+                        .take(MAX_PREFIX_EXPANSIONS);
+                    for term in expansions {
+                        self.add_term_scores(term, &mut scores);
+                    }
+                }
+            }
+        }
+        for term in query.phrases.iter().flatten() {
+            self.add_term_scores(term, &mut scores);
+        }
+
+        let mut ranked: Vec<(u32, f32)> = scores
+            .into_iter()
+            .filter(|(slot, _)| {
+                query.phrases.iter().all(|p| self.contains(*slot, p))
+                    && !query.excluded.iter().any(|p| self.contains(*slot, p))
+            })
+            .collect();
+        ranked.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+
+        ranked
+            .into_iter()
+            .take(limit)
+            .filter_map(|(slot, score)| {
+                let entry = self.docs[slot as usize].as_ref()?;
+                Some((entry.id.clone(), score))
+            })
+            .collect()
+    }
+
+    fn add_term_scores(&self, term: &str, scores: &mut HashMap<u32, f32>) {
+        let Some(postings) = self.postings.get(term) else {
+            return;
+        };
+        let n = self.slots.len() as f32;
+        let df = postings.len() as f32;
+        let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+
+        for (slot, doc_postings) in postings {
+            let Some(entry) = self.docs[*slot as usize].as_ref() else {
+                continue;
+            };
+            let mut score = 0.0;
+            for field in [TITLE, TAGS, BODY] {
+                let tf = doc_postings.positions[field].len() as f32;
+                if tf == 0.0 {
+                    continue;
+                }
+                let average = (self.total_lengths[field] as f32 / n).max(1.0);
+                let norm = 1.0 - B + B * entry.lengths[field] as f32 / average;
+                score += FIELD_BOOSTS[field] * idf * tf * (K1 + 1.0) / (tf + K1 * norm);
+            }
+            *scores.entry(*slot).or_default() += score;
+        }
+    }
+
+    /// Whether the document contains `phrase` as consecutive tokens in a
+    /// single field.
+    fn contains(&self, slot: u32, phrase: &[String]) -> bool {
+        let mut lists = Vec::with_capacity(phrase.len());
+        for token in phrase {
+            match self.postings.get(token).and_then(|p| p.get(&slot)) {
+                Some(postings) => lists.push(postings),
+                None => return false,
+            }
+        }
+        (0..FIELD_COUNT).any(|field| {
+            lists[0].positions[field].iter().any(|&start| {
+                lists.iter().enumerate().skip(1).all(|(offset, postings)| {
+                    postings.positions[field]
+                        .binary_search(&(start + offset as u32))
+                        .is_ok()
+                })
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(docs: &[(&str, &str, &[&str], &str)]) -> KeywordIndex {
+        let mut index = KeywordIndex::new();
+        for (id, title, tags, body) in docs {
+            let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+            index.insert(id, title, &tags, body);
+        }
+        index
+    }
+
+    fn ids(results: Vec<(String, f32)>) -> Vec<String> {
+        results.into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn test_tokenize_mixed_scripts() {
+        assert_eq!(
+            tokenize("Rust's borrow-checker, 2024!"),
+            vec!["rust", "s", "borrow", "checker", "2024"]
+        );
+        assert_eq!(tokenize("노트를 정리"), vec!["노트", "트를", "정리"]);
+        assert_eq!(tokenize("API키"), vec!["api", "키"]);
+        assert_eq!(tokenize("Ünïcode ÉTÉ"), vec!["ünïcode", "été"]);
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = Query::parse(r#"rust "borrow checker" -unsafe -"old api" own*"#);
+        assert_eq!(
+            query.terms,
+            vec![
+                Term::Exact("rust".to_string()),
+                Term::Prefix("own".to_string())
+            ]
+        );
+        assert_eq!(query.phrases, vec![vec!["borrow", "checker"]]);
+        assert_eq!(query.excluded, vec![vec!["unsafe"], vec!["old", "api"]]);
+        assert!(Query::parse("  -only ").is_empty());
+    }
+
+    #[test]
+    fn test_bm25_prefers_rarer_and_denser_matches() {
+        let index = index(&[
+            ("a", "Cooking", &[], "rust rust rust on the pan"),
+            (
+                "b",
+                "Notes",
+                &[],
+                "rust is a language; the language is fast",
+            ),
+            ("c", "Other", &[], "the the the"),
+        ]);
+        let results = index.search(&Query::parse("rust language"), 10);
+        assert_eq!(ids(results.clone()), vec!["b", "a"]);
+        assert!(results[0].1 > results[1].1);
+
+        // "the" occurs in every document and adds little
+        let results = index.search(&Query::parse("the pan"), 10);
+        assert_eq!(results[0].0, "a");
+    }
+
+    #[test]
+    fn test_title_and_tag_boosts() {
+        let index = index(&[
+            (
+                "body",
+                "Misc",
+                &[],
+                "a note about gardening and other things",
+            ),
+            (
+                "title",
+                "Gardening",
+                &[],
+                "a note about plants and other things",
+            ),
+            (
+                "tag",
+                "Misc",
+                &["gardening"],
+                "a note about plants and other things",
+            ),
+        ]);
+        let results = ids(index.search(&Query::parse("gardening"), 10));
+        assert_eq!(results, vec!["title", "tag", "body"]);
+    }
+
+    #[test]
+    fn test_phrases_exclusions_and_prefixes() {
+        let index = index(&[
+            ("a", "A", &[], "the borrow checker rejects this"),
+            ("b", "B", &[], "checker of borrow requests"),
+            ("c", "C", &[], "borrow checker in unsafe code"),
+        ]);
+        let results = ids(index.search(&Query::parse(r#""borrow checker""#), 10));
+        assert_eq!(results.len(), 2);
+        assert!(!results.contains(&"b".to_string()));
+
+        let results = ids(index.search(&Query::parse(r#""borrow checker" -unsafe"#), 10));
+        assert_eq!(results, vec!["a"]);
+
+        let results = ids(index.search(&Query::parse("requ*"), 10));
+        assert_eq!(results, vec!["b"]);
+    }
+
+    #[test]
+    fn test_cjk_partial_words_match() {
+        let index = index(&[
+            ("ko", "회의", &[], "노트를 정리했다"),
+            ("en", "Meeting", &[], "cleaned up notes"),
+        ]);
+        assert_eq!(ids(index.search(&Query::parse("노트"), 10)), vec!["ko"]);
+        assert_eq!(
+            ids(index.search(&Query::parse("\"노트를\""), 10)),
+            vec!["ko"]
+        );
+    }
+
+    #[test]
+    fn test_reinsert_and_remove() {
+        let mut index = index(&[("a", "A", &[], "alpha"), ("b", "B", &[], "beta")]);
+        index.insert("a", "A", &[], "gamma");
+        assert!(index.search(&Query::parse("alpha"), 10).is_empty());
+        assert_eq!(ids(index.search(&Query::parse("gamma"), 10)), vec!["a"]);
+
+        index.remove("a");
+        assert_eq!(index.slots.len(), 1);
+        assert!(index.search(&Query::parse("gamma"), 10).is_empty());
+        assert!(!index.postings.contains_key("gamma"));
+
+        // Freed slots are reused
+        index.insert("c", "C", &[], "delta");
+        assert_eq!(index.docs.len(), 2);
+        assert_eq!(ids(index.search(&Query::parse("delta"), 10)), vec!["c"]);
+    }
+}
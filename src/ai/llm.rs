@@ -3,21 +3,179 @@ use candle_core::quantized::gguf_file;
 use candle_core::{Device, Tensor};
 use candle_transformers::generation::LogitsProcessor;
 use candle_transformers::models::quantized_llama as llama;
+use candle_transformers::models::quantized_phi3 as phi3;
+use candle_transformers::models::quantized_qwen2 as qwen2;
 use hf_hub::{api::sync::Api, Repo, RepoType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokenizers::Tokenizer;
 use tokio::sync::Mutex;
 
+use super::chat_template::ChatTemplate;
+use super::providers::{ChatMessage, TokenSink};
+use super::tokens::{TokenCounter, DEFAULT_LOCAL_CONTEXT, MAX_LOCAL_CONTEXT};
+
 const DEFAULT_MODEL_REPO: &str = "TheBloke/TinyLlama-1.1B-Chat-v1.0-GGUF";
 const DEFAULT_MODEL_FILE: &str = "tinyllama-1.1b-chat-v1.0.Q4_K_M.gguf";
 const DEFAULT_TOKENIZER_REPO: &str = "TinyLlama/TinyLlama-1.1B-Chat-v1.0";
 
+/// How the next token is picked from the model's output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplingParams {
+    /// 0 always picks the most likely token
+    pub temperature: f64,
+    /// Nucleus sampling cutoff; `None` samples from the whole distribution
+    pub top_p: Option<f64>,
+    /// Divides the logits of recently seen tokens; 1.0 disables it
+    pub repeat_penalty: f32,
+    /// Number of recent tokens the repeat penalty looks at
+    pub repeat_last_n: usize,
+    /// Fixed seed for reproducible output; random when unset
+    pub seed: Option<u64>,
+}
+
+impl Default for SamplingParams {
+    fn default() -> Self {
+        Self {
+            temperature: 0.8,
+            top_p: Some(0.95),
+            repeat_penalty: 1.1,
+            repeat_last_n: 64,
+            seed: None,
+        }
+    }
+}
+
+impl SamplingParams {
+    fn logits_processor(&self) -> LogitsProcessor {
+        let seed = self.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(42, |d| d.as_nanos() as u64)
+        });
+        let temperature = (self.temperature > 0.0).then_some(self.temperature);
+        LogitsProcessor::new(seed, temperature, self.top_p)
+    }
+
+    fn sample(
+        &self,
+        processor: &mut LogitsProcessor,
+        logits: &Tensor,
+        tokens: &[u32],
+    ) -> Result<u32> {
+        let logits = logits.squeeze(0)?.squeeze(0)?;
+        let logits = if self.repeat_penalty == 1.0 || self.repeat_last_n == 0 {
+            logits
+        } else {
+            let start = tokens.len().saturating_sub(self.repeat_last_n);
+            candle_transformers::utils::apply_repeat_penalty(
+                &logits,
+                self.repeat_penalty,
+                &tokens[start..],
+            )?
+        };
+        processor
+            .sample(&logits)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
+
+/// Quantized weights for the GGUF architectures the engine can run. Llama
+/// covers Llama 2/3, Mistral and TinyLlama.
+enum ModelWeights {
+    Llama(llama::ModelWeights),
+    Qwen2(qwen2::ModelWeights),
+    /// Phi-3's KV cache can't be cleared, so each generation starts from a
+    /// copy of the freshly loaded weights (the tensors themselves are shared)
+    Phi3 {
+        fresh: Box<phi3::ModelWeights>,
+        live: Box<phi3::ModelWeights>,
+    },
+}
+
+impl ModelWeights {
+    fn load(
+        architecture: Option<&str>,
+        content: gguf_file::Content,
+        file: &mut std::fs::File,
+        device: &Device,
+    ) -> Result<Self> {
+        let weights = match architecture {
+            Some("qwen2") => Self::Qwen2(qwen2::ModelWeights::from_gguf(content, file, device)?),
+            Some("phi3") => {
+                let fresh = Box::new(phi3::ModelWeights::from_gguf(false, content, file, device)?);
+                Self::Phi3 {
+                    live: fresh.clone(),
+                    fresh,
+                }
+            }
+            _ => Self::Llama(llama::ModelWeights::from_gguf(content, file, device)?),
+        };
+        Ok(weights)
+    }
+
+    /// Drops the cached keys and values of the previous generation.
+    fn reset(&mut self) {
+        if let Self::Phi3 { fresh, live } = self {
+            *live = fresh.clone();
+        }
+    }
+
+    fn forward(&mut self, input: &Tensor, index_pos: usize) -> candle_core::Result<Tensor> {
+        match self {
+            Self::Llama(model) => model.forward(input, index_pos),
+            Self::Qwen2(model) => model.forward(input, index_pos),
+            Self::Phi3 { live, .. } => live.forward(input, index_pos),
+        }
+    }
+}
+
+fn metadata_str<'a>(content: &'a gguf_file::Content, key: &str) -> Option<&'a str> {
+    content
+        .metadata
+        .get(key)
+        .and_then(|v| v.to_string().ok())
+        .map(String::as_str)
+}
+
+/// Repos to look for `tokenizer.json` in, most likely first. GGUF repos often
+/// ship without one, so the original model's repo is tried next: the base
+/// model the GGUF names, or the repo's name without its `-GGUF` suffix.
+fn tokenizer_repos(repo_id: &str, base_model_url: Option<&str>) -> Vec<String> {
+    let mut repos = vec![repo_id.to_string()];
+    if let Some(url) = base_model_url {
+        let repo = url
+            .trim_start_matches("https://huggingface.co/")
+            .trim_end_matches('/');
+        if repo.split('/').count() == 2 {
+            repos.push(repo.to_string());
+        }
+    }
+    for suffix in ["-GGUF", "-gguf"] {
+        if let Some(base) = repo_id.strip_suffix(suffix) {
+            repos.push(base.to_string());
+        }
+    }
+    if repo_id.contains("TinyLlama") {
+        repos.push(DEFAULT_TOKENIZER_REPO.to_string());
+    }
+    let mut seen = HashSet::new();
+    repos.retain(|r| seen.insert(r.clone()));
+    repos
+}
+
 pub struct LlmEngine {
-    model: Option<llama::ModelWeights>,
-    tokenizer: Option<Tokenizer>,
+    model: Option<ModelWeights>,
+    tokenizer: Option<Arc<Tokenizer>>,
     device: Device,
     model_path: Option<PathBuf>,
+    template: ChatTemplate,
+    stop_tokens: HashSet<u32>,
+    context_length: usize,
 }
 
 impl LlmEngine {
@@ -28,6 +186,9 @@ impl LlmEngine {
             tokenizer: None,
             device,
             model_path: None,
+            template: ChatTemplate::Zephyr,
+            stop_tokens: HashSet::new(),
+            context_length: DEFAULT_LOCAL_CONTEXT,
         })
     }
 
@@ -61,33 +222,111 @@ impl LlmEngine {
         let content = gguf_file::Content::read(&mut file)
             .map_err(|e| anyhow::anyhow!("Failed to read GGUF: {}", e))?;
 
-        let model = llama::ModelWeights::from_gguf(content, &mut file, &self.device)
-            .map_err(|e| anyhow::anyhow!("Failed to load model weights: {}", e))?;
+        let architecture = metadata_str(&content, "general.architecture").map(str::to_string);
+        let name = format!(
+            "{} {} {}",
+            metadata_str(&content, "general.name").unwrap_or_default(),
+            repo_id,
+            filename
+        );
+        let template = ChatTemplate::detect(
+            metadata_str(&content, "tokenizer.chat_template"),
+            architecture.as_deref(),
+            &name,
+        );
+        let context_length = architecture
+            .as_ref()
+            .and_then(|arch| content.metadata.get(&format!("{}.context_length", arch)))
+            .and_then(|v| v.to_u32().ok())
+            .map_or(DEFAULT_LOCAL_CONTEXT, |n| {
+                (n as usize).min(MAX_LOCAL_CONTEXT)
+            });
+        let eos_token = content
+            .metadata
+            .get("tokenizer.ggml.eos_token_id")
+            .and_then(|v| v.to_u32().ok());
+        let tokenizer_repos = tokenizer_repos(
+            &repo_id,
+            metadata_str(&content, "general.base_model.0.repo_url"),
+        );
+        tracing::info!(
+            "Model architecture {:?}, chat template {:?}",
+            architecture,
+            template
+        );
 
-        let tokenizer_repo = if repo_id.contains("TinyLlama") {
-            DEFAULT_TOKENIZER_REPO
-        } else {
-            &repo_id
-        };
+        let model = ModelWeights::load(architecture.as_deref(), content, &mut file, &self.device)
+            .map_err(|e| anyhow::anyhow!("Failed to load model weights: {}", e))?;
 
-        tracing::info!("Loading tokenizer from: {}", tokenizer_repo);
-        let tokenizer_repo = api.repo(Repo::new(tokenizer_repo.to_string(), RepoType::Model));
-        let tokenizer_path = tokenizer_repo
-            .get("tokenizer.json")
-            .context("Failed to download tokenizer")?;
+        let tokenizer_path = tokenizer_repos
+            .iter()
+            .find_map(|tokenizer_repo| {
+                tracing::info!("Loading tokenizer from: {}", tokenizer_repo);
+                api.repo(Repo::new(tokenizer_repo.clone(), RepoType::Model))
+                    .get("tokenizer.json")
+                    .ok()
+            })
+            .with_context(|| {
+                format!(
+                    "Failed to download tokenizer (tried {})",
+                    tokenizer_repos.join(", ")
+                )
+            })?;
 
         let tokenizer =
             Tokenizer::from_file(&tokenizer_path).map_err(|e| anyhow::anyhow!("{}", e))?;
 
+        let mut stop_tokens: HashSet<u32> = template
+            .stop_tokens()
+            .iter()
+            .filter_map(|token| tokenizer.token_to_id(token))
+            .chain(eos_token)
+            .collect();
+        if stop_tokens.is_empty() {
+            stop_tokens.insert(tokenizer.token_to_id("<|endoftext|>").unwrap_or(2));
+        }
+
         self.model = Some(model);
-        self.tokenizer = Some(tokenizer);
+        self.tokenizer = Some(Arc::new(tokenizer));
         self.model_path = Some(model_path);
+        self.template = template;
+        self.stop_tokens = stop_tokens;
+        self.context_length = context_length;
 
         tracing::info!("Model loaded successfully");
         Ok(())
     }
 
     pub fn generate_sync(&mut self, prompt: &str, max_tokens: u32) -> Result<String> {
+        self.generate_with(prompt, max_tokens, |_| true)
+    }
+
+    /// Generates a reply to a single user message, handing each newly
+    /// decoded piece of text to `on_text`. Generation stops early when
+    /// `on_text` returns `false`.
+    pub fn generate_with(
+        &mut self,
+        prompt: &str,
+        max_tokens: u32,
+        on_text: impl FnMut(&str) -> bool,
+    ) -> Result<String> {
+        let messages = [ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        self.chat_with(&messages, max_tokens, &SamplingParams::default(), on_text)
+    }
+
+    /// Generates the assistant's next turn in `messages`, laid out in the
+    /// loaded model's chat template, streaming text to `on_text` as in
+    /// [`generate_with`](Self::generate_with).
+    pub fn chat_with(
+        &mut self,
+        messages: &[ChatMessage],
+        max_tokens: u32,
+        params: &SamplingParams,
+        mut on_text: impl FnMut(&str) -> bool,
+    ) -> Result<String> {
         let model = self
             .model
             .as_mut()
@@ -98,10 +337,7 @@ impl LlmEngine {
             .as_ref()
             .ok_or_else(|| anyhow::anyhow!("Tokenizer not loaded"))?;
 
-        let formatted_prompt = format!(
-            "<|system|>\nYou are a helpful assistant.</s>\n<|user|>\n{}</s>\n<|assistant|>\n",
-            prompt
-        );
+        let formatted_prompt = self.template.render(messages);
 
         let tokens = tokenizer
             .encode(formatted_prompt.as_str(), true)
@@ -110,54 +346,104 @@ impl LlmEngine {
         let prompt_tokens = tokens.get_ids().to_vec();
         let mut all_tokens = prompt_tokens.clone();
 
-        let mut logits_processor = LogitsProcessor::new(42, Some(0.8), Some(0.95));
+        let mut logits_processor = params.logits_processor();
+        model.reset();
 
         let mut next_token = {
             let input = Tensor::new(&prompt_tokens[..], &self.device)?.unsqueeze(0)?;
             let logits = model.forward(&input, 0)?;
-            let logits = logits.squeeze(0)?.squeeze(0)?;
-            logits_processor
-                .sample(&logits)
-                .map_err(|e| anyhow::anyhow!("{}", e))?
+            params.sample(&mut logits_processor, &logits, &all_tokens)?
         };
 
         all_tokens.push(next_token);
 
-        let eos_token = tokenizer
-            .token_to_id("</s>")
-            .unwrap_or(tokenizer.token_to_id("<|endoftext|>").unwrap_or(2));
+        let mut decoder = IncrementalDecoder::default();
+        let mut stopped = false;
 
         for i in 0..max_tokens {
-            if next_token == eos_token {
+            if self.stop_tokens.contains(&next_token) {
+                all_tokens.pop();
                 break;
             }
 
+            if let Some(text) = decoder.next(tokenizer, &all_tokens[prompt_tokens.len()..])? {
+                if !on_text(&text) {
+                    stopped = true;
+                    break;
+                }
+            }
+
             let input = Tensor::new(&[next_token], &self.device)?.unsqueeze(0)?;
             let logits = model.forward(&input, prompt_tokens.len() + i as usize)?;
-            let logits = logits.squeeze(0)?.squeeze(0)?;
 
-            next_token = logits_processor
-                .sample(&logits)
-                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            next_token = params.sample(&mut logits_processor, &logits, &all_tokens)?;
             all_tokens.push(next_token);
         }
-
         let generated_tokens = &all_tokens[prompt_tokens.len()..];
         let response = tokenizer
             .decode(generated_tokens, true)
             .map_err(|e| anyhow::anyhow!("Decode failed: {}", e))?;
 
-        Ok(response.trim().to_string())
+        let response = response.trim();
+        if !stopped {
+            if let Some(text) = decoder.delta(response) {
+                on_text(&text);
+            }
+        }
+        Ok(response.to_string())
     }
 
     pub fn is_loaded(&self) -> bool {
         self.model.is_some() && self.tokenizer.is_some()
     }
+
+    /// Counter for the loaded model's tokens and its context length.
+    pub fn token_budget(&self) -> (TokenCounter, usize) {
+        match self.tokenizer {
+            Some(ref tokenizer) => (
+                TokenCounter::Tokenizer(tokenizer.clone()),
+                self.context_length,
+            ),
+            None => (TokenCounter::Estimate, DEFAULT_LOCAL_CONTEXT),
+        }
+    }
+}
+
+/// Turns a growing token sequence into text deltas.
+///
+/// Tokens do not map to whole characters, so the sequence is re-decoded each
+/// step and only the new suffix is emitted, holding back output that ends in
+/// an incomplete UTF-8 sequence.
+#[derive(Default)]
+struct IncrementalDecoder {
+    emitted: usize,
+}
+
+impl IncrementalDecoder {
+    fn next(&mut self, tokenizer: &Tokenizer, tokens: &[u32]) -> Result<Option<String>> {
+        let text = tokenizer
+            .decode(tokens, true)
+            .map_err(|e| anyhow::anyhow!("Decode failed: {}", e))?;
+        Ok(self.delta(&text))
+    }
+
+    fn delta(&mut self, text: &str) -> Option<String> {
+        // Match the trimmed final response
+        let text = text.trim_start();
+        if text.ends_with('\u{FFFD}') || text.len() <= self.emitted {
+            return None;
+        }
+        let delta = text.get(self.emitted..)?.to_string();
+        self.emitted = text.len();
+        Some(delta)
+    }
 }
 
 pub struct AsyncLlmEngine {
     inner: Arc<Mutex<LlmEngine>>,
     loaded: Arc<Mutex<bool>>,
+    /// Kept outside `inner` so prompts can be measured during a generation
+    budget: Arc<Mutex<Option<(TokenCounter, usize)>>>,
 }
 
 impl AsyncLlmEngine {
@@ -165,12 +451,14 @@ impl AsyncLlmEngine {
         Ok(Self {
             inner: Arc::new(Mutex::new(LlmEngine::new()?)),
             loaded: Arc::new(Mutex::new(false)),
+            budget: Arc::new(Mutex::new(None)),
         })
     }
 
     pub async fn load_model(&self, model_name: &str) -> Result<()> {
         let mut engine = self.inner.lock().await;
         engine.load_model(model_name)?;
+        *self.budget.lock().await = Some(engine.token_budget());
         *self.loaded.lock().await = true;
         Ok(())
     }
@@ -183,19 +471,45 @@ impl AsyncLlmEngine {
         Ok(())
     }
 
-    pub async fn generate(&self, prompt: &str, max_tokens: u32) -> Result<String> {
+    /// Generates the assistant's next turn in `messages` on a blocking
+    /// thread, streaming it to `tokens` when given. Load and generation
+    /// failures are reported as text, and sent to `tokens` as well.
+    pub async fn chat(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        params: SamplingParams,
+        tokens: Option<TokenSink>,
+    ) -> Result<String> {
         if self.ensure_loaded().await.is_err() {
-            return Ok(
-                "[AI not available] Failed to load model. Check logs for details.".to_string(),
-            );
+            let message =
+                "[AI not available] Failed to load model. Check logs for details.".to_string();
+            if let Some(tokens) = tokens {
+                let _ = tokens.send(message.clone());
+            }
+            return Ok(message);
         }
 
-        let mut engine = self.inner.lock().await;
-        match engine.generate_sync(prompt, max_tokens) {
+        let inner = self.inner.clone();
+        let sink = tokens.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut engine = inner.blocking_lock();
+            engine.chat_with(&messages, max_tokens, &params, |text| {
+                sink.as_ref()
+                    .is_none_or(|sink| sink.send(text.to_string()).is_ok())
+            })
+        })
+        .await?;
+
+        match result {
             Ok(response) => Ok(response),
             Err(e) => {
                 tracing::error!("Generation failed: {}", e);
-                Ok(format!("[AI error] {}", e))
+                let message = format!("[AI error] {}", e);
+                if let Some(tokens) = tokens {
+                    let _ = tokens.send(message.clone());
+                }
+                Ok(message)
             }
         }
     }
@@ -203,4 +517,53 @@ impl AsyncLlmEngine {
     pub async fn is_loaded(&self) -> bool {
         *self.loaded.lock().await
     }
+
+    /// Token counter and context length of the loaded model, or an estimate
+    /// and the default model's context length before one is loaded.
+    pub async fn token_budget(&self) -> (TokenCounter, usize) {
+        self.budget
+            .lock()
+            .await
+            .clone()
+            .unwrap_or((TokenCounter::Estimate, DEFAULT_LOCAL_CONTEXT))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incremental_decoder_emits_suffixes() {
+        let mut decoder = IncrementalDecoder::default();
+        assert_eq!(decoder.delta(" Hel").as_deref(), Some("Hel"));
+        assert_eq!(decoder.delta(" Hello").as_deref(), Some("lo"));
+        assert_eq!(decoder.delta(" Hello").as_deref(), None);
+        // Half a multi-byte character decodes to a replacement char
+        assert_eq!(decoder.delta(" Hello \u{FFFD}").as_deref(), None);
+        assert_eq!(decoder.delta(" Hello 世").as_deref(), Some(" 世"));
+    }
+
+    #[test]
+    fn test_tokenizer_repos_fall_back_to_base_model() {
+        assert_eq!(
+            tokenizer_repos(
+                "bartowski/Llama-3.2-1B-Instruct-GGUF",
+                Some("https://huggingface.co/meta-llama/Llama-3.2-1B-Instruct")
+            ),
+            vec![
+                "bartowski/Llama-3.2-1B-Instruct-GGUF",
+                "meta-llama/Llama-3.2-1B-Instruct",
+                "bartowski/Llama-3.2-1B-Instruct",
+            ]
+        );
+        assert_eq!(
+            tokenizer_repos(DEFAULT_MODEL_REPO, None),
+            vec![
+                DEFAULT_MODEL_REPO,
+                "TheBloke/TinyLlama-1.1B-Chat-v1.0",
+                DEFAULT_TOKENIZER_REPO,
+            ]
+        );
+    }
 }
@@ -1,24 +1,49 @@
+pub mod cards;
+mod chat_template;
+mod chunking;
+pub mod config;
+pub mod conversations;
+mod embedding_cache;
 mod embeddings;
+pub mod extract;
+mod index_store;
+mod keyword;
 mod llm;
 pub mod ollama;
 pub mod providers;
 mod rag;
+mod rerank;
 mod search;
-
-pub use providers::{create_provider, LlmConfig, LlmProvider, LlmProviderTrait};
+pub mod tagging;
+mod tokens;
+
+pub use chunking::Chunk;
+pub use config::AiConfig;
+pub use embeddings::EmbeddingModelInfo;
+pub use providers::{
+    create_provider, ChatMessage, FallbackChain, LlmConfig, LlmProvider, LlmProviderTrait,
+    RetryPolicy, TokenSink,
+};
 pub use rag::RagPipeline;
+pub use rerank::RerankerInfo;
+pub use search::IndexStats;
 pub use search::NoteDocument;
-pub use search::SearchResult;
+pub use search::{SearchOptions, SearchResult};
 
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use self::search::AsyncSearchIndex;
+use self::tokens::TokenCounter;
+use crate::jobs::{Progress, SharedJobRegistry};
 use crate::rpc::{
-    AiChatRequest, AiChatResponse, AiIndexRequest, AiIndexResponse, AiRagRequest, AiRagResponse,
-    AiRagSource, AiSearchRequest, AiSearchResponse, AiSummarizeRequest, AiSummarizeResponse,
+    AiChatRequest, AiChatResponse, AiExtractRequest, AiExtractResponse, AiIndexRequest,
+    AiIndexResponse, AiRagRequest, AiRagResponse, AiRagSource, AiRemoveRequest, AiRemoveResponse,
+    AiSearchRequest, AiSearchResponse, AiSummarizeRequest, AiSummarizeResponse, LlmSelection,
 };
+use crate::utils::text::truncate;
 
 static RAG_PIPELINE: std::sync::OnceLock<Arc<RwLock<Option<RagPipeline>>>> =
     std::sync::OnceLock::new();
@@ -27,82 +52,415 @@ fn get_pipeline_lock() -> &'static Arc<RwLock<Option<RagPipeline>>> {
     RAG_PIPELINE.get_or_init(|| Arc::new(RwLock::new(None)))
 }
 
-pub async fn init_pipeline(index_path: Option<PathBuf>, model_path: Option<String>) -> Result<()> {
-    let pipeline = if let Some(path) = index_path {
-        RagPipeline::new(&path)?
+fn data_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("naidis")
+}
+
+/// Where the search index is persisted between runs.
+fn default_index_path() -> PathBuf {
+    data_dir().join("ai_index")
+}
+
+fn embedding_model_of(config: &AiConfig) -> String {
+    config
+        .embedding_model
+        .clone()
+        .unwrap_or_else(|| embeddings::DEFAULT_EMBEDDING_MODEL_ID.to_string())
+}
+
+/// Embedding model from the saved config, falling back to the default.
+fn configured_embedding_model(data_dir: &Path) -> String {
+    embedding_model_of(&config::load(data_dir).unwrap_or_default())
+}
+
+async fn build_pipeline(
+    index_path: Option<PathBuf>,
+    model_path: Option<String>,
+) -> Result<RagPipeline> {
+    let embedding_model = configured_embedding_model(&data_dir());
+    let mut pipeline = if let Some(path) = index_path {
+        RagPipeline::new(&path, &embedding_model)?
     } else {
-        RagPipeline::new_in_memory()?
+        RagPipeline::new_in_memory(&embedding_model)?
     };
 
+    // Search still works without reranking, so a reranker that fails to
+    // load is not fatal
+    let saved = config::load(&data_dir()).unwrap_or_default();
+    pipeline.set_sampling(saved.sampling);
+    if let Err(e) = pipeline.set_reranker(saved.reranker.as_deref()) {
+        tracing::warn!("Failed to load reranker: {}", e);
+    }
+
     if let Some(model) = model_path {
-        let mut p = pipeline;
-        p.load_model(&model).await?;
-        *get_pipeline_lock().write().await = Some(p);
-    } else {
-        *get_pipeline_lock().write().await = Some(pipeline);
+        pipeline.load_model(&model).await?;
     }
+    Ok(pipeline)
+}
 
+pub async fn init_pipeline(index_path: Option<PathBuf>, model_path: Option<String>) -> Result<()> {
+    let pipeline = build_pipeline(index_path, model_path).await?;
+    let index = pipeline.search_index();
+    *get_pipeline_lock().write().await = Some(pipeline);
+    spawn_embedding_rebuild(index).await;
     Ok(())
 }
 
 pub async fn ensure_pipeline() -> Result<()> {
-    let guard = get_pipeline_lock().read().await;
-    if guard.is_none() {
-        drop(guard);
-        init_pipeline(None, None).await?;
+    if get_pipeline_lock().read().await.is_some() {
+        return Ok(());
+    }
+    // Re-check under the write lock so concurrent callers build it once
+    let mut guard = get_pipeline_lock().write().await;
+    if guard.is_some() {
+        return Ok(());
     }
+    let pipeline = build_pipeline(Some(default_index_path()), None).await?;
+    let index = pipeline.search_index();
+    *guard = Some(pipeline);
+    drop(guard);
+    spawn_embedding_rebuild(index).await;
     Ok(())
 }
 
-pub async fn chat(request: &AiChatRequest) -> Result<AiChatResponse> {
-    if let Some(ref provider_name) = request.provider {
-        let provider_type = match provider_name.to_lowercase().as_str() {
-            "openai" => LlmProvider::OpenAI,
-            "anthropic" => LlmProvider::Anthropic,
-            "zai" | "z.ai" => LlmProvider::Zai,
-            "groq" => LlmProvider::Groq,
-            _ => LlmProvider::Local,
-        };
+/// Job registry that index rebuilds are reported to, set at startup.
+static JOBS: std::sync::OnceLock<SharedJobRegistry> = std::sync::OnceLock::new();
 
-        if !matches!(provider_type, LlmProvider::Local) {
-            let config = LlmConfig {
-                provider: provider_type,
-                api_key: request.api_key.clone(),
-                model: request.model.clone(),
-                base_url: None,
-            };
+/// Embeds the chunks of a freshly loaded index that have no vector, e.g.
+/// after a model change or an interrupted indexing run. This runs as a
+/// background job so AI requests are served meanwhile; a failure is logged
+/// and the chunks are retried the next time the index is loaded.
+async fn spawn_embedding_rebuild(index: AsyncSearchIndex) {
+    if !index.has_missing_embeddings().await {
+        return;
+    }
+    let rebuild = |progress: Progress| async move {
+        let rebuilt = index
+            .rebuild_missing_embeddings(&progress)
+            .await
+            .inspect_err(|e| tracing::warn!("Failed to re-embed notes: {}", e))?;
+        tracing::info!("Re-embedded {} chunks in the AI index", rebuilt);
+        Ok(serde_json::json!({"embedded": rebuilt}))
+    };
+    match JOBS.get() {
+        Some(jobs) => {
+            jobs.spawn("ai.embeddings.rebuild", rebuild);
+        }
+        None => {
+            tokio::spawn(async move {
+                let _ = rebuild(Progress::none()).await;
+            });
+        }
+    }
+}
 
-            let llm = create_provider(&config)?;
-            let response = llm.generate(&request.message, 1024).await?;
+/// Loads a previously persisted index in the background at startup, so the
+/// first search after a restart finds the vault already indexed. Without a
+/// stored index the pipeline stays lazy. Index rebuilds run as jobs on
+/// `jobs`.
+pub fn preload_index(jobs: &SharedJobRegistry) {
+    let _ = JOBS.set(jobs.clone());
+    if !index_store::exists(&default_index_path()) {
+        return;
+    }
+    tokio::spawn(async {
+        if let Err(e) = ensure_pipeline().await {
+            tracing::warn!("Failed to load AI index: {}", e);
+        }
+    });
+}
 
-            return Ok(AiChatResponse {
-                response,
-                sources: None,
-            });
+pub fn get_config(data_dir: &Path) -> Result<AiConfig> {
+    config::load(data_dir)
+}
+
+/// Settings changed by [`update_config`] that the loaded pipeline has yet to
+/// pick up, see [`apply_config_changes`].
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ConfigChanges {
+    /// New embedding model; the index has to be re-embedded
+    pub embedding_model: Option<String>,
+    pub reranker: bool,
+    pub sampling: bool,
+}
+
+impl ConfigChanges {
+    pub fn is_empty(&self) -> bool {
+        self.embedding_model.is_none() && !self.reranker && !self.sampling
+    }
+}
+
+/// Saves `config`, normalising model names to their full ids. Secrets sent
+/// back as [`config::REDACTED`] keep their saved values.
+pub fn update_config(data_dir: &Path, mut config: AiConfig) -> Result<ConfigChanges> {
+    if let Some(ref name) = config.embedding_model {
+        let Some(spec) = embeddings::resolve_model(name) else {
+            bail!("Unknown embedding model: {}", name);
+        };
+        config.embedding_model = Some(spec.id.to_string());
+    }
+    if let Some(ref name) = config.reranker {
+        let Some(id) = rerank::resolve_reranker(name) else {
+            bail!("Unknown reranker model: {}", name);
+        };
+        config.reranker = Some(id.to_string());
+    }
+
+    let previous = config::load(data_dir).unwrap_or_default();
+    config.restore_secrets(&previous);
+    let model = embedding_model_of(&config);
+    let changes = ConfigChanges {
+        embedding_model: (embedding_model_of(&previous) != model).then_some(model),
+        reranker: previous.reranker != config.reranker,
+        sampling: previous.sampling != config.sampling,
+    };
+    config::save(data_dir, &config)?;
+    Ok(changes)
+}
+
+/// Applies saved config changes to the pipeline. Loading a model can mean
+/// downloading it, so this is meant to run as a job.
+pub async fn apply_config_changes(changes: &ConfigChanges, progress: &Progress) -> Result<()> {
+    if changes.reranker {
+        progress.report(0.0, "Loading reranker");
+        let reranker = config::load(&data_dir())?.reranker;
+        // An unloaded pipeline reads the config when it is built
+        if let Some(pipeline) = get_pipeline_lock().write().await.as_mut() {
+            pipeline.set_reranker(reranker.as_deref())?;
+        }
+    }
+    if changes.sampling {
+        let sampling = config::load(&data_dir())?.sampling;
+        if let Some(pipeline) = get_pipeline_lock().write().await.as_mut() {
+            pipeline.set_sampling(sampling);
         }
     }
+    if let Some(ref model) = changes.embedding_model {
+        set_embedding_model(model, progress).await?;
+    }
+    Ok(())
+}
 
-    ensure_pipeline().await?;
+/// Rerankers that can be configured, with the one in use.
+pub async fn reranker_models() -> (Vec<RerankerInfo>, Option<String>) {
+    let active = match get_pipeline_lock().read().await.as_ref() {
+        Some(pipeline) => pipeline.reranker_model().map(str::to_string),
+        None => config::load(&data_dir()).unwrap_or_default().reranker,
+    };
+    (rerank::supported_rerankers(), active)
+}
+
+/// Embedding models that can be selected, with the one in use.
+pub async fn embedding_models() -> (Vec<EmbeddingModelInfo>, String) {
+    let active = match get_pipeline_lock().read().await.as_ref() {
+        Some(pipeline) => pipeline.embedding_model().await,
+        None => None,
+    };
+    let active = active.unwrap_or_else(|| configured_embedding_model(&data_dir()));
+    (embeddings::supported_models(), active)
+}
 
+/// Switches the search index to `model_id` and re-embeds it. Vectors the
+/// model produced before are read back from the embedding cache.
+pub async fn set_embedding_model(model_id: &str, progress: &Progress) -> Result<bool> {
+    progress.report(0.0, "Loading embedding model");
+    ensure_pipeline().await?;
     let guard = get_pipeline_lock().read().await;
     let pipeline = guard.as_ref().unwrap();
+    pipeline.set_embedding_model(model_id, progress).await
+}
 
-    if let Some(ref context) = request.context {
-        if !context.is_empty() {
-            for (i, note_content) in context.iter().enumerate() {
-                let doc = NoteDocument {
-                    id: format!("context_{}", i),
-                    title: format!("Context {}", i + 1),
-                    content: note_content.clone(),
-                    path: String::new(),
-                    embedding: None,
-                };
-                pipeline.index_note(doc).await?;
+/// Resolves one provider of a request. Settings it leaves out come from the
+/// saved config, with request headers added to the configured ones.
+fn provider_config(selection: &LlmSelection, saved: &AiConfig) -> Result<LlmConfig> {
+    let name = selection.provider.as_deref().unwrap_or("local");
+    let mut config = LlmConfig {
+        provider: name.parse()?,
+        api_key: selection.api_key.clone(),
+        model: selection.model.clone(),
+        base_url: selection.base_url.clone(),
+        ..Default::default()
+    };
+    match config.provider {
+        LlmProvider::Ollama => {
+            let settings = &saved.ollama;
+            config.base_url = config.base_url.or_else(|| settings.host.clone());
+            config.model = config.model.or_else(|| settings.model.clone());
+            config.options = settings.options.clone();
+        }
+        LlmProvider::Custom => {
+            let settings = &saved.custom;
+            config.base_url = config.base_url.or_else(|| settings.base_url.clone());
+            config.model = config.model.or_else(|| settings.model.clone());
+            config.api_key = config.api_key.or_else(|| settings.api_key.clone());
+            config.headers = settings.headers.clone();
+            config.headers.extend(selection.headers.clone());
+        }
+        _ => {}
+    }
+    Ok(config)
+}
+
+/// Builds the providers a request runs on: the one it names, then its
+/// fallbacks, or the configured fallback chain when it gives none. Unknown
+/// provider names are an error rather than a silent switch to the local
+/// engine. Providers that can't be built, say for a missing API key, are
+/// left out, and are only an error when none is left. Returns `None` when
+/// only the local engine is left, which callers run directly.
+pub fn provider_chain(selection: &LlmSelection) -> Result<Option<FallbackChain>> {
+    let saved = config::load(&data_dir()).unwrap_or_default();
+    let mut chain = FallbackChain::new(saved.retry.clone());
+    let mut remote = false;
+    let mut error = None;
+    for (label, config) in chain_configs(selection, &saved)? {
+        if matches!(config.provider, LlmProvider::Local) {
+            chain.push(label, Box::new(LocalEngineProvider));
+            continue;
+        }
+        match create_provider(&config) {
+            Ok(provider) => {
+                remote = true;
+                chain.push(label, provider);
+            }
+            Err(e) => {
+                tracing::warn!("Skipping provider {}: {}", label, e);
+                error.get_or_insert(e);
             }
         }
     }
+    if let (true, Some(e)) = (chain.is_empty(), error) {
+        return Err(e);
+    }
+    Ok(remote.then_some(chain))
+}
+
+/// A chain of just the local engine, for callers that need a chain even
+/// when [`provider_chain`] leaves the request to the local engine. It is not
+/// retried: local failures are not transient.
+pub fn local_chain() -> FallbackChain {
+    let mut chain = FallbackChain::new(RetryPolicy {
+        max_retries: 0,
+        ..Default::default()
+    });
+    chain.push(LOCAL_PROVIDER, Box::new(LocalEngineProvider));
+    chain
+}
+
+/// Labelled provider configs of the chain [`provider_chain`] builds. Saved
+/// or requested fallbacks with an unknown provider are skipped.
+fn chain_configs(selection: &LlmSelection, saved: &AiConfig) -> Result<Vec<(String, LlmConfig)>> {
+    let fallback = if selection.fallback.is_empty() {
+        &saved.fallback
+    } else {
+        &selection.fallback
+    };
+    let label = |entry: &LlmSelection| entry.provider.as_deref().unwrap_or_default().to_lowercase();
+
+    let mut configs = Vec::new();
+    if selection.provider.is_some() {
+        configs.push((label(selection), provider_config(selection, saved)?));
+    }
+    for entry in fallback.iter().filter(|e| e.provider.is_some()) {
+        match provider_config(entry, saved) {
+            Ok(config) => configs.push((label(entry), config)),
+            Err(e) => tracing::warn!("Skipping fallback provider {}: {}", label(entry), e),
+        }
+    }
+    Ok(configs)
+}
+
+/// Smallest context window among the providers a request may end up on.
+fn chain_context_window(selection: &LlmSelection) -> Result<usize> {
+    let saved = config::load(&data_dir()).unwrap_or_default();
+    Ok(chain_configs(selection, &saved)?
+        .iter()
+        .map(|(_, config)| tokens::context_window(config))
+        .min()
+        .unwrap_or(tokens::DEFAULT_LOCAL_CONTEXT))
+}
 
-    let response = pipeline.query(&request.message, 5).await?;
+/// The in-process engine as a chain member, so it can back up remote
+/// providers.
+struct LocalEngineProvider;
+
+impl LocalEngineProvider {
+    async fn run(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        tokens: Option<TokenSink>,
+    ) -> Result<String> {
+        ensure_pipeline().await?;
+        let guard = get_pipeline_lock().read().await;
+        let pipeline = guard.as_ref().unwrap();
+        pipeline.chat(messages, max_tokens, tokens).await
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmProviderTrait for LocalEngineProvider {
+    async fn generate(&self, prompt: &str, max_tokens: u32) -> Result<String> {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        self.run(messages, max_tokens, None).await
+    }
+
+    async fn chat(&self, messages: Vec<ChatMessage>, max_tokens: u32) -> Result<String> {
+        self.run(messages, max_tokens, None).await
+    }
+
+    fn name(&self) -> &'static str {
+        "Local"
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        tokens: TokenSink,
+    ) -> Result<String> {
+        self.run(messages, max_tokens, Some(tokens)).await
+    }
+}
+
+/// Answer length for RAG queries on remote providers
+const REMOTE_ANSWER_TOKENS: u32 = 1024;
+
+/// Provider name reported for answers from the in-process engine
+const LOCAL_PROVIDER: &str = "local";
+
+pub async fn chat(request: &AiChatRequest) -> Result<AiChatResponse> {
+    run_chat(request, None).await
+}
+
+/// Like [`chat`], sending the answer to `tokens` as it is generated.
+pub async fn chat_stream(request: &AiChatRequest, tokens: TokenSink) -> Result<AiChatResponse> {
+    run_chat(request, Some(tokens)).await
+}
+
+async fn run_chat(request: &AiChatRequest, tokens: Option<TokenSink>) -> Result<AiChatResponse> {
+    if let Some(chain) = provider_chain(&request.llm)? {
+        let answer = chain.generate(&request.message, 1024, tokens).await?;
+        return Ok(AiChatResponse {
+            response: answer.text,
+            sources: None,
+            provider: Some(answer.provider),
+        });
+    }
+
+    ensure_pipeline().await?;
+
+    let guard = get_pipeline_lock().read().await;
+    let pipeline = guard.as_ref().unwrap();
+
+    let context = request.context.as_deref().unwrap_or_default();
+    let response = pipeline
+        .query_with_context(&request.message, 5, context, tokens)
+        .await?;
 
     let sources: Vec<String> = response
         .sources
@@ -117,37 +475,38 @@ pub async fn chat(request: &AiChatRequest) -> Result<AiChatResponse> {
         } else {
             Some(sources)
         },
+        provider: Some(LOCAL_PROVIDER.to_string()),
     })
 }
 
 pub async fn summarize(request: &AiSummarizeRequest) -> Result<AiSummarizeResponse> {
-    if let Some(ref provider_name) = request.provider {
-        let provider_type = match provider_name.to_lowercase().as_str() {
-            "openai" => LlmProvider::OpenAI,
-            "anthropic" => LlmProvider::Anthropic,
-            "zai" | "z.ai" => LlmProvider::Zai,
-            "groq" => LlmProvider::Groq,
-            _ => LlmProvider::Local,
-        };
+    run_summarize(request, None).await
+}
 
-        if !matches!(provider_type, LlmProvider::Local) {
-            let config = LlmConfig {
-                provider: provider_type,
-                api_key: request.api_key.clone(),
-                model: request.model.clone(),
-                base_url: None,
-            };
-
-            let llm = create_provider(&config)?;
-            let max_words = request.max_length.unwrap_or(500) / 5;
-            let prompt = format!(
-                "Summarize the following text in approximately {} words:\n\n{}",
-                max_words, request.text
-            );
-            let summary = llm.generate(&prompt, (max_words * 2) as u32).await?;
-
-            return Ok(AiSummarizeResponse { summary });
-        }
+pub async fn summarize_stream(
+    request: &AiSummarizeRequest,
+    tokens: TokenSink,
+) -> Result<AiSummarizeResponse> {
+    run_summarize(request, Some(tokens)).await
+}
+
+async fn run_summarize(
+    request: &AiSummarizeRequest,
+    tokens: Option<TokenSink>,
+) -> Result<AiSummarizeResponse> {
+    if let Some(chain) = provider_chain(&request.llm)? {
+        let max_words = request.max_length.unwrap_or(500) / 5;
+        let prompt = format!(
+            "Summarize the following text in approximately {} words:\n\n{}",
+            max_words, request.text
+        );
+        let answer = chain
+            .generate(&prompt, (max_words * 2) as u32, tokens)
+            .await?;
+        return Ok(AiSummarizeResponse {
+            summary: answer.text,
+            provider: Some(answer.provider),
+        });
     }
 
     ensure_pipeline().await?;
@@ -156,12 +515,34 @@ pub async fn summarize(request: &AiSummarizeRequest) -> Result<AiSummarizeRespon
     let pipeline = guard.as_ref().unwrap();
 
     let max_words = request.max_length.unwrap_or(500) / 5;
-    let summary = pipeline.summarize(&request.text, max_words).await?;
+    let summary = match tokens {
+        Some(tokens) => {
+            pipeline
+                .summarize_stream(&request.text, max_words, tokens)
+                .await?
+        }
+        None => pipeline.summarize(&request.text, max_words).await?,
+    };
 
-    Ok(AiSummarizeResponse { summary })
+    Ok(AiSummarizeResponse {
+        summary,
+        provider: Some(LOCAL_PROVIDER.to_string()),
+    })
 }
 
-pub async fn index_notes(request: &AiIndexRequest) -> Result<AiIndexResponse> {
+/// Notes embedded per batch; progress is reported after each one.
+const INDEX_BATCH_SIZE: usize = 32;
+
+pub async fn index_notes(request: &AiIndexRequest, progress: &Progress) -> Result<AiIndexResponse> {
+    if let Some(ref model) = request.embedding_model {
+        let data_dir = data_dir();
+        let mut config = get_config(&data_dir)?;
+        config.embedding_model = Some(model.clone());
+        let changes = update_config(&data_dir, config)?;
+        apply_config_changes(&changes, progress).await?;
+    }
+
+    progress.report(0.0, "Loading embedding model");
     ensure_pipeline().await?;
 
     let guard = get_pipeline_lock().read().await;
@@ -180,11 +561,56 @@ pub async fn index_notes(request: &AiIndexRequest) -> Result<AiIndexResponse> {
         .collect();
 
     let count = docs.len();
-    pipeline.index_notes(docs).await?;
+    let mut indexed = 0;
+    let mut stats = IndexStats::default();
+    for batch in docs.chunks(INDEX_BATCH_SIZE) {
+        let batch_stats = pipeline.index_notes(batch.to_vec(), request.force).await?;
+        stats.added += batch_stats.added;
+        stats.updated += batch_stats.updated;
+        stats.unchanged += batch_stats.unchanged;
+        indexed += batch.len();
+        progress.step(
+            indexed,
+            count,
+            format!("Indexed {}/{} notes", indexed, count),
+        );
+    }
+
+    let removed = if request.prune {
+        let keep = request.notes.iter().map(|n| n.id.clone()).collect();
+        pipeline.retain_notes(&keep).await
+    } else {
+        0
+    };
+    // Rewriting a large index for a no-op sync is wasted work
+    if stats.changed() || removed > 0 {
+        pipeline.save_index().await?;
+    }
 
     Ok(AiIndexResponse {
         indexed_count: count,
         success: true,
+        added_count: stats.added,
+        updated_count: stats.updated,
+        unchanged_count: stats.unchanged,
+        removed_count: removed,
+    })
+}
+
+pub async fn remove_notes(request: &AiRemoveRequest) -> Result<AiRemoveResponse> {
+    ensure_pipeline().await?;
+
+    let guard = get_pipeline_lock().read().await;
+    let pipeline = guard.as_ref().unwrap();
+
+    let removed = pipeline.remove_notes(&request.ids, &request.paths).await;
+    if removed > 0 {
+        pipeline.save_index().await?;
+    }
+
+    Ok(AiRemoveResponse {
+        removed_count: removed,
+        success: true,
     })
 }
 
@@ -195,102 +621,178 @@ pub async fn search_notes(request: &AiSearchRequest) -> Result<AiSearchResponse>
     let pipeline = guard.as_ref().unwrap();
 
     let limit = request.limit.unwrap_or(10);
-    let results = pipeline.search(&request.query, limit).await?;
+    let results = pipeline
+        .search_with(&request.query, limit, &request.options)
+        .await?;
 
     let items: Vec<crate::rpc::AiSearchResultItem> = results
         .into_iter()
-        .map(|r| crate::rpc::AiSearchResultItem {
-            id: r.id,
-            title: r.title,
-            path: r.path,
-            score: r.score,
-            snippet: truncate(&r.content, 200),
+        .map(|r| {
+            let snippet = truncate(r.passage(), 200);
+            let (heading_path, start_line, end_line) = chunk_location(r.chunk);
+            crate::rpc::AiSearchResultItem {
+                id: r.id,
+                title: r.title,
+                path: r.path,
+                score: r.score,
+                snippet,
+                heading_path,
+                start_line,
+                end_line,
+            }
         })
         .collect();
 
     Ok(AiSearchResponse { results: items })
 }
 
-pub async fn generate_chapters(transcript: &str) -> Result<Vec<(f64, String)>> {
-    ensure_pipeline().await?;
+pub async fn extract(request: &AiExtractRequest) -> Result<AiExtractResponse> {
+    let chain = provider_chain(&request.llm)?.unwrap_or_else(local_chain);
+    let extraction = extract::extract(
+        &chain,
+        &request.text,
+        &request.schema,
+        request.instructions.as_deref(),
+        request.max_tokens.unwrap_or(1024),
+    )
+    .await?;
+    Ok(AiExtractResponse {
+        data: extraction.data,
+        provider: extraction.provider,
+        attempts: extraction.attempts,
+    })
+}
 
-    let guard = get_pipeline_lock().read().await;
-    let pipeline = guard.as_ref().unwrap();
+/// Characters of transcript the local engine is given to find chapters in
+const LOCAL_TRANSCRIPT_CHARS: usize = 8000;
 
-    let chapters = pipeline.detect_chapters(transcript).await?;
+/// Chapters of a transcript from the local engine, as `(start, title)`.
+pub async fn generate_chapters(transcript: &str) -> Result<Vec<(f64, String)>> {
+    let transcript = truncate(transcript, LOCAL_TRANSCRIPT_CHARS);
+    let chapters = extract_chapters(&local_chain(), &transcript, None).await?;
     Ok(chapters.into_iter().map(|c| (c.start, c.title)).collect())
 }
 
+/// Splits a transcript whose lines start with `[<seconds>s]` into chapters.
+pub async fn extract_chapters(
+    chain: &FallbackChain,
+    transcript: &str,
+    duration: Option<f64>,
+) -> Result<Vec<crate::rpc::Chapter>> {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "chapters": {
+                "type": "array",
+                "minItems": 1,
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "start": {"type": "number", "minimum": 0},
+                        "title": {"type": "string", "minLength": 1, "maxLength": 80}
+                    },
+                    "required": ["start", "title"]
+                }
+            }
+        },
+        "required": ["chapters"]
+    });
+    let mut instructions = "Identify 5-10 logical chapters of this video transcript. \
+        Give each the timestamp in seconds where it starts and a concise title under 50 characters."
+        .to_string();
+    if let Some(duration) = duration {
+        instructions.push_str(&format!(" The video is {:.0} seconds long.", duration));
+    }
+
+    let extraction =
+        extract::extract(chain, transcript, &schema, Some(&instructions), 1024).await?;
+    let chapters = extraction.data.get("chapters").cloned().unwrap_or_default();
+    Ok(serde_json::from_value(chapters)?)
+}
+
 pub async fn rag_query(request: &AiRagRequest) -> Result<AiRagResponse> {
+    run_rag_query(request, None).await
+}
+
+pub async fn rag_query_stream(request: &AiRagRequest, tokens: TokenSink) -> Result<AiRagResponse> {
+    run_rag_query(request, Some(tokens)).await
+}
+
+async fn run_rag_query(request: &AiRagRequest, tokens: Option<TokenSink>) -> Result<AiRagResponse> {
     ensure_pipeline().await?;
 
     let guard = get_pipeline_lock().read().await;
     let pipeline = guard.as_ref().unwrap();
 
     let limit = request.limit.unwrap_or(5);
-    let search_results = pipeline.search(&request.query, limit).await?;
-
-    let context = search_results
+    let options = SearchOptions {
+        rerank: request.rerank,
+        ..Default::default()
+    };
+    let search_results = pipeline
+        .search_with(&request.query, limit, &options)
+        .await?;
+
+    let chain = provider_chain(&request.llm)?;
+    let (counter, context_window, answer_tokens) = match chain {
+        Some(_) => (
+            TokenCounter::Estimate,
+            chain_context_window(&request.llm)?,
+            REMOTE_ANSWER_TOKENS,
+        ),
+        None => {
+            let (counter, window) = pipeline.token_budget().await;
+            (counter, window, rag::LOCAL_ANSWER_TOKENS)
+        }
+    };
+    let passages: Vec<(String, &str)> = search_results
         .iter()
-        .enumerate()
-        .map(|(i, r)| format!("[{}] {}\n{}", i + 1, r.title, truncate(&r.content, 1000)))
-        .collect::<Vec<_>>()
-        .join("\n\n---\n\n");
-
-    let prompt = format!(
-        r#"You are a helpful assistant that answers questions based on the user's notes.
-
-Context from relevant notes:
-{}
-
-Question: {}
-
-Instructions:
-- Answer based ONLY on the provided context
-- If the context doesn't contain relevant information, say so
-- Reference the source notes by their numbers [1], [2], etc.
-- Be concise and direct
-
-Answer:"#,
-        context, request.query
+        .map(|r| (r.title.clone(), r.passage()))
+        .collect();
+    let packed = rag::pack_prompt(
+        &request.query,
+        &passages,
+        &counter,
+        context_window,
+        answer_tokens,
     );
+    let max_tokens = packed.usage.answer_tokens as u32;
 
-    let answer = if let Some(ref provider_name) = request.provider {
-        let provider_type = match provider_name.to_lowercase().as_str() {
-            "openai" => LlmProvider::OpenAI,
-            "anthropic" => LlmProvider::Anthropic,
-            "zai" | "z.ai" => LlmProvider::Zai,
-            "groq" => LlmProvider::Groq,
-            _ => LlmProvider::Local,
-        };
-
-        if !matches!(provider_type, LlmProvider::Local) {
-            let config = LlmConfig {
-                provider: provider_type,
-                api_key: request.api_key.clone(),
-                model: request.model.clone(),
-                base_url: None,
-            };
-            let llm = create_provider(&config)?;
-            llm.generate(&prompt, 1024).await?
-        } else {
-            pipeline.query(&request.query, limit).await?.answer
+    let (answer, provider) = match chain {
+        Some(chain) => {
+            let answer = chain.generate(&packed.prompt, max_tokens, tokens).await?;
+            (answer.text, answer.provider)
         }
-    } else {
-        pipeline.query(&request.query, limit).await?.answer
+        None => (
+            pipeline
+                .generate(&packed.prompt, max_tokens, tokens)
+                .await?,
+            LOCAL_PROVIDER.to_string(),
+        ),
     };
 
+    let mut search_results: Vec<Option<SearchResult>> =
+        search_results.into_iter().map(Some).collect();
+    let search_results = packed
+        .included
+        .iter()
+        .filter_map(|&i| search_results[i].take());
+
     let sources: Vec<AiRagSource> = search_results
-        .into_iter()
         .map(|s| {
             let mut metadata = std::collections::HashMap::new();
             metadata.insert("title".to_string(), s.title.clone());
             metadata.insert("path".to_string(), s.path.clone());
+            let content = truncate(s.passage(), 200);
+            let (heading_path, start_line, end_line) = chunk_location(s.chunk);
             AiRagSource {
                 id: s.path,
                 score: s.score,
-                content: truncate(&s.content, 200),
+                content,
                 metadata: Some(metadata),
+                heading_path,
+                start_line,
+                end_line,
             }
         })
         .collect();
@@ -298,19 +800,21 @@ Answer:"#,
     Ok(AiRagResponse {
         response: answer,
         sources,
+        provider: Some(provider),
+        usage: Some(packed.usage),
     })
 }
 
-fn truncate(s: &str, max: usize) -> String {
-    if s.len() <= max {
-        s.to_string()
-    } else {
-        format!("{}...", &s[..max])
+/// Heading path and line range of a matched chunk, for API responses.
+fn chunk_location(chunk: Option<Chunk>) -> (Vec<String>, Option<usize>, Option<usize>) {
+    match chunk {
+        Some(c) => (c.heading_path, Some(c.start_line), Some(c.end_line)),
+        None => (Vec::new(), None, None),
     }
 }
 
-pub async fn download_model(model_id: &str) -> Result<String> {
-    use hf_hub::{api::sync::Api, Repo, RepoType};
+pub async fn download_model(model_id: &str, progress: &Progress) -> Result<String> {
+    use hf_hub::{api::sync::ApiBuilder, Repo, RepoType};
 
     let (repo_id, filename) = if model_id.contains(':') {
         let parts: Vec<&str> = model_id.split(':').collect();
@@ -324,15 +828,30 @@ pub async fn download_model(model_id: &str) -> Result<String> {
 
     tracing::info!("Downloading model: {} / {}", repo_id, filename);
 
-    let api = Api::new()?;
-    let repo = api.repo(Repo::new(repo_id.clone(), RepoType::Model));
-
-    let _model_path = repo.get(&filename)?;
+    // The sync hf-hub client has no progress callback, so only the stage is
+    // reported while the file transfers.
+    progress.report(0.0, format!("Downloading {}", filename));
+    let repo = Repo::new(repo_id.clone(), RepoType::Model);
+    let file = filename.clone();
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let api = ApiBuilder::new().with_progress(false).build()?;
+        api.repo(repo).get(&file)?;
+        Ok(())
+    })
+    .await??;
 
     tracing::info!("Model downloaded successfully");
     Ok(format!("{}:{}", repo_id, filename))
 }
 
+/// Embeds passages with the configured embedding model. Vectors are cached
+/// by text, so embedding the same passages again is cheap.
+pub async fn embed_passages(texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+    ensure_pipeline().await?;
+    let guard = get_pipeline_lock().read().await;
+    guard.as_ref().unwrap().embed(texts).await
+}
+
 pub async fn find_related_notes(
     content: &str,
     limit: usize,
@@ -1,7 +1,11 @@
 use anyhow::Result;
+use futures::StreamExt;
+use ollama_rs::generation::chat::{request::ChatMessageRequest, ChatMessage, MessageRole};
 use ollama_rs::{generation::completion::request::GenerationRequest, Ollama};
 use serde::{Deserialize, Serialize};
 
+use super::providers::{TokenSink, DEFAULT_OLLAMA_HOST};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OllamaModel {
     pub name: String,
@@ -15,8 +19,21 @@ pub struct OllamaStatus {
     pub models: Vec<OllamaModel>,
 }
 
+/// Client for the configured Ollama host (see `AiConfig::ollama`).
+fn client() -> Ollama {
+    let host = super::config::load(&super::data_dir())
+        .unwrap_or_default()
+        .ollama
+        .host
+        .unwrap_or_else(|| DEFAULT_OLLAMA_HOST.to_string());
+    Ollama::try_new(host.as_str()).unwrap_or_else(|e| {
+        tracing::warn!("Invalid Ollama host {:?}: {}", host, e);
+        Ollama::default()
+    })
+}
+
 pub async fn check_status() -> OllamaStatus {
-    let ollama = Ollama::default();
+    let ollama = client();
 
     match ollama.list_local_models().await {
         Ok(models) => OllamaStatus {
@@ -38,7 +55,7 @@ pub async fn check_status() -> OllamaStatus {
 }
 
 pub async fn list_models() -> Result<Vec<OllamaModel>> {
-    let ollama = Ollama::default();
+    let ollama = client();
     let models = ollama.list_local_models().await?;
 
     Ok(models
@@ -52,7 +69,7 @@ pub async fn list_models() -> Result<Vec<OllamaModel>> {
 }
 
 pub async fn generate(model: &str, prompt: &str, system: Option<&str>) -> Result<String> {
-    let ollama = Ollama::default();
+    let ollama = client();
 
     let mut request = GenerationRequest::new(model.to_string(), prompt.to_string());
 
@@ -69,10 +86,47 @@ pub async fn chat(
     messages: Vec<(String, String)>,
     system: Option<&str>,
 ) -> Result<String> {
-    use ollama_rs::generation::chat::{request::ChatMessageRequest, ChatMessage, MessageRole};
+    let ollama = client();
+    let request = chat_request(model, messages, system);
+    let response = ollama.send_chat_messages(request).await?;
+
+    Ok(response.message.content)
+}
+
+/// Like [`chat`], sending each streamed chunk to `tokens`.
+pub async fn chat_stream(
+    model: &str,
+    messages: Vec<(String, String)>,
+    system: Option<&str>,
+    tokens: TokenSink,
+) -> Result<String> {
+    let ollama = client();
+    let request = chat_request(model, messages, system);
+    let mut stream = ollama.send_chat_messages_stream(request).await?;
+
+    let mut full = String::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|_| anyhow::anyhow!("Ollama stream failed"))?;
+        let text = chunk.message.content;
+        if !text.is_empty() {
+            full.push_str(&text);
+            if tokens.send(text).is_err() {
+                break;
+            }
+        }
+        if chunk.done {
+            break;
+        }
+    }
 
-    let ollama = Ollama::default();
+    Ok(full)
+}
 
+fn chat_request(
+    model: &str,
+    messages: Vec<(String, String)>,
+    system: Option<&str>,
+) -> ChatMessageRequest {
     let mut chat_messages: Vec<ChatMessage> = messages
         .into_iter()
         .map(|(role, content)| {
@@ -89,10 +143,7 @@ pub async fn chat(
         chat_messages.insert(0, ChatMessage::new(MessageRole::System, sys.to_string()));
     }
 
-    let request = ChatMessageRequest::new(model.to_string(), chat_messages);
-    let response = ollama.send_chat_messages(request).await?;
-
-    Ok(response.message.content)
+    ChatMessageRequest::new(model.to_string(), chat_messages)
 }
 
 fn format_size(bytes: u64) -> String {
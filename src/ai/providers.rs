@@ -2,6 +2,13 @@ use anyhow::{Context, Result};
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+/// Receives generated text incrementally. Streaming calls still return the
+/// full completion; once the receiver is dropped they stop early with what
+/// they have so far.
+pub type TokenSink = mpsc::UnboundedSender<String>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -15,6 +22,27 @@ pub enum LlmProvider {
     #[serde(alias = "zai", alias = "z.ai")]
     Zai,
     Groq,
+    /// Any server speaking the OpenAI chat API, e.g. LM Studio, vLLM or the
+    /// llama.cpp server
+    #[serde(alias = "openai_compatible")]
+    Custom,
+}
+
+impl std::str::FromStr for LlmProvider {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "local" => Ok(LlmProvider::Local),
+            "ollama" => Ok(LlmProvider::Ollama),
+            "openai" => Ok(LlmProvider::OpenAI),
+            "anthropic" => Ok(LlmProvider::Anthropic),
+            "zai" | "z.ai" => Ok(LlmProvider::Zai),
+            "groq" => Ok(LlmProvider::Groq),
+            "custom" | "openai_compatible" | "openai-compatible" => Ok(LlmProvider::Custom),
+            _ => anyhow::bail!("Unknown LLM provider: {}", name),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +51,12 @@ pub struct LlmConfig {
     pub api_key: Option<String>,
     pub model: Option<String>,
     pub base_url: Option<String>,
+    /// Provider-specific generation options, e.g. Ollama's `num_ctx`
+    #[serde(default)]
+    pub options: Option<serde_json::Value>,
+    /// Extra HTTP headers for OpenAI-compatible servers
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
 }
 
 impl Default for LlmConfig {
@@ -32,6 +66,8 @@ impl Default for LlmConfig {
             api_key: None,
             model: None,
             base_url: None,
+            options: None,
+            headers: HashMap::new(),
         }
     }
 }
@@ -47,13 +83,278 @@ pub trait LlmProviderTrait: Send + Sync {
     async fn generate(&self, prompt: &str, max_tokens: u32) -> Result<String>;
     async fn chat(&self, messages: Vec<ChatMessage>, max_tokens: u32) -> Result<String>;
     fn name(&self) -> &'static str;
+
+    /// Like [`chat`](Self::chat), but sends text to `tokens` as it arrives.
+    /// Providers without native streaming send the whole completion at once.
+    async fn chat_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        tokens: TokenSink,
+    ) -> Result<String> {
+        let response = self.chat(messages, max_tokens).await?;
+        let _ = tokens.send(response.clone());
+        Ok(response)
+    }
+
+    async fn generate_stream(
+        &self,
+        prompt: &str,
+        max_tokens: u32,
+        tokens: TokenSink,
+    ) -> Result<String> {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        self.chat_stream(messages, max_tokens, tokens).await
+    }
+
+    /// Like [`chat`](Self::chat), asking for a JSON object matching `schema`.
+    /// Providers without a JSON mode rely on the prompt alone, so callers
+    /// still have to validate the reply.
+    async fn chat_json(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        _schema: &serde_json::Value,
+    ) -> Result<String> {
+        self.chat(messages, max_tokens).await
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderError {
+    #[error("{provider} API error: {status} - {body}")]
+    Status {
+        provider: &'static str,
+        status: u16,
+        body: String,
+    },
+}
+
+/// Whether `err` is worth retrying: rate limits, timeouts, server errors and
+/// failed connections. Bad requests and auth failures are not.
+pub fn is_retryable(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(ProviderError::Status { status, .. }) = cause.downcast_ref() {
+            return matches!(status, 408 | 409 | 425 | 429) || *status >= 500;
+        }
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_timeout() || e.is_connect())
+    })
+}
+
+/// How often and how patiently a [`FallbackChain`] retries one provider.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, for retryable errors only
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 8_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (0-based), doubling each time.
+    pub fn backoff(&self, retry: u32) -> std::time::Duration {
+        let factor = 1u64.checked_shl(retry).unwrap_or(u64::MAX);
+        let ms = self
+            .initial_backoff_ms
+            .saturating_mul(factor)
+            .min(self.max_backoff_ms);
+        std::time::Duration::from_millis(ms)
+    }
+}
+
+/// What a [`FallbackChain`] produced, and which provider produced it.
+#[derive(Debug, Clone)]
+pub struct ChainResponse {
+    pub text: String,
+    pub provider: String,
+}
+
+/// Providers tried in order: each is retried with backoff on retryable
+/// errors, and the next one takes over when it gives up or fails outright.
+pub struct FallbackChain {
+    providers: Vec<(String, Box<dyn LlmProviderTrait>)>,
+    policy: RetryPolicy,
+}
+
+impl FallbackChain {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            providers: Vec::new(),
+            policy,
+        }
+    }
+
+    /// Appends a provider, reported as `label` when it answers.
+    pub fn push(&mut self, label: impl Into<String>, provider: Box<dyn LlmProviderTrait>) {
+        self.providers.push((label.into(), provider));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    pub async fn generate(
+        &self,
+        prompt: &str,
+        max_tokens: u32,
+        tokens: Option<TokenSink>,
+    ) -> Result<ChainResponse> {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        self.chat(messages, max_tokens, tokens).await
+    }
+
+    /// Runs `messages` down the chain, streaming to `tokens` when given. Once
+    /// a provider has streamed text, its failure ends the chain, since a
+    /// second answer would be appended to the first.
+    pub async fn chat(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        tokens: Option<TokenSink>,
+    ) -> Result<ChainResponse> {
+        self.run(messages, max_tokens, tokens.as_ref(), None).await
+    }
+
+    /// Runs `messages` down the chain in each provider's JSON mode; see
+    /// [`LlmProviderTrait::chat_json`].
+    pub async fn chat_json(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        schema: &serde_json::Value,
+    ) -> Result<ChainResponse> {
+        self.run(messages, max_tokens, None, Some(schema)).await
+    }
+
+    async fn run(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        tokens: Option<&TokenSink>,
+        schema: Option<&serde_json::Value>,
+    ) -> Result<ChainResponse> {
+        let mut failures = Vec::new();
+        for (label, provider) in &self.providers {
+            let mut retry = 0;
+            loop {
+                let (result, streamed) = attempt(
+                    provider.as_ref(),
+                    messages.clone(),
+                    max_tokens,
+                    tokens,
+                    schema,
+                )
+                .await;
+                let err = match result {
+                    Ok(text) => {
+                        return Ok(ChainResponse {
+                            text,
+                            provider: label.clone(),
+                        })
+                    }
+                    Err(err) if streamed => return Err(err),
+                    Err(err) => err,
+                };
+
+                if retry < self.policy.max_retries && is_retryable(&err) {
+                    let delay = self.policy.backoff(retry);
+                    tracing::warn!("{} failed ({}), retrying in {:?}", label, err, delay);
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                    continue;
+                }
+                tracing::warn!("{} failed: {}", label, err);
+                failures.push(format!("{}: {}", label, err));
+                break;
+            }
+        }
+        anyhow::bail!("All LLM providers failed: {}", failures.join("; "))
+    }
+}
+
+/// One call to `provider`, also reporting whether any text reached `tokens`.
+/// JSON calls are never streamed.
+async fn attempt(
+    provider: &dyn LlmProviderTrait,
+    messages: Vec<ChatMessage>,
+    max_tokens: u32,
+    tokens: Option<&TokenSink>,
+    schema: Option<&serde_json::Value>,
+) -> (Result<String>, bool) {
+    if let Some(schema) = schema {
+        return (
+            provider.chat_json(messages, max_tokens, schema).await,
+            false,
+        );
+    }
+    let Some(tokens) = tokens else {
+        return (provider.chat(messages, max_tokens).await, false);
+    };
+
+    let (sink, mut received) = mpsc::unbounded_channel();
+    let call = provider.chat_stream(messages, max_tokens, sink);
+    let forward = async {
+        let mut streamed = false;
+        while let Some(text) = received.recv().await {
+            streamed = true;
+            if tokens.send(text).is_err() {
+                break;
+            }
+        }
+        streamed
+    };
+    tokio::join!(call, forward)
+}
+
+/// Feeds the `data:` payload of each server-sent event in `response` to
+/// `on_data`, stopping when it returns `false` or the body ends.
+async fn read_sse_data(
+    mut response: reqwest::Response,
+    mut on_data: impl FnMut(&str) -> Result<bool>,
+) -> Result<()> {
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim_end().strip_prefix("data:") else {
+                continue;
+            };
+            if !on_data(data.trim_start())? {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
 }
 
 pub struct OpenAIProvider {
     client: Client,
+    /// Sent as a bearer token unless empty; local servers often need none
     api_key: String,
     model: String,
     base_url: String,
+    headers: HashMap<String, String>,
 }
 
 impl OpenAIProvider {
@@ -62,9 +363,19 @@ impl OpenAIProvider {
             client: Client::new(),
             api_key,
             model: model.unwrap_or_else(|| "gpt-4o-mini".to_string()),
-            base_url: base_url.unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+            base_url: base_url
+                .unwrap_or_else(|| "https://api.openai.com/v1".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            headers: HashMap::new(),
         }
     }
+
+    /// Adds headers sent with every request, after the defaults.
+    pub fn with_headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
 }
 
 #[derive(Serialize)]
@@ -73,6 +384,10 @@ struct OpenAIRequest {
     messages: Vec<OpenAIMessage>,
     max_tokens: u32,
     temperature: f32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -91,17 +406,29 @@ struct OpenAIChoice {
     message: OpenAIMessage,
 }
 
-#[async_trait]
-impl LlmProviderTrait for OpenAIProvider {
-    async fn generate(&self, prompt: &str, max_tokens: u32) -> Result<String> {
-        let messages = vec![ChatMessage {
-            role: "user".to_string(),
-            content: prompt.to_string(),
-        }];
-        self.chat(messages, max_tokens).await
-    }
+#[derive(Deserialize)]
+struct OpenAIStreamChunk {
+    choices: Vec<OpenAIStreamChoice>,
+}
 
-    async fn chat(&self, messages: Vec<ChatMessage>, max_tokens: u32) -> Result<String> {
+#[derive(Deserialize)]
+struct OpenAIStreamChoice {
+    delta: OpenAIDelta,
+}
+
+#[derive(Deserialize)]
+struct OpenAIDelta {
+    content: Option<String>,
+}
+
+impl OpenAIProvider {
+    async fn send(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        stream: bool,
+        response_format: Option<serde_json::Value>,
+    ) -> Result<reqwest::Response> {
         let openai_messages: Vec<OpenAIMessage> = messages
             .into_iter()
             .map(|m| OpenAIMessage {
@@ -115,24 +442,105 @@ impl LlmProviderTrait for OpenAIProvider {
             messages: openai_messages,
             max_tokens,
             temperature: 0.7,
+            stream,
+            response_format,
         };
 
-        let response = self
+        let mut builder = self
             .client
             .post(format!("{}/chat/completions", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
+            .header("Content-Type", "application/json");
+        if !self.api_key.is_empty() {
+            builder = builder.header("Authorization", format!("Bearer {}", self.api_key));
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let response = builder
             .json(&request)
             .send()
             .await
-            .context("Failed to call OpenAI API")?;
+            .with_context(|| format!("Failed to call OpenAI API at {}", self.base_url))?;
 
         if !response.status().is_success() {
             let status = response.status();
             let text = response.text().await.unwrap_or_default();
-            anyhow::bail!("OpenAI API error: {} - {}", status, text);
+            return Err(ProviderError::Status {
+                provider: "OpenAI",
+                status: status.as_u16(),
+                body: text,
+            }
+            .into());
         }
+        Ok(response)
+    }
+}
 
+#[async_trait]
+impl LlmProviderTrait for OpenAIProvider {
+    async fn generate(&self, prompt: &str, max_tokens: u32) -> Result<String> {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        self.chat(messages, max_tokens).await
+    }
+
+    async fn chat(&self, messages: Vec<ChatMessage>, max_tokens: u32) -> Result<String> {
+        let response = self.send(messages, max_tokens, false, None).await?;
+        self.read_message(response).await
+    }
+
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        tokens: TokenSink,
+    ) -> Result<String> {
+        let response = self.send(messages, max_tokens, true, None).await?;
+        let mut full = String::new();
+        read_sse_data(response, |data| {
+            if data == "[DONE]" {
+                return Ok(false);
+            }
+            let chunk: OpenAIStreamChunk =
+                serde_json::from_str(data).context("Failed to parse OpenAI stream chunk")?;
+            if let Some(text) = chunk
+                .choices
+                .into_iter()
+                .next()
+                .and_then(|c| c.delta.content)
+                .filter(|t| !t.is_empty())
+            {
+                full.push_str(&text);
+                return Ok(tokens.send(text).is_ok());
+            }
+            Ok(true)
+        })
+        .await?;
+        Ok(full)
+    }
+
+    /// Uses JSON mode, which compatible servers support more widely than
+    /// schema-constrained output; the schema itself is in the prompt.
+    async fn chat_json(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        _schema: &serde_json::Value,
+    ) -> Result<String> {
+        let format = serde_json::json!({"type": "json_object"});
+        let response = self.send(messages, max_tokens, false, Some(format)).await?;
+        self.read_message(response).await
+    }
+}
+
+impl OpenAIProvider {
+    async fn read_message(&self, response: reqwest::Response) -> Result<String> {
         let data: OpenAIResponse = response
             .json()
             .await
@@ -143,24 +551,25 @@ impl LlmProviderTrait for OpenAIProvider {
             .map(|c| c.message.content.clone())
             .ok_or_else(|| anyhow::anyhow!("No response from OpenAI"))
     }
-
-    fn name(&self) -> &'static str {
-        "OpenAI"
-    }
 }
 
 pub struct AnthropicProvider {
     client: Client,
     api_key: String,
     model: String,
+    base_url: String,
 }
 
 impl AnthropicProvider {
-    pub fn new(api_key: String, model: Option<String>) -> Self {
+    pub fn new(api_key: String, model: Option<String>, base_url: Option<String>) -> Self {
         Self {
             client: Client::new(),
             api_key,
             model: model.unwrap_or_else(|| "claude-3-5-sonnet-20241022".to_string()),
+            base_url: base_url
+                .unwrap_or_else(|| "https://api.anthropic.com/v1".to_string())
+                .trim_end_matches('/')
+                .to_string(),
         }
     }
 }
@@ -169,9 +578,28 @@ impl AnthropicProvider {
 struct AnthropicRequest {
     model: String,
     max_tokens: u32,
+    /// System messages, which the API takes apart from the conversation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
     messages: Vec<AnthropicMessage>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<AnthropicTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct AnthropicTool {
+    name: &'static str,
+    description: &'static str,
+    input_schema: serde_json::Value,
 }
 
+/// Tool Anthropic models are made to call to return structured output
+const EXTRACT_TOOL: &str = "record_extraction";
+
 #[derive(Serialize, Deserialize)]
 struct AnthropicMessage {
     role: String,
@@ -183,22 +611,38 @@ struct AnthropicResponse {
     content: Vec<AnthropicContent>,
 }
 
+/// A `text` block, or a `tool_use` block carrying `input`
 #[derive(Deserialize)]
 struct AnthropicContent {
-    text: String,
+    text: Option<String>,
+    input: Option<serde_json::Value>,
 }
 
-#[async_trait]
-impl LlmProviderTrait for AnthropicProvider {
-    async fn generate(&self, prompt: &str, max_tokens: u32) -> Result<String> {
-        let messages = vec![ChatMessage {
-            role: "user".to_string(),
-            content: prompt.to_string(),
-        }];
-        self.chat(messages, max_tokens).await
-    }
+/// One streaming event; only `content_block_delta` and `error` carry data we use.
+#[derive(Deserialize)]
+struct AnthropicStreamEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    delta: Option<AnthropicDelta>,
+    error: Option<serde_json::Value>,
+}
 
-    async fn chat(&self, messages: Vec<ChatMessage>, max_tokens: u32) -> Result<String> {
+#[derive(Deserialize)]
+struct AnthropicDelta {
+    text: Option<String>,
+}
+
+impl AnthropicProvider {
+    async fn send(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        stream: bool,
+        tool: Option<AnthropicTool>,
+    ) -> Result<reqwest::Response> {
+        let (system, messages): (Vec<_>, Vec<_>) =
+            messages.into_iter().partition(|m| m.role == "system");
+        let system: Vec<String> = system.into_iter().map(|m| m.content).collect();
         let anthropic_messages: Vec<AnthropicMessage> = messages
             .into_iter()
             .map(|m| AnthropicMessage {
@@ -215,12 +659,18 @@ impl LlmProviderTrait for AnthropicProvider {
         let request = AnthropicRequest {
             model: self.model.clone(),
             max_tokens,
+            system: (!system.is_empty()).then(|| system.join("\n\n")),
             messages: anthropic_messages,
+            stream,
+            tool_choice: tool
+                .as_ref()
+                .map(|t| serde_json::json!({"type": "tool", "name": t.name})),
+            tools: tool.into_iter().collect(),
         };
 
         let response = self
             .client
-            .post("https://api.anthropic.com/v1/messages")
+            .post(format!("{}/messages", self.base_url))
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("Content-Type", "application/json")
@@ -232,23 +682,286 @@ impl LlmProviderTrait for AnthropicProvider {
         if !response.status().is_success() {
             let status = response.status();
             let text = response.text().await.unwrap_or_default();
-            anyhow::bail!("Anthropic API error: {} - {}", status, text);
+            return Err(ProviderError::Status {
+                provider: "Anthropic",
+                status: status.as_u16(),
+                body: text,
+            }
+            .into());
         }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmProviderTrait for AnthropicProvider {
+    async fn generate(&self, prompt: &str, max_tokens: u32) -> Result<String> {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        self.chat(messages, max_tokens).await
+    }
 
+    async fn chat(&self, messages: Vec<ChatMessage>, max_tokens: u32) -> Result<String> {
+        let response = self.send(messages, max_tokens, false, None).await?;
         let data: AnthropicResponse = response
             .json()
             .await
             .context("Failed to parse Anthropic response")?;
 
         data.content
-            .first()
-            .map(|c| c.text.clone())
+            .into_iter()
+            .find_map(|c| c.text)
             .ok_or_else(|| anyhow::anyhow!("No response from Anthropic"))
     }
 
     fn name(&self) -> &'static str {
         "Anthropic"
     }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        tokens: TokenSink,
+    ) -> Result<String> {
+        let response = self.send(messages, max_tokens, true, None).await?;
+        let mut full = String::new();
+        read_sse_data(response, |data| {
+            let event: AnthropicStreamEvent =
+                serde_json::from_str(data).context("Failed to parse Anthropic stream event")?;
+            match event.kind.as_str() {
+                "content_block_delta" => {
+                    if let Some(text) = event.delta.and_then(|d| d.text) {
+                        full.push_str(&text);
+                        return Ok(tokens.send(text).is_ok());
+                    }
+                    Ok(true)
+                }
+                "message_stop" => Ok(false),
+                "error" => {
+                    anyhow::bail!("Anthropic API error: {}", event.error.unwrap_or_default())
+                }
+                _ => Ok(true),
+            }
+        })
+        .await?;
+        Ok(full)
+    }
+
+    /// Forces a call to a tool whose input schema is `schema`, and returns
+    /// the tool input.
+    async fn chat_json(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        schema: &serde_json::Value,
+    ) -> Result<String> {
+        let tool = AnthropicTool {
+            name: EXTRACT_TOOL,
+            description: "Record the extracted data.",
+            input_schema: schema.clone(),
+        };
+        let response = self.send(messages, max_tokens, false, Some(tool)).await?;
+        let data: AnthropicResponse = response
+            .json()
+            .await
+            .context("Failed to parse Anthropic response")?;
+
+        data.content
+            .into_iter()
+            .find_map(|c| c.input)
+            .map(|input| input.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Anthropic did not call the extraction tool"))
+    }
+}
+
+pub const DEFAULT_OLLAMA_HOST: &str = "http://localhost:11434";
+pub const DEFAULT_OLLAMA_MODEL: &str = "llama3.2";
+
+/// Talks to an Ollama server over its native `/api/chat` endpoint.
+pub struct OllamaProvider {
+    client: Client,
+    host: String,
+    model: String,
+    /// Passed as Ollama's `options`, e.g. `{"num_ctx": 8192}`
+    options: Option<serde_json::Value>,
+}
+
+impl OllamaProvider {
+    pub fn new(
+        host: Option<String>,
+        model: Option<String>,
+        options: Option<serde_json::Value>,
+    ) -> Self {
+        Self {
+            client: Client::new(),
+            host: host
+                .unwrap_or_else(|| DEFAULT_OLLAMA_HOST.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            model: model.unwrap_or_else(|| DEFAULT_OLLAMA_MODEL.to_string()),
+            options,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    options: serde_json::Map<String, serde_json::Value>,
+    /// `"json"`, or a JSON Schema the reply must follow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>,
+}
+
+/// A whole response, or one line of a streamed one
+#[derive(Deserialize)]
+struct OllamaResponse {
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaMessage {
+    content: String,
+}
+
+impl OllamaProvider {
+    async fn send(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        stream: bool,
+        format: Option<serde_json::Value>,
+    ) -> Result<reqwest::Response> {
+        let mut options = match self.options {
+            Some(serde_json::Value::Object(ref options)) => options.clone(),
+            _ => serde_json::Map::new(),
+        };
+        options
+            .entry("num_predict")
+            .or_insert_with(|| max_tokens.into());
+
+        let request = OllamaRequest {
+            model: self.model.clone(),
+            messages,
+            stream,
+            options,
+            format,
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/api/chat", self.host))
+            .json(&request)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach Ollama at {}", self.host))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(ProviderError::Status {
+                provider: "Ollama",
+                status: status.as_u16(),
+                body: text,
+            }
+            .into());
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmProviderTrait for OllamaProvider {
+    async fn generate(&self, prompt: &str, max_tokens: u32) -> Result<String> {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        self.chat(messages, max_tokens).await
+    }
+
+    async fn chat(&self, messages: Vec<ChatMessage>, max_tokens: u32) -> Result<String> {
+        let response = self.send(messages, max_tokens, false, None).await?;
+        self.read_message(response).await
+    }
+
+    fn name(&self) -> &'static str {
+        "Ollama"
+    }
+
+    /// Ollama streams one JSON object per line rather than server-sent events.
+    async fn chat_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        tokens: TokenSink,
+    ) -> Result<String> {
+        let mut response = self.send(messages, max_tokens, true, None).await?;
+        let mut full = String::new();
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let data: OllamaResponse =
+                    serde_json::from_str(&line).context("Failed to parse Ollama stream chunk")?;
+                if let Some(error) = data.error {
+                    anyhow::bail!("Ollama API error: {}", error);
+                }
+                if let Some(text) = data.message.map(|m| m.content).filter(|t| !t.is_empty()) {
+                    full.push_str(&text);
+                    if tokens.send(text).is_err() {
+                        return Ok(full);
+                    }
+                }
+                if data.done {
+                    return Ok(full);
+                }
+            }
+        }
+        Ok(full)
+    }
+
+    /// Ollama constrains decoding to the schema itself.
+    async fn chat_json(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        schema: &serde_json::Value,
+    ) -> Result<String> {
+        let response = self
+            .send(messages, max_tokens, false, Some(schema.clone()))
+            .await?;
+        self.read_message(response).await
+    }
+}
+
+impl OllamaProvider {
+    async fn read_message(&self, response: reqwest::Response) -> Result<String> {
+        let data: OllamaResponse = response
+            .json()
+            .await
+            .context("Failed to parse Ollama response")?;
+        if let Some(error) = data.error {
+            anyhow::bail!("Ollama API error: {}", error);
+        }
+
+        data.message
+            .map(|m| m.content)
+            .ok_or_else(|| anyhow::anyhow!("No response from Ollama"))
+    }
 }
 
 pub fn create_provider(config: &LlmConfig) -> Result<Box<dyn LlmProviderTrait>> {
@@ -272,6 +985,7 @@ pub fn create_provider(config: &LlmConfig) -> Result<Box<dyn LlmProviderTrait>>
             Ok(Box::new(AnthropicProvider::new(
                 api_key,
                 config.model.clone(),
+                config.base_url.clone(),
             )))
         }
         LlmProvider::Zai => {
@@ -299,8 +1013,31 @@ pub fn create_provider(config: &LlmConfig) -> Result<Box<dyn LlmProviderTrait>>
                 Some("https://api.groq.com/openai/v1".to_string()),
             )))
         }
-        LlmProvider::Local | LlmProvider::Ollama => {
-            anyhow::bail!("Use dedicated local/ollama engine instead of provider abstraction")
+        LlmProvider::Custom => {
+            let base_url = config
+                .base_url
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("Base URL required for a custom provider"))?;
+            let model = config
+                .model
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("Model required for a custom provider"))?;
+            Ok(Box::new(
+                OpenAIProvider::new(
+                    config.api_key.clone().unwrap_or_default(),
+                    Some(model),
+                    Some(base_url),
+                )
+                .with_headers(config.headers.clone()),
+            ))
+        }
+        LlmProvider::Ollama => Ok(Box::new(OllamaProvider::new(
+            config.base_url.clone(),
+            config.model.clone(),
+            config.options.clone(),
+        ))),
+        LlmProvider::Local => {
+            anyhow::bail!("Use the local engine instead of provider abstraction")
         }
     }
 }
@@ -323,7 +1060,341 @@ mod tests {
             api_key: None,
             model: None,
             base_url: None,
+            options: None,
+            headers: HashMap::new(),
+        };
+        assert!(create_provider(&config).is_err());
+
+        let config = LlmConfig {
+            provider: LlmProvider::Custom,
+            model: Some("qwen".to_string()),
+            ..Default::default()
         };
         assert!(create_provider(&config).is_err());
     }
+
+    #[test]
+    fn test_provider_names() {
+        assert!(matches!("Ollama".parse(), Ok(LlmProvider::Ollama)));
+        assert!(matches!(
+            "openai_compatible".parse(),
+            Ok(LlmProvider::Custom)
+        ));
+        assert!(matches!("z.ai".parse(), Ok(LlmProvider::Zai)));
+        assert!("llamafile".parse::<LlmProvider>().is_err());
+    }
+
+    /// Serves one HTTP response with `body` on a local port.
+    async fn serve_once(content_type: &'static str, body: String) -> String {
+        serve_once_capturing(content_type, body).await.0
+    }
+
+    /// Like [`serve_once`], also returning the raw request once received.
+    async fn serve_once_capturing(
+        content_type: &'static str,
+        body: String,
+    ) -> (String, tokio::sync::oneshot::Receiver<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (request_tx, request_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            // Read until the whole body announced by Content-Length is in
+            let mut received = Vec::new();
+            let mut buf = vec![0u8; 16 * 1024];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                received.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&received);
+                let complete = text.split_once("\r\n\r\n").is_some_and(|(head, body)| {
+                    let length = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse().unwrap_or(0))
+                        })
+                        .unwrap_or(0);
+                    body.len() >= length
+                });
+                if n == 0 || complete {
+                    break;
+                }
+            }
+            let _ = request_tx.send(String::from_utf8_lossy(&received).to_string());
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        (format!("http://{}", addr), request_rx)
+    }
+
+    #[tokio::test]
+    async fn test_openai_chat_stream() {
+        let body = [
+            r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":"Hel"}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":"lo 世界"}}]}"#,
+            "data: [DONE]",
+        ]
+        .map(|line| format!("{}\n\n", line))
+        .concat();
+        let base_url = serve_once("text/event-stream", body).await;
+
+        let provider = OpenAIProvider::new("key".to_string(), None, Some(base_url));
+        let (tokens, mut received) = mpsc::unbounded_channel();
+        let full = provider.generate_stream("hi", 16, tokens).await.unwrap();
+
+        assert_eq!(full, "Hello 世界");
+        assert_eq!(received.recv().await.as_deref(), Some("Hel"));
+        assert_eq!(received.recv().await.as_deref(), Some("lo 世界"));
+        assert!(received.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ollama_chat_sends_options() {
+        let body = r#"{"model":"qwen2.5","message":{"role":"assistant","content":"Hi there"},"done":true}"#;
+        let (host, request) = serve_once_capturing("application/json", body.to_string()).await;
+
+        let provider = OllamaProvider::new(
+            Some(format!("{}/", host)),
+            Some("qwen2.5".to_string()),
+            Some(serde_json::json!({"num_ctx": 8192, "temperature": 0.2})),
+        );
+        assert_eq!(provider.generate("hello", 64).await.unwrap(), "Hi there");
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /api/chat "));
+        let json: serde_json::Value =
+            serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(json["model"], "qwen2.5");
+        assert_eq!(json["stream"], false);
+        assert_eq!(json["options"]["num_ctx"], 8192);
+        assert_eq!(json["options"]["num_predict"], 64);
+        assert_eq!(json["messages"][0]["content"], "hello");
+    }
+
+    #[tokio::test]
+    async fn test_ollama_chat_stream() {
+        let body = [
+            r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#,
+            r#"{"message":{"role":"assistant","content":"lo"},"done":false}"#,
+            r#"{"message":{"role":"assistant","content":""},"done":true}"#,
+        ]
+        .map(|line| format!("{}\n", line))
+        .concat();
+        let host = serve_once("application/x-ndjson", body).await;
+
+        let provider = OllamaProvider::new(Some(host), None, None);
+        let (tokens, mut received) = mpsc::unbounded_channel();
+        let full = provider.generate_stream("hi", 16, tokens).await.unwrap();
+
+        assert_eq!(full, "Hello");
+        assert_eq!(received.recv().await.as_deref(), Some("Hel"));
+        assert_eq!(received.recv().await.as_deref(), Some("lo"));
+        assert!(received.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ollama_error_is_reported() {
+        let body = r#"{"error":"model 'nope' not found"}"#.to_string();
+        let host = serve_once("application/json", body).await;
+        let provider = OllamaProvider::new(Some(host), Some("nope".to_string()), None);
+        let err = provider.generate("hi", 8).await.unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+
+    #[tokio::test]
+    async fn test_custom_provider_sends_headers() {
+        let body = r#"{"choices":[{"message":{"role":"assistant","content":"ok"}}]}"#;
+        let (base_url, request) = serve_once_capturing("application/json", body.to_string()).await;
+
+        let config = LlmConfig {
+            provider: LlmProvider::Custom,
+            model: Some("local-model".to_string()),
+            base_url: Some(format!("{}/v1/", base_url)),
+            headers: HashMap::from([("X-Team".to_string(), "notes".to_string())]),
+            ..Default::default()
+        };
+        let provider = create_provider(&config).unwrap();
+        assert_eq!(provider.generate("hi", 8).await.unwrap(), "ok");
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /v1/chat/completions "));
+        let head = request.to_lowercase();
+        assert!(head.contains("x-team: notes"));
+        // No key configured, so no bearer token
+        assert!(!head.contains("authorization:"));
+        assert!(request.contains(r#""model":"local-model""#));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_sends_system_prompt_apart() {
+        let body = r#"{"content":[{"type":"text","text":"ok"}]}"#;
+        let (base_url, request) = serve_once_capturing("application/json", body.to_string()).await;
+
+        let provider = AnthropicProvider::new("key".to_string(), None, Some(base_url));
+        let messages = vec![
+            ChatMessage {
+                role: "system".to_string(),
+                content: "Answer briefly.".to_string(),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: "hi".to_string(),
+            },
+            ChatMessage {
+                role: "assistant".to_string(),
+                content: "hello".to_string(),
+            },
+            ChatMessage {
+                role: "user".to_string(),
+                content: "again".to_string(),
+            },
+        ];
+        assert_eq!(provider.chat(messages, 8).await.unwrap(), "ok");
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /messages "));
+        let json: serde_json::Value =
+            serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(json["system"], "Answer briefly.");
+        let roles: Vec<&str> = json["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, ["user", "assistant", "user"]);
+    }
+
+    #[tokio::test]
+    async fn test_json_mode_requests() {
+        let schema =
+            serde_json::json!({"type": "object", "properties": {"tags": {"type": "array"}}});
+        let messages = || {
+            vec![ChatMessage {
+                role: "user".to_string(),
+                content: "Tag this as JSON".to_string(),
+            }]
+        };
+
+        let body = r#"{"message":{"role":"assistant","content":"{\"tags\":[]}"},"done":true}"#;
+        let (host, request) = serve_once_capturing("application/json", body.to_string()).await;
+        let provider = OllamaProvider::new(Some(host), None, None);
+        let reply = provider.chat_json(messages(), 64, &schema).await.unwrap();
+        assert_eq!(reply, r#"{"tags":[]}"#);
+        let request = request.await.unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(json["format"], schema);
+
+        let body = r#"{"choices":[{"message":{"role":"assistant","content":"{}"}}]}"#;
+        let (base_url, request) = serve_once_capturing("application/json", body.to_string()).await;
+        let provider = OpenAIProvider::new(String::new(), None, Some(base_url));
+        assert_eq!(
+            provider.chat_json(messages(), 64, &schema).await.unwrap(),
+            "{}"
+        );
+        let request = request.await.unwrap();
+        assert!(request.contains(r#""response_format":{"type":"json_object"}"#));
+    }
+
+    /// Fails with `status` for the first `failures` calls, then answers.
+    struct Flaky {
+        status: u16,
+        failures: u32,
+        calls: std::sync::atomic::AtomicU32,
+    }
+
+    #[async_trait]
+    impl LlmProviderTrait for Flaky {
+        async fn generate(&self, _prompt: &str, _max_tokens: u32) -> Result<String> {
+            unreachable!()
+        }
+
+        async fn chat(&self, _messages: Vec<ChatMessage>, _max_tokens: u32) -> Result<String> {
+            let call = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if call < self.failures {
+                return Err(ProviderError::Status {
+                    provider: "Flaky",
+                    status: self.status,
+                    body: String::new(),
+                }
+                .into());
+            }
+            Ok(format!("answer after {}", call))
+        }
+
+        fn name(&self) -> &'static str {
+            "Flaky"
+        }
+    }
+
+    fn flaky(status: u16, failures: u32) -> Box<Flaky> {
+        Box::new(Flaky {
+            status,
+            failures,
+            calls: Default::default(),
+        })
+    }
+
+    fn quick_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_retries_then_falls_back() {
+        let mut chain = FallbackChain::new(quick_policy(1));
+        // Rate limited twice: one retry isn't enough
+        chain.push("anthropic", flaky(429, 2));
+        chain.push("groq", flaky(503, 1));
+        let response = chain.generate("hi", 8, None).await.unwrap();
+        assert_eq!(response.provider, "groq");
+        assert_eq!(response.text, "answer after 1");
+    }
+
+    #[tokio::test]
+    async fn test_chain_skips_non_retryable_errors() {
+        let mut chain = FallbackChain::new(quick_policy(3));
+        chain.push("openai", flaky(401, 1));
+        chain.push("ollama", flaky(500, 0));
+        let response = chain.generate("hi", 8, None).await.unwrap();
+        assert_eq!(response.provider, "ollama");
+
+        let mut chain = FallbackChain::new(quick_policy(0));
+        chain.push("openai", flaky(400, 5));
+        let err = chain.generate("hi", 8, None).await.unwrap_err();
+        assert!(err.to_string().contains("openai: Flaky API error: 400"));
+    }
+
+    #[tokio::test]
+    async fn test_chain_streams_from_the_answering_provider() {
+        let mut chain = FallbackChain::new(quick_policy(0));
+        chain.push("first", flaky(502, 1));
+        chain.push("second", flaky(502, 0));
+        let (tokens, mut received) = mpsc::unbounded_channel();
+        let response = chain.generate("hi", 8, Some(tokens)).await.unwrap();
+        assert_eq!(response.provider, "second");
+        assert_eq!(received.recv().await.as_deref(), Some("answer after 0"));
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0).as_millis(), 500);
+        assert_eq!(policy.backoff(2).as_millis(), 2000);
+        assert_eq!(policy.backoff(10).as_millis(), 8000);
+        assert_eq!(policy.backoff(70).as_millis(), 8000);
+    }
 }
@@ -1,33 +1,53 @@
 use anyhow::Result;
+use std::collections::HashSet;
 use std::path::Path;
 
-use super::llm::AsyncLlmEngine;
-use super::search::{AsyncSearchIndex, NoteDocument, SearchResult};
+use super::llm::{AsyncLlmEngine, SamplingParams};
+use super::providers::{ChatMessage, TokenSink};
+use super::rerank::{AsyncReranker, RERANK_CANDIDATES};
+use super::search::{AsyncSearchIndex, IndexStats, NoteDocument, SearchOptions, SearchResult};
+use super::tokens::{self, TokenCounter, DEFAULT_LOCAL_CONTEXT};
+use crate::jobs::Progress;
+use crate::rpc::RagTokenUsage;
+use crate::utils::text::truncate;
+
+/// Most tokens of one passage put in a prompt, so a long note can't crowd
+/// out the others
+const PASSAGE_TOKENS: usize = 512;
+/// Answer length for local RAG queries
+pub const LOCAL_ANSWER_TOKENS: u32 = 512;
+const PASSAGE_SEPARATOR: &str = "\n\n---\n\n";
 
 pub struct RagPipeline {
     search_index: AsyncSearchIndex,
+    reranker: Option<AsyncReranker>,
     llm_engine: Option<AsyncLlmEngine>,
     model_path: Option<String>,
+    sampling: SamplingParams,
 }
 
 impl RagPipeline {
-    pub fn new(index_path: &Path) -> Result<Self> {
-        let search_index = AsyncSearchIndex::new(index_path)?;
+    pub fn new(index_path: &Path, embedding_model: &str) -> Result<Self> {
+        let search_index = AsyncSearchIndex::new(index_path, embedding_model)?;
 
         Ok(Self {
             search_index,
+            reranker: None,
             llm_engine: None,
             model_path: None,
+            sampling: SamplingParams::default(),
         })
     }
 
-    pub fn new_in_memory() -> Result<Self> {
-        let search_index = AsyncSearchIndex::new_in_memory()?;
+    pub fn new_in_memory(embedding_model: &str) -> Result<Self> {
+        let search_index = AsyncSearchIndex::new_in_memory(embedding_model)?;
 
         Ok(Self {
             search_index,
+            reranker: None,
             llm_engine: None,
             model_path: None,
+            sampling: SamplingParams::default(),
         })
     }
 
@@ -44,79 +64,202 @@ impl RagPipeline {
         Ok(())
     }
 
-    pub async fn index_notes(&self, notes: Vec<NoteDocument>) -> Result<()> {
-        self.search_index.index_documents(notes).await
+    /// Re-embeds the index with another model; see
+    /// [`AsyncSearchIndex::set_embedding_model`].
+    pub async fn set_embedding_model(&self, model_id: &str, progress: &Progress) -> Result<bool> {
+        self.search_index
+            .set_embedding_model(model_id, progress)
+            .await
+    }
+
+    /// Handle on the search index that outlives a lock on the pipeline.
+    pub fn search_index(&self) -> AsyncSearchIndex {
+        self.search_index.clone()
+    }
+
+    pub async fn embedding_model(&self) -> Option<String> {
+        self.search_index.embedding_model().await
+    }
+
+    /// Embeds passages outside the index, e.g. saved articles.
+    pub async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.search_index.embed(texts).await
+    }
+
+    /// Loads the reranker applied by [`search_with`](Self::search_with), or
+    /// turns reranking off with `None`.
+    pub fn set_reranker(&mut self, model_id: Option<&str>) -> Result<()> {
+        self.reranker = match model_id {
+            Some(id) if self.reranker_model() == super::rerank::resolve_reranker(id) => {
+                return Ok(())
+            }
+            Some(id) => Some(AsyncReranker::new(id)?),
+            None => None,
+        };
+        Ok(())
+    }
+
+    pub fn reranker_model(&self) -> Option<&'static str> {
+        self.reranker.as_ref().map(|r| r.model_id())
+    }
+
+    pub async fn index_notes(&self, notes: Vec<NoteDocument>, force: bool) -> Result<IndexStats> {
+        self.search_index.index_documents(notes, force).await
     }
 
     pub async fn index_note(&self, note: NoteDocument) -> Result<()> {
         self.search_index.index_document(note).await
     }
 
+    pub async fn remove_notes(&self, ids: &[String], paths: &[String]) -> usize {
+        self.search_index.remove_documents(ids, paths).await
+    }
+
+    /// Drops every note not in `keep`.
+    pub async fn retain_notes(&self, keep: &HashSet<String>) -> usize {
+        self.search_index.retain_documents(keep).await
+    }
+
+    /// Persists the search index, if it has a directory.
+    pub async fn save_index(&self) -> Result<()> {
+        self.search_index.save().await
+    }
+
+    /// Hybrid search without reranking.
     pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
         self.search_index.hybrid_search(query, limit).await
     }
 
-    pub async fn query(&self, question: &str, top_k: usize) -> Result<RagResponse> {
-        let search_results = self.search_index.hybrid_search(question, top_k).await?;
-
-        let context = search_results
-            .iter()
-            .enumerate()
-            .map(|(i, r)| {
-                format!(
-                    "[{}] {}\n{}",
-                    i + 1,
-                    r.title,
-                    truncate_content(&r.content, 1000)
-                )
-            })
-            .collect::<Vec<_>>()
-            .join("\n\n---\n\n");
+    /// Searches with `options`, then reorders the top candidates with the
+    /// reranker when one is loaded. A failing reranker leaves the search
+    /// order as it was.
+    pub async fn search_with(
+        &self,
+        query: &str,
+        limit: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let reranker = self
+            .reranker
+            .as_ref()
+            .filter(|_| options.rerank != Some(false));
+        let Some(reranker) = reranker else {
+            return self.search_index.search(query, limit, options).await;
+        };
 
-        let prompt = format!(
-            r#"You are a helpful assistant that answers questions based on the user's notes.
+        let mut candidates = self
+            .search_index
+            .search(query, limit.max(RERANK_CANDIDATES), options)
+            .await?;
+        match reranker.rerank(query, candidates.clone(), limit).await {
+            Ok(reranked) => Ok(reranked),
+            Err(e) => {
+                tracing::warn!("Reranking failed, keeping search order: {}", e);
+                candidates.truncate(limit);
+                Ok(candidates)
+            }
+        }
+    }
 
-Context from relevant notes:
-{}
+    pub async fn query(&self, question: &str, top_k: usize) -> Result<RagResponse> {
+        self.run_query(question, top_k, &[], None).await
+    }
 
-Question: {}
+    /// Like [`query`](Self::query), streaming the answer to `tokens`.
+    pub async fn query_stream(
+        &self,
+        question: &str,
+        top_k: usize,
+        tokens: TokenSink,
+    ) -> Result<RagResponse> {
+        self.run_query(question, top_k, &[], Some(tokens)).await
+    }
 
-Instructions:
-- Answer based ONLY on the provided context
-- If the context doesn't contain relevant information, say so
-- Reference the source notes by their numbers [1], [2], etc.
-- Be concise and direct
+    /// Like [`query`](Self::query), with caller-supplied passages placed
+    /// ahead of the retrieved notes. They are only part of this prompt and
+    /// never enter the index.
+    pub async fn query_with_context(
+        &self,
+        question: &str,
+        top_k: usize,
+        context: &[String],
+        tokens: Option<TokenSink>,
+    ) -> Result<RagResponse> {
+        self.run_query(question, top_k, context, tokens).await
+    }
 
-Answer:"#,
-            context, question
+    async fn run_query(
+        &self,
+        question: &str,
+        top_k: usize,
+        extra_context: &[String],
+        tokens: Option<TokenSink>,
+    ) -> Result<RagResponse> {
+        let search_results = self
+            .search_with(question, top_k, &SearchOptions::default())
+            .await?;
+
+        let provided = extra_context
+            .iter()
+            .enumerate()
+            .map(|(i, text)| (format!("Context {}", i + 1), text.as_str()));
+        let retrieved = search_results
+            .iter()
+            .map(|r| (r.title.clone(), r.passage()));
+        let passages: Vec<(String, &str)> = provided.chain(retrieved).collect();
+
+        let (counter, context_window) = self.token_budget().await;
+        let packed = pack_prompt(
+            question,
+            &passages,
+            &counter,
+            context_window,
+            LOCAL_ANSWER_TOKENS,
         );
+        let answer = self
+            .generate(&packed.prompt, packed.usage.answer_tokens as u32, tokens)
+            .await?;
 
-        let answer = if let Some(ref engine) = self.llm_engine {
-            engine.generate(&prompt, 512).await?
-        } else {
-            let engine = AsyncLlmEngine::new()?;
-            engine.generate(&prompt, 512).await?
-        };
-
-        let sources: Vec<RagSource> = search_results
+        let sources: Vec<RagSource> = packed
+            .included
             .iter()
+            .filter_map(|&i| search_results.get(i.checked_sub(extra_context.len())?))
             .map(|r| RagSource {
                 id: r.id.clone(),
                 title: r.title.clone(),
                 path: r.path.clone(),
                 score: r.score,
-                snippet: truncate_content(&r.content, 200),
+                snippet: truncate(r.passage(), 200),
             })
             .collect();
 
         Ok(RagResponse {
             answer,
             sources,
-            context_used: context,
+            context_used: packed.context,
+            usage: packed.usage,
         })
     }
 
     pub async fn summarize(&self, text: &str, max_words: usize) -> Result<String> {
+        self.run_summarize(text, max_words, None).await
+    }
+
+    pub async fn summarize_stream(
+        &self,
+        text: &str,
+        max_words: usize,
+        tokens: TokenSink,
+    ) -> Result<String> {
+        self.run_summarize(text, max_words, Some(tokens)).await
+    }
+
+    async fn run_summarize(
+        &self,
+        text: &str,
+        max_words: usize,
+        tokens: Option<TokenSink>,
+    ) -> Result<String> {
         let prompt = format!(
             r#"Summarize the following text in approximately {} words. Be concise and capture the main points.
 
@@ -127,54 +270,55 @@ Summary:"#,
             max_words, text
         );
 
-        if let Some(ref engine) = self.llm_engine {
-            engine.generate(&prompt, (max_words * 2) as u32).await
-        } else {
-            let engine = AsyncLlmEngine::new()?;
-            engine.generate(&prompt, (max_words * 2) as u32).await
-        }
+        self.generate(&prompt, (max_words * 2) as u32, tokens).await
     }
 
-    pub async fn detect_chapters(&self, transcript: &str) -> Result<Vec<Chapter>> {
-        let prompt = format!(
-            r#"Analyze this video transcript and identify major topic changes to create chapter markers.
-Output format: One chapter per line in the format "TIMESTAMP_SECONDS|CHAPTER_TITLE"
-Example: "0|Introduction" or "120|Main Topic"
-
-Transcript (first 8000 chars):
-{}
+    /// Token counter and context length of the local engine's model.
+    pub async fn token_budget(&self) -> (TokenCounter, usize) {
+        match self.llm_engine {
+            Some(ref engine) => engine.token_budget().await,
+            None => (TokenCounter::Estimate, DEFAULT_LOCAL_CONTEXT),
+        }
+    }
 
-Chapters:"#,
-            truncate_content(transcript, 8000)
-        );
+    /// Sampling used for every local generation.
+    pub fn set_sampling(&mut self, sampling: SamplingParams) {
+        self.sampling = sampling;
+    }
 
-        let response = if let Some(ref engine) = self.llm_engine {
-            engine.generate(&prompt, 256).await?
-        } else {
-            let engine = AsyncLlmEngine::new()?;
-            engine.generate(&prompt, 256).await?
-        };
+    /// Answers a single prompt with the local engine.
+    pub async fn generate(
+        &self,
+        prompt: &str,
+        max_tokens: u32,
+        tokens: Option<TokenSink>,
+    ) -> Result<String> {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        self.chat(messages, max_tokens, tokens).await
+    }
 
-        let mut chapters = Vec::new();
-        for line in response.lines() {
-            if let Some((ts_str, title)) = line.split_once('|') {
-                if let Ok(timestamp) = ts_str.trim().parse::<f64>() {
-                    chapters.push(Chapter {
-                        start: timestamp,
-                        title: title.trim().to_string(),
-                    });
-                }
+    /// Runs the loaded engine, or a fresh one that auto-loads the default
+    /// model, on a conversation.
+    pub async fn chat(
+        &self,
+        messages: Vec<ChatMessage>,
+        max_tokens: u32,
+        tokens: Option<TokenSink>,
+    ) -> Result<String> {
+        let fallback;
+        let engine = match self.llm_engine {
+            Some(ref engine) => engine,
+            None => {
+                fallback = AsyncLlmEngine::new()?;
+                &fallback
             }
-        }
-
-        if chapters.is_empty() {
-            chapters.push(Chapter {
-                start: 0.0,
-                title: "Introduction".to_string(),
-            });
-        }
-
-        Ok(chapters)
+        };
+        engine
+            .chat(messages, max_tokens, self.sampling.clone(), tokens)
+            .await
     }
 
     async fn fallback_generate(&self, _prompt: &str) -> Result<String> {
@@ -186,11 +330,84 @@ Chapters:"#,
     }
 }
 
-fn truncate_content(content: &str, max_chars: usize) -> String {
-    if content.len() <= max_chars {
-        content.to_string()
-    } else {
-        format!("{}...", &content[..max_chars])
+fn rag_prompt(context: &str, question: &str) -> String {
+    format!(
+        r#"You are a helpful assistant that answers questions based on the user's notes.
+
+Context from relevant notes:
+{}
+
+Question: {}
+
+Instructions:
+- Answer based ONLY on the provided context
+- If the context doesn't contain relevant information, say so
+- Reference the source notes by their numbers [1], [2], etc.
+- Be concise and direct
+
+Answer:"#,
+        context, question
+    )
+}
+
+/// A RAG prompt fitted into a context window.
+pub struct PackedPrompt {
+    pub prompt: String,
+    /// The numbered passages as they appear in the prompt
+    pub context: String,
+    /// Indices of the passages that made it in, in prompt order
+    pub included: Vec<usize>,
+    pub usage: RagTokenUsage,
+}
+
+/// Builds the RAG prompt for `question` from `(title, text)` passages in
+/// rank order, keeping as many as fit in `context_window` next to the
+/// instructions and `answer_tokens` of answer. Passages are cut to
+/// [`PASSAGE_TOKENS`], and the last one that fits may be cut shorter.
+pub fn pack_prompt(
+    question: &str,
+    passages: &[(String, &str)],
+    counter: &TokenCounter,
+    context_window: usize,
+    answer_tokens: u32,
+) -> PackedPrompt {
+    let overhead = counter.count(&rag_prompt("", question));
+    let headers: usize = passages
+        .iter()
+        .enumerate()
+        .map(|(i, (title, _))| {
+            counter.count(&format!("[{}] {}\n", i + 1, title)) + counter.count(PASSAGE_SEPARATOR)
+        })
+        .sum();
+    // Small windows give the answer no more than half
+    let answer_tokens = (answer_tokens as usize).min(context_window / 2);
+    let budget = context_window.saturating_sub(overhead + headers + answer_tokens);
+
+    let texts: Vec<&str> = passages.iter().map(|(_, text)| *text).collect();
+    let packed = tokens::pack(counter, &texts, budget, PASSAGE_TOKENS);
+    let context = packed
+        .iter()
+        .enumerate()
+        .map(|(i, p)| format!("[{}] {}\n{}", i + 1, passages[p.index].0, p.text))
+        .collect::<Vec<_>>()
+        .join(PASSAGE_SEPARATOR);
+    let prompt = rag_prompt(&context, question);
+    let prompt_tokens = counter.count(&prompt);
+
+    PackedPrompt {
+        usage: RagTokenUsage {
+            context_window,
+            prompt_tokens,
+            context_tokens: prompt_tokens.saturating_sub(overhead),
+            answer_tokens: context_window
+                .saturating_sub(prompt_tokens)
+                .min(answer_tokens),
+            exact: counter.is_exact(),
+            sources_dropped: passages.len() - packed.len(),
+        },
+        included: packed.iter().map(|p| p.index).collect(),
+        prompt,
+        context,
     }
 }
 
@@ -199,6 +416,7 @@ pub struct RagResponse {
     pub answer: String,
     pub sources: Vec<RagSource>,
     pub context_used: String,
+    pub usage: RagTokenUsage,
 }
 
 #[derive(Debug, Clone)]
@@ -209,9 +427,3 @@ pub struct RagSource {
     pub score: f32,
     pub snippet: String,
 }
-
-#[derive(Debug, Clone)]
-pub struct Chapter {
-    pub start: f64,
-    pub title: String,
-}
@@ -0,0 +1,211 @@
+//! Optional cross-encoder reranking of search candidates.
+//!
+//! Hybrid search ranks by term overlap and vector closeness, which lets a
+//! note that shares the query's words outrank one that answers it. A
+//! cross-encoder reads the query and each passage together and scores how
+//! well the passage answers it; it is too slow for the whole vault, so it
+//! only reorders the top candidates.
+
+use anyhow::{bail, Context, Result};
+use fastembed::{RerankInitOptions, RerankerModel, TextRerank};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use super::search::SearchResult;
+
+/// Candidates rescored when fewer results are asked for
+pub const RERANK_CANDIDATES: usize = 20;
+
+struct RerankerSpec {
+    id: &'static str,
+    model: RerankerModel,
+    description: &'static str,
+}
+
+const RERANKERS: &[RerankerSpec] = &[
+    RerankerSpec {
+        id: "BAAI/bge-reranker-base",
+        model: RerankerModel::BGERerankerBase,
+        description: "English and Chinese",
+    },
+    RerankerSpec {
+        id: "BAAI/bge-reranker-v2-m3",
+        model: RerankerModel::BGERerankerV2M3,
+        description: "Multilingual",
+    },
+    RerankerSpec {
+        id: "jinaai/jina-reranker-v1-turbo-en",
+        model: RerankerModel::JINARerankerV1TurboEn,
+        description: "English, fast",
+    },
+    RerankerSpec {
+        id: "jinaai/jina-reranker-v2-base-multilingual",
+        model: RerankerModel::JINARerankerV2BaseMultiligual,
+        description: "Multilingual",
+    },
+];
+
+fn find_spec(name: &str) -> Option<&'static RerankerSpec> {
+    let name = name.trim();
+    RERANKERS.iter().find(|spec| {
+        let short = spec.id.rsplit('/').next().unwrap_or(spec.id);
+        spec.id.eq_ignore_ascii_case(name) || short.eq_ignore_ascii_case(name)
+    })
+}
+
+/// Finds a reranker by full id or by the name after the organisation,
+/// ignoring case. Returns its full id.
+pub fn resolve_reranker(name: &str) -> Option<&'static str> {
+    find_spec(name).map(|spec| spec.id)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RerankerInfo {
+    pub id: String,
+    pub description: String,
+}
+
+pub fn supported_rerankers() -> Vec<RerankerInfo> {
+    RERANKERS
+        .iter()
+        .map(|spec| RerankerInfo {
+            id: spec.id.to_string(),
+            description: spec.description.to_string(),
+        })
+        .collect()
+}
+
+pub struct Reranker {
+    model: TextRerank,
+    id: &'static str,
+}
+
+impl Reranker {
+    pub fn new(model_id: &str) -> Result<Self> {
+        let Some(spec) = find_spec(model_id) else {
+            bail!("Unknown reranker model: {}", model_id);
+        };
+        let model = TextRerank::try_new(RerankInitOptions::new(spec.model.clone()))
+            .context("Failed to initialize reranker model")?;
+        Ok(Self { model, id: spec.id })
+    }
+
+    /// Scores each passage against `query`, in the order given.
+    pub fn score(&self, query: &str, passages: &[&str]) -> Result<Vec<f32>> {
+        let results = self
+            .model
+            .rerank(query, passages.to_vec(), false, None)
+            .context("Failed to rerank passages")?;
+        let mut scores = vec![f32::MIN; passages.len()];
+        for result in results {
+            if let Some(score) = scores.get_mut(result.index) {
+                *score = result.score;
+            }
+        }
+        Ok(scores)
+    }
+
+    pub fn model_id(&self) -> &'static str {
+        self.id
+    }
+}
+
+pub struct AsyncReranker {
+    inner: Arc<Mutex<Reranker>>,
+    model_id: &'static str,
+}
+
+impl AsyncReranker {
+    pub fn new(model_id: &str) -> Result<Self> {
+        let reranker = Reranker::new(model_id)?;
+        Ok(Self {
+            model_id: reranker.model_id(),
+            inner: Arc::new(Mutex::new(reranker)),
+        })
+    }
+
+    /// Reorders `results` by relevance to `query` and keeps the best `limit`.
+    /// Scores become the reranker's relevance, squashed into 0..1.
+    pub async fn rerank(
+        &self,
+        query: &str,
+        results: Vec<SearchResult>,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        if results.is_empty() {
+            return Ok(results);
+        }
+        let passages: Vec<String> = results
+            .iter()
+            .map(|r| format!("{}\n{}", r.title, r.passage()))
+            .collect();
+        let passage_refs: Vec<&str> = passages.iter().map(String::as_str).collect();
+        let scores = {
+            let reranker = self.inner.lock().await;
+            reranker.score(query, &passage_refs)?
+        };
+        Ok(apply_scores(results, &scores, limit))
+    }
+
+    pub fn model_id(&self) -> &'static str {
+        self.model_id
+    }
+}
+
+/// Sorts `results` by `scores` (raw cross-encoder logits), keeping the
+/// earlier rank on ties, and truncates to `limit`.
+fn apply_scores(results: Vec<SearchResult>, scores: &[f32], limit: usize) -> Vec<SearchResult> {
+    let mut scored: Vec<(SearchResult, f32)> =
+        results.into_iter().zip(scores.iter().copied()).collect();
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(mut result, logit)| {
+            result.score = 1.0 / (1.0 + (-logit).exp());
+            result
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(id: &str, score: f32) -> SearchResult {
+        SearchResult {
+            id: id.to_string(),
+            title: id.to_string(),
+            content: String::new(),
+            path: format!("{}.md", id),
+            score,
+            chunk: None,
+        }
+    }
+
+    #[test]
+    fn test_scores_reorder_and_truncate() {
+        let results = vec![result("a", 1.0), result("b", 0.9), result("c", 0.8)];
+        let reranked = apply_scores(results, &[-2.0, 3.0, -2.0], 2);
+
+        let ids: Vec<&str> = reranked.iter().map(|r| r.id.as_str()).collect();
+        // Equal logits keep the fused order
+        assert_eq!(ids, vec!["b", "a"]);
+        assert!(reranked[0].score > 0.9 && reranked[0].score < 1.0);
+        assert!(reranked[1].score < 0.5);
+    }
+
+    #[test]
+    fn test_resolve_reranker() {
+        assert_eq!(
+            resolve_reranker("BGE-Reranker-Base"),
+            Some("BAAI/bge-reranker-base")
+        );
+        assert_eq!(
+            resolve_reranker("jinaai/jina-reranker-v1-turbo-en"),
+            Some("jinaai/jina-reranker-v1-turbo-en")
+        );
+        assert!(resolve_reranker("cross-encoder/unknown").is_none());
+    }
+}
@@ -1,16 +1,26 @@
-use anyhow::{Context, Result};
-use std::path::Path;
+use anyhow::{bail, Context, Result};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use super::embeddings::{cosine_similarity, AsyncEmbeddingEngine};
+use super::chunking::{self, Chunk, CHUNKER_ID};
+use super::embeddings::{cosine_similarity, resolve_model, AsyncEmbeddingEngine};
+use super::index_store::{self, StoredIndex};
+use super::keyword::{KeywordIndex, Query};
+use super::INDEX_BATCH_SIZE;
+use crate::dataview;
+use crate::jobs::Progress;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct NoteDocument {
     pub id: String,
     pub title: String,
     pub content: String,
     pub path: String,
+    #[serde(skip)]
     pub embedding: Option<Vec<f32>>,
 }
 
@@ -21,217 +31,898 @@ pub struct SearchResult {
     pub content: String,
     pub path: String,
     pub score: f32,
+    /// Part of the note that matched best
+    pub chunk: Option<Chunk>,
+}
+
+impl SearchResult {
+    /// The best-matching chunk's text, or the whole note without one.
+    pub fn passage(&self) -> &str {
+        self.chunk.as_ref().map_or(&self.content, |c| &c.text)
+    }
+}
+
+/// Which rankings a search uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    /// BM25 only; scores are raw BM25 scores
+    Keyword,
+    /// Embedding similarity only; scores are cosine similarities
+    Semantic,
+    /// Both rankings fused by reciprocal rank; scores are 0..=1, with 1 for a
+    /// note ranked first by both
+    #[default]
+    Hybrid,
+}
+
+/// Per-request ranking and filters.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    pub mode: SearchMode,
+    /// Weight of the semantic ranking against the keyword ranking in hybrid
+    /// mode, from 0 (keyword only) to 1 (semantic only); 0.5 when unset
+    #[serde(deserialize_with = "finite")]
+    pub alpha: Option<f32>,
+    /// Drops results scoring below this, on the scale of `mode`
+    #[serde(deserialize_with = "finite")]
+    pub min_score: Option<f32>,
+    /// Only notes whose path starts with this
+    pub path_prefix: Option<String>,
+    /// Only notes with all of these tags; `project` also matches `project/a`
+    pub tags: Vec<String>,
+    /// Only notes whose frontmatter has these values; a list field matches
+    /// when it contains the value
+    pub frontmatter: HashMap<String, serde_json::Value>,
+    /// Rescore the top candidates with the configured reranker, see
+    /// [`super::rerank`]; on whenever one is configured unless set to false.
+    /// `min_score` still applies to the scores before reranking.
+    pub rerank: Option<bool>,
+}
+
+/// Rejects numbers that overflow `f32`, which would slip past clamping and
+/// score comparisons.
+fn finite<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    match Option::<f32>::deserialize(deserializer)? {
+        Some(value) if !value.is_finite() => Err(de::Error::custom(format!(
+            "expected a finite number, got {}",
+            value
+        ))),
+        value => Ok(value),
+    }
+}
+
+/// Rank constant of reciprocal rank fusion; damps the difference between
+/// the first few ranks.
+const RRF_K: f32 = 60.0;
+/// Candidates taken from each ranking before fusing, at least
+const MIN_FUSION_CANDIDATES: usize = 30;
+
+/// Fuses keyword and semantic rankings by weighted reciprocal rank, scaled so
+/// a note ranked first in both scores 1.
+fn fuse(keyword: Vec<SearchResult>, semantic: Vec<SearchResult>, alpha: f32) -> Vec<SearchResult> {
+    let mut fused: HashMap<String, SearchResult> = HashMap::new();
+    let rank_score =
+        |weight: f32, rank: usize| weight * (RRF_K + 1.0) / (RRF_K + rank as f32 + 1.0);
+
+    for (rank, mut result) in keyword.into_iter().enumerate() {
+        result.score = rank_score(1.0 - alpha, rank);
+        fused.insert(result.id.clone(), result);
+    }
+    // The semantically closest chunk makes the better snippet
+    for (rank, mut result) in semantic.into_iter().enumerate() {
+        let score = rank_score(alpha, rank);
+        fused
+            .entry(result.id.clone())
+            .and_modify(|e| {
+                e.score += score;
+                e.chunk = result.chunk.take();
+            })
+            .or_insert_with(|| SearchResult { score, ..result });
+    }
+
+    let mut results: Vec<SearchResult> = fused.into_values().collect();
+    results.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    results
+}
+
+/// Compares frontmatter values loosely, so `2024` matches `"2024"` and
+/// `Draft` matches `draft`.
+fn frontmatter_matches(actual: &serde_json::Value, expected: &serde_json::Value) -> bool {
+    use serde_json::Value;
+    match (actual, expected) {
+        (_, Value::Array(wanted)) => wanted.iter().all(|w| frontmatter_matches(actual, w)),
+        (Value::Array(items), _) => items.iter().any(|item| frontmatter_matches(item, expected)),
+        _ => {
+            let text = |v: &Value| match v {
+                Value::String(s) => s.to_lowercase(),
+                other => other.to_string(),
+            };
+            text(actual) == text(expected)
+        }
+    }
+}
+
+/// Outcome of an upsert, by document.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IndexStats {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+impl IndexStats {
+    pub fn changed(&self) -> bool {
+        self.added + self.updated > 0
+    }
+}
+
+/// Hash of the text a document is embedded from; equal hashes mean the
+/// stored vectors are still valid.
+fn content_hash(doc: &NoteDocument) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(doc.title.as_bytes());
+    hasher.update([0]);
+    hasher.update(doc.content.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Chunks are embedded with the note title and their headings, which often
+/// carry the topic the chunk text only implies.
+fn embedding_text(doc: &NoteDocument, chunk: &Chunk) -> String {
+    if chunk.heading_path.is_empty() {
+        format!("{}\n{}", doc.title, chunk.text)
+    } else {
+        format!(
+            "{}\n{}\n{}",
+            doc.title,
+            chunk.heading_path.join(" > "),
+            chunk.text
+        )
+    }
+}
+
+/// Persisted key of a chunk vector: `<document id>#<chunk index>`.
+fn chunk_key(doc_id: &str, chunk: usize) -> String {
+    format!("{}#{}", doc_id, chunk)
+}
+
+fn parse_chunk_key(key: &str) -> Option<(&str, usize)> {
+    let (doc_id, chunk) = key.rsplit_once('#')?;
+    Some((doc_id, chunk.parse().ok()?))
+}
+
+/// Vector of one chunk of a document.
+#[derive(Clone, Debug, PartialEq)]
+struct ChunkVector {
+    doc_id: String,
+    chunk: usize,
+    vector: Vec<f32>,
+}
+
+/// A chunk waiting for a vector, with the text it is embedded from.
+struct PendingChunk {
+    doc_id: String,
+    chunk: usize,
+    text: String,
+}
+
+/// Frontmatter `tags` plus inline `#tags`, lowercased and without `#`.
+fn note_tags(content: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    if let Ok(frontmatter) = dataview::extract_frontmatter(content) {
+        for key in ["tags", "tag"] {
+            match frontmatter.get(key) {
+                Some(serde_json::Value::Array(items)) => {
+                    tags.extend(items.iter().filter_map(|v| v.as_str()).map(str::to_string))
+                }
+                Some(serde_json::Value::String(value)) => {
+                    tags.extend(value.split([',', ' ']).map(str::to_string))
+                }
+                _ => {}
+            }
+        }
+    }
+    tags.extend(dataview::extract_tags(content));
+
+    let mut seen = HashSet::new();
+    tags.into_iter()
+        .map(|t| t.trim().trim_start_matches('#').to_lowercase())
+        .filter(|t| !t.is_empty() && seen.insert(t.clone()))
+        .collect()
+}
+
+/// Data derived from a document's content. Rebuilt on load rather than
+/// stored, since it follows from the content alone.
+struct Derived {
+    hash: String,
+    chunks: Vec<Chunk>,
+    tags: Vec<String>,
+    frontmatter: HashMap<String, serde_json::Value>,
+}
+
+impl Derived {
+    fn of(doc: &NoteDocument) -> Self {
+        Self {
+            hash: content_hash(doc),
+            chunks: chunking::split_markdown(&doc.content),
+            tags: note_tags(&doc.content),
+            frontmatter: dataview::extract_frontmatter(&doc.content).unwrap_or_default(),
+        }
+    }
+}
+
+/// Subdirectory of the index holding the embedding cache
+const EMBEDDING_CACHE_DIR: &str = "embedding_cache";
+
+/// Loads the embedding model; without one the index still does keyword search.
+fn load_engine(model_id: &str, cache_dir: Option<&Path>) -> Option<AsyncEmbeddingEngine> {
+    match AsyncEmbeddingEngine::new(model_id, cache_dir) {
+        Ok(engine) => Some(engine),
+        Err(e) => {
+            tracing::warn!("Embedding model {} unavailable: {}", model_id, e);
+            None
+        }
+    }
 }
 
 pub struct SearchIndex {
     documents: Vec<NoteDocument>,
-    embeddings: Vec<(String, Vec<f32>)>,
+    embeddings: Vec<ChunkVector>,
+    /// Hash, chunks and metadata per document id
+    derived: HashMap<String, Derived>,
+    keyword: KeywordIndex,
     embedding_engine: Option<AsyncEmbeddingEngine>,
+    /// Directory the index is persisted to; `None` keeps it in memory
+    index_path: Option<PathBuf>,
+    /// Model, dimension and chunking of the vectors in `embeddings`
+    model_id: Option<String>,
+    dimension: usize,
+    chunker: Option<String>,
 }
 
 impl SearchIndex {
-    pub fn new(_index_path: &Path) -> Result<Self> {
-        let embedding_engine = AsyncEmbeddingEngine::new().ok();
+    /// Opens the index persisted in `index_path`, or starts an empty one there,
+    /// embedding with `model_id`.
+    pub fn new(index_path: &Path, model_id: &str) -> Result<Self> {
+        let cache_dir = index_path.join(EMBEDDING_CACHE_DIR);
+        let embedding_engine = load_engine(model_id, Some(&cache_dir));
+        Self::open(Some(index_path), embedding_engine)
+    }
 
-        Ok(Self {
+    pub fn new_in_memory(model_id: &str) -> Result<Self> {
+        let embedding_engine = load_engine(model_id, None);
+        Self::open(None, embedding_engine)
+    }
+
+    fn open(
+        index_path: Option<&Path>,
+        embedding_engine: Option<AsyncEmbeddingEngine>,
+    ) -> Result<Self> {
+        let mut index = Self {
             documents: Vec::new(),
             embeddings: Vec::new(),
+            derived: HashMap::new(),
+            keyword: KeywordIndex::new(),
+            model_id: embedding_engine.as_ref().map(|e| e.model_id().to_string()),
+            dimension: embedding_engine.as_ref().map_or(0, |e| e.dimension()),
+            chunker: Some(CHUNKER_ID.to_string()),
             embedding_engine,
-        })
+            index_path: index_path.map(Path::to_path_buf),
+        };
+
+        let stored = match index_path {
+            Some(path) => index_store::load(path)?,
+            None => None,
+        };
+        if let Some(stored) = stored {
+            let can_embed = index.embedding_engine.is_some();
+            index.restore(stored, can_embed);
+        }
+        Ok(index)
     }
 
-    pub fn new_in_memory() -> Result<Self> {
-        let embedding_engine = AsyncEmbeddingEngine::new().ok();
+    fn restore(&mut self, stored: StoredIndex, can_embed: bool) {
+        self.documents = stored.documents;
+        self.derived = self
+            .documents
+            .iter()
+            .map(|d| (d.id.clone(), Derived::of(d)))
+            .collect();
+        self.keyword.clear();
+        for doc in &self.documents {
+            self.keyword.insert(
+                &doc.id,
+                &doc.title,
+                &self.derived[&doc.id].tags,
+                &doc.content,
+            );
+        }
 
-        Ok(Self {
-            documents: Vec::new(),
-            embeddings: Vec::new(),
-            embedding_engine,
-        })
+        // Keep only vectors that still map onto a chunk; this also drops the
+        // whole-note vectors of indexes from before chunking
+        let vectors: Vec<ChunkVector> = stored
+            .embeddings
+            .into_iter()
+            .filter_map(|(key, vector)| {
+                let (doc_id, chunk) = parse_chunk_key(&key)?;
+                let derived = self.derived.get(doc_id)?;
+                (chunk < derived.chunks.len()).then(|| ChunkVector {
+                    doc_id: doc_id.to_string(),
+                    chunk,
+                    vector,
+                })
+            })
+            .collect();
+
+        let outdated = stored.model_id != self.model_id
+            || stored.dimension != self.dimension
+            || stored.chunker != self.chunker;
+        match can_embed {
+            // Vectors from another model live in a different space, and
+            // other chunking means other chunks; keep the documents and let
+            // `AsyncSearchIndex::rebuild_missing_embeddings` redo them
+            true if outdated => {
+                self.embeddings.clear();
+                tracing::info!(
+                    "AI index was built with {:?} ({:?}), rebuilding for {:?} ({:?})",
+                    stored.model_id,
+                    stored.chunker,
+                    self.model_id,
+                    self.chunker
+                );
+            }
+            true => self.embeddings = vectors,
+            // Without an engine nothing can be re-embedded; keep what was
+            // stored so saving doesn't lose it
+            false => {
+                self.embeddings = vectors;
+                self.model_id = stored.model_id;
+                self.dimension = stored.dimension;
+                self.chunker = stored.chunker;
+            }
+        }
     }
 
-    pub async fn index_document(&mut self, doc: NoteDocument) -> Result<()> {
-        if let Some(ref engine) = self.embedding_engine {
-            let text = format!("{} {}", doc.title, doc.content);
-            if let Ok(embedding) = engine.embed_single(&text).await {
-                self.embeddings.push((doc.id.clone(), embedding));
+    /// Up to `limit` chunks that have no vector, e.g. after a model change,
+    /// and how many there are in all.
+    fn missing_embeddings(&self, limit: usize) -> (Vec<PendingChunk>, usize) {
+        let embedded: HashSet<(&str, usize)> = self
+            .embeddings
+            .iter()
+            .map(|v| (v.doc_id.as_str(), v.chunk))
+            .collect();
+        let mut pending = Vec::new();
+        let mut total = 0;
+        for doc in &self.documents {
+            let Some(derived) = self.derived.get(&doc.id) else {
+                continue;
+            };
+            for (i, chunk) in derived.chunks.iter().enumerate() {
+                if embedded.contains(&(doc.id.as_str(), i)) {
+                    continue;
+                }
+                total += 1;
+                if pending.len() < limit {
+                    pending.push(PendingChunk {
+                        doc_id: doc.id.clone(),
+                        chunk: i,
+                        text: embedding_text(doc, chunk),
+                    });
+                }
             }
         }
+        (pending, total)
+    }
+
+    /// Adds the vectors `model_id` computed for `pending`, skipping chunks
+    /// that were embedded, changed or removed in the meantime. Returns how
+    /// many were added.
+    fn add_embeddings(
+        &mut self,
+        model_id: &str,
+        pending: Vec<PendingChunk>,
+        vectors: Vec<Vec<f32>>,
+    ) -> usize {
+        if self.model_id.as_deref() != Some(model_id) {
+            return 0;
+        }
+        let documents: HashMap<&str, &NoteDocument> = self
+            .documents
+            .iter()
+            .map(|doc| (doc.id.as_str(), doc))
+            .collect();
+        let embedded: HashSet<(&str, usize)> = self
+            .embeddings
+            .iter()
+            .map(|v| (v.doc_id.as_str(), v.chunk))
+            .collect();
+        let added: Vec<ChunkVector> = pending
+            .into_iter()
+            .zip(vectors)
+            .filter(|(p, _)| {
+                let current = documents.get(p.doc_id.as_str()).and_then(|doc| {
+                    let chunk = self.derived.get(&doc.id)?.chunks.get(p.chunk)?;
+                    Some(embedding_text(doc, chunk))
+                });
+                current.as_deref() == Some(p.text.as_str())
+                    && !embedded.contains(&(p.doc_id.as_str(), p.chunk))
+            })
+            .map(|(p, vector)| ChunkVector {
+                doc_id: p.doc_id,
+                chunk: p.chunk,
+                vector,
+            })
+            .collect();
+
+        let count = added.len();
+        self.embeddings.extend(added);
+        count
+    }
+
+    /// Switches to another embedding model, dropping every vector so that
+    /// [`AsyncSearchIndex::rebuild_missing_embeddings`] redoes them. Returns
+    /// false when `model_id` is already in use.
+    pub async fn set_embedding_model(&mut self, model_id: &str) -> Result<bool> {
+        let Some(spec) = resolve_model(model_id) else {
+            bail!("Unknown embedding model: {}", model_id);
+        };
+        let current = self.embedding_engine.as_ref().map(|e| e.model_id());
+        if current == Some(spec.id) {
+            return Ok(false);
+        }
+
+        let cache_dir = self
+            .index_path
+            .as_ref()
+            .map(|p| p.join(EMBEDDING_CACHE_DIR));
+        let engine = AsyncEmbeddingEngine::new(spec.id, cache_dir.as_deref())?;
+        self.model_id = Some(engine.model_id().to_string());
+        self.dimension = engine.dimension();
+        self.embedding_engine = Some(engine);
+        self.embeddings.clear();
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Writes the index to its directory; a no-op for in-memory indexes.
+    pub fn save(&self) -> Result<()> {
+        let Some(ref path) = self.index_path else {
+            return Ok(());
+        };
+        index_store::save(
+            path,
+            &StoredIndex {
+                model_id: self.model_id.clone(),
+                dimension: self.dimension,
+                chunker: self.chunker.clone(),
+                documents: self.documents.clone(),
+                embeddings: self
+                    .embeddings
+                    .iter()
+                    .map(|v| (chunk_key(&v.doc_id, v.chunk), v.vector.clone()))
+                    .collect(),
+            },
+        )
+    }
 
-        self.documents.push(doc);
+    pub async fn index_document(&mut self, doc: NoteDocument) -> Result<()> {
+        self.index_documents(vec![doc], false).await?;
         Ok(())
     }
 
-    pub async fn index_documents(&mut self, docs: Vec<NoteDocument>) -> Result<()> {
+    /// Inserts or replaces documents by id. Documents whose content hash
+    /// matches the indexed copy keep their vectors unless `force` is set, so
+    /// re-sending an unchanged vault only embeds what was edited.
+    pub async fn index_documents(
+        &mut self,
+        docs: Vec<NoteDocument>,
+        force: bool,
+    ) -> Result<IndexStats> {
+        let mut stats = IndexStats::default();
+        let mut positions: HashMap<String, usize> = self
+            .documents
+            .iter()
+            .enumerate()
+            .map(|(i, d)| (d.id.clone(), i))
+            .collect();
+        let mut vector_counts: HashMap<String, usize> = HashMap::new();
+        for v in &self.embeddings {
+            *vector_counts.entry(v.doc_id.clone()).or_default() += 1;
+        }
+        let mut to_embed: Vec<usize> = Vec::new();
+
+        for doc in docs {
+            let hash = content_hash(&doc);
+            let changed = self.derived.get(&doc.id).is_none_or(|d| d.hash != hash);
+            let position = match positions.get(&doc.id) {
+                Some(&i) => {
+                    if changed {
+                        stats.updated += 1;
+                    } else {
+                        stats.unchanged += 1;
+                    }
+                    self.documents[i] = doc;
+                    i
+                }
+                None => {
+                    stats.added += 1;
+                    positions.insert(doc.id.clone(), self.documents.len());
+                    self.documents.push(doc);
+                    self.documents.len() - 1
+                }
+            };
+
+            let id = self.documents[position].id.clone();
+            if changed {
+                let doc = &self.documents[position];
+                let derived = Derived::of(doc);
+                self.keyword
+                    .insert(&id, &doc.title, &derived.tags, &doc.content);
+                self.derived.insert(id.clone(), derived);
+            }
+            let chunk_count = self.derived[&id].chunks.len();
+            let complete = vector_counts.get(&id) == Some(&chunk_count);
+            if changed || force || !complete {
+                to_embed.push(position);
+            }
+        }
+
+        // The same id may appear twice in one request; embed it once
+        to_embed.sort_unstable();
+        to_embed.dedup();
+        if to_embed.is_empty() {
+            return Ok(stats);
+        }
+        let stale: HashSet<&str> = to_embed
+            .iter()
+            .map(|&i| self.documents[i].id.as_str())
+            .collect();
+        self.embeddings
+            .retain(|v| !stale.contains(v.doc_id.as_str()));
+
         if let Some(ref engine) = self.embedding_engine {
-            let texts: Vec<String> = docs
-                .iter()
-                .map(|d| format!("{} {}", d.title, d.content))
-                .collect();
+            let mut keys = Vec::new();
+            let mut texts = Vec::new();
+            for &i in &to_embed {
+                let doc = &self.documents[i];
+                for (chunk_index, chunk) in self.derived[&doc.id].chunks.iter().enumerate() {
+                    keys.push((doc.id.clone(), chunk_index));
+                    texts.push(embedding_text(doc, chunk));
+                }
+            }
             let text_refs: Vec<&str> = texts.iter().map(|s| s.as_str()).collect();
 
-            if let Ok(embeddings) = engine.embed(&text_refs).await {
-                for (doc, embedding) in docs.iter().zip(embeddings) {
-                    self.embeddings.push((doc.id.clone(), embedding));
-                }
+            // On failure the documents stay without vectors and
+            // `AsyncSearchIndex::rebuild_missing_embeddings` picks them up later
+            if let Ok(vectors) = engine.embed(&text_refs).await {
+                self.embeddings.extend(keys.into_iter().zip(vectors).map(
+                    |((doc_id, chunk), vector)| ChunkVector {
+                        doc_id,
+                        chunk,
+                        vector,
+                    },
+                ));
             }
         }
 
-        self.documents.extend(docs);
-        Ok(())
+        Ok(stats)
     }
 
-    pub fn fulltext_search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
-        let query_lower = query.to_lowercase();
-        let query_words: Vec<&str> = query_lower.split_whitespace().collect();
+    /// Removes documents matching any of `ids` or `paths`, with their
+    /// vectors. Returns how many were removed.
+    pub fn remove_documents(&mut self, ids: &[String], paths: &[String]) -> usize {
+        let ids: HashSet<&str> = ids.iter().map(String::as_str).collect();
+        let paths: HashSet<&str> = paths.iter().map(String::as_str).collect();
+        let removed: HashSet<String> = self
+            .documents
+            .iter()
+            .filter(|d| ids.contains(d.id.as_str()) || paths.contains(d.path.as_str()))
+            .map(|d| d.id.clone())
+            .collect();
+        self.remove_ids(&removed)
+    }
 
-        let mut scored: Vec<(usize, f32)> = self
+    /// Removes every document whose id is not in `keep`, e.g. notes deleted
+    /// from the vault since the last full sync. Returns how many were removed.
+    pub fn retain_documents(&mut self, keep: &HashSet<String>) -> usize {
+        let removed: HashSet<String> = self
             .documents
+            .iter()
+            .filter(|d| !keep.contains(&d.id))
+            .map(|d| d.id.clone())
+            .collect();
+        self.remove_ids(&removed)
+    }
+
+    fn remove_ids(&mut self, removed: &HashSet<String>) -> usize {
+        if removed.is_empty() {
+            return 0;
+        }
+        self.documents.retain(|d| !removed.contains(&d.id));
+        self.embeddings.retain(|v| !removed.contains(&v.doc_id));
+        self.derived.retain(|id, _| !removed.contains(id));
+        for id in removed {
+            self.keyword.remove(id);
+        }
+        removed.len()
+    }
+
+    fn result(&self, doc: &NoteDocument, score: f32, chunk: Option<Chunk>) -> SearchResult {
+        SearchResult {
+            id: doc.id.clone(),
+            title: doc.title.clone(),
+            content: doc.content.clone(),
+            path: doc.path.clone(),
+            score,
+            chunk,
+        }
+    }
+
+    /// The chunk of `doc_id` matching the most query terms.
+    fn best_keyword_chunk(&self, doc_id: &str, query: &Query) -> Option<Chunk> {
+        let chunks = &self.derived.get(doc_id)?.chunks;
+        chunks
             .iter()
             .enumerate()
-            .map(|(idx, doc)| {
-                let title_lower = doc.title.to_lowercase();
-                let content_lower = doc.content.to_lowercase();
-
-                let mut score = 0.0f32;
-                for word in &query_words {
-                    if title_lower.contains(word) {
-                        score += 2.0;
-                    }
-                    if content_lower.contains(word) {
-                        score += 1.0;
-                    }
-                }
-                (idx, score)
+            // Earlier chunks win ties
+            .max_by_key(|(i, chunk)| (query.matched_terms(&chunk.text), std::cmp::Reverse(*i)))
+            .map(|(_, chunk)| chunk.clone())
+    }
+
+    /// Whether `doc` passes the filters in `options`.
+    fn accepts(&self, doc: &NoteDocument, options: &SearchOptions) -> bool {
+        if let Some(ref prefix) = options.path_prefix {
+            if !doc.path.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        if options.tags.is_empty() && options.frontmatter.is_empty() {
+            return true;
+        }
+        let Some(derived) = self.derived.get(&doc.id) else {
+            return false;
+        };
+        let has_tag = |wanted: &String| {
+            let wanted = wanted.trim_start_matches('#').to_lowercase();
+            derived.tags.iter().any(|tag| {
+                tag == &wanted
+                    || tag
+                        .strip_prefix(wanted.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
             })
-            .filter(|(_, score)| *score > 0.0)
-            .collect();
+        };
+        options.tags.iter().all(has_tag)
+            && options.frontmatter.iter().all(|(key, expected)| {
+                derived
+                    .frontmatter
+                    .get(key)
+                    .is_some_and(|actual| frontmatter_matches(actual, expected))
+            })
+    }
 
-        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    fn documents_by_id(&self) -> HashMap<&str, &NoteDocument> {
+        self.documents.iter().map(|d| (d.id.as_str(), d)).collect()
+    }
 
-        let results: Vec<SearchResult> = scored
+    fn keyword_hits(
+        &self,
+        query: &str,
+        limit: usize,
+        options: &SearchOptions,
+    ) -> Vec<SearchResult> {
+        let query = Query::parse(query);
+        let documents = self.documents_by_id();
+
+        self.keyword
+            .search(&query, usize::MAX)
             .into_iter()
+            .filter_map(|(id, score)| {
+                let doc = *documents.get(id.as_str())?;
+                self.accepts(doc, options).then_some((doc, score))
+            })
             .take(limit)
-            .map(|(idx, score)| {
-                let doc = &self.documents[idx];
-                SearchResult {
-                    id: doc.id.clone(),
-                    title: doc.title.clone(),
-                    content: doc.content.clone(),
-                    path: doc.path.clone(),
-                    score,
-                }
+            .map(|(doc, score)| {
+                let chunk = self.best_keyword_chunk(&doc.id, &query);
+                self.result(doc, score, chunk)
             })
-            .collect();
-
-        Ok(results)
+            .collect()
     }
 
-    pub async fn semantic_search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+    /// Ranks notes by their best-matching chunk.
+    async fn semantic_hits(
+        &self,
+        query: &str,
+        limit: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
         let engine = self
             .embedding_engine
             .as_ref()
             .context("Embedding engine not available")?;
 
-        let query_embedding = engine.embed_single(query).await?;
-
-        let mut scored: Vec<(String, f32)> = self
-            .embeddings
-            .iter()
-            .map(|(id, emb)| (id.clone(), cosine_similarity(&query_embedding, emb)))
-            .collect();
+        let query_embedding = engine.embed_query(query).await?;
+        let documents = self.documents_by_id();
 
-        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let mut best: HashMap<&str, (usize, f32)> = HashMap::new();
+        for v in &self.embeddings {
+            let score = cosine_similarity(&query_embedding, &v.vector);
+            let entry = best.entry(v.doc_id.as_str()).or_insert((v.chunk, score));
+            if score > entry.1 {
+                *entry = (v.chunk, score);
+            }
+        }
 
-        let top_ids: Vec<(String, f32)> = scored.into_iter().take(limit).collect();
+        let mut scored: Vec<(&NoteDocument, usize, f32)> = best
+            .into_iter()
+            .filter_map(|(id, (chunk, score))| {
+                let doc = *documents.get(id)?;
+                self.accepts(doc, options).then_some((doc, chunk, score))
+            })
+            .collect();
+        scored.sort_by(|a, b| {
+            b.2.partial_cmp(&a.2)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.id.cmp(&b.0.id))
+        });
 
-        let results: Vec<SearchResult> = top_ids
+        let results: Vec<SearchResult> = scored
             .into_iter()
-            .filter_map(|(id, score)| {
-                self.documents
-                    .iter()
-                    .find(|d| d.id == id)
-                    .map(|doc| SearchResult {
-                        id: doc.id.clone(),
-                        title: doc.title.clone(),
-                        content: doc.content.clone(),
-                        path: doc.path.clone(),
-                        score,
-                    })
+            .take(limit)
+            .map(|(doc, chunk, score)| {
+                let chunk = self
+                    .derived
+                    .get(&doc.id)
+                    .and_then(|d| d.chunks.get(chunk).cloned());
+                self.result(doc, score, chunk)
             })
             .collect();
 
         Ok(results)
     }
 
-    pub async fn hybrid_search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
-        let fulltext_results = self.fulltext_search(query, limit * 2)?;
-
-        let semantic_results = if self.embedding_engine.is_some() {
-            self.semantic_search(query, limit * 2)
-                .await
-                .unwrap_or_default()
-        } else {
-            Vec::new()
-        };
+    /// Ranks notes with BM25; see [`super::keyword`] for the query syntax.
+    pub fn fulltext_search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        Ok(self.keyword_hits(query, limit, &SearchOptions::default()))
+    }
 
-        let mut combined: std::collections::HashMap<String, SearchResult> =
-            std::collections::HashMap::new();
+    pub async fn semantic_search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        self.semantic_hits(query, limit, &SearchOptions::default())
+            .await
+    }
 
-        for result in fulltext_results {
-            combined.insert(result.id.clone(), result);
-        }
+    pub async fn hybrid_search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        self.search(query, limit, &SearchOptions::default()).await
+    }
 
-        for result in semantic_results {
-            combined
-                .entry(result.id.clone())
-                .and_modify(|e| e.score = (e.score + result.score) / 2.0)
-                .or_insert(result);
-        }
+    pub async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let mut results = match options.mode {
+            SearchMode::Keyword => self.keyword_hits(query, limit, options),
+            SearchMode::Semantic => self.semantic_hits(query, limit, options).await?,
+            SearchMode::Hybrid => {
+                let candidates = limit.saturating_mul(3).max(MIN_FUSION_CANDIDATES);
+                let keyword = self.keyword_hits(query, candidates, options);
+                let (semantic, alpha) = if self.embedding_engine.is_some() {
+                    let semantic = self
+                        .semantic_hits(query, candidates, options)
+                        .await
+                        .unwrap_or_default();
+                    (semantic, options.alpha.unwrap_or(0.5).clamp(0.0, 1.0))
+                } else {
+                    // Keyword ranking alone still scores up to 1
+                    (Vec::new(), 0.0)
+                };
+                fuse(keyword, semantic, alpha)
+            }
+        };
 
-        let mut results: Vec<SearchResult> = combined.into_values().collect();
-        results.sort_by(|a, b| {
-            b.score
-                .partial_cmp(&a.score)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+        if let Some(min_score) = options.min_score {
+            results.retain(|r| r.score >= min_score);
+        }
         results.truncate(limit);
-
         Ok(results)
     }
 
     pub fn clear(&mut self) -> Result<()> {
         self.documents.clear();
         self.embeddings.clear();
-        Ok(())
+        self.derived.clear();
+        self.keyword.clear();
+        self.save()
     }
 }
 
+#[derive(Clone)]
 pub struct AsyncSearchIndex {
     inner: Arc<RwLock<SearchIndex>>,
 }
 
 impl AsyncSearchIndex {
-    pub fn new(index_path: &Path) -> Result<Self> {
+    pub fn new(index_path: &Path, model_id: &str) -> Result<Self> {
         Ok(Self {
-            inner: Arc::new(RwLock::new(SearchIndex::new(index_path)?)),
+            inner: Arc::new(RwLock::new(SearchIndex::new(index_path, model_id)?)),
         })
     }
 
-    pub fn new_in_memory() -> Result<Self> {
+    pub fn new_in_memory(model_id: &str) -> Result<Self> {
         Ok(Self {
-            inner: Arc::new(RwLock::new(SearchIndex::new_in_memory()?)),
+            inner: Arc::new(RwLock::new(SearchIndex::new_in_memory(model_id)?)),
         })
     }
 
+    /// Switches to another embedding model and re-embeds every chunk with it.
+    /// Vectors the model computed before come from the embedding cache.
+    /// Returns false when `model_id` is already in use.
+    pub async fn set_embedding_model(&self, model_id: &str, progress: &Progress) -> Result<bool> {
+        if !self
+            .inner
+            .write()
+            .await
+            .set_embedding_model(model_id)
+            .await?
+        {
+            return Ok(false);
+        }
+        self.rebuild_missing_embeddings(progress).await?;
+        Ok(true)
+    }
+
+    /// Id of the model new vectors are computed with, if one is loaded.
+    pub async fn embedding_model(&self) -> Option<String> {
+        let index = self.inner.read().await;
+        index
+            .embedding_engine
+            .as_ref()
+            .map(|e| e.model_id().to_string())
+    }
+
+    /// Embeds passages with the index's model, through its vector cache.
+    pub async fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let index = self.inner.read().await;
+        let engine = index
+            .embedding_engine
+            .as_ref()
+            .context("Embedding model unavailable")?;
+        engine.embed(texts).await
+    }
+
     pub async fn index_document(&self, doc: NoteDocument) -> Result<()> {
         let mut index = self.inner.write().await;
         index.index_document(doc).await
     }
 
-    pub async fn index_documents(&self, docs: Vec<NoteDocument>) -> Result<()> {
+    pub async fn index_documents(
+        &self,
+        docs: Vec<NoteDocument>,
+        force: bool,
+    ) -> Result<IndexStats> {
         let mut index = self.inner.write().await;
-        index.index_documents(docs).await
+        index.index_documents(docs, force).await
+    }
+
+    pub async fn remove_documents(&self, ids: &[String], paths: &[String]) -> usize {
+        let mut index = self.inner.write().await;
+        index.remove_documents(ids, paths)
+    }
+
+    pub async fn retain_documents(&self, keep: &HashSet<String>) -> usize {
+        let mut index = self.inner.write().await;
+        index.retain_documents(keep)
     }
 
     pub async fn fulltext_search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
@@ -249,8 +940,422 @@ impl AsyncSearchIndex {
         index.hybrid_search(query, limit).await
     }
 
+    pub async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let index = self.inner.read().await;
+        index.search(query, limit, options).await
+    }
+
     pub async fn clear(&self) -> Result<()> {
         let mut index = self.inner.write().await;
         index.clear()
     }
+
+    /// Whether any chunk is waiting for a vector.
+    pub async fn has_missing_embeddings(&self) -> bool {
+        let index = self.inner.read().await;
+        index.embedding_engine.is_some() && index.missing_embeddings(0).1 > 0
+    }
+
+    /// Embeds chunks that have no vector, e.g. after a model change. Works a
+    /// batch at a time and embeds without holding the index lock, so
+    /// searches and indexing carry on meanwhile. Returns how many were
+    /// embedded.
+    pub async fn rebuild_missing_embeddings(&self, progress: &Progress) -> Result<usize> {
+        let mut rebuilt = 0;
+        loop {
+            let (engine, pending, remaining) = {
+                let index = self.inner.read().await;
+                let Some(engine) = index.embedding_engine.clone() else {
+                    break;
+                };
+                let (pending, remaining) = index.missing_embeddings(INDEX_BATCH_SIZE);
+                (engine, pending, remaining)
+            };
+            if pending.is_empty() {
+                break;
+            }
+            progress.step(rebuilt, rebuilt + remaining, "Embedding notes");
+
+            let texts: Vec<&str> = pending.iter().map(|p| p.text.as_str()).collect();
+            let vectors = match engine.embed(&texts).await {
+                Ok(vectors) => vectors,
+                Err(e) => {
+                    // Keep the batches already embedded
+                    self.save().await?;
+                    return Err(e);
+                }
+            };
+            let added =
+                self.inner
+                    .write()
+                    .await
+                    .add_embeddings(engine.model_id(), pending, vectors);
+            // Nothing usable means the index changed under us, e.g. another
+            // model was selected; whoever changed it rebuilds
+            if added == 0 {
+                break;
+            }
+            rebuilt += added;
+        }
+        if rebuilt > 0 {
+            self.save().await?;
+        }
+        Ok(rebuilt)
+    }
+
+    pub async fn save(&self) -> Result<()> {
+        let index = self.inner.read().await;
+        index.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(id: &str, content: &str) -> NoteDocument {
+        NoteDocument {
+            id: id.to_string(),
+            title: id.to_string(),
+            content: content.to_string(),
+            path: format!("{}.md", id),
+            embedding: None,
+        }
+    }
+
+    fn vector(doc_id: &str, chunk: usize, vector: Vec<f32>) -> ChunkVector {
+        ChunkVector {
+            doc_id: doc_id.to_string(),
+            chunk,
+            vector,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut index = SearchIndex::open(Some(dir.path()), None).unwrap();
+        index
+            .index_documents(
+                vec![doc("a", "rust ownership"), doc("b", "gardening")],
+                false,
+            )
+            .await
+            .unwrap();
+        index.save().unwrap();
+
+        let reopened = SearchIndex::open(Some(dir.path()), None).unwrap();
+        let results = reopened.fulltext_search("rust", 5).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, "a");
+    }
+
+    #[test]
+    fn test_model_change_drops_vectors() {
+        let mut index = SearchIndex::open(None, None).unwrap();
+        index.model_id = Some("new-model".to_string());
+        index.dimension = 2;
+        let stored = |chunker: &str| StoredIndex {
+            model_id: Some("old-model".to_string()),
+            dimension: 2,
+            chunker: Some(chunker.to_string()),
+            documents: vec![doc("a", "text")],
+            embeddings: vec![("a#0".to_string(), vec![1.0, 0.0])],
+        };
+
+        index.restore(stored(CHUNKER_ID), true);
+        assert_eq!(index.documents.len(), 1);
+        assert!(index.embeddings.is_empty());
+
+        index.model_id = Some("old-model".to_string());
+        index.restore(stored(CHUNKER_ID), true);
+        assert_eq!(index.embeddings, vec![vector("a", 0, vec![1.0, 0.0])]);
+
+        index.restore(stored("markdown-v0"), true);
+        assert!(index.embeddings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_missing_embeddings_are_batched_and_checked() {
+        let mut index = SearchIndex::open(None, None).unwrap();
+        index.model_id = Some("model".to_string());
+        index
+            .index_documents(vec![doc("a", "first"), doc("b", "second")], false)
+            .await
+            .unwrap();
+
+        let (pending, total) = index.missing_embeddings(1);
+        assert_eq!((pending.len(), total), (1, 2));
+        assert_eq!(index.missing_embeddings(0).0.len(), 0);
+
+        let (pending, _) = index.missing_embeddings(2);
+        assert_eq!(
+            index.add_embeddings("other", pending, vec![vec![1.0]; 2]),
+            0
+        );
+
+        // `b` changes while its batch is being embedded
+        let (pending, _) = index.missing_embeddings(2);
+        index
+            .index_documents(vec![doc("b", "edited")], false)
+            .await
+            .unwrap();
+        assert_eq!(
+            index.add_embeddings("model", pending, vec![vec![1.0]; 2]),
+            1
+        );
+        assert_eq!(index.embeddings, vec![vector("a", 0, vec![1.0])]);
+        assert_eq!(index.missing_embeddings(2).1, 1);
+    }
+
+    #[test]
+    fn test_restore_drops_vectors_without_chunk() {
+        let mut index = SearchIndex::open(None, None).unwrap();
+        index.restore(
+            StoredIndex {
+                model_id: None,
+                dimension: 1,
+                chunker: Some(CHUNKER_ID.to_string()),
+                documents: vec![doc("notes#1", "text")],
+                embeddings: vec![
+                    ("notes#1#0".to_string(), vec![1.0]),
+                    ("notes#1#1".to_string(), vec![1.0]),
+                    ("notes#1".to_string(), vec![1.0]),
+                    ("gone#0".to_string(), vec![1.0]),
+                ],
+            },
+            false,
+        );
+        assert_eq!(index.embeddings, vec![vector("notes#1", 0, vec![1.0])]);
+    }
+
+    #[tokio::test]
+    async fn test_results_carry_best_chunk() {
+        let mut index = SearchIndex::open(None, None).unwrap();
+        let content = format!(
+            "# Setup\n{}\n\n# Borrowing\nThe borrow checker enforces lifetimes.",
+            "Install the toolchain. ".repeat(60)
+        );
+        index
+            .index_documents(vec![doc("rust", &content)], false)
+            .await
+            .unwrap();
+
+        let results = index.fulltext_search("lifetimes", 5).unwrap();
+        let chunk = results[0].chunk.as_ref().unwrap();
+        assert_eq!(chunk.heading_path, vec!["Borrowing".to_string()]);
+        assert_eq!((chunk.start_line, chunk.end_line), (4, 5));
+        assert!(results[0].passage().contains("borrow checker"));
+        assert!(!results[0].passage().contains("toolchain"));
+    }
+
+    #[tokio::test]
+    async fn test_reindexing_replaces_by_id() {
+        let mut index = SearchIndex::open(None, None).unwrap();
+        let stats = index
+            .index_documents(vec![doc("a", "first"), doc("b", "second")], false)
+            .await
+            .unwrap();
+        assert_eq!(stats.added, 2);
+
+        let stats = index
+            .index_documents(vec![doc("a", "first"), doc("b", "edited")], false)
+            .await
+            .unwrap();
+        assert_eq!(
+            stats,
+            IndexStats {
+                added: 0,
+                updated: 1,
+                unchanged: 1
+            }
+        );
+        assert_eq!(index.documents.len(), 2);
+        assert!(index.fulltext_search("second", 5).unwrap().is_empty());
+        assert_eq!(index.fulltext_search("edited", 5).unwrap()[0].id, "b");
+
+        let stats = index
+            .index_documents(vec![doc("a", "first")], false)
+            .await
+            .unwrap();
+        assert!(!stats.changed());
+    }
+
+    #[tokio::test]
+    async fn test_changed_document_drops_stale_vector() {
+        let mut index = SearchIndex::open(None, None).unwrap();
+        index
+            .index_documents(vec![doc("a", "old"), doc("b", "kept")], false)
+            .await
+            .unwrap();
+        index.embeddings = vec![vector("a", 0, vec![1.0]), vector("b", 0, vec![0.5])];
+
+        index
+            .index_documents(vec![doc("a", "new"), doc("b", "kept")], false)
+            .await
+            .unwrap();
+        assert_eq!(index.embeddings, vec![vector("b", 0, vec![0.5])]);
+
+        index
+            .index_documents(vec![doc("b", "kept")], true)
+            .await
+            .unwrap();
+        assert!(index.embeddings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_remove_by_id_and_path() {
+        let mut index = SearchIndex::open(None, None).unwrap();
+        index
+            .index_documents(vec![doc("a", "x"), doc("b", "y"), doc("c", "z")], false)
+            .await
+            .unwrap();
+        index.embeddings = vec![vector("a", 0, vec![1.0])];
+
+        let removed = index.remove_documents(&["a".to_string()], &["b.md".to_string()]);
+        assert_eq!(removed, 2);
+        assert!(index.embeddings.is_empty());
+        assert_eq!(index.documents.len(), 1);
+        assert_eq!(index.remove_documents(&["a".to_string()], &[]), 0);
+
+        // A removed note is new again when re-sent
+        let stats = index
+            .index_documents(vec![doc("a", "x")], false)
+            .await
+            .unwrap();
+        assert_eq!(stats.added, 1);
+
+        let keep: HashSet<String> = ["a".to_string()].into_iter().collect();
+        assert_eq!(index.retain_documents(&keep), 1);
+        assert_eq!(index.documents[0].id, "a");
+    }
+
+    #[test]
+    fn test_content_hash_ignores_path() {
+        let a = doc("a", "text");
+        let mut moved = a.clone();
+        moved.path = "folder/a.md".to_string();
+        assert_eq!(content_hash(&a), content_hash(&moved));
+
+        let mut edited = a.clone();
+        edited.content = "text!".to_string();
+        assert_ne!(content_hash(&a), content_hash(&edited));
+        assert_eq!(content_hash(&a).len(), 64);
+    }
+
+    #[test]
+    fn test_note_tags_from_frontmatter_and_body() {
+        let content = "---\ntags:\n  - Rust\n  - \"lang/systems\"\n---\nSee #rust and #Ownership";
+        assert_eq!(
+            note_tags(content),
+            vec!["rust", "lang/systems", "ownership"]
+        );
+        assert_eq!(note_tags("---\ntags: [a, b]\n---\n"), vec!["a", "b"]);
+    }
+
+    fn hit(id: &str) -> SearchResult {
+        SearchResult {
+            id: id.to_string(),
+            title: id.to_string(),
+            content: String::new(),
+            path: String::new(),
+            score: 0.0,
+            chunk: None,
+        }
+    }
+
+    #[test]
+    fn test_fusion_rewards_agreement() {
+        let fused = fuse(
+            vec![hit("both"), hit("keyword")],
+            vec![hit("both"), hit("semantic")],
+            0.5,
+        );
+        let ids: Vec<&str> = fused.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["both", "keyword", "semantic"]);
+        assert!((fused[0].score - 1.0).abs() < 1e-6);
+        assert!((fused[1].score - fused[2].score).abs() < 1e-6);
+
+        // Leaning semantic puts the semantic-only hit ahead
+        let fused = fuse(
+            vec![hit("both"), hit("keyword")],
+            vec![hit("both"), hit("semantic")],
+            0.8,
+        );
+        assert_eq!(fused[1].id, "semantic");
+    }
+
+    #[test]
+    fn test_options_reject_non_finite_numbers() {
+        let options: SearchOptions =
+            serde_json::from_value(serde_json::json!({"alpha": 0.3, "min_score": null})).unwrap();
+        assert_eq!(options.alpha, Some(0.3));
+        assert_eq!(options.min_score, None);
+
+        let overflow = serde_json::json!({"alpha": 1e39});
+        assert!(serde_json::from_value::<SearchOptions>(overflow).is_err());
+        let overflow = serde_json::json!({"min_score": -1e39});
+        assert!(serde_json::from_value::<SearchOptions>(overflow).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_filters_and_min_score() {
+        let mut index = SearchIndex::open(None, None).unwrap();
+        let mut work = doc(
+            "work",
+            "---\nstatus: Draft\nyear: 2024\ntags: [project/alpha]\n---\nrust plans",
+        );
+        work.path = "Work/plan.md".to_string();
+        let mut home = doc("home", "---\nstatus: done\n---\nrust recipes #cooking");
+        home.path = "Home/food.md".to_string();
+        index
+            .index_documents(vec![work, home], false)
+            .await
+            .unwrap();
+
+        let search = |options: SearchOptions| {
+            let index = &index;
+            async move {
+                index
+                    .search("rust", 10, &options)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| r.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(search(SearchOptions::default()).await.len(), 2);
+        let by_path = SearchOptions {
+            path_prefix: Some("Work/".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(by_path).await, vec!["work"]);
+        let by_tag = SearchOptions {
+            tags: vec!["#project".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(search(by_tag).await, vec!["work"]);
+        let by_frontmatter = SearchOptions {
+            frontmatter: [
+                ("status".to_string(), serde_json::json!("draft")),
+                ("year".to_string(), serde_json::json!("2024")),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        assert_eq!(search(by_frontmatter).await, vec!["work"]);
+        let strict = SearchOptions {
+            mode: SearchMode::Keyword,
+            min_score: Some(1000.0),
+            ..Default::default()
+        };
+        assert!(search(strict).await.is_empty());
+    }
 }
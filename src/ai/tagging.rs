@@ -0,0 +1,467 @@
+//! Label suggestions for saved articles, newsletters and notes.
+//!
+//! An item's labels are scored by how its nearest already-labeled neighbours
+//! are labeled, comparing embeddings, and optionally by the LLM choosing from
+//! the label tree. Only labels in the [`LabelStore`] are suggested.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::embeddings::cosine_similarity;
+use crate::labels::{Label, LabelStore, LabelTree};
+use crate::newsletter::{Newsletter, NewsletterStore};
+use crate::reading::{Article, ReadingStore};
+use crate::rpc::{LabelItemType, LabelSuggestRequest, LabelSuggestResponse, LabelSuggestion};
+use crate::utils::text;
+
+/// Labeled items an item is compared with, most recently saved first
+const MAX_LABELED_ITEMS: usize = 500;
+/// Most similar labeled items that vote on labels
+const NEIGHBORS: usize = 10;
+/// Bytes of an item's body that are embedded or shown to the LLM
+const ITEM_TEXT_BYTES: usize = 2000;
+const DEFAULT_LIMIT: usize = 5;
+const LLM_TOKENS: u32 = 512;
+
+/// The item to label, or one that already is.
+struct Item {
+    id: Option<String>,
+    text: String,
+    /// Ids of its labels that are in the label store
+    labels: Vec<String>,
+    saved_at: DateTime<Utc>,
+}
+
+fn item_text(title: &str, body: &str) -> String {
+    format!("{}\n\n{}", title, text::prefix(body, ITEM_TEXT_BYTES))
+        .trim()
+        .to_string()
+}
+
+/// Items store labels by name; ids are accepted too.
+fn label_ids(store: &LabelStore, names: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .filter_map(|name| store.get(name).or_else(|| store.get_by_name(name)))
+        .map(|label| label.id.clone())
+        .collect()
+}
+
+fn article_item(article: &Article, labels: &LabelStore) -> Item {
+    Item {
+        id: Some(article.id.clone()),
+        text: item_text(&article.title, &article.content),
+        labels: label_ids(labels, &article.labels),
+        saved_at: article.saved_at,
+    }
+}
+
+fn newsletter_item(newsletter: &Newsletter, labels: &LabelStore) -> Item {
+    Item {
+        id: Some(newsletter.id.clone()),
+        text: item_text(&newsletter.subject, &newsletter.content_text),
+        labels: label_ids(labels, &newsletter.labels),
+        saved_at: newsletter.saved_at,
+    }
+}
+
+/// Suggests labels for the item `request` names, best first, and applies
+/// those at or above `apply_threshold` to articles and newsletters. Notes
+/// live in the vault, so their suggestions are only returned.
+pub async fn suggest(
+    data_dir: &Path,
+    request: &LabelSuggestRequest,
+) -> Result<LabelSuggestResponse> {
+    let labels = LabelStore::new(data_dir.to_path_buf())?;
+    let reading = ReadingStore::new(data_dir.to_path_buf())?;
+    let newsletters = NewsletterStore::new(data_dir.to_path_buf())?;
+
+    let target = match request.item_type {
+        LabelItemType::Article => {
+            let id = request.id.as_deref().context("Article id required")?;
+            let article = reading
+                .get(id)
+                .with_context(|| format!("Article not found: {}", id))?;
+            article_item(article, &labels)
+        }
+        LabelItemType::Newsletter => {
+            let id = request.id.as_deref().context("Newsletter id required")?;
+            let newsletter = newsletters
+                .get(id)
+                .with_context(|| format!("Newsletter not found: {}", id))?;
+            newsletter_item(newsletter, &labels)
+        }
+        LabelItemType::Note => Item {
+            id: None,
+            text: item_text(
+                request.title.as_deref().unwrap_or_default(),
+                request
+                    .content
+                    .as_deref()
+                    .context("Note content required")?,
+            ),
+            labels: Vec::new(),
+            saved_at: Utc::now(),
+        },
+    };
+    let paths = label_paths(&labels.get_tree());
+
+    let mut labeled: Vec<Item> = reading
+        .iter()
+        .map(|a| article_item(a, &labels))
+        .chain(newsletters.iter().map(|n| newsletter_item(n, &labels)))
+        .filter(|item| !item.labels.is_empty() && item.id != target.id)
+        .collect();
+    labeled.sort_by_key(|item| std::cmp::Reverse(item.saved_at));
+    labeled.truncate(MAX_LABELED_ITEMS);
+    drop((labels, reading, newsletters));
+
+    let similarity = if labeled.is_empty() || paths.is_empty() {
+        None
+    } else {
+        match similarity_votes(&target, &labeled).await {
+            Ok(votes) => Some(votes),
+            // The LLM pass can still suggest labels
+            Err(e) if request.use_llm => {
+                tracing::warn!("Skipping label similarity: {}", e);
+                None
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    let (llm, provider) = if request.use_llm && !paths.is_empty() {
+        let (votes, provider) = llm_votes(request, &target, &paths).await?;
+        (Some(votes), Some(provider))
+    } else {
+        (None, None)
+    };
+
+    let mut suggestions = rank(&paths, similarity.as_ref(), llm.as_ref(), &target.labels);
+    suggestions.truncate(request.limit.unwrap_or(DEFAULT_LIMIT));
+    if let (Some(threshold), Some(id)) = (request.apply_threshold, target.id.as_deref()) {
+        apply(data_dir, request.item_type, id, &mut suggestions, threshold)?;
+    }
+
+    Ok(LabelSuggestResponse {
+        suggestions,
+        provider,
+    })
+}
+
+/// Every label with its path from the root of the tree, sorted by path.
+fn label_paths(tree: &[LabelTree]) -> Vec<(String, Label)> {
+    fn walk(nodes: &[LabelTree], parent: Option<&str>, out: &mut Vec<(String, Label)>) {
+        for node in nodes {
+            let path = match parent {
+                Some(parent) => format!("{}/{}", parent, node.label.name),
+                None => node.label.name.clone(),
+            };
+            walk(&node.children, Some(&path), out);
+            out.push((path, node.label.clone()));
+        }
+    }
+
+    let mut paths = Vec::new();
+    walk(tree, None, &mut paths);
+    paths.sort_by(|a, b| a.0.cmp(&b.0));
+    paths
+}
+
+async fn similarity_votes(target: &Item, labeled: &[Item]) -> Result<HashMap<String, f32>> {
+    let texts: Vec<&str> = std::iter::once(&target.text)
+        .chain(labeled.iter().map(|item| &item.text))
+        .map(String::as_str)
+        .collect();
+    let vectors = super::embed_passages(&texts).await?;
+    let (target_vector, vectors) = vectors.split_first().context("No embedding generated")?;
+
+    let neighbors = vectors
+        .iter()
+        .zip(labeled)
+        .map(|(v, item)| (cosine_similarity(target_vector, v), item.labels.as_slice()))
+        .collect();
+    Ok(vote(neighbors))
+}
+
+/// Share of the [`NEIGHBORS`] most similar items carrying each label, each
+/// item weighted by its similarity.
+fn vote(mut neighbors: Vec<(f32, &[String])>) -> HashMap<String, f32> {
+    neighbors.retain(|(similarity, _)| *similarity > 0.0);
+    neighbors.sort_by(|a, b| b.0.total_cmp(&a.0));
+    neighbors.truncate(NEIGHBORS);
+
+    let total: f32 = neighbors.iter().map(|(similarity, _)| similarity).sum();
+    let mut votes = HashMap::new();
+    for (similarity, labels) in neighbors {
+        for label in labels {
+            *votes.entry(label.clone()).or_insert(0.0) += similarity / total;
+        }
+    }
+    votes
+}
+
+#[derive(Deserialize)]
+struct LlmLabels {
+    labels: Vec<LlmLabel>,
+}
+
+#[derive(Deserialize)]
+struct LlmLabel {
+    label: String,
+    confidence: f32,
+}
+
+/// Asks the LLM to pick labels by path, returning confidences by label id
+/// and the provider that answered.
+async fn llm_votes(
+    request: &LabelSuggestRequest,
+    target: &Item,
+    paths: &[(String, Label)],
+) -> Result<(HashMap<String, f32>, String)> {
+    let names: Vec<&str> = paths.iter().map(|(path, _)| path.as_str()).collect();
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "labels": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "label": {"enum": names},
+                        "confidence": {"type": "number", "minimum": 0, "maximum": 1}
+                    },
+                    "required": ["label", "confidence"]
+                }
+            }
+        },
+        "required": ["labels"]
+    });
+    let instructions = format!(
+        "Choose the labels that fit the text, each with a confidence from 0 to 1. \
+         Use only labels from this list, and leave out any that don't fit:\n{}",
+        names
+            .iter()
+            .map(|name| format!("- {}", name))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+
+    let chain = super::provider_chain(&request.llm)?.unwrap_or_else(super::local_chain);
+    let extraction = super::extract::extract(
+        &chain,
+        &target.text,
+        &schema,
+        Some(&instructions),
+        LLM_TOKENS,
+    )
+    .await?;
+    let picked: LlmLabels = serde_json::from_value(extraction.data)?;
+
+    let ids: HashMap<&str, &str> = paths
+        .iter()
+        .map(|(path, label)| (path.as_str(), label.id.as_str()))
+        .collect();
+    let votes = picked
+        .labels
+        .into_iter()
+        .filter_map(|p| Some((ids.get(p.label.as_str())?.to_string(), p.confidence)))
+        .collect();
+    Ok((votes, extraction.provider))
+}
+
+/// Scores every label by the mean of the scores that were computed, best
+/// first, leaving out labels the item already has and ones nothing voted for.
+fn rank(
+    paths: &[(String, Label)],
+    similarity: Option<&HashMap<String, f32>>,
+    llm: Option<&HashMap<String, f32>>,
+    existing: &[String],
+) -> Vec<LabelSuggestion> {
+    let score = |votes: Option<&HashMap<String, f32>>, id: &str| {
+        votes.map(|v| v.get(id).copied().unwrap_or(0.0).clamp(0.0, 1.0))
+    };
+    let mut suggestions: Vec<LabelSuggestion> = paths
+        .iter()
+        .filter(|(_, label)| !existing.contains(&label.id))
+        .filter_map(|(path, label)| {
+            let similarity = score(similarity, &label.id);
+            let llm = score(llm, &label.id);
+            let scores: Vec<f32> = similarity.into_iter().chain(llm).collect();
+            let confidence = scores.iter().sum::<f32>() / scores.len().max(1) as f32;
+            (confidence > 0.0).then(|| LabelSuggestion {
+                label_id: label.id.clone(),
+                name: label.name.clone(),
+                path: path.clone(),
+                confidence,
+                similarity,
+                llm,
+                applied: false,
+            })
+        })
+        .collect();
+    suggestions.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    suggestions
+}
+
+/// Adds suggestions at or above `threshold` to the item by name, counting
+/// them in the label store.
+fn apply(
+    data_dir: &Path,
+    item_type: LabelItemType,
+    id: &str,
+    suggestions: &mut [LabelSuggestion],
+    threshold: f32,
+) -> Result<()> {
+    let mut chosen: Vec<&mut LabelSuggestion> = suggestions
+        .iter_mut()
+        .filter(|s| s.confidence >= threshold)
+        .collect();
+    if chosen.is_empty() {
+        return Ok(());
+    }
+
+    let mut labels = LabelStore::new(data_dir.to_path_buf())?;
+    let mut reading = ReadingStore::new(data_dir.to_path_buf())?;
+    let mut newsletters = NewsletterStore::new(data_dir.to_path_buf())?;
+    for suggestion in chosen.iter_mut() {
+        match item_type {
+            LabelItemType::Article => {
+                reading.add_label(id, &suggestion.name)?;
+            }
+            LabelItemType::Newsletter => {
+                newsletters.add_label(id, &suggestion.name)?;
+            }
+            LabelItemType::Note => return Ok(()),
+        }
+        labels.increment_count(&suggestion.label_id)?;
+        suggestion.applied = true;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labels::CreateLabelRequest;
+    use crate::reading::SaveArticleRequest;
+    use tempfile::tempdir;
+
+    fn create(store: &mut LabelStore, name: &str, parent: Option<&Label>) -> Label {
+        store
+            .create(CreateLabelRequest {
+                name: name.to_string(),
+                color: None,
+                description: None,
+                parent_id: parent.map(|p| p.id.clone()),
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_label_paths_follow_the_tree() {
+        let dir = tempdir().unwrap();
+        let mut store = LabelStore::new(dir.path().to_path_buf()).unwrap();
+        let programming = create(&mut store, "Programming", None);
+        create(&mut store, "Rust", Some(&programming));
+        create(&mut store, "Cooking", None);
+
+        let paths: Vec<String> = label_paths(&store.get_tree())
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, vec!["Cooking", "Programming", "Programming/Rust"]);
+    }
+
+    #[test]
+    fn test_vote_weights_nearest_items() {
+        let rust = vec!["rust".to_string()];
+        let both = vec!["rust".to_string(), "web".to_string()];
+        let cooking = vec!["cooking".to_string()];
+        let votes = vote(vec![
+            (0.9, rust.as_slice()),
+            (0.6, both.as_slice()),
+            (0.3, cooking.as_slice()),
+            (-0.5, cooking.as_slice()),
+        ]);
+
+        assert!((votes["rust"] - 1.5 / 1.8).abs() < 1e-6);
+        assert!((votes["web"] - 0.6 / 1.8).abs() < 1e-6);
+        assert!((votes["cooking"] - 0.3 / 1.8).abs() < 1e-6);
+        assert!(vote(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_rank_averages_sources_and_skips_existing() {
+        let dir = tempdir().unwrap();
+        let mut store = LabelStore::new(dir.path().to_path_buf()).unwrap();
+        let rust = create(&mut store, "Rust", None);
+        let web = create(&mut store, "Web", None);
+        let cooking = create(&mut store, "Cooking", None);
+        let paths = label_paths(&store.get_tree());
+
+        let similarity = HashMap::from([(rust.id.clone(), 0.8), (web.id.clone(), 0.2)]);
+        let llm = HashMap::from([(rust.id.clone(), 1.0), (cooking.id.clone(), 0.9)]);
+
+        let ranked = rank(&paths, Some(&similarity), Some(&llm), &[]);
+        let names: Vec<&str> = ranked.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Rust", "Cooking", "Web"]);
+        assert!((ranked[0].confidence - 0.9).abs() < 1e-6);
+        assert_eq!(ranked[1].similarity, Some(0.0));
+
+        let ranked = rank(
+            &paths,
+            Some(&similarity),
+            None,
+            std::slice::from_ref(&rust.id),
+        );
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].name, "Web");
+        assert_eq!(ranked[0].llm, None);
+    }
+
+    #[test]
+    fn test_apply_adds_labels_above_threshold() {
+        let dir = tempdir().unwrap();
+        let data_dir = dir.path().to_path_buf();
+        let mut labels = LabelStore::new(data_dir.clone()).unwrap();
+        let rust = create(&mut labels, "Rust", None);
+        let web = create(&mut labels, "Web", None);
+        let article = ReadingStore::new(data_dir.clone())
+            .unwrap()
+            .save(SaveArticleRequest {
+                url: None,
+                title: "Ownership".to_string(),
+                author: None,
+                content: "Borrowing and lifetimes".to_string(),
+                excerpt: None,
+                site_name: None,
+                article_type: None,
+                labels: None,
+                thumbnail_url: None,
+            })
+            .unwrap();
+
+        let paths = label_paths(&labels.get_tree());
+        let similarity = HashMap::from([(rust.id.clone(), 0.9), (web.id.clone(), 0.3)]);
+        let mut suggestions = rank(&paths, Some(&similarity), None, &[]);
+        apply(
+            &data_dir,
+            LabelItemType::Article,
+            &article.id,
+            &mut suggestions,
+            0.5,
+        )
+        .unwrap();
+
+        assert!(suggestions[0].applied);
+        assert!(!suggestions[1].applied);
+        let reading = ReadingStore::new(data_dir.clone()).unwrap();
+        assert_eq!(reading.get(&article.id).unwrap().labels, vec!["Rust"]);
+        let labels = LabelStore::new(data_dir).unwrap();
+        assert_eq!(labels.get(&rust.id).unwrap().item_count, 1);
+    }
+}
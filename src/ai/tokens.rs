@@ -0,0 +1,195 @@
+//! Token counts for fitting prompts into a model's context window.
+//!
+//! The local engine's tokenizer gives exact counts. Remote models are
+//! counted with an estimate that errs high for scripts such as Hangul or
+//! CJK, where a character is often a token of its own.
+
+use std::sync::Arc;
+use tokenizers::Tokenizer;
+
+use super::providers::{LlmConfig, LlmProvider};
+use crate::utils::text;
+
+/// Context window assumed for the local engine before a model is loaded;
+/// the default model's
+pub const DEFAULT_LOCAL_CONTEXT: usize = 2048;
+/// Longest sequence the quantized Llama implementation supports
+pub const MAX_LOCAL_CONTEXT: usize = 4096;
+
+#[derive(Clone)]
+pub enum TokenCounter {
+    Tokenizer(Arc<Tokenizer>),
+    Estimate,
+}
+
+impl TokenCounter {
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Self::Tokenizer(_))
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        match self {
+            Self::Tokenizer(tokenizer) => match tokenizer.encode(text, false) {
+                Ok(encoding) => encoding.len(),
+                Err(_) => estimate(text),
+            },
+            Self::Estimate => estimate(text),
+        }
+    }
+
+    /// Longest prefix of `text` that fits in `max_tokens`.
+    pub fn prefix<'a>(&self, text: &'a str, max_tokens: usize) -> &'a str {
+        if let Self::Tokenizer(tokenizer) = self {
+            if let Ok(encoding) = tokenizer.encode(text, false) {
+                if encoding.len() <= max_tokens {
+                    return text;
+                }
+                let end = match max_tokens {
+                    0 => 0,
+                    n => encoding.get_offsets()[n - 1].1,
+                };
+                return text::prefix(text, end);
+            }
+        }
+        let mut used: usize = 0;
+        for (i, c) in text.char_indices() {
+            used += if c.is_ascii() { 1 } else { 4 };
+            if used.div_ceil(4) > max_tokens {
+                return &text[..i];
+            }
+        }
+        text
+    }
+}
+
+/// About four ASCII characters per token, and a token for every other
+/// character.
+fn estimate(text: &str) -> usize {
+    let ascii = text.bytes().filter(u8::is_ascii).count();
+    let other = text.chars().filter(|c| !c.is_ascii()).count();
+    ascii.div_ceil(4) + other
+}
+
+/// Context window of a remote model, from its name where known.
+pub fn context_window(config: &LlmConfig) -> usize {
+    let model = config.model.as_deref().unwrap_or_default().to_lowercase();
+    match config.provider {
+        LlmProvider::Local => DEFAULT_LOCAL_CONTEXT,
+        LlmProvider::Ollama => config
+            .options
+            .as_ref()
+            .and_then(|o| o.get("num_ctx"))
+            .and_then(|n| n.as_u64())
+            .map_or(2048, |n| n as usize),
+        LlmProvider::Anthropic => 200_000,
+        LlmProvider::OpenAI => {
+            const WINDOWS: &[(&str, usize)] = &[
+                ("gpt-4.1", 1_000_000),
+                ("gpt-4o", 128_000),
+                ("gpt-4-turbo", 128_000),
+                ("gpt-4", 8_192),
+                ("gpt-3.5", 16_385),
+                ("o1", 200_000),
+                ("o3", 200_000),
+                ("o4", 200_000),
+            ];
+            WINDOWS
+                .iter()
+                .find(|(prefix, _)| model.starts_with(prefix))
+                .map_or(128_000, |(_, window)| *window)
+        }
+        LlmProvider::Zai => 128_000,
+        LlmProvider::Groq if model.contains("mixtral") => 32_768,
+        LlmProvider::Groq => 128_000,
+        LlmProvider::Custom => 8_192,
+    }
+}
+
+/// A passage that made it into a prompt.
+pub struct Packed {
+    /// Position in the passages given to [`pack`]
+    pub index: usize,
+    pub text: String,
+}
+
+/// Fills `budget` tokens with `passages` in order, each cut to at most
+/// `per_passage` tokens. The passage that overflows the budget is cut to
+/// the space left, unless that is too little to be useful; the rest are
+/// dropped.
+pub fn pack(
+    counter: &TokenCounter,
+    passages: &[&str],
+    budget: usize,
+    per_passage: usize,
+) -> Vec<Packed> {
+    const MIN_USEFUL: usize = 32;
+    let mut packed = Vec::new();
+    let mut left = budget;
+    for (index, passage) in passages.iter().enumerate() {
+        let room = left.min(per_passage);
+        if room < MIN_USEFUL {
+            break;
+        }
+        let text = counter.prefix(passage, room);
+        let used = counter.count(text);
+        let text = if text.len() < passage.len() {
+            format!("{}...", text)
+        } else {
+            text.to_string()
+        };
+        left = left.saturating_sub(used);
+        packed.push(Packed { index, text });
+    }
+    packed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_counts_non_ascii_per_char() {
+        assert_eq!(estimate("abcdefgh"), 2);
+        assert_eq!(estimate("안녕하세요"), 5);
+        let text = "안녕하세요 world";
+        let prefix = TokenCounter::Estimate.prefix(text, 3);
+        assert_eq!(prefix, "안녕하");
+        assert!(estimate(prefix) <= 3);
+    }
+
+    #[test]
+    fn test_pack_fills_budget_in_order() {
+        let counter = TokenCounter::Estimate;
+        let long = "word ".repeat(100);
+        let passages = [long.as_str(), "short passage", long.as_str()];
+        let packed = pack(&counter, &passages, 150, 100);
+
+        let indices: Vec<usize> = packed.iter().map(|p| p.index).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+        assert!(packed[0].text.ends_with("..."));
+        assert!(counter.count(&packed[0].text) <= 101);
+        assert_eq!(packed[1].text, "short passage");
+        // Only ~46 tokens were left for the last one
+        assert!(counter.count(&packed[2].text) < 50);
+
+        assert!(pack(&counter, &passages, 20, 100).is_empty());
+    }
+
+    #[test]
+    fn test_context_window_by_model() {
+        let config = |provider, model: &str| LlmConfig {
+            provider,
+            model: Some(model.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            context_window(&config(LlmProvider::OpenAI, "gpt-4o-mini")),
+            128_000
+        );
+        assert_eq!(context_window(&config(LlmProvider::OpenAI, "gpt-4")), 8_192);
+        let mut ollama = config(LlmProvider::Ollama, "llama3.2");
+        assert_eq!(context_window(&ollama), 2048);
+        ollama.options = Some(serde_json::json!({"num_ctx": 8192}));
+        assert_eq!(context_window(&ollama), 8192);
+    }
+}
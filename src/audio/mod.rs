@@ -2,6 +2,8 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
+use crate::jobs::Progress;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TranscriptionSegment {
     pub start: f64,
@@ -228,7 +230,9 @@ fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
     resampled
 }
 
-pub async fn download_model(model_name: Option<&str>) -> Result<String> {
+pub async fn download_model(model_name: Option<&str>, progress: &Progress) -> Result<String> {
+    use tokio::io::AsyncWriteExt;
+
     let model = model_name.unwrap_or("base.en");
     let model_filename = format!("ggml-{}.bin", model);
 
@@ -252,7 +256,7 @@ pub async fn download_model(model_name: Option<&str>) -> Result<String> {
 
     tracing::info!("Downloading Whisper model from: {}", url);
 
-    let response = reqwest::get(&url)
+    let mut response = reqwest::get(&url)
         .await
         .context("Failed to download Whisper model")?;
 
@@ -260,8 +264,29 @@ pub async fn download_model(model_name: Option<&str>) -> Result<String> {
         anyhow::bail!("Failed to download model: HTTP {}", response.status());
     }
 
-    let bytes = response.bytes().await?;
-    tokio::fs::write(&model_path, bytes)
+    // Stream into a partial file so an interrupted download is never
+    // mistaken for a complete model on the next call.
+    let partial_path = model_path.with_extension("bin.part");
+    let mut file = tokio::fs::File::create(&partial_path)
+        .await
+        .context("Failed to save model file")?;
+    let total = response.content_length();
+    let mut downloaded = 0u64;
+    let stage = format!("Downloading {}", model_filename);
+    progress.report(0.0, stage.as_str());
+
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)
+            .await
+            .context("Failed to save model file")?;
+        downloaded += chunk.len() as u64;
+        if let Some(total) = total.filter(|t| *t > 0) {
+            progress.report(downloaded as f32 * 100.0 / total as f32, stage.as_str());
+        }
+    }
+    file.flush().await?;
+    drop(file);
+    tokio::fs::rename(&partial_path, &model_path)
         .await
         .context("Failed to save model file")?;
 
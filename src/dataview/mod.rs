@@ -46,18 +46,36 @@ pub fn parse_note_metadata(request: &ParseNoteRequest) -> Result<NoteMeta> {
     })
 }
 
-fn extract_frontmatter(content: &str) -> Result<HashMap<String, serde_json::Value>> {
+pub(crate) fn extract_frontmatter(content: &str) -> Result<HashMap<String, serde_json::Value>> {
     let fm_re = Regex::new(r"^---\s*\n([\s\S]*?)\n---")?;
 
     if let Some(caps) = fm_re.captures(content) {
         let yaml_str = &caps[1];
         let mut map = HashMap::new();
+        // Key whose value is being continued by a block list (`- item` lines)
+        let mut list_key: Option<String> = None;
 
         for line in yaml_str.lines() {
+            if let (Some(key), Some(item)) = (&list_key, line.trim_start().strip_prefix("- ")) {
+                let item = serde_json::Value::String(
+                    item.trim().trim_matches('"').trim_matches('\'').to_string(),
+                );
+                match map.get_mut(key) {
+                    Some(serde_json::Value::Array(items)) => items.push(item),
+                    Some(value) => *value = serde_json::Value::Array(vec![item]),
+                    None => {}
+                }
+                continue;
+            }
+            list_key = None;
+
             if let Some((key, value)) = line.split_once(':') {
                 let key = key.trim().to_string();
                 let value = value.trim();
 
+                if value.is_empty() {
+                    list_key = Some(key.clone());
+                }
                 let json_value = if value.starts_with('[') && value.ends_with(']') {
                     let items: Vec<String> = value[1..value.len() - 1]
                         .split(',')
@@ -90,7 +108,7 @@ fn extract_frontmatter(content: &str) -> Result<HashMap<String, serde_json::Valu
     Ok(HashMap::new())
 }
 
-fn extract_tags(content: &str) -> Vec<String> {
+pub(crate) fn extract_tags(content: &str) -> Vec<String> {
     let tag_re = Regex::new(r"#([a-zA-Z][a-zA-Z0-9_/-]*)").unwrap();
     tag_re
         .captures_iter(content)
@@ -359,3 +377,40 @@ pub fn list_query(request: &QueryRequest) -> Result<Vec<String>> {
         })
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn frontmatter(content: &str) -> HashMap<String, serde_json::Value> {
+        parse_note_metadata(&ParseNoteRequest {
+            content: content.to_string(),
+            path: "notes/test.md".to_string(),
+            created: None,
+            modified: None,
+            size: None,
+        })
+        .unwrap()
+        .frontmatter
+    }
+
+    #[test]
+    fn test_frontmatter_block_list() {
+        let fm =
+            frontmatter("---\ntags:\n  - rust\n  - \"async\"\nstatus: draft\naliases:\n---\nBody");
+        assert_eq!(fm["tags"], json!(["rust", "async"]));
+        assert_eq!(fm["status"], json!("draft"));
+        // An empty value without list items stays an empty string
+        assert_eq!(fm["aliases"], json!(""));
+    }
+
+    #[test]
+    fn test_frontmatter_inline_values() {
+        let fm = frontmatter("---\ntags: [a, 'b']\ncount: 3\npublished: true\n- stray\n---\n");
+        assert_eq!(fm["tags"], json!(["a", "b"]));
+        assert_eq!(fm["count"], json!(3));
+        assert_eq!(fm["published"], json!(true));
+        assert_eq!(fm.len(), 3);
+    }
+}
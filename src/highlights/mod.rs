@@ -208,7 +208,7 @@ impl HighlightStore {
             })
             .collect();
 
-        results.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        results.sort_by_key(|h| std::cmp::Reverse(h.created_at));
 
         let offset = q.offset.unwrap_or(0);
         let limit = q.limit.unwrap_or(100);
@@ -0,0 +1,359 @@
+//! Background jobs for long-running operations.
+//!
+//! Model downloads, batch extraction, newsletter fetches and note indexing can
+//! take minutes, so their methods return a [`JobInfo`] right away and do the
+//! work on a spawned task. Every state change is broadcast as a [`JobInfo`]
+//! snapshot; the stdio transport forwards these as `$/progress` notifications
+//! and the HTTP transport as server-sent events.
+
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast;
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+/// Finished jobs kept around for `jobs.list` before the oldest are dropped.
+const MAX_FINISHED_JOBS: usize = 50;
+const EVENT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        self != JobStatus::Running
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: String,
+    /// Method that started the job, e.g. `model.download`
+    pub kind: String,
+    pub status: JobStatus,
+    /// 0-100
+    pub percent: f32,
+    pub stage: Option<String>,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+struct JobEntry {
+    info: JobInfo,
+    abort: Option<AbortHandle>,
+}
+
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<String, JobEntry>>,
+    events: broadcast::Sender<JobInfo>,
+}
+
+pub type SharedJobRegistry = Arc<JobRegistry>;
+
+pub fn create_shared_registry() -> SharedJobRegistry {
+    let (events, _) = broadcast::channel(EVENT_CAPACITY);
+    Arc::new(JobRegistry {
+        jobs: Mutex::new(HashMap::new()),
+        events,
+    })
+}
+
+impl JobRegistry {
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, JobEntry>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Starts `task` in the background and returns the initial snapshot.
+    ///
+    /// The task receives a [`Progress`] handle for reporting; its output
+    /// becomes the job's `result`, and an error or panic marks it failed.
+    pub fn spawn<F, Fut>(self: &Arc<Self>, kind: &str, task: F) -> JobInfo
+    where
+        F: FnOnce(Progress) -> Fut,
+        Fut: Future<Output = anyhow::Result<Value>> + Send + 'static,
+    {
+        let now = Utc::now();
+        let info = JobInfo {
+            id: Uuid::new_v4().to_string(),
+            kind: kind.to_string(),
+            status: JobStatus::Running,
+            percent: 0.0,
+            stage: None,
+            result: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        self.lock().insert(
+            info.id.clone(),
+            JobEntry {
+                info: info.clone(),
+                abort: None,
+            },
+        );
+        let _ = self.events.send(info.clone());
+
+        let future = task(Progress {
+            job: Some((info.id.clone(), Arc::clone(self))),
+        });
+        let registry = Arc::clone(self);
+        let id = info.id.clone();
+        let handle = tokio::spawn(async move {
+            let outcome = match AssertUnwindSafe(future).catch_unwind().await {
+                Ok(outcome) => outcome,
+                Err(_) => Err(anyhow::anyhow!("Job panicked")),
+            };
+            registry.finish(&id, outcome);
+        });
+
+        if let Some(entry) = self.lock().get_mut(&info.id) {
+            entry.abort = Some(handle.abort_handle());
+        }
+        info
+    }
+
+    pub fn get(&self, id: &str) -> Option<JobInfo> {
+        self.lock().get(id).map(|entry| entry.info.clone())
+    }
+
+    /// Lists all known jobs, newest first.
+    pub fn list(&self) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self.lock().values().map(|e| e.info.clone()).collect();
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+        jobs
+    }
+
+    /// Aborts a running job. Returns `None` for an unknown id; a job that has
+    /// already finished is returned unchanged.
+    pub fn cancel(&self, id: &str) -> Option<JobInfo> {
+        let info = {
+            let mut jobs = self.lock();
+            let entry = jobs.get_mut(id)?;
+            if entry.info.status.is_finished() {
+                return Some(entry.info.clone());
+            }
+            if let Some(abort) = entry.abort.take() {
+                abort.abort();
+            }
+            entry.info.status = JobStatus::Cancelled;
+            entry.info.updated_at = Utc::now();
+            entry.info.clone()
+        };
+        let _ = self.events.send(info.clone());
+        Some(info)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<JobInfo> {
+        self.events.subscribe()
+    }
+
+    fn report(&self, id: &str, percent: f32, stage: String) {
+        let percent = percent.clamp(0.0, 100.0);
+        let info = {
+            let mut jobs = self.lock();
+            let Some(entry) = jobs.get_mut(id) else {
+                return;
+            };
+            let job = &mut entry.info;
+            if job.status.is_finished() {
+                return;
+            }
+            // Byte-level reporters call this per chunk; only whole-percent
+            // steps and stage changes are worth an event.
+            let same_stage = job.stage.as_deref() == Some(stage.as_str());
+            if same_stage && (percent - job.percent).abs() < 1.0 {
+                return;
+            }
+            job.percent = percent;
+            job.stage = Some(stage);
+            job.updated_at = Utc::now();
+            job.clone()
+        };
+        let _ = self.events.send(info);
+    }
+
+    fn finish(&self, id: &str, outcome: anyhow::Result<Value>) {
+        let info = {
+            let mut jobs = self.lock();
+            let Some(entry) = jobs.get_mut(id) else {
+                return;
+            };
+            entry.abort = None;
+            let job = &mut entry.info;
+            if job.status.is_finished() {
+                return;
+            }
+            match outcome {
+                Ok(result) => {
+                    job.status = JobStatus::Completed;
+                    job.percent = 100.0;
+                    job.result = Some(result);
+                }
+                Err(e) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(e.to_string());
+                }
+            }
+            job.updated_at = Utc::now();
+            let info = job.clone();
+            prune_finished(&mut jobs);
+            info
+        };
+        let _ = self.events.send(info);
+    }
+}
+
+fn prune_finished(jobs: &mut HashMap<String, JobEntry>) {
+    let mut finished: Vec<(DateTime<Utc>, String)> = jobs
+        .values()
+        .filter(|e| e.info.status.is_finished())
+        .map(|e| (e.info.updated_at, e.info.id.clone()))
+        .collect();
+    if finished.len() <= MAX_FINISHED_JOBS {
+        return;
+    }
+    finished.sort();
+    for (_, id) in finished.iter().take(finished.len() - MAX_FINISHED_JOBS) {
+        jobs.remove(id);
+    }
+}
+
+/// Progress sink handed to long-running operations.
+///
+/// `Progress::none()` discards updates, so the same code path serves callers
+/// that are not running inside a job.
+#[derive(Clone, Default)]
+pub struct Progress {
+    job: Option<(String, SharedJobRegistry)>,
+}
+
+impl Progress {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    pub fn report(&self, percent: f32, stage: impl Into<String>) {
+        if let Some((id, registry)) = &self.job {
+            registry.report(id, percent, stage.into());
+        }
+    }
+
+    /// Reports `done` out of `total` steps.
+    pub fn step(&self, done: usize, total: usize, stage: impl Into<String>) {
+        let percent = if total == 0 {
+            100.0
+        } else {
+            done as f32 * 100.0 / total as f32
+        };
+        self.report(percent, stage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    async fn wait_finished(registry: &JobRegistry, id: &str) -> JobInfo {
+        for _ in 0..200 {
+            let info = registry.get(id).unwrap();
+            if info.status.is_finished() {
+                return info;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("job {} did not finish", id);
+    }
+
+    #[tokio::test]
+    async fn test_job_completes_with_result() {
+        let registry = create_shared_registry();
+        let mut events = registry.subscribe();
+
+        let job = registry.spawn("test.job", |progress| async move {
+            progress.step(1, 2, "halfway");
+            Ok(json!({"answer": 42}))
+        });
+        assert_eq!(job.status, JobStatus::Running);
+
+        let info = wait_finished(&registry, &job.id).await;
+        assert_eq!(info.status, JobStatus::Completed);
+        assert_eq!(info.percent, 100.0);
+        assert_eq!(info.result, Some(json!({"answer": 42})));
+
+        let first = events.recv().await.unwrap();
+        assert_eq!(first.status, JobStatus::Running);
+        let second = events.recv().await.unwrap();
+        assert_eq!(second.stage.as_deref(), Some("halfway"));
+        assert_eq!(second.percent, 50.0);
+        assert_eq!(events.recv().await.unwrap().status, JobStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_job_failure_is_recorded() {
+        let registry = create_shared_registry();
+        let job = registry.spawn("test.job", |_| async move {
+            Err::<Value, _>(anyhow::anyhow!("boom"))
+        });
+
+        let info = wait_finished(&registry, &job.id).await;
+        assert_eq!(info.status, JobStatus::Failed);
+        assert_eq!(info.error.as_deref(), Some("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stops_running_job() {
+        let registry = create_shared_registry();
+        let job = registry.spawn("test.job", |_| async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(Value::Null)
+        });
+
+        let cancelled = registry.cancel(&job.id).unwrap();
+        assert_eq!(cancelled.status, JobStatus::Cancelled);
+        assert!(registry.cancel("missing").is_none());
+
+        // Late progress from an aborted task must not revive the job
+        registry.report(&job.id, 10.0, "late".to_string());
+        assert_eq!(registry.get(&job.id).unwrap().status, JobStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_list_and_small_progress_steps() {
+        let registry = create_shared_registry();
+        let first = registry.spawn("a", |_| async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(Value::Null)
+        });
+        let second = registry.spawn("b", |_| async move {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(Value::Null)
+        });
+        assert_eq!(registry.list().len(), 2);
+
+        let mut events = registry.subscribe();
+        registry.report(&first.id, 10.0, "downloading".to_string());
+        registry.report(&first.id, 10.5, "downloading".to_string());
+        registry.report(&first.id, 12.0, "downloading".to_string());
+        assert_eq!(events.recv().await.unwrap().percent, 10.0);
+        assert_eq!(events.recv().await.unwrap().percent, 12.0);
+
+        registry.cancel(&first.id);
+        registry.cancel(&second.id);
+    }
+}
@@ -282,7 +282,7 @@ impl LabelStore {
             .values()
             .map(|l| (l.name.clone(), l.item_count))
             .collect();
-        top_labels.sort_by(|a, b| b.1.cmp(&a.1));
+        top_labels.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
         top_labels.truncate(10);
 
         LabelStats {
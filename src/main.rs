@@ -8,6 +8,7 @@ mod epub;
 mod git;
 mod highlights;
 mod integrations;
+mod jobs;
 mod labels;
 mod newsletter;
 mod nlp;
@@ -49,7 +50,8 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
         ))
-        .with(tracing_subscriber::fmt::layer())
+        // stdout carries JSON-RPC traffic in stdio mode, so logs go to stderr
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
     let cli = Cli::parse();
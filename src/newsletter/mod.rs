@@ -10,6 +10,8 @@ use std::path::PathBuf;
 use thiserror::Error;
 use uuid::Uuid;
 
+use crate::jobs::Progress;
+
 #[derive(Error, Debug)]
 pub enum NewsletterError {
     #[error("IO error: {0}")]
@@ -186,6 +188,10 @@ impl NewsletterStore {
         self.newsletters.get(id)
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Newsletter> {
+        self.newsletters.values()
+    }
+
     pub fn query(&self, q: NewsletterQuery) -> Vec<&Newsletter> {
         let mut results: Vec<&Newsletter> = self
             .newsletters
@@ -228,7 +234,7 @@ impl NewsletterStore {
             })
             .collect();
 
-        results.sort_by(|a, b| b.received_at.cmp(&a.received_at));
+        results.sort_by_key(|n| std::cmp::Reverse(n.received_at));
 
         let offset = q.offset.unwrap_or(0);
         let limit = q.limit.unwrap_or(50);
@@ -255,6 +261,19 @@ impl NewsletterStore {
         Ok(starred)
     }
 
+    pub fn add_label(&mut self, id: &str, label: &str) -> Result<Newsletter, NewsletterError> {
+        let newsletter = self
+            .newsletters
+            .get_mut(id)
+            .ok_or_else(|| NewsletterError::NotFound(id.to_string()))?;
+        if !newsletter.labels.iter().any(|l| l == label) {
+            newsletter.labels.push(label.to_string());
+        }
+        let updated = newsletter.clone();
+        self.save_all()?;
+        Ok(updated)
+    }
+
     pub fn delete(&mut self, id: &str) -> Result<(), NewsletterError> {
         self.newsletters
             .remove(id)
@@ -441,7 +460,9 @@ fn extract_content(mail: &mailparse::ParsedMail) -> (String, Option<String>) {
 pub async fn fetch_newsletters(
     data_dir: PathBuf,
     req: FetchNewslettersRequest,
+    progress: &Progress,
 ) -> Result<Vec<Newsletter>, NewsletterError> {
+    progress.report(0.0, "Connecting to mail server");
     let mut session = connect_imap(&req.config).await?;
 
     let folder = req.config.folder.as_deref().unwrap_or("INBOX");
@@ -464,7 +485,13 @@ pub async fn fetch_newsletters(
     let mut store = NewsletterStore::new(data_dir)?;
     let mut newsletters = Vec::new();
 
-    for msg_id in message_ids {
+    let total = message_ids.len();
+    for (index, msg_id) in message_ids.into_iter().enumerate() {
+        progress.step(
+            index,
+            total,
+            format!("Fetching message {}/{}", index + 1, total),
+        );
         let mut fetches = session.fetch(msg_id.to_string(), "RFC822").await?;
 
         while let Some(fetch_result) = fetches.next().await {
@@ -743,6 +770,21 @@ mod tests {
         assert!(!store.get("test-123").unwrap().is_starred);
     }
 
+    #[test]
+    fn test_newsletter_store_add_label() {
+        let dir = tempdir().unwrap();
+        let mut store = NewsletterStore::new(dir.path().to_path_buf()).unwrap();
+
+        store.add(create_test_newsletter()).unwrap();
+
+        let updated = store.add_label("test-123", "rust").unwrap();
+        assert_eq!(updated.labels, vec!["tech", "rust"]);
+
+        let same = store.add_label("test-123", "rust").unwrap();
+        assert_eq!(same.labels.len(), 2);
+        assert!(store.add_label("missing", "rust").is_err());
+    }
+
     #[test]
     fn test_newsletter_store_delete() {
         let dir = tempdir().unwrap();
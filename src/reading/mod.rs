@@ -262,6 +262,10 @@ impl ReadingStore {
         self.articles.get(id)
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &Article> {
+        self.articles.values()
+    }
+
     pub fn query(&self, q: ArticleQuery) -> Vec<&Article> {
         let mut results: Vec<&Article> = self
             .articles
@@ -1,3 +1,4 @@
+mod routes;
 mod server;
 mod types;
 
@@ -0,0 +1,254 @@
+use axum::http::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{method, parse, str_param, to_value, MethodSpec, RpcContext, RpcError, RpcResult};
+use crate::ai;
+use crate::rpc::server::get_data_dir;
+use crate::rpc::types::*;
+use crate::tier::{check_ai_limit, check_rag_limit};
+use crate::utils::text::truncate;
+
+pub(super) fn methods() -> Vec<MethodSpec> {
+    vec![
+        method!("ai.chat", Post "/api/ai/chat", ai_chat),
+        method!("ai.summarize", Post "/api/ai/summarize", ai_summarize),
+        method!("ai.index", Post "/api/ai/index", ai_index),
+        method!("ai.remove", Post "/api/ai/remove", ai_remove),
+        method!("ai.search", Post "/api/ai/search", ai_search),
+        method!("ai.rag", Post "/api/ai/rag", ai_rag),
+        method!("ai.extract", Post "/api/ai/extract", ai_extract),
+        method!("ai.related", Post "/api/ai/related", ai_related_notes),
+        method!("ai.conversations.list", Get "/api/ai/conversations", conversations_list),
+        method!("ai.conversations.create", Post "/api/ai/conversations", conversations_create),
+        method!("ai.conversations.get", Get "/api/ai/conversations/{id}", conversations_get),
+        method!("ai.conversations.send", Post "/api/ai/conversations/{id}/messages", conversations_send),
+        method!("ai.conversations.rename", Put "/api/ai/conversations/{id}", conversations_rename),
+        method!("ai.conversations.delete", Delete "/api/ai/conversations/{id}", conversations_delete),
+        method!("ai.config.get", Get "/api/ai/config", ai_get_config),
+        method!("ai.config.update", Post "/api/ai/config", ai_update_config),
+        method!("ai.embedding.models", Get "/api/ai/embedding/models", ai_embedding_models),
+        method!("ai.reranker.models", Get "/api/ai/reranker/models", ai_reranker_models),
+        method!("ollama.status", Get "/api/ollama/status", ollama_status),
+        method!("ollama.models", Get "/api/ollama/models", ollama_models),
+        method!("ollama.generate", Post "/api/ollama/generate", ollama_generate),
+        method!("ollama.chat", Post "/api/ollama/chat", ollama_chat),
+    ]
+}
+
+async fn ai_chat(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: AiChatRequest = parse(&params)?;
+    check_ai_limit(&ctx.tier, &ctx.state.usage_tracker).await?;
+    match ctx.tokens {
+        Some(tokens) => to_value(ai::chat_stream(&request, tokens).await?),
+        None => to_value(ai::chat(&request).await?),
+    }
+}
+
+async fn conversations_list(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let conversations = ai::conversations::list(&get_data_dir())?;
+    Ok(json!({"conversations": conversations}))
+}
+
+async fn conversations_create(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: ConversationCreateRequest = parse(&params)?;
+    to_value(ai::conversations::create(&get_data_dir(), &request)?)
+}
+
+async fn conversations_get(_ctx: RpcContext, params: Value) -> RpcResult {
+    let id = str_param(&params, "id");
+    match ai::conversations::get(&get_data_dir(), id).map_err(RpcError::bad_request)? {
+        Some(conversation) => to_value(conversation),
+        None => Err(RpcError::bad_request(format!(
+            "Conversation not found: {}",
+            id
+        ))),
+    }
+}
+
+/// Continues a conversation; streams the reply like `ai.chat`. Turns grounded
+/// in notes also count against the RAG limit.
+async fn conversations_send(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: ConversationMessageRequest = parse(&params)?;
+    let Some(conversation) =
+        ai::conversations::get(&get_data_dir(), &request.id).map_err(RpcError::bad_request)?
+    else {
+        return Err(RpcError::bad_request(format!(
+            "Conversation not found: {}",
+            request.id
+        )));
+    };
+    if request.rag.unwrap_or(conversation.rag) {
+        check_rag_limit(&ctx.tier, &ctx.state.usage_tracker).await?;
+    }
+    check_ai_limit(&ctx.tier, &ctx.state.usage_tracker).await?;
+    let reply = ai::conversations::send(&get_data_dir(), &request, ctx.tokens).await;
+    to_value(reply.map_err(RpcError::bad_request)?)
+}
+
+async fn conversations_rename(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: ConversationRenameRequest = parse(&params)?;
+    let conversation = ai::conversations::rename(&get_data_dir(), &request.id, &request.title);
+    to_value(conversation.map_err(RpcError::bad_request)?)
+}
+
+async fn conversations_delete(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: ConversationDeleteRequest = parse(&params)?;
+    let deleted =
+        ai::conversations::delete(&get_data_dir(), &request.id).map_err(RpcError::bad_request)?;
+    Ok(json!({"deleted": deleted}))
+}
+
+async fn ai_summarize(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: AiSummarizeRequest = parse(&params)?;
+    check_ai_limit(&ctx.tier, &ctx.state.usage_tracker).await?;
+    match ctx.tokens {
+        Some(tokens) => to_value(ai::summarize_stream(&request, tokens).await?),
+        None => to_value(ai::summarize(&request).await?),
+    }
+}
+
+async fn ai_index(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: AiIndexRequest = parse(&params)?;
+    to_value(ctx.state.jobs.spawn("ai.index", |progress| async move {
+        Ok(serde_json::to_value(
+            ai::index_notes(&request, &progress).await?,
+        )?)
+    }))
+}
+
+async fn ai_remove(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: AiRemoveRequest = parse(&params)?;
+    to_value(ai::remove_notes(&request).await?)
+}
+
+/// Returns the config with API keys and auth headers masked.
+async fn ai_get_config(_ctx: RpcContext, _params: Value) -> RpcResult {
+    to_value(ai::get_config(&get_data_dir())?.redacted())
+}
+
+/// Saves the config. Model changes are applied to the loaded index in a
+/// background job, returned as `job`.
+async fn ai_update_config(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: ai::AiConfig = parse(&params)?;
+    let changes = ai::update_config(&get_data_dir(), request).map_err(RpcError::bad_request)?;
+    if changes.is_empty() {
+        return Ok(json!({"success": true}));
+    }
+
+    let job = ctx
+        .state
+        .jobs
+        .spawn("ai.config.apply", |progress| async move {
+            ai::apply_config_changes(&changes, &progress).await?;
+            Ok(serde_json::to_value(&changes)?)
+        });
+    Ok(json!({"success": true, "job": job}))
+}
+
+async fn ai_embedding_models(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let (models, active) = ai::embedding_models().await;
+    Ok(json!({"models": models, "active": active}))
+}
+
+async fn ai_reranker_models(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let (models, active) = ai::reranker_models().await;
+    Ok(json!({"models": models, "active": active}))
+}
+
+async fn ai_search(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: AiSearchRequest = parse(&params)?;
+    to_value(ai::search_notes(&request).await?)
+}
+
+async fn ai_rag(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: AiRagRequest = parse(&params)?;
+    check_rag_limit(&ctx.tier, &ctx.state.usage_tracker).await?;
+    match ctx.tokens {
+        Some(tokens) => to_value(ai::rag_query_stream(&request, tokens).await?),
+        None => to_value(ai::rag_query(&request).await?),
+    }
+}
+
+async fn ai_extract(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: AiExtractRequest = parse(&params)?;
+    ai::extract::check_schema(&request.schema).map_err(RpcError::bad_request)?;
+    check_ai_limit(&ctx.tier, &ctx.state.usage_tracker).await?;
+    to_value(ai::extract(&request).await?)
+}
+
+async fn ai_related_notes(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: RelatedNotesRequest = parse(&params)?;
+    let limit = request.limit.unwrap_or(5);
+
+    let results =
+        ai::find_related_notes(&request.content, limit, request.current_path.as_deref()).await?;
+    let notes: Vec<RelatedNote> = results
+        .into_iter()
+        .map(|r| RelatedNote {
+            id: r.id,
+            title: r.title,
+            path: r.path,
+            score: r.score,
+            snippet: truncate(&r.content, 150),
+        })
+        .collect();
+    to_value(RelatedNotesResponse { notes })
+}
+
+async fn ollama_status(_ctx: RpcContext, _params: Value) -> RpcResult {
+    to_value(ai::ollama::check_status().await)
+}
+
+async fn ollama_models(_ctx: RpcContext, _params: Value) -> RpcResult {
+    match ai::ollama::list_models().await {
+        Ok(models) => Ok(json!({"models": models})),
+        Err(e) => Err(RpcError::Status(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            json!({"error": e.to_string(), "models": []}),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct OllamaGenerateRequest {
+    model: String,
+    prompt: String,
+    system: Option<String>,
+}
+
+async fn ollama_generate(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: OllamaGenerateRequest = parse(&params)?;
+    let response =
+        ai::ollama::generate(&request.model, &request.prompt, request.system.as_deref()).await?;
+    Ok(json!({"response": response}))
+}
+
+#[derive(Deserialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<OllamaChatMessage>,
+    system: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaChatMessage {
+    role: String,
+    content: String,
+}
+
+async fn ollama_chat(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: OllamaChatRequest = parse(&params)?;
+    let messages: Vec<(String, String)> = request
+        .messages
+        .into_iter()
+        .map(|m| (m.role, m.content))
+        .collect();
+
+    let system = request.system.as_deref();
+    let response = match ctx.tokens {
+        Some(tokens) => ai::ollama::chat_stream(&request.model, messages, system, tokens).await?,
+        None => ai::ollama::chat(&request.model, messages, system).await?,
+    };
+    Ok(json!({"response": response}))
+}
@@ -0,0 +1,103 @@
+use serde_json::{json, Value};
+
+use super::{method, parse, to_value, MethodSpec, RpcContext, RpcResult};
+use crate::integrations::{gcal, hoarder, kindle, readwise, todoist, wallabag};
+use crate::rpc::types::*;
+use crate::tier::{check_pro_feature, ProFeature};
+
+pub(super) fn methods() -> Vec<MethodSpec> {
+    vec![
+        method!("wallabag.sync", Post "/api/wallabag/sync", wallabag_sync),
+        method!("hoarder.sync", Post "/api/hoarder/sync", hoarder_sync),
+        method!("readwise.sync", Post "/api/readwise/sync", readwise_sync),
+        method!("kindle.sync", Post "/api/kindle/sync", kindle_sync),
+        method!("todoist.tasks", Post "/api/todoist/tasks", todoist_fetch_tasks),
+        method!("todoist.projects", Post "/api/todoist/projects", todoist_fetch_projects),
+        method!("todoist.create", Post "/api/todoist/create", todoist_create_task),
+        method!("todoist.complete", Post "/api/todoist/complete", todoist_complete_task),
+        method!("todoist.sync", Post "/api/todoist/sync", todoist_sync),
+        method!("gcal.events", Post "/api/gcal/events", gcal_fetch_events),
+        method!("gcal.today", Post "/api/gcal/today", gcal_fetch_today),
+        method!("gcal.create", Post "/api/gcal/create", gcal_create_event),
+        method!("gcal.sync", Post "/api/gcal/sync", gcal_sync),
+    ]
+}
+
+async fn wallabag_sync(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: WallabagSyncRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncWallabag)?;
+    to_value(wallabag::sync(&request.config, request.limit).await?)
+}
+
+async fn hoarder_sync(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: HoarderSyncRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncHoarder)?;
+    to_value(hoarder::sync(&request.config, request.limit).await?)
+}
+
+async fn readwise_sync(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: ReadwiseSyncRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncReadwise)?;
+    to_value(readwise::sync(&request.config, request.updated_after.as_deref()).await?)
+}
+
+async fn kindle_sync(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: KindleSyncRequest = parse(&params)?;
+    let path = std::path::Path::new(&request.clippings_path);
+    to_value(kindle::sync_from_file(path).await?)
+}
+
+async fn todoist_fetch_tasks(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: todoist::FetchTasksRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncTodoist)?;
+    to_value(todoist::fetch_tasks(&request).await?)
+}
+
+async fn todoist_fetch_projects(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: todoist::FetchProjectsRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncTodoist)?;
+    to_value(todoist::fetch_projects(&request).await?)
+}
+
+async fn todoist_create_task(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: todoist::CreateTaskRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncTodoist)?;
+    to_value(todoist::create_task(&request).await?)
+}
+
+async fn todoist_complete_task(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: todoist::CompleteTaskRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncTodoist)?;
+    let completed = todoist::complete_task(&request).await?;
+    Ok(json!({"completed": completed}))
+}
+
+async fn todoist_sync(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: todoist::SyncToObsidianRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncTodoist)?;
+    to_value(todoist::sync_to_obsidian(&request).await?)
+}
+
+async fn gcal_fetch_events(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: gcal::FetchEventsRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncGcal)?;
+    to_value(gcal::fetch_events(&request).await?)
+}
+
+async fn gcal_fetch_today(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: gcal::FetchTodayEventsRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncGcal)?;
+    to_value(gcal::fetch_today_events(&request).await?)
+}
+
+async fn gcal_create_event(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: gcal::CreateEventRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncGcal)?;
+    to_value(gcal::create_event(&request).await?)
+}
+
+async fn gcal_sync(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: gcal::SyncToObsidianRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::SyncGcal)?;
+    to_value(gcal::sync_to_obsidian(&request).await?)
+}
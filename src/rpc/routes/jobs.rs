@@ -0,0 +1,110 @@
+use axum::{
+    extract::{Path, State},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures::stream::{self, Stream};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use super::{method, str_param, to_value, MethodSpec, RpcContext, RpcError, RpcResult};
+use crate::jobs::{JobInfo, SharedJobRegistry};
+use crate::rpc::server::AppState;
+
+pub(super) fn methods() -> Vec<MethodSpec> {
+    vec![
+        method!("jobs.list", Get "/api/jobs", jobs_list),
+        method!("jobs.get", Get "/api/jobs/{id}", jobs_get),
+        method!("jobs.cancel", Post "/api/jobs/{id}/cancel", jobs_cancel),
+    ]
+}
+
+async fn jobs_list(ctx: RpcContext, _params: Value) -> RpcResult {
+    to_value(ctx.state.jobs.list())
+}
+
+async fn jobs_get(ctx: RpcContext, params: Value) -> RpcResult {
+    let id = str_param(&params, "id");
+    match ctx.state.jobs.get(id) {
+        Some(info) => to_value(info),
+        None => Err(RpcError::bad_request(format!("Job not found: {}", id))),
+    }
+}
+
+async fn jobs_cancel(ctx: RpcContext, params: Value) -> RpcResult {
+    let id = str_param(&params, "id");
+    match ctx.state.jobs.cancel(id) {
+        Some(info) => to_value(info),
+        None => Err(RpcError::bad_request(format!("Job not found: {}", id))),
+    }
+}
+
+/// `GET /api/jobs/{id}/events`: the job's current snapshot followed by every
+/// update, ending once the job finishes.
+pub(super) async fn job_events(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Response {
+    // Subscribe before taking the snapshot so no update falls in between
+    let receiver = state.jobs.subscribe();
+    let Some(snapshot) = state.jobs.get(&id) else {
+        return RpcError::bad_request(format!("Job not found: {}", id)).into_response();
+    };
+    Sse::new(event_stream(state.jobs.clone(), receiver, Some(snapshot)))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// `GET /api/jobs/events`: updates for every job, for as long as the client
+/// stays connected.
+pub(super) async fn all_job_events(State(state): State<Arc<AppState>>) -> Response {
+    let receiver = state.jobs.subscribe();
+    Sse::new(event_stream(state.jobs.clone(), receiver, None))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Streams `progress` events. With a snapshot, only that job is followed and
+/// the stream ends after its final state; otherwise all jobs are forwarded.
+fn event_stream(
+    jobs: SharedJobRegistry,
+    receiver: Receiver<JobInfo>,
+    snapshot: Option<JobInfo>,
+) -> impl Stream<Item = Result<Event, axum::Error>> {
+    let follow = snapshot.as_ref().map(|info| info.id.clone());
+    stream::unfold(
+        (receiver, snapshot, false),
+        move |(mut receiver, pending, done)| {
+            let jobs = jobs.clone();
+            let follow = follow.clone();
+            async move {
+                if done {
+                    return None;
+                }
+                let info = match pending {
+                    Some(info) => info,
+                    None => loop {
+                        match receiver.recv().await {
+                            Ok(info) if follow.as_ref().is_none_or(|id| *id == info.id) => {
+                                break info
+                            }
+                            Ok(_) => continue,
+                            // Missed updates are superseded by the current snapshot
+                            Err(RecvError::Lagged(_)) => match follow.as_deref() {
+                                Some(id) => break jobs.get(id)?,
+                                None => continue,
+                            },
+                            Err(RecvError::Closed) => return None,
+                        }
+                    },
+                };
+                let done = follow.is_some() && info.status.is_finished();
+                let event = Event::default().event("progress").json_data(&info);
+                Some((event, (receiver, None, done)))
+            }
+        },
+    )
+}
@@ -0,0 +1,170 @@
+use serde_json::{json, Value};
+
+use super::{method, parse, str_param, to_value, MethodSpec, RpcContext, RpcResult};
+use crate::audio;
+use crate::epub;
+use crate::pdf;
+use crate::rpc::types::*;
+use crate::rss;
+use crate::tier::{
+    self, check_pro_feature, check_rss_limit, middleware::TierErrorResponse, ProFeature,
+};
+use crate::tts;
+use crate::web_clip;
+use crate::youtube;
+
+pub(super) fn methods() -> Vec<MethodSpec> {
+    vec![
+        method!("youtube.extract", Post "/api/youtube/extract", youtube_extract),
+        method!("youtube.batch", Post "/api/youtube/batch", youtube_extract_batch),
+        method!("webclip.extract", Post "/api/webclip/extract", webclip_extract),
+        method!("rss.fetch", Post "/api/rss/fetch", rss_fetch),
+        method!("pdf.extract", Post "/api/pdf/extract", pdf_extract),
+        method!("pdf.tables", Post "/api/pdf/tables", pdf_extract_tables),
+        method!("epub.parse", Post "/api/epub/parse", epub_parse),
+        method!("epub.to_markdown", Post "/api/epub/to-markdown", epub_to_markdown),
+        method!("epub.metadata", Post "/api/epub/metadata", epub_metadata),
+        method!("epub.chapter", Post "/api/epub/chapter", epub_chapter),
+        method!("tts.speak", Post "/api/tts/speak", tts_speak),
+        method!("tts.stop", Post "/api/tts/stop", tts_stop),
+        method!("tts.voices", Get "/api/tts/voices", tts_voices),
+        method!("tts.status", Get "/api/tts/status", tts_status),
+        method!("tts.read_article", Post "/api/tts/read-article", tts_read_article),
+        method!("audio.transcribe", Post "/api/audio/transcribe", audio_transcribe),
+        method!("audio.model.download", Post "/api/audio/model/download", audio_model_download),
+    ]
+}
+
+async fn youtube_extract(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: YouTubeRequest = parse(&params)?;
+    if request.generate_ai_chapters {
+        check_pro_feature(&ctx.tier, ProFeature::YoutubeAiChapters)?;
+    }
+    to_value(youtube::extract(&request).await?)
+}
+
+async fn youtube_extract_batch(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: YouTubeBatchRequest = parse(&params)?;
+    let limits = tier::limits::FreeLimits::get();
+
+    if !ctx.tier.is_pro() && request.urls.len() > limits.youtube_batch_size as usize {
+        return Err(TierErrorResponse::pro_only(ProFeature::YoutubeBatch.as_str()).into());
+    }
+
+    if request.generate_ai_chapters {
+        check_pro_feature(&ctx.tier, ProFeature::YoutubeAiChapters)?;
+    }
+
+    to_value(
+        ctx.state
+            .jobs
+            .spawn("youtube.batch", |progress| async move {
+                Ok(serde_json::to_value(
+                    youtube::extract_batch(&request, &progress).await,
+                )?)
+            }),
+    )
+}
+
+async fn webclip_extract(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: WebClipRequest = parse(&params)?;
+    to_value(web_clip::extract(&request).await?)
+}
+
+async fn rss_fetch(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: RssRequest = parse(&params)?;
+    check_rss_limit(&ctx.tier, &ctx.state.usage_tracker, &request.url).await?;
+
+    let response = rss::fetch(&request).await?;
+    let mut tracker = ctx.state.usage_tracker.write().await;
+    let _ = tracker.add_rss_feed(&request.url);
+    to_value(response)
+}
+
+async fn pdf_extract(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: PdfRequest = parse(&params)?;
+    if request.ocr {
+        check_pro_feature(&ctx.tier, ProFeature::PdfOcr)?;
+    }
+    to_value(pdf::extract(&request).await?)
+}
+
+async fn pdf_extract_tables(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: PdfTablesRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::PdfTables)?;
+    let tables = pdf::extract_tables_only(&request.path).await?;
+    Ok(json!({"tables": tables}))
+}
+
+async fn epub_parse(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: epub::ParseEpubRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::Epub)?;
+    to_value(epub::parse_epub(request)?)
+}
+
+async fn epub_to_markdown(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: epub::EpubToMarkdownRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::Epub)?;
+    let markdown = epub::epub_to_markdown(request)?;
+    Ok(json!({"markdown": markdown}))
+}
+
+async fn epub_metadata(ctx: RpcContext, params: Value) -> RpcResult {
+    check_pro_feature(&ctx.tier, ProFeature::Epub)?;
+    to_value(epub::get_epub_metadata(str_param(&params, "path"))?)
+}
+
+async fn epub_chapter(ctx: RpcContext, params: Value) -> RpcResult {
+    check_pro_feature(&ctx.tier, ProFeature::Epub)?;
+    let index = params.get("index").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+    to_value(epub::get_epub_chapter(str_param(&params, "path"), index)?)
+}
+
+async fn tts_speak(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: tts::TtsRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::Tts)?;
+    tts::speak(request)?;
+    Ok(json!({"success": true}))
+}
+
+async fn tts_stop(ctx: RpcContext, _params: Value) -> RpcResult {
+    check_pro_feature(&ctx.tier, ProFeature::Tts)?;
+    tts::stop_speaking()?;
+    Ok(json!({"success": true}))
+}
+
+async fn tts_voices(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let voices = tts::list_voices()?;
+    Ok(json!({"voices": voices}))
+}
+
+async fn tts_status(_ctx: RpcContext, _params: Value) -> RpcResult {
+    to_value(tts::check_tts_status())
+}
+
+async fn tts_read_article(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: tts::ReadArticleRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::Tts)?;
+    tts::read_article(request)?;
+    Ok(json!({"success": true}))
+}
+
+async fn audio_transcribe(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: TranscribeRequest = parse(&params)?;
+    to_value(audio::transcribe(&request).await?)
+}
+
+async fn audio_model_download(ctx: RpcContext, params: Value) -> RpcResult {
+    let model_name = params
+        .get("model_name")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    to_value(
+        ctx.state
+            .jobs
+            .spawn("audio.model.download", |progress| async move {
+                let path = audio::download_model(model_name.as_deref(), &progress).await?;
+                Ok(json!({"success": true, "path": path}))
+            }),
+    )
+}
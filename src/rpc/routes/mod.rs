@@ -0,0 +1,538 @@
+//! Shared method registry for the HTTP and JSON-RPC transports.
+//!
+//! Every endpoint is declared once as a [`MethodSpec`]: a JSON-RPC method name,
+//! an optional HTTP verb and path, and a handler that takes JSON params. Both
+//! the axum router and the JSON-RPC dispatcher are built from this table, so a
+//! method added here is reachable over HTTP, `/rpc` and stdio alike.
+
+mod ai;
+mod integrations;
+mod jobs;
+mod media;
+mod notes;
+mod reading;
+mod sr;
+mod system;
+mod utils;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, on, MethodFilter, MethodRouter},
+    Json, Router,
+};
+use futures::future::BoxFuture;
+use futures::stream::{self, StreamExt};
+use serde::de::{
+    self, value::MapDeserializer, DeserializeOwned, Deserializer, IntoDeserializer, Visitor,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use super::server::AppState;
+use crate::ai::TokenSink;
+use crate::tier::extract_tier_from_headers;
+use crate::tier::limits::TierType;
+use crate::tier::middleware::TierErrorResponse;
+
+/// Per-call context handed to every handler, regardless of transport.
+#[derive(Clone)]
+pub struct RpcContext {
+    pub state: Arc<AppState>,
+    pub tier: TierType,
+    /// Set when the caller asked for `"stream": true` and the transport can
+    /// deliver incremental output (SSE over HTTP, `$/stream` over stdio).
+    /// Handlers that generate text send it here as it is produced.
+    pub tokens: Option<TokenSink>,
+}
+
+pub type RpcResult = Result<Value, RpcError>;
+pub type Handler = fn(RpcContext, Value) -> BoxFuture<'static, RpcResult>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verb {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Verb {
+    fn filter(self) -> MethodFilter {
+        match self {
+            Verb::Get => MethodFilter::GET,
+            Verb::Post => MethodFilter::POST,
+            Verb::Put => MethodFilter::PUT,
+            Verb::Delete => MethodFilter::DELETE,
+        }
+    }
+}
+
+pub struct MethodSpec {
+    /// JSON-RPC method name, e.g. `reading.save`
+    pub name: &'static str,
+    /// HTTP binding, if the method is also exposed as a REST route
+    pub http: Option<(Verb, &'static str)>,
+    pub handler: Handler,
+}
+
+/// Declares a [`MethodSpec`] for an `async fn(RpcContext, Value) -> RpcResult`.
+macro_rules! method {
+    ($name:literal, $handler:path) => {
+        $crate::rpc::routes::MethodSpec {
+            name: $name,
+            http: None,
+            handler: |ctx, params| Box::pin($handler(ctx, params)),
+        }
+    };
+    ($name:literal, $verb:ident $path:literal, $handler:path) => {
+        $crate::rpc::routes::MethodSpec {
+            name: $name,
+            http: Some(($crate::rpc::routes::Verb::$verb, $path)),
+            handler: |ctx, params| Box::pin($handler(ctx, params)),
+        }
+    };
+}
+pub(crate) use method;
+
+#[derive(Debug)]
+pub enum RpcError {
+    /// Params did not match the method's request type
+    InvalidParams(String),
+    /// Params were well-formed but rejected by the handler
+    BadRequest(String),
+    MethodNotFound(String),
+    Internal(String),
+    /// Tier gate rejected the call (402/429 over HTTP)
+    Tier(Box<TierErrorResponse>),
+    /// Failure that carries its own response body and HTTP status
+    Status(StatusCode, Value),
+}
+
+impl<E: std::fmt::Display> From<E> for RpcError {
+    fn from(err: E) -> Self {
+        RpcError::Internal(err.to_string())
+    }
+}
+
+impl From<TierErrorResponse> for RpcError {
+    fn from(err: TierErrorResponse) -> Self {
+        RpcError::Tier(Box::new(err))
+    }
+}
+
+impl RpcError {
+    pub fn bad_request(err: impl std::fmt::Display) -> Self {
+        RpcError::BadRequest(err.to_string())
+    }
+
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcError::InvalidParams(_) => -32602,
+            RpcError::MethodNotFound(_) => -32601,
+            RpcError::Tier(_) => -32001,
+            RpcError::BadRequest(_) | RpcError::Internal(_) | RpcError::Status(..) => -32000,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            RpcError::InvalidParams(msg) => format!("Invalid params: {}", msg),
+            RpcError::MethodNotFound(method) => format!("Method not found: {}", method),
+            RpcError::BadRequest(msg) | RpcError::Internal(msg) => msg.clone(),
+            RpcError::Tier(err) => err.error.clone(),
+            RpcError::Status(_, body) => body
+                .get("message")
+                .or_else(|| body.get("error"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("Request failed")
+                .to_string(),
+        }
+    }
+
+    /// Builds the `error` member of a JSON-RPC response.
+    pub fn to_jsonrpc(&self) -> Value {
+        let mut error = json!({
+            "code": self.code(),
+            "message": self.message(),
+        });
+        match self {
+            RpcError::Tier(err) => {
+                error["data"] = serde_json::to_value(err).unwrap_or(Value::Null);
+            }
+            RpcError::Status(_, body) => {
+                error["data"] = body.clone();
+            }
+            _ => {}
+        }
+        error
+    }
+}
+
+impl IntoResponse for RpcError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            RpcError::Tier(err) => return (*err).into_response(),
+            RpcError::Status(status, body) => return (status, Json(body)).into_response(),
+            RpcError::InvalidParams(_) | RpcError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            RpcError::MethodNotFound(_) => StatusCode::NOT_FOUND,
+            RpcError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, Json(json!({"error": self.message()}))).into_response()
+    }
+}
+
+/// Deserializes handler params into a request type.
+///
+/// Query-string params arrive as strings, so a failed parse of an all-string
+/// object is retried reading each string as whatever its field expects: a
+/// number or boolean only where the field is one, the string itself elsewhere.
+pub fn parse<T: DeserializeOwned>(params: &Value) -> Result<T, RpcError> {
+    T::deserialize(params).or_else(|err| {
+        let Some(query) = query_strings(params) else {
+            return Err(RpcError::InvalidParams(err.to_string()));
+        };
+        T::deserialize(MapDeserializer::<_, serde_json::Error>::new(query))
+            .map_err(|_| RpcError::InvalidParams(err.to_string()))
+    })
+}
+
+fn query_strings(params: &Value) -> Option<impl Iterator<Item = (&str, QueryStr<'_>)>> {
+    let obj = params.as_object()?;
+    if obj.is_empty() || !obj.values().all(Value::is_string) {
+        return None;
+    }
+    Some(
+        obj.iter()
+            .map(|(key, value)| (key.as_str(), QueryStr(value.as_str().unwrap_or_default()))),
+    )
+}
+
+/// A query-string value, parsed only when the target type asks for a number
+/// or boolean.
+struct QueryStr<'a>(&'a str);
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $ty:ty, $visit:ident;)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let parsed: $ty = self.0.parse().map_err(de::Error::custom)?;
+                visitor.$visit(parsed)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for QueryStr<'_> {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0
+            .into_deserializer()
+            .deserialize_enum(name, variants, visitor)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => bool, visit_bool;
+        deserialize_i8 => i8, visit_i8;
+        deserialize_i16 => i16, visit_i16;
+        deserialize_i32 => i32, visit_i32;
+        deserialize_i64 => i64, visit_i64;
+        deserialize_u8 => u8, visit_u8;
+        deserialize_u16 => u16, visit_u16;
+        deserialize_u32 => u32, visit_u32;
+        deserialize_u64 => u64, visit_u64;
+        deserialize_f32 => f32, visit_f32;
+        deserialize_f64 => f64, visit_f64;
+    }
+
+    serde::forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, serde_json::Error> for QueryStr<'_> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+pub fn to_value<T: Serialize>(value: T) -> RpcResult {
+    Ok(serde_json::to_value(value)?)
+}
+
+/// Reads an optional string param, defaulting to `""` like the old ad-hoc handlers.
+pub fn str_param<'a>(params: &'a Value, key: &str) -> &'a str {
+    params.get(key).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+/// Whether the caller asked for incremental output.
+pub fn wants_stream(params: &Value) -> bool {
+    match params.get("stream") {
+        Some(Value::Bool(stream)) => *stream,
+        Some(Value::String(stream)) => stream == "true",
+        _ => false,
+    }
+}
+
+pub fn methods() -> &'static [MethodSpec] {
+    static METHODS: OnceLock<Vec<MethodSpec>> = OnceLock::new();
+    METHODS.get_or_init(|| {
+        let mut methods = Vec::new();
+        methods.extend(system::methods());
+        methods.extend(media::methods());
+        methods.extend(ai::methods());
+        methods.extend(integrations::methods());
+        methods.extend(utils::methods());
+        methods.extend(notes::methods());
+        methods.extend(reading::methods());
+        methods.extend(sr::methods());
+        methods.extend(jobs::methods());
+        methods
+    })
+}
+
+pub fn find(name: &str) -> Option<&'static MethodSpec> {
+    static INDEX: OnceLock<HashMap<&'static str, &'static MethodSpec>> = OnceLock::new();
+    INDEX
+        .get_or_init(|| methods().iter().map(|spec| (spec.name, spec)).collect())
+        .get(name)
+        .copied()
+}
+
+pub async fn dispatch(ctx: RpcContext, method: &str, params: Value) -> RpcResult {
+    let spec = find(method).ok_or_else(|| RpcError::MethodNotFound(method.to_string()))?;
+    let params = match params {
+        Value::Null => Value::Object(Map::new()),
+        other => other,
+    };
+    (spec.handler)(ctx, params).await
+}
+
+/// Builds an axum router with every registered HTTP binding, plus the
+/// HTTP-only job event streams.
+pub fn router() -> Router<Arc<AppState>> {
+    methods()
+        .iter()
+        .filter_map(|spec| spec.http.map(|(verb, path)| (spec, verb, path)))
+        .fold(Router::new(), |router, (spec, verb, path)| {
+            router.route(path, http_route(spec, verb))
+        })
+        .route("/api/jobs/events", get(jobs::all_job_events))
+        .route("/api/jobs/{id}/events", get(jobs::job_events))
+}
+
+fn http_route(spec: &'static MethodSpec, verb: Verb) -> MethodRouter<Arc<AppState>> {
+    on(
+        verb.filter(),
+        move |State(state): State<Arc<AppState>>,
+              headers: HeaderMap,
+              path: Option<Path<HashMap<String, String>>>,
+              Query(query): Query<HashMap<String, String>>,
+              body: Bytes| async move {
+            let mut params = if verb == Verb::Get || body.is_empty() {
+                Value::Object(
+                    query
+                        .into_iter()
+                        .map(|(k, v)| (k, Value::String(v)))
+                        .collect(),
+                )
+            } else {
+                match serde_json::from_slice(&body) {
+                    Ok(value) => value,
+                    Err(e) => return RpcError::InvalidParams(e.to_string()).into_response(),
+                }
+            };
+            if let (Some(Path(path)), Some(obj)) = (path, params.as_object_mut()) {
+                for (key, value) in path {
+                    obj.insert(key, Value::String(value));
+                }
+            }
+
+            let ctx = RpcContext {
+                state,
+                tier: extract_tier_from_headers(&headers),
+                tokens: None,
+            };
+            if wants_stream(&params) {
+                return stream_response(ctx, spec.name, params).await;
+            }
+            match dispatch(ctx, spec.name, params).await {
+                Ok(value) => (StatusCode::OK, Json(value)).into_response(),
+                Err(err) => err.into_response(),
+            }
+        },
+    )
+}
+
+/// Runs a call with a token sink and answers with server-sent events:
+/// `token` events carrying `{"text"}`, then `done` with the result or `error`
+/// with the JSON-RPC error object.
+///
+/// A call that fails before producing any text gets a plain error response,
+/// so tier and validation errors keep their HTTP status codes.
+async fn stream_response(mut ctx: RpcContext, method: &'static str, params: Value) -> Response {
+    let (sink, mut tokens) = tokio::sync::mpsc::unbounded_channel();
+    ctx.tokens = Some(sink);
+    let mut call = tokio::spawn(dispatch(ctx, method, params));
+
+    let first = tokio::select! {
+        biased;
+        token = tokens.recv() => token,
+        result = &mut call => return completed_stream_response(result, tokens),
+    };
+    // `None` means the sink was dropped, i.e. the handler already returned
+    let Some(first) = first else {
+        return completed_stream_response(call.await, tokens);
+    };
+
+    let rest = stream::unfold(Some((tokens, call)), |state| async move {
+        let (mut tokens, call) = state?;
+        match tokens.recv().await {
+            Some(text) => Some((token_event(text), Some((tokens, call)))),
+            // The sink is dropped when the handler returns
+            None => {
+                let event = match call.await {
+                    Ok(Ok(value)) => Event::default().event("done").json_data(&value),
+                    Ok(Err(err)) => Event::default().event("error").json_data(err.to_jsonrpc()),
+                    Err(err) => Event::default()
+                        .event("error")
+                        .json_data(RpcError::Internal(err.to_string()).to_jsonrpc()),
+                };
+                Some((event, None))
+            }
+        }
+    });
+    let events = stream::once(async move { token_event(first) }).chain(rest);
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn completed_stream_response(
+    result: Result<RpcResult, tokio::task::JoinError>,
+    mut tokens: tokio::sync::mpsc::UnboundedReceiver<String>,
+) -> Response {
+    let value = match result {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => return err.into_response(),
+        Err(err) => return RpcError::Internal(err.to_string()).into_response(),
+    };
+    // Text may still be buffered; the sink is gone, so this drains it all
+    let mut events: Vec<Result<Event, axum::Error>> = Vec::new();
+    while let Ok(text) = tokens.try_recv() {
+        events.push(token_event(text));
+    }
+    events.push(Event::default().event("done").json_data(&value));
+    Sse::new(stream::iter(events)).into_response()
+}
+
+fn token_event(text: String) -> Result<Event, axum::Error> {
+    Event::default()
+        .event("token")
+        .json_data(json!({"text": text}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_method_names_are_unique() {
+        let mut seen = std::collections::HashSet::new();
+        for spec in methods() {
+            assert!(seen.insert(spec.name), "duplicate method {}", spec.name);
+        }
+    }
+
+    #[test]
+    fn test_http_bindings_are_unique() {
+        let mut seen = std::collections::HashSet::new();
+        for (verb, path) in methods().iter().filter_map(|spec| spec.http) {
+            assert!(seen.insert((path, verb as u8)), "duplicate route {}", path);
+        }
+    }
+
+    #[test]
+    fn test_reading_and_sr_methods_registered() {
+        for name in [
+            "reading.save",
+            "reading.query",
+            "highlights.create",
+            "labels.tree",
+            "sr.session.create",
+            "sr.mastery.review",
+            "snippets.update",
+        ] {
+            assert!(find(name).is_some(), "missing method {}", name);
+        }
+    }
+
+    #[test]
+    fn test_parse_coerces_query_strings() {
+        let params = json!({"limit": "5", "command_filter": "open"});
+        let request: crate::utils::history::HistoryListRequest = parse(&params).unwrap();
+        assert_eq!(request.limit, Some(5));
+        assert_eq!(request.command_filter.as_deref(), Some("open"));
+    }
+
+    #[test]
+    fn test_parse_coerces_only_fields_that_need_it() {
+        let params = json!({"query": "2024", "limit": "5"});
+        let request: crate::utils::emoji::EmojiSearchRequest = parse(&params).unwrap();
+        assert_eq!(request.query, "2024");
+        assert_eq!(request.limit, Some(5));
+
+        let params = json!({"query": "2024", "limit": "many"});
+        let result: Result<crate::utils::emoji::EmojiSearchRequest, _> = parse(&params);
+        assert!(matches!(result, Err(RpcError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn test_parse_keeps_numeric_looking_strings() {
+        let params = json!({"search": "2024"});
+        let request: crate::utils::snippets::SnippetListRequest = parse(&params).unwrap();
+        assert_eq!(request.search.as_deref(), Some("2024"));
+    }
+
+    #[test]
+    fn test_wants_stream() {
+        assert!(wants_stream(&json!({"stream": true})));
+        assert!(wants_stream(&json!({"stream": "true"})));
+        assert!(!wants_stream(&json!({"stream": false})));
+        assert!(!wants_stream(&json!({"message": "hi"})));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(RpcError::MethodNotFound("x".into()).code(), -32601);
+        assert_eq!(RpcError::InvalidParams("x".into()).code(), -32602);
+        let tier = RpcError::from(TierErrorResponse::pro_only("epub"));
+        let error = tier.to_jsonrpc();
+        assert_eq!(error["code"], -32001);
+        assert_eq!(error["data"]["code"], "PRO_ONLY");
+    }
+}
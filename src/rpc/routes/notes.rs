@@ -0,0 +1,173 @@
+use serde_json::{json, Value};
+
+use super::{method, parse, to_value, MethodSpec, RpcContext, RpcError, RpcResult};
+use crate::dataview;
+use crate::git;
+use crate::nlp;
+use crate::periodic;
+use crate::tables;
+use crate::tasks;
+
+pub(super) fn methods() -> Vec<MethodSpec> {
+    vec![
+        method!("tasks.parse", Post "/api/tasks/parse", tasks_parse),
+        method!("tasks.query", Post "/api/tasks/query", tasks_query),
+        method!("nlp.parse_date", Post "/api/nlp/parse-date", nlp_parse_date),
+        method!("nlp.suggest_dates", Post "/api/nlp/suggest-dates", nlp_suggest_dates),
+        method!("dataview.parse", Post "/api/dataview/parse", dataview_parse),
+        method!("dataview.query", Post "/api/dataview/query", dataview_query),
+        method!("dataview.table", Post "/api/dataview/table", dataview_table),
+        method!("tables.parse", Post "/api/tables/parse", tables_parse),
+        method!("tables.format", Post "/api/tables/format", tables_format),
+        method!("tables.sort", Post "/api/tables/sort", tables_sort),
+        method!("tables.add_row", Post "/api/tables/add-row", tables_add_row),
+        method!("tables.add_column", Post "/api/tables/add-column", tables_add_column),
+        method!("periodic.daily", Post "/api/periodic/daily", periodic_daily),
+        method!("periodic.weekly", Post "/api/periodic/weekly", periodic_weekly),
+        method!("periodic.monthly", Post "/api/periodic/monthly", periodic_monthly),
+        method!("periodic.quarterly", Post "/api/periodic/quarterly", periodic_quarterly),
+        method!("periodic.yearly", Post "/api/periodic/yearly", periodic_yearly),
+        method!("periodic.navigate", Post "/api/periodic/navigate", periodic_navigate),
+        method!("git.status", Post "/api/git/status", git_status),
+        method!("git.commit", Post "/api/git/commit", git_commit),
+        method!("git.push", Post "/api/git/push", git_push),
+        method!("git.pull", Post "/api/git/pull", git_pull),
+        method!("git.sync", Post "/api/git/sync", git_sync),
+        method!("git.log", Post "/api/git/log", git_log),
+        method!("git.diff", Post "/api/git/diff", git_diff),
+        method!("git.init", Post "/api/git/init", git_init),
+    ]
+}
+
+async fn tasks_parse(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: tasks::TaskParseRequest = parse(&params)?;
+    to_value(tasks::parse_tasks(&request)?)
+}
+
+async fn tasks_query(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: tasks::TaskQueryRequest = parse(&params)?;
+    to_value(tasks::query_tasks(&request)?)
+}
+
+async fn nlp_parse_date(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: nlp::NlpDateParseRequest = parse(&params)?;
+    to_value(nlp::parse_natural_date(&request).map_err(RpcError::bad_request)?)
+}
+
+async fn nlp_suggest_dates(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: nlp::DateSuggestRequest = parse(&params)?;
+    let suggestions = nlp::suggest_dates(&request);
+    Ok(json!({"suggestions": suggestions}))
+}
+
+async fn dataview_parse(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: dataview::ParseNoteRequest = parse(&params)?;
+    to_value(dataview::parse_note_metadata(&request)?)
+}
+
+async fn dataview_query(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: dataview::QueryRequest = parse(&params)?;
+    to_value(dataview::query_notes(&request)?)
+}
+
+async fn dataview_table(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: dataview::TableQueryRequest = parse(&params)?;
+    to_value(dataview::table_query(&request)?)
+}
+
+async fn tables_parse(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: tables::ParseTableRequest = parse(&params)?;
+    to_value(tables::parse_table(&request).map_err(RpcError::bad_request)?)
+}
+
+async fn tables_format(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: tables::FormatTableRequest = parse(&params)?;
+    let markdown = tables::format_table(&request)?;
+    Ok(json!({"markdown": markdown}))
+}
+
+async fn tables_sort(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: tables::SortTableRequest = parse(&params)?;
+    to_value(tables::sort_table(&request).map_err(RpcError::bad_request)?)
+}
+
+async fn tables_add_row(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: tables::AddRowRequest = parse(&params)?;
+    to_value(tables::add_row(&request)?)
+}
+
+async fn tables_add_column(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: tables::AddColumnRequest = parse(&params)?;
+    to_value(tables::add_column(&request)?)
+}
+
+async fn periodic_daily(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: periodic::DailyNoteRequest = parse(&params)?;
+    to_value(periodic::generate_daily_note(&request)?)
+}
+
+async fn periodic_weekly(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: periodic::WeeklyNoteRequest = parse(&params)?;
+    to_value(periodic::generate_weekly_note(&request)?)
+}
+
+async fn periodic_monthly(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: periodic::MonthlyNoteRequest = parse(&params)?;
+    to_value(periodic::generate_monthly_note(&request)?)
+}
+
+async fn periodic_quarterly(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: periodic::QuarterlyNoteRequest = parse(&params)?;
+    to_value(periodic::generate_quarterly_note(&request)?)
+}
+
+async fn periodic_yearly(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: periodic::YearlyNoteRequest = parse(&params)?;
+    to_value(periodic::generate_yearly_note(&request)?)
+}
+
+async fn periodic_navigate(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: periodic::NavigatePeriodicRequest = parse(&params)?;
+    let date = periodic::navigate_periodic(&request).map_err(RpcError::bad_request)?;
+    Ok(json!({"date": date}))
+}
+
+async fn git_status(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: git::GitStatusRequest = parse(&params)?;
+    to_value(git::git_status(&request)?)
+}
+
+async fn git_commit(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: git::GitCommitRequest = parse(&params)?;
+    to_value(git::git_commit(&request)?)
+}
+
+async fn git_push(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: git::GitPushRequest = parse(&params)?;
+    to_value(git::git_push(&request)?)
+}
+
+async fn git_pull(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: git::GitPullRequest = parse(&params)?;
+    to_value(git::git_pull(&request)?)
+}
+
+async fn git_sync(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: git::GitSyncRequest = parse(&params)?;
+    to_value(git::git_sync(&request)?)
+}
+
+async fn git_log(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: git::GitLogRequest = parse(&params)?;
+    to_value(git::git_log(&request)?)
+}
+
+async fn git_diff(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: git::GitDiffRequest = parse(&params)?;
+    to_value(git::git_diff(&request)?)
+}
+
+async fn git_init(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: git::GitConfig = parse(&params)?;
+    to_value(git::git_init(&request)?)
+}
@@ -0,0 +1,235 @@
+use serde_json::{json, Value};
+
+use super::{method, parse, str_param, to_value, MethodSpec, RpcContext, RpcError, RpcResult};
+use crate::ai;
+use crate::highlights;
+use crate::labels;
+use crate::newsletter;
+use crate::reading;
+use crate::rpc::server::get_data_dir;
+use crate::rpc::types::LabelSuggestRequest;
+use crate::tier::{check_ai_limit, check_pro_feature, ProFeature};
+
+pub(super) fn methods() -> Vec<MethodSpec> {
+    vec![
+        method!("highlights.create", Post "/api/highlights", highlights_create),
+        method!("highlights.query", Post "/api/highlights/query", highlights_query),
+        method!("highlights.update", Post "/api/highlights/update", highlights_update),
+        method!("highlights.delete", Post "/api/highlights/delete", highlights_delete),
+        method!("highlights.export", Post "/api/highlights/export", highlights_export),
+        method!("reading.save", Post "/api/reading/save", reading_save),
+        method!("reading.query", Post "/api/reading/query", reading_query),
+        method!("reading.get", Post "/api/reading/get", reading_get),
+        method!("reading.update", Post "/api/reading/update", reading_update),
+        method!("reading.delete", Post "/api/reading/delete", reading_delete),
+        method!("reading.archive", Post "/api/reading/archive", reading_archive),
+        method!("reading.favorite", Post "/api/reading/favorite", reading_toggle_favorite),
+        method!("reading.stats", Get "/api/reading/stats", reading_stats),
+        method!("reading.labels", Get "/api/reading/labels", reading_labels),
+        method!("newsletter.fetch", Post "/api/newsletter/fetch", newsletter_fetch),
+        method!("newsletter.query", Post "/api/newsletter/query", newsletter_query),
+        method!("newsletter.get", Post "/api/newsletter/get", newsletter_get),
+        method!("newsletter.read", Post "/api/newsletter/read", newsletter_mark_read),
+        method!("newsletter.star", Post "/api/newsletter/star", newsletter_toggle_star),
+        method!("newsletter.delete", Post "/api/newsletter/delete", newsletter_delete),
+        method!("newsletter.to_markdown", Post "/api/newsletter/to-markdown", newsletter_to_markdown),
+        method!("newsletter.senders", Get "/api/newsletter/senders", newsletter_senders),
+        method!("labels.list", Get "/api/labels", labels_list),
+        method!("labels.create", Post "/api/labels", labels_create),
+        method!("labels.update", Post "/api/labels/update", labels_update),
+        method!("labels.delete", Post "/api/labels/delete", labels_delete),
+        method!("labels.tree", Get "/api/labels/tree", labels_tree),
+        method!("labels.stats", Get "/api/labels/stats", labels_stats),
+        method!("labels.merge", Post "/api/labels/merge", labels_merge),
+        method!("labels.search", Post "/api/labels/search", labels_search),
+        method!("labels.suggest", Post "/api/labels/suggest", labels_suggest),
+    ]
+}
+
+async fn highlights_create(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: highlights::CreateHighlightRequest = parse(&params)?;
+    to_value(highlights::create_highlight(get_data_dir(), request)?)
+}
+
+async fn highlights_query(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: highlights::HighlightQuery = parse(&params)?;
+    to_value(highlights::query_highlights(get_data_dir(), request)?)
+}
+
+async fn highlights_update(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: highlights::UpdateHighlightRequest = parse(&params)?;
+    to_value(highlights::update_highlight(get_data_dir(), request)?)
+}
+
+async fn highlights_delete(_ctx: RpcContext, params: Value) -> RpcResult {
+    highlights::delete_highlight(get_data_dir(), str_param(&params, "id"))?;
+    Ok(json!({"deleted": true}))
+}
+
+async fn highlights_export(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: highlights::HighlightExport = parse(&params)?;
+    let content = highlights::export_highlights(get_data_dir(), request)?;
+    Ok(json!({"content": content}))
+}
+
+async fn reading_save(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: reading::SaveArticleRequest = parse(&params)?;
+    to_value(reading::save_article(get_data_dir(), request)?)
+}
+
+async fn reading_query(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: reading::ArticleQuery = parse(&params)?;
+    to_value(reading::query_articles(get_data_dir(), request)?)
+}
+
+async fn reading_get(_ctx: RpcContext, params: Value) -> RpcResult {
+    to_value(reading::get_article(
+        get_data_dir(),
+        str_param(&params, "id"),
+    )?)
+}
+
+async fn reading_update(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: reading::UpdateArticleRequest = parse(&params)?;
+    to_value(reading::update_article(get_data_dir(), request)?)
+}
+
+async fn reading_delete(_ctx: RpcContext, params: Value) -> RpcResult {
+    reading::delete_article(get_data_dir(), str_param(&params, "id"))?;
+    Ok(json!({"deleted": true}))
+}
+
+async fn reading_archive(_ctx: RpcContext, params: Value) -> RpcResult {
+    to_value(reading::archive_article(
+        get_data_dir(),
+        str_param(&params, "id"),
+    )?)
+}
+
+async fn reading_toggle_favorite(_ctx: RpcContext, params: Value) -> RpcResult {
+    to_value(reading::toggle_favorite(
+        get_data_dir(),
+        str_param(&params, "id"),
+    )?)
+}
+
+async fn reading_stats(_ctx: RpcContext, _params: Value) -> RpcResult {
+    to_value(reading::get_reading_stats(get_data_dir())?)
+}
+
+async fn reading_labels(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let labels = reading::get_all_labels(get_data_dir())?;
+    Ok(json!({"labels": labels}))
+}
+
+async fn newsletter_fetch(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: newsletter::FetchNewslettersRequest = parse(&params)?;
+    check_pro_feature(&ctx.tier, ProFeature::Newsletter)?;
+    to_value(
+        ctx.state
+            .jobs
+            .spawn("newsletter.fetch", |progress| async move {
+                let newsletters =
+                    newsletter::fetch_newsletters(get_data_dir(), request, &progress).await?;
+                Ok(serde_json::to_value(newsletters)?)
+            }),
+    )
+}
+
+async fn newsletter_query(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: newsletter::NewsletterQuery = parse(&params)?;
+    to_value(newsletter::query_newsletters(get_data_dir(), request)?)
+}
+
+async fn newsletter_get(_ctx: RpcContext, params: Value) -> RpcResult {
+    to_value(newsletter::get_newsletter(
+        get_data_dir(),
+        str_param(&params, "id"),
+    )?)
+}
+
+async fn newsletter_mark_read(_ctx: RpcContext, params: Value) -> RpcResult {
+    newsletter::mark_newsletter_read(get_data_dir(), str_param(&params, "id"))?;
+    Ok(json!({"success": true}))
+}
+
+async fn newsletter_toggle_star(_ctx: RpcContext, params: Value) -> RpcResult {
+    let starred = newsletter::toggle_newsletter_star(get_data_dir(), str_param(&params, "id"))?;
+    Ok(json!({"starred": starred}))
+}
+
+async fn newsletter_delete(_ctx: RpcContext, params: Value) -> RpcResult {
+    newsletter::delete_newsletter(get_data_dir(), str_param(&params, "id"))?;
+    Ok(json!({"deleted": true}))
+}
+
+async fn newsletter_to_markdown(_ctx: RpcContext, params: Value) -> RpcResult {
+    let include_metadata = params
+        .get("include_metadata")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    let markdown = newsletter::newsletter_to_markdown(
+        get_data_dir(),
+        str_param(&params, "id"),
+        include_metadata,
+    )?;
+    Ok(json!({"markdown": markdown}))
+}
+
+async fn newsletter_senders(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let senders = newsletter::get_newsletter_senders(get_data_dir())?;
+    Ok(json!({"senders": senders}))
+}
+
+async fn labels_list(_ctx: RpcContext, _params: Value) -> RpcResult {
+    to_value(labels::list_labels(get_data_dir())?)
+}
+
+async fn labels_create(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: labels::CreateLabelRequest = parse(&params)?;
+    to_value(labels::create_label(get_data_dir(), request)?)
+}
+
+async fn labels_update(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: labels::UpdateLabelRequest = parse(&params)?;
+    to_value(labels::update_label(get_data_dir(), request)?)
+}
+
+async fn labels_delete(_ctx: RpcContext, params: Value) -> RpcResult {
+    labels::delete_label(get_data_dir(), str_param(&params, "id"))?;
+    Ok(json!({"deleted": true}))
+}
+
+async fn labels_tree(_ctx: RpcContext, _params: Value) -> RpcResult {
+    to_value(labels::get_label_tree(get_data_dir())?)
+}
+
+async fn labels_stats(_ctx: RpcContext, _params: Value) -> RpcResult {
+    to_value(labels::get_label_stats(get_data_dir())?)
+}
+
+async fn labels_merge(_ctx: RpcContext, params: Value) -> RpcResult {
+    to_value(labels::merge_labels(
+        get_data_dir(),
+        str_param(&params, "source_id"),
+        str_param(&params, "target_id"),
+    )?)
+}
+
+async fn labels_search(_ctx: RpcContext, params: Value) -> RpcResult {
+    to_value(labels::search_labels(
+        get_data_dir(),
+        str_param(&params, "query"),
+    )?)
+}
+
+/// Suggests labels for an article, newsletter or note; the LLM pass counts
+/// against the AI limit.
+async fn labels_suggest(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: LabelSuggestRequest = parse(&params)?;
+    if request.use_llm {
+        check_ai_limit(&ctx.tier, &ctx.state.usage_tracker).await?;
+    }
+    let response = ai::tagging::suggest(&get_data_dir(), &request).await;
+    to_value(response.map_err(RpcError::bad_request)?)
+}
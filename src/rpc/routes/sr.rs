@@ -0,0 +1,286 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{method, parse, str_param, to_value, MethodSpec, RpcContext, RpcError, RpcResult};
+use crate::ai;
+use crate::rpc::server::get_data_dir;
+use crate::rpc::types::MasteryGenerateRequest;
+use crate::spaced_repetition::{self, SpacedRepetitionError};
+use crate::tier::{check_ai_limit, check_sr_cards_limit, check_sr_limit, sr_cards_remaining};
+
+pub(super) fn methods() -> Vec<MethodSpec> {
+    vec![
+        method!("sr.config.get", Get "/api/sr/config", sr_get_config),
+        method!("sr.config.update", Post "/api/sr/config", sr_update_config),
+        method!("sr.highlight.register", Post "/api/sr/highlight/register", sr_register_highlight),
+        method!("sr.highlight.review", Post "/api/sr/highlight/review", sr_review_highlight),
+        method!("sr.mastery.create", Post "/api/sr/mastery", sr_create_mastery_card),
+        method!("sr.mastery.generate", Post "/api/sr/mastery/generate", sr_generate_mastery_cards),
+        method!("sr.mastery.review", Post "/api/sr/mastery/review", sr_review_mastery_card),
+        method!("sr.mastery.delete", Post "/api/sr/mastery/delete", sr_delete_mastery_card),
+        method!("sr.session.create", Post "/api/sr/session/create", sr_create_session),
+        method!("sr.session.due", Get "/api/sr/session/due", sr_get_due_counts),
+        method!("sr.session.active", Get "/api/sr/session/active", sr_get_active_session),
+        method!("sr.session.answer", Post "/api/sr/session/{id}/answer", sr_answer_session_item),
+        method!("sr.session.undo", Post "/api/sr/session/{id}/undo", sr_undo_session_answer),
+        method!("sr.session.resume", Get "/api/sr/session/{id}/resume", sr_resume_session),
+        method!("sr.frequency.document", Post "/api/sr/frequency/document", sr_set_document_frequency),
+        method!("sr.frequency.source", Post "/api/sr/frequency/source", sr_set_source_frequency),
+        method!("sr.stats", Get "/api/sr/stats", sr_get_stats),
+        method!("sr.review_log", Post "/api/sr/review-log", sr_get_review_log),
+        method!("sr.fsrs.optimize", Post "/api/sr/fsrs/optimize", sr_optimize_fsrs),
+    ]
+}
+
+/// Store failures stay internal errors; the rest are caused by the request,
+/// like an unknown session or an invalid cloze, and become bad requests.
+fn sr_error(err: SpacedRepetitionError) -> RpcError {
+    match err {
+        SpacedRepetitionError::Io(_)
+        | SpacedRepetitionError::Json(_)
+        | SpacedRepetitionError::Highlights(_)
+        | SpacedRepetitionError::Reading(_) => RpcError::Internal(err.to_string()),
+        _ => RpcError::bad_request(err),
+    }
+}
+
+async fn sr_get_config(_ctx: RpcContext, _params: Value) -> RpcResult {
+    to_value(spaced_repetition::get_config(get_data_dir()).map_err(sr_error)?)
+}
+
+async fn sr_update_config(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: spaced_repetition::SpacedRepetitionConfig = parse(&params)?;
+    spaced_repetition::update_config(get_data_dir(), request).map_err(sr_error)?;
+    Ok(json!({"success": true}))
+}
+
+#[derive(Deserialize)]
+struct RegisterHighlightRequest {
+    highlight_id: String,
+}
+
+async fn sr_register_highlight(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: RegisterHighlightRequest = parse(&params)?;
+    check_sr_limit(&ctx.tier, &ctx.state.usage_tracker).await?;
+
+    let data = spaced_repetition::register_highlight(get_data_dir(), request.highlight_id)
+        .map_err(sr_error)?;
+    let mut tracker = ctx.state.usage_tracker.write().await;
+    let _ = tracker.increment_sr_card();
+    to_value(data)
+}
+
+#[derive(Deserialize)]
+struct ReviewHighlightRequest {
+    highlight_id: String,
+    action: spaced_repetition::HighlightReviewAction,
+}
+
+async fn sr_review_highlight(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: ReviewHighlightRequest = parse(&params)?;
+    to_value(
+        spaced_repetition::review_highlight(get_data_dir(), request.highlight_id, request.action)
+            .map_err(sr_error)?,
+    )
+}
+
+/// Returns the created card. Cloze text with several `{{cN::...}}` numbers
+/// makes one card per number: the first is returned with the rest under
+/// `siblings`.
+async fn sr_create_mastery_card(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: spaced_repetition::CreateMasteryCardRequest = parse(&params)?;
+    let count = request.card_count().map_err(sr_error)?;
+    check_sr_cards_limit(&ctx.tier, &ctx.state.usage_tracker, count as u32).await?;
+
+    let mut cards =
+        spaced_repetition::create_mastery_card(get_data_dir(), request).map_err(sr_error)?;
+    let mut tracker = ctx.state.usage_tracker.write().await;
+    for _ in &cards {
+        let _ = tracker.increment_sr_card();
+    }
+    let siblings = cards.split_off(1);
+    let mut card = to_value(cards.remove(0))?;
+    if !siblings.is_empty() {
+        card["siblings"] = to_value(siblings)?;
+    }
+    Ok(card)
+}
+
+/// Drafts mastery cards from a highlight, creating them when `create` is set.
+/// On a limited tier no more cards are drafted than it can still add.
+async fn sr_generate_mastery_cards(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: MasteryGenerateRequest = parse(&params)?;
+    check_ai_limit(&ctx.tier, &ctx.state.usage_tracker).await?;
+    let card_limit = if request.create {
+        check_sr_limit(&ctx.tier, &ctx.state.usage_tracker).await?;
+        sr_cards_remaining(&ctx.tier, &ctx.state.usage_tracker)
+            .await
+            .map(|n| n as usize)
+    } else {
+        None
+    };
+
+    let response = ai::cards::generate(&get_data_dir(), &request, card_limit).await;
+    let response = response.map_err(RpcError::bad_request)?;
+    let mut tracker = ctx.state.usage_tracker.write().await;
+    for _ in &response.cards {
+        let _ = tracker.increment_sr_card();
+    }
+    to_value(response)
+}
+
+#[derive(Deserialize)]
+struct ReviewMasteryCardRequest {
+    card_id: String,
+    feedback: spaced_repetition::ReviewFeedback,
+}
+
+async fn sr_review_mastery_card(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: ReviewMasteryCardRequest = parse(&params)?;
+    to_value(
+        spaced_repetition::review_mastery_card(get_data_dir(), request.card_id, request.feedback)
+            .map_err(sr_error)?,
+    )
+}
+
+#[derive(Deserialize)]
+struct DeleteMasteryCardRequest {
+    card_id: String,
+}
+
+async fn sr_delete_mastery_card(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: DeleteMasteryCardRequest = parse(&params)?;
+    spaced_repetition::delete_mastery_card(get_data_dir(), request.card_id).map_err(sr_error)?;
+    let mut tracker = ctx.state.usage_tracker.write().await;
+    let _ = tracker.decrement_sr_card();
+    Ok(json!({"success": true}))
+}
+
+async fn sr_create_session(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: spaced_repetition::session::CreateSessionRequest = parse(&params)?;
+    to_value(
+        spaced_repetition::session::create_review_session(get_data_dir(), request)
+            .map_err(sr_error)?,
+    )
+}
+
+async fn sr_get_due_counts(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let (highlights, mastery) =
+        spaced_repetition::session::get_due_counts(get_data_dir()).map_err(sr_error)?;
+    Ok(json!({
+        "highlights_due": highlights,
+        "mastery_cards_due": mastery
+    }))
+}
+
+/// The unfinished session to offer resuming, if any.
+async fn sr_get_active_session(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let session =
+        spaced_repetition::session::get_active_session(get_data_dir()).map_err(sr_error)?;
+    Ok(json!({"session": session}))
+}
+
+async fn sr_answer_session_item(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: spaced_repetition::session::SessionAnswerRequest = parse(&params)?;
+    to_value(
+        spaced_repetition::session::answer_session_item(get_data_dir(), request)
+            .map_err(sr_error)?,
+    )
+}
+
+async fn sr_undo_session_answer(_ctx: RpcContext, params: Value) -> RpcResult {
+    let id = str_param(&params, "id");
+    to_value(spaced_repetition::session::undo_session_answer(get_data_dir(), id).map_err(sr_error)?)
+}
+
+async fn sr_resume_session(_ctx: RpcContext, params: Value) -> RpcResult {
+    let id = str_param(&params, "id");
+    to_value(spaced_repetition::session::resume_session(get_data_dir(), id).map_err(sr_error)?)
+}
+
+async fn sr_set_document_frequency(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: spaced_repetition::frequency::SetDocumentFrequencyRequest = parse(&params)?;
+    spaced_repetition::set_document_frequency(
+        get_data_dir(),
+        request.document_id,
+        request.multiplier,
+    )
+    .map_err(sr_error)?;
+    Ok(json!({"success": true}))
+}
+
+async fn sr_set_source_frequency(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: spaced_repetition::frequency::SetSourceTypeFrequencyRequest = parse(&params)?;
+    spaced_repetition::set_source_type_frequency(
+        get_data_dir(),
+        request.source_type,
+        request.multiplier,
+    )
+    .map_err(sr_error)?;
+    Ok(json!({"success": true}))
+}
+
+async fn sr_get_stats(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let stats = spaced_repetition::get_stats(get_data_dir()).map_err(sr_error)?;
+    to_value(spaced_repetition::stats::StatsResponse::from(&stats))
+}
+
+#[derive(Deserialize)]
+struct ReviewLogRequest {
+    item_id: Option<String>,
+    limit: Option<usize>,
+}
+
+async fn sr_get_review_log(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: ReviewLogRequest = parse(&params)?;
+    let entries = spaced_repetition::get_review_log(
+        get_data_dir(),
+        request.item_id.as_deref(),
+        request.limit,
+    )
+    .map_err(sr_error)?;
+    Ok(json!({"entries": entries}))
+}
+
+#[derive(Deserialize)]
+struct OptimizeFsrsRequest {
+    #[serde(default)]
+    apply: bool,
+}
+
+/// Fits FSRS weights to the review log in a background job.
+async fn sr_optimize_fsrs(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: OptimizeFsrsRequest = parse(&params)?;
+    to_value(
+        ctx.state
+            .jobs
+            .spawn("sr.fsrs.optimize", |progress| async move {
+                let report = tokio::task::spawn_blocking(move || {
+                    spaced_repetition::optimize_fsrs(get_data_dir(), request.apply, &progress)
+                })
+                .await??;
+                Ok(serde_json::to_value(report)?)
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_errors_are_bad_requests() {
+        let not_found = sr_error(SpacedRepetitionError::SessionNotFound("s1".to_string()));
+        assert!(matches!(not_found, RpcError::BadRequest(_)));
+        assert!(matches!(
+            sr_error(SpacedRepetitionError::InvalidCloze("c1".to_string())),
+            RpcError::BadRequest(_)
+        ));
+
+        let io = std::io::Error::other("disk full");
+        assert!(matches!(
+            sr_error(SpacedRepetitionError::Io(io)),
+            RpcError::Internal(_)
+        ));
+    }
+}
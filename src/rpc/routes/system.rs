@@ -0,0 +1,381 @@
+use axum::http::StatusCode;
+use serde_json::Value;
+
+use super::{method, parse, to_value, MethodSpec, RpcContext, RpcError, RpcResult};
+use crate::ai;
+use crate::rpc::types::*;
+use crate::youtube;
+
+pub(super) fn methods() -> Vec<MethodSpec> {
+    vec![
+        method!("health.check", health_check),
+        method!("system.status", Get "/api/status", system_status),
+        method!("tier.usage", Get "/api/tier/usage", tier_usage_stats),
+        method!("model.download", Post "/api/model/download", model_download),
+        method!("deps.download", Post "/api/deps/download", deps_download),
+        method!("deps.install", Post "/api/deps/install", deps_install),
+    ]
+}
+
+async fn health_check(_ctx: RpcContext, _params: Value) -> RpcResult {
+    Ok(Value::String("ok".to_string()))
+}
+
+async fn tier_usage_stats(ctx: RpcContext, _params: Value) -> RpcResult {
+    let tracker = ctx.state.usage_tracker.read().await;
+    to_value(tracker.get_stats())
+}
+
+async fn system_status(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let mut dependencies = Vec::new();
+
+    let ytdlp_installed = check_command("yt-dlp", &["--version"]).await;
+    dependencies.push(DependencyStatus {
+        name: "yt-dlp".to_string(),
+        installed: ytdlp_installed.0,
+        version: ytdlp_installed.1,
+        install_hint:
+            "macOS: brew install yt-dlp\nWindows: choco install yt-dlp\nLinux: pip install yt-dlp"
+                .to_string(),
+    });
+
+    let tesseract_installed = check_command("tesseract", &["--version"]).await;
+    dependencies.push(DependencyStatus {
+        name: "tesseract".to_string(),
+        installed: tesseract_installed.0,
+        version: tesseract_installed.1,
+        install_hint: "macOS: brew install tesseract tesseract-lang\nWindows: choco install tesseract\nLinux: apt install tesseract-ocr".to_string(),
+    });
+
+    let pdftoppm_installed = check_command("pdftoppm", &["-v"]).await;
+    dependencies.push(DependencyStatus {
+        name: "pdftoppm".to_string(),
+        installed: pdftoppm_installed.0,
+        version: pdftoppm_installed.1,
+        install_hint: "macOS: brew install poppler\nWindows: choco install poppler\nLinux: apt install poppler-utils".to_string(),
+    });
+
+    let magick_installed = check_command("magick", &["--version"]).await;
+    dependencies.push(DependencyStatus {
+        name: "imagemagick".to_string(),
+        installed: magick_installed.0,
+        version: magick_installed.1,
+        install_hint: "macOS: brew install imagemagick\nWindows: choco install imagemagick\nLinux: apt install imagemagick".to_string(),
+    });
+
+    let model_loaded = false;
+    let ai_model = ModelStatus {
+        loaded: model_loaded,
+        model_name: if model_loaded {
+            Some("TinyLlama-1.1B".to_string())
+        } else {
+            None
+        },
+        model_size: if model_loaded {
+            Some("~700MB".to_string())
+        } else {
+            None
+        },
+        download_progress: None,
+    };
+
+    let ready = ytdlp_installed.0;
+
+    to_value(SystemStatusResponse {
+        dependencies,
+        ai_model,
+        ready,
+    })
+}
+
+async fn check_command(cmd: &str, args: &[&str]) -> (bool, Option<String>) {
+    match tokio::process::Command::new(cmd).args(args).output().await {
+        Ok(output) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .map(|s| s.trim().to_string());
+            (true, version)
+        }
+        _ => (false, None),
+    }
+}
+
+async fn model_download(ctx: RpcContext, params: Value) -> RpcResult {
+    let request: ModelDownloadRequest = parse(&params)?;
+    let model_id = request
+        .model_id
+        .unwrap_or_else(|| "TheBloke/TinyLlama-1.1B-Chat-v1.0-GGUF".to_string());
+
+    to_value(
+        ctx.state
+            .jobs
+            .spawn("model.download", |progress| async move {
+                let model_name = ai::download_model(&model_id, &progress)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Download failed: {}", e))?;
+                Ok(serde_json::to_value(ModelDownloadResponse {
+                    success: true,
+                    message: "Model downloaded successfully".to_string(),
+                    model_name: Some(model_name),
+                })?)
+            }),
+    )
+}
+
+async fn deps_download(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: DependencyDownloadRequest = parse(&params)?;
+    let (status, response) = match request.name.as_str() {
+        "yt-dlp" => match youtube::ensure_yt_dlp().await {
+            Ok(path) => (
+                StatusCode::OK,
+                DependencyDownloadResponse {
+                    success: true,
+                    message: "yt-dlp downloaded successfully".to_string(),
+                    path: Some(path.to_string_lossy().to_string()),
+                },
+            ),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                DependencyDownloadResponse {
+                    success: false,
+                    message: format!("Download failed: {}", e),
+                    path: None,
+                },
+            ),
+        },
+        _ => (
+            StatusCode::BAD_REQUEST,
+            DependencyDownloadResponse {
+                success: false,
+                message: format!(
+                    "Unknown dependency: {}. Only 'yt-dlp' can be auto-downloaded.",
+                    request.name
+                ),
+                path: None,
+            },
+        ),
+    };
+    with_status(status, serde_json::to_value(response)?)
+}
+
+async fn deps_install(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: DependencyInstallRequest = parse(&params)?;
+    let (status, response) = install_dependency(&request.name).await;
+    with_status(status, serde_json::to_value(response)?)
+}
+
+fn with_status(status: StatusCode, body: Value) -> RpcResult {
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(RpcError::Status(status, body))
+    }
+}
+
+async fn install_dependency(name: &str) -> (StatusCode, DependencyInstallResponse) {
+    let install_config = get_install_config(name);
+
+    if install_config.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            DependencyInstallResponse {
+                success: false,
+                message: format!("Unknown dependency: {}", name),
+                method: "none".to_string(),
+            },
+        );
+    }
+
+    let config = install_config.unwrap();
+
+    #[cfg(target_os = "macos")]
+    {
+        if has_homebrew().await {
+            return run_install_command("brew", &["install"], &config.brew_packages, "homebrew")
+                .await;
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        if has_chocolatey().await {
+            return run_install_command(
+                "choco",
+                &["install", "-y"],
+                &config.choco_packages,
+                "chocolatey",
+            )
+            .await;
+        }
+        if has_winget().await {
+            return run_install_command(
+                "winget",
+                &[
+                    "install",
+                    "--accept-source-agreements",
+                    "--accept-package-agreements",
+                ],
+                &config.winget_packages,
+                "winget",
+            )
+            .await;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if has_apt().await {
+            return run_install_command(
+                "sudo",
+                &["apt", "install", "-y"],
+                &config.apt_packages,
+                "apt",
+            )
+            .await;
+        }
+    }
+
+    (
+        StatusCode::BAD_REQUEST,
+        DependencyInstallResponse {
+            success: false,
+            message: "No supported package manager found. Please install manually.".to_string(),
+            method: "none".to_string(),
+        },
+    )
+}
+
+struct InstallConfig {
+    brew_packages: Vec<&'static str>,
+    choco_packages: Vec<&'static str>,
+    winget_packages: Vec<&'static str>,
+    apt_packages: Vec<&'static str>,
+}
+
+fn get_install_config(name: &str) -> Option<InstallConfig> {
+    match name {
+        "yt-dlp" => Some(InstallConfig {
+            brew_packages: vec!["yt-dlp"],
+            choco_packages: vec!["yt-dlp"],
+            winget_packages: vec!["yt-dlp.yt-dlp"],
+            apt_packages: vec!["yt-dlp"],
+        }),
+        "tesseract" => Some(InstallConfig {
+            brew_packages: vec!["tesseract", "tesseract-lang"],
+            choco_packages: vec!["tesseract"],
+            winget_packages: vec!["UB-Mannheim.TesseractOCR"],
+            apt_packages: vec![
+                "tesseract-ocr",
+                "tesseract-ocr-kor",
+                "tesseract-ocr-jpn",
+                "tesseract-ocr-chi-sim",
+            ],
+        }),
+        "pdftoppm" | "poppler" => Some(InstallConfig {
+            brew_packages: vec!["poppler"],
+            choco_packages: vec!["poppler"],
+            winget_packages: vec![],
+            apt_packages: vec!["poppler-utils"],
+        }),
+        "imagemagick" => Some(InstallConfig {
+            brew_packages: vec!["imagemagick"],
+            choco_packages: vec!["imagemagick"],
+            winget_packages: vec!["ImageMagick.ImageMagick"],
+            apt_packages: vec!["imagemagick"],
+        }),
+        _ => None,
+    }
+}
+
+async fn has_homebrew() -> bool {
+    tokio::process::Command::new("brew")
+        .arg("--version")
+        .output()
+        .await
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(target_os = "windows")]
+async fn has_chocolatey() -> bool {
+    tokio::process::Command::new("choco")
+        .arg("--version")
+        .output()
+        .await
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(target_os = "windows")]
+async fn has_winget() -> bool {
+    tokio::process::Command::new("winget")
+        .arg("--version")
+        .output()
+        .await
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(target_os = "linux")]
+async fn has_apt() -> bool {
+    tokio::process::Command::new("apt")
+        .arg("--version")
+        .output()
+        .await
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+async fn run_install_command(
+    cmd: &str,
+    base_args: &[&str],
+    packages: &[&str],
+    method: &str,
+) -> (StatusCode, DependencyInstallResponse) {
+    if packages.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            DependencyInstallResponse {
+                success: false,
+                message: format!("No packages available for {}", method),
+                method: method.to_string(),
+            },
+        );
+    }
+
+    let mut args: Vec<&str> = base_args.to_vec();
+    args.extend(packages);
+
+    eprintln!("[naidis] Running: {} {}", cmd, args.join(" "));
+
+    match tokio::process::Command::new(cmd).args(&args).output().await {
+        Ok(output) if output.status.success() => (
+            StatusCode::OK,
+            DependencyInstallResponse {
+                success: true,
+                message: format!("Installed via {}", method),
+                method: method.to_string(),
+            },
+        ),
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                DependencyInstallResponse {
+                    success: false,
+                    message: format!("Install failed: {}", stderr),
+                    method: method.to_string(),
+                },
+            )
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            DependencyInstallResponse {
+                success: false,
+                message: format!("Failed to run {}: {}", cmd, e),
+                method: method.to_string(),
+            },
+        ),
+    }
+}
@@ -0,0 +1,232 @@
+use serde_json::{json, Value};
+
+use super::{method, parse, str_param, to_value, MethodSpec, RpcContext, RpcError, RpcResult};
+use crate::rpc::types::*;
+use crate::utils;
+
+pub(super) fn methods() -> Vec<MethodSpec> {
+    vec![
+        method!("calc.calculate", Post "/api/calc", calculate),
+        method!("calc.convert", Post "/api/calc/convert", convert_unit),
+        method!("snippets.list", Get "/api/snippets", list_snippets),
+        method!("snippets.create", Post "/api/snippets", create_snippet),
+        method!("snippets.expand", Post "/api/snippets/expand", expand_snippet),
+        method!("snippets.update", Put "/api/snippets/{id}", update_snippet),
+        method!("snippets.delete", Delete "/api/snippets/{id}", delete_snippet),
+        method!("datetime.format", Post "/api/datetime/format", format_datetime),
+        method!("datetime.parse", Post "/api/datetime/parse", parse_datetime),
+        method!("datetime.calc", Post "/api/datetime/calc", calc_datetime),
+        method!("datetime.diff", Post "/api/datetime/diff", diff_datetime),
+        method!("datetime.quick", Post "/api/datetime/quick", quick_date),
+        method!("emoji.search", Post "/api/emoji/search", search_emoji),
+        method!("emoji.shortcode", Post "/api/emoji/shortcode", get_emoji_by_shortcode),
+        method!("emoji.groups", Get "/api/emoji/groups", list_emoji_groups),
+        method!("favorites.list", Get "/api/favorites", list_favorites),
+        method!("favorites.add", Post "/api/favorites", add_favorite),
+        method!("favorites.toggle", Post "/api/favorites/toggle", toggle_favorite),
+        method!("favorites.remove", Delete "/api/favorites/{id}", remove_favorite),
+        method!("vault.save", Post "/api/vault/save", vault_save),
+        method!("vault.read", Post "/api/vault/read", vault_read),
+        method!("vault.list", Post "/api/vault/list", vault_list),
+        method!("vault.delete", Post "/api/vault/delete", vault_delete),
+        method!("vault.move", Post "/api/vault/move", vault_move),
+        method!("vault.search", Post "/api/vault/search", vault_search),
+        method!("history.list", Get "/api/history", list_history),
+        method!("history.add", Post "/api/history", add_history),
+        method!("history.clear", Post "/api/history/clear", clear_history),
+        method!("history.frequent", Get "/api/history/frequent", get_frequent_commands),
+        method!("layouts.list", Get "/api/layouts", list_layouts),
+        method!("layouts.save", Post "/api/layouts", save_layout),
+        method!("layouts.get", Get "/api/layouts/{id}", get_layout),
+        method!("layouts.update", Put "/api/layouts/{id}", update_layout),
+        method!("layouts.delete", Delete "/api/layouts/{id}", delete_layout),
+        method!("links.suggest", Post "/api/links/suggest", suggest_links),
+        method!("links.backlinks", Post "/api/links/backlinks", find_backlinks),
+    ]
+}
+
+async fn calculate(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: CalcRequest = parse(&params)?;
+    to_value(utils::calculator::calculate(&request).map_err(RpcError::bad_request)?)
+}
+
+async fn convert_unit(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: UnitConvertRequest = parse(&params)?;
+    to_value(utils::calculator::convert_unit(&request).map_err(RpcError::bad_request)?)
+}
+
+async fn list_snippets(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: SnippetListRequest = parse(&params)?;
+    to_value(utils::snippets::list_snippets(&request)?)
+}
+
+async fn create_snippet(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: SnippetCreateRequest = parse(&params)?;
+    to_value(utils::snippets::create_snippet(&request)?)
+}
+
+async fn expand_snippet(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: SnippetExpandRequest = parse(&params)?;
+    let content = utils::snippets::expand_snippet(&request)?;
+    Ok(json!({"content": content}))
+}
+
+async fn update_snippet(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: SnippetUpdateRequest = parse(&params)?;
+    to_value(utils::snippets::update_snippet(&request)?)
+}
+
+async fn delete_snippet(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: SnippetDeleteRequest = parse(&params)?;
+    let removed = utils::snippets::delete_snippet(&request)?;
+    Ok(json!({"deleted": removed}))
+}
+
+async fn format_datetime(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: DateTimeFormatRequest = parse(&params)?;
+    to_value(utils::datetime::format_datetime(&request).map_err(RpcError::bad_request)?)
+}
+
+async fn parse_datetime(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: DateTimeParseRequest = parse(&params)?;
+    to_value(utils::datetime::parse_datetime(&request).map_err(RpcError::bad_request)?)
+}
+
+async fn calc_datetime(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: DateTimeCalcRequest = parse(&params)?;
+    to_value(utils::datetime::calc_datetime(&request).map_err(RpcError::bad_request)?)
+}
+
+async fn diff_datetime(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: DateTimeDiffRequest = parse(&params)?;
+    to_value(utils::datetime::diff_datetime(&request).map_err(RpcError::bad_request)?)
+}
+
+async fn quick_date(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: QuickDateRequest = parse(&params)?;
+    to_value(utils::datetime::quick_date(&request).map_err(RpcError::bad_request)?)
+}
+
+async fn search_emoji(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: EmojiSearchRequest = parse(&params)?;
+    to_value(utils::emoji::search_emoji(&request)?)
+}
+
+async fn get_emoji_by_shortcode(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: EmojiByShortcodeRequest = parse(&params)?;
+    to_value(utils::emoji::get_emoji_by_shortcode(&request)?)
+}
+
+async fn list_emoji_groups(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let groups = utils::emoji::list_emoji_groups();
+    Ok(json!({"groups": groups}))
+}
+
+async fn list_favorites(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: FavoriteListRequest = parse(&params)?;
+    to_value(utils::favorites::list_favorites(&request)?)
+}
+
+async fn add_favorite(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: FavoriteAddRequest = parse(&params)?;
+    to_value(utils::favorites::add_favorite(&request)?)
+}
+
+async fn toggle_favorite(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: FavoriteAddRequest = parse(&params)?;
+    let (added, item) = utils::favorites::toggle_favorite(&request)?;
+    Ok(json!({"added": added, "item": item}))
+}
+
+async fn remove_favorite(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: FavoriteRemoveRequest = parse(&params)?;
+    let removed = utils::favorites::remove_favorite(&request)?;
+    Ok(json!({"deleted": removed}))
+}
+
+async fn vault_save(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: VaultSaveRequest = parse(&params)?;
+    to_value(utils::vault::save_file(&request)?)
+}
+
+async fn vault_read(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: VaultReadRequest = parse(&params)?;
+    to_value(utils::vault::read_file(&request)?)
+}
+
+async fn vault_list(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: VaultListRequest = parse(&params)?;
+    to_value(utils::vault::list_files(&request)?)
+}
+
+async fn vault_delete(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: VaultDeleteRequest = parse(&params)?;
+    let deleted = utils::vault::delete_file(&request)?;
+    Ok(json!({"deleted": deleted}))
+}
+
+async fn vault_move(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: VaultMoveRequest = parse(&params)?;
+    let moved = utils::vault::move_file(&request)?;
+    Ok(json!({"moved": moved}))
+}
+
+async fn vault_search(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: VaultSearchRequest = parse(&params)?;
+    to_value(utils::vault::search_vault(&request)?)
+}
+
+async fn list_history(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: HistoryListRequest = parse(&params)?;
+    to_value(utils::history::list_history(&request)?)
+}
+
+async fn add_history(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: HistoryAddRequest = parse(&params)?;
+    to_value(utils::history::add_history(&request)?)
+}
+
+async fn clear_history(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: HistoryClearRequest = parse(&params)?;
+    let removed = utils::history::clear_history(&request)?;
+    Ok(json!({"removed": removed}))
+}
+
+async fn get_frequent_commands(_ctx: RpcContext, _params: Value) -> RpcResult {
+    let commands = utils::history::get_frequent_commands(10)?;
+    Ok(json!({"commands": commands}))
+}
+
+async fn list_layouts(_ctx: RpcContext, _params: Value) -> RpcResult {
+    to_value(utils::layouts::list_layouts()?)
+}
+
+async fn save_layout(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: LayoutSaveRequest = parse(&params)?;
+    to_value(utils::layouts::save_layout(&request)?)
+}
+
+async fn get_layout(_ctx: RpcContext, params: Value) -> RpcResult {
+    to_value(utils::layouts::get_layout(str_param(&params, "id"))?)
+}
+
+async fn update_layout(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: LayoutUpdateRequest = parse(&params)?;
+    to_value(utils::layouts::update_layout(&request)?)
+}
+
+async fn delete_layout(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: LayoutDeleteRequest = parse(&params)?;
+    let deleted = utils::layouts::delete_layout(&request)?;
+    Ok(json!({"deleted": deleted}))
+}
+
+async fn suggest_links(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: LinkSuggestRequest = parse(&params)?;
+    to_value(utils::links::suggest_links(&request)?)
+}
+
+async fn find_backlinks(_ctx: RpcContext, params: Value) -> RpcResult {
+    let request: BacklinkRequest = parse(&params)?;
+    to_value(utils::links::find_backlinks(&request)?)
+}
@@ -1,5 +1,24 @@
 use serde::{Deserialize, Serialize};
 
+/// The LLM a request runs on. Fields left out fall back to the saved AI
+/// config; no provider means the local engine.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LlmSelection {
+    pub provider: Option<String>,
+    pub api_key: Option<String>,
+    pub model: Option<String>,
+    /// Server URL for `ollama` and `custom` (OpenAI-compatible) providers
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Extra HTTP headers sent to `custom` providers
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, String>,
+    /// Tried in order when `provider` keeps failing; the saved chain is used
+    /// when this is empty
+    #[serde(default)]
+    pub fallback: Vec<LlmSelection>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct YouTubeRequest {
     pub url: String,
@@ -8,9 +27,8 @@ pub struct YouTubeRequest {
     #[serde(default)]
     pub generate_ai_chapters: bool,
     pub language: Option<String>,
-    pub provider: Option<String>,
-    pub api_key: Option<String>,
-    pub model: Option<String>,
+    #[serde(flatten)]
+    pub llm: LlmSelection,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,9 +63,8 @@ pub struct YouTubeBatchRequest {
     #[serde(default)]
     pub generate_ai_chapters: bool,
     pub language: Option<String>,
-    pub provider: Option<String>,
-    pub api_key: Option<String>,
-    pub model: Option<String>,
+    #[serde(flatten)]
+    pub llm: LlmSelection,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -137,29 +154,33 @@ pub struct AiChatRequest {
     pub message: String,
     pub context: Option<Vec<String>>,
     pub system_prompt: Option<String>,
-    pub provider: Option<String>,
-    pub api_key: Option<String>,
-    pub model: Option<String>,
+    #[serde(flatten)]
+    pub llm: LlmSelection,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiChatResponse {
     pub response: String,
     pub sources: Option<Vec<String>>,
+    /// Provider that produced the answer; `local` for the in-process engine
+    #[serde(default)]
+    pub provider: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiSummarizeRequest {
     pub text: String,
     pub max_length: Option<usize>,
-    pub provider: Option<String>,
-    pub api_key: Option<String>,
-    pub model: Option<String>,
+    #[serde(flatten)]
+    pub llm: LlmSelection,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiSummarizeResponse {
     pub summary: String,
+    /// Provider that produced the answer; `local` for the in-process engine
+    #[serde(default)]
+    pub provider: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -173,18 +194,54 @@ pub struct NoteItem {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiIndexRequest {
     pub notes: Vec<NoteItem>,
+    /// Re-embed every note, even those whose content is unchanged
+    #[serde(default)]
+    pub force: bool,
+    /// Treat `notes` as the whole vault and drop indexed notes not in it
+    #[serde(default)]
+    pub prune: bool,
+    /// Switch the index to this embedding model (and save it as the
+    /// configured one) before indexing
+    #[serde(default)]
+    pub embedding_model: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiIndexResponse {
     pub indexed_count: usize,
     pub success: bool,
+    #[serde(default)]
+    pub added_count: usize,
+    #[serde(default)]
+    pub updated_count: usize,
+    /// Notes skipped because their content hash matched the index
+    #[serde(default)]
+    pub unchanged_count: usize,
+    #[serde(default)]
+    pub removed_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiRemoveRequest {
+    #[serde(default)]
+    pub ids: Vec<String>,
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiRemoveResponse {
+    pub removed_count: usize,
+    pub success: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiSearchRequest {
     pub query: String,
     pub limit: Option<usize>,
+    /// `mode`, `alpha`, `min_score` and filters
+    #[serde(flatten)]
+    pub options: crate::ai::SearchOptions,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -193,7 +250,14 @@ pub struct AiSearchResultItem {
     pub title: String,
     pub path: String,
     pub score: f32,
+    /// Text of the best-matching chunk
     pub snippet: String,
+    /// Headings the snippet sits under, outermost first
+    #[serde(default)]
+    pub heading_path: Vec<String>,
+    /// 1-based, inclusive line range of the snippet in the note
+    pub start_line: Option<usize>,
+    pub end_line: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -206,23 +270,183 @@ pub struct AiRagRequest {
     pub query: String,
     pub limit: Option<usize>,
     pub system_prompt: Option<String>,
-    pub provider: Option<String>,
-    pub api_key: Option<String>,
-    pub model: Option<String>,
+    #[serde(flatten)]
+    pub llm: LlmSelection,
+    /// Rerank retrieved chunks with the configured reranker; on by default
+    /// when one is configured
+    #[serde(default)]
+    pub rerank: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConversationCreateRequest {
+    pub title: Option<String>,
+    pub system_prompt: Option<String>,
+    /// Ground every turn in the indexed notes unless a turn says otherwise
+    #[serde(default)]
+    pub rag: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationMessageRequest {
+    pub id: String,
+    pub message: String,
+    /// Passages for this turn only; they are not indexed or stored
+    pub context: Option<Vec<String>>,
+    /// Overrides the conversation's `rag` setting for this turn
+    #[serde(default)]
+    pub rag: Option<bool>,
+    #[serde(flatten)]
+    pub llm: LlmSelection,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationRenameRequest {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationDeleteRequest {
+    pub id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiRagSource {
     pub id: String,
     pub score: f32,
+    /// Text of the chunk used as context
     pub content: String,
     pub metadata: Option<std::collections::HashMap<String, String>>,
+    #[serde(default)]
+    pub heading_path: Vec<String>,
+    pub start_line: Option<usize>,
+    pub end_line: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiRagResponse {
     pub response: String,
     pub sources: Vec<AiRagSource>,
+    /// Provider that produced the answer; `local` for the in-process engine
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub usage: Option<RagTokenUsage>,
+}
+
+/// How a RAG prompt was fitted into the model's context window.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RagTokenUsage {
+    pub context_window: usize,
+    pub prompt_tokens: usize,
+    /// Part of the prompt taken up by note passages
+    pub context_tokens: usize,
+    /// Room left for the answer
+    pub answer_tokens: usize,
+    /// Counted with the model's tokenizer rather than estimated
+    pub exact: bool,
+    /// Retrieved passages left out for lack of room
+    pub sources_dropped: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiExtractRequest {
+    pub text: String,
+    /// JSON Schema of the data to extract; its root must be an object
+    pub schema: serde_json::Value,
+    /// What to extract, in words; defaults to a generic instruction
+    pub instructions: Option<String>,
+    pub max_tokens: Option<u32>,
+    #[serde(flatten)]
+    pub llm: LlmSelection,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiExtractResponse {
+    /// Extracted data, validated against the request's schema
+    pub data: serde_json::Value,
+    /// Provider that produced the data; `local` for the in-process engine
+    pub provider: String,
+    /// Replies it took, counting repairs of invalid ones
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelItemType {
+    Article,
+    Newsletter,
+    Note,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelSuggestRequest {
+    pub item_type: LabelItemType,
+    /// Article or newsletter id
+    pub id: Option<String>,
+    /// Notes aren't stored here, so they are passed by title and content
+    pub title: Option<String>,
+    pub content: Option<String>,
+    /// Most suggestions returned; 5 by default
+    pub limit: Option<usize>,
+    /// Also ask the LLM to pick from the label tree
+    #[serde(default)]
+    pub use_llm: bool,
+    /// Suggestions at or above this confidence are added to the article or
+    /// newsletter
+    pub apply_threshold: Option<f32>,
+    #[serde(flatten)]
+    pub llm: LlmSelection,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelSuggestion {
+    pub label_id: String,
+    pub name: String,
+    /// Names from the root of the label tree down, joined with `/`
+    pub path: String,
+    /// 0 to 1; the mean of the scores below that were computed
+    pub confidence: f32,
+    /// Similarity-weighted share of the nearest labeled items with this label;
+    /// set when there were labeled items to compare with
+    pub similarity: Option<f32>,
+    /// The LLM's own confidence, 0 if it passed over this label; set when
+    /// the LLM pass ran
+    pub llm: Option<f32>,
+    #[serde(default)]
+    pub applied: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelSuggestResponse {
+    pub suggestions: Vec<LabelSuggestion>,
+    /// Provider of the LLM pass, if one ran
+    pub provider: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MasteryGenerateRequest {
+    pub highlight_id: String,
+    /// Most cards to propose; 4 by default, at most 10
+    pub max_cards: Option<usize>,
+    /// Create the drafts as mastery cards instead of only returning them
+    #[serde(default)]
+    pub create: bool,
+    #[serde(flatten)]
+    pub llm: LlmSelection,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MasteryGenerateResponse {
+    /// Proposed cards, ready to send to `sr.mastery.create` once reviewed
+    pub drafts: Vec<crate::spaced_repetition::CreateMasteryCardRequest>,
+    /// Cards created from the drafts when `create` was set
+    pub cards: Vec<crate::spaced_repetition::MasteryCard>,
+    /// Indices into `drafts` of those not created because the card quota
+    /// ran out
+    pub skipped: Vec<usize>,
+    pub provider: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
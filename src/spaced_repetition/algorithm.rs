@@ -6,6 +6,8 @@ use serde::{Deserialize, Serialize};
 pub enum AlgorithmType {
     SM2,
     HalfLife,
+    /// Free Spaced Repetition Scheduler: per-card stability and difficulty
+    Fsrs,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +33,8 @@ pub struct SM2Data {
     pub ease_factor: f32,
     pub interval: i32,
     pub repetitions: i32,
+    #[serde(default)]
+    pub last_reviewed_at: Option<DateTime<Utc>>,
 }
 
 impl Default for SM2Data {
@@ -39,6 +43,7 @@ impl Default for SM2Data {
             ease_factor: 2.5,
             interval: 0,
             repetitions: 0,
+            last_reviewed_at: None,
         }
     }
 }
@@ -51,8 +56,15 @@ impl Algorithm for SM2Data {
         Some(Utc::now() + Duration::days(self.interval as i64))
     }
 
+    /// SM-2 intervals are taken as the time recall falls to 90%, on the
+    /// FSRS forgetting curve. Cards not yet reviewed count as recalled.
     fn get_recall_probability(&self) -> f64 {
-        1.0
+        match self.last_reviewed_at {
+            Some(last_reviewed) if self.interval > 0 => {
+                fsrs::retrievability(fsrs::elapsed_days(last_reviewed), self.interval as f64)
+            }
+            _ => 1.0,
+        }
     }
 }
 
@@ -81,6 +93,31 @@ impl Algorithm for HalfLifeData {
     }
 }
 
+/// FSRS state. It doesn't implement [`Algorithm`]: its schedule depends on
+/// the configured target retention, see [`fsrs::next_review_date`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FsrsData {
+    /// Days until recall probability falls to 90%
+    pub stability: f64,
+    /// 1 (easy) to 10 (hard)
+    pub difficulty: f64,
+    pub last_reviewed_at: Option<DateTime<Utc>>,
+}
+
+/// Recall probability now under `algorithm`, if the item has state for it.
+pub fn recall_probability(
+    algorithm: &AlgorithmType,
+    sm2: Option<&SM2Data>,
+    half_life: Option<&HalfLifeData>,
+    fsrs: Option<&FsrsData>,
+) -> Option<f64> {
+    match algorithm {
+        AlgorithmType::SM2 => sm2.map(Algorithm::get_recall_probability),
+        AlgorithmType::HalfLife => half_life.map(Algorithm::get_recall_probability),
+        AlgorithmType::Fsrs => fsrs.map(fsrs::recall_probability),
+    }
+}
+
 pub mod sm2 {
     use super::*;
 
@@ -106,6 +143,7 @@ pub mod sm2 {
                 _ => (data.interval as f32 * data.ease_factor).round() as i32,
             };
         }
+        data.last_reviewed_at = Some(Utc::now());
 
         let q = quality as f32;
         data.ease_factor =
@@ -174,6 +212,169 @@ pub mod halflife {
     }
 }
 
+/// FSRS-4.5: recall follows a power forgetting curve set by each card's
+/// stability, which grows on successful reviews by an amount depending on its
+/// difficulty and how much it had been forgotten.
+pub mod fsrs {
+    use super::*;
+
+    pub type Weights = [f64; 17];
+
+    /// Published defaults, fitted on a large set of Anki review logs
+    pub const DEFAULT_WEIGHTS: Weights = [
+        0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461,
+        2.1072, 0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+    ];
+    pub const DEFAULT_RETENTION: f64 = 0.9;
+
+    const DECAY: f64 = -0.5;
+    /// Makes recall exactly 90% after `stability` days
+    const FACTOR: f64 = 19.0 / 81.0;
+    const MIN_STABILITY: f64 = 0.1;
+    const MAX_INTERVAL_DAYS: f64 = 36500.0;
+
+    /// 1 (again) to 4 (easy); `None` for feedback that leaves the card as is.
+    pub fn grade(feedback: &ReviewFeedback) -> Option<u8> {
+        match feedback {
+            ReviewFeedback::Again | ReviewFeedback::Soon => Some(1),
+            ReviewFeedback::Hard => Some(2),
+            ReviewFeedback::Good | ReviewFeedback::Later => Some(3),
+            ReviewFeedback::Easy | ReviewFeedback::Someday => Some(4),
+            ReviewFeedback::Never => None,
+        }
+    }
+
+    pub fn elapsed_days(since: DateTime<Utc>) -> f64 {
+        (Utc::now().signed_duration_since(since).num_seconds() as f64 / 86400.0).max(0.0)
+    }
+
+    pub fn retrievability(elapsed_days: f64, stability: f64) -> f64 {
+        (1.0 + FACTOR * elapsed_days / stability).powf(DECAY)
+    }
+
+    /// Days after a review until recall falls to `retention`.
+    pub fn interval_days(stability: f64, retention: f64) -> f64 {
+        stability / FACTOR * (retention.powf(1.0 / DECAY) - 1.0)
+    }
+
+    pub fn recall_probability(data: &FsrsData) -> f64 {
+        match data.last_reviewed_at {
+            Some(last_reviewed) => retrievability(elapsed_days(last_reviewed), data.stability),
+            None => 0.0,
+        }
+    }
+
+    fn initial_difficulty(grade: u8, w: &Weights) -> f64 {
+        (w[4] - (grade as f64 - 3.0) * w[5]).clamp(1.0, 10.0)
+    }
+
+    /// Stability and difficulty after a review graded `grade`, taken
+    /// `elapsed_days` after the previous one; `memory` is `None` for a
+    /// card's first review.
+    pub fn next_memory(
+        memory: Option<(f64, f64)>,
+        grade: u8,
+        elapsed_days: f64,
+        w: &Weights,
+    ) -> (f64, f64) {
+        let grade = grade.clamp(1, 4);
+        let Some((stability, difficulty)) = memory else {
+            let stability = w[grade as usize - 1].max(MIN_STABILITY);
+            return (stability, initial_difficulty(grade, w));
+        };
+
+        let recall = retrievability(elapsed_days, stability);
+        let next_stability = if grade == 1 {
+            let forgotten = w[11]
+                * difficulty.powf(-w[12])
+                * ((stability + 1.0).powf(w[13]) - 1.0)
+                * (w[14] * (1.0 - recall)).exp();
+            forgotten.min(stability)
+        } else {
+            let hard_penalty = if grade == 2 { w[15] } else { 1.0 };
+            let easy_bonus = if grade == 4 { w[16] } else { 1.0 };
+            stability
+                * (w[8].exp()
+                    * (11.0 - difficulty)
+                    * stability.powf(-w[9])
+                    * ((w[10] * (1.0 - recall)).exp() - 1.0)
+                    * hard_penalty
+                    * easy_bonus
+                    + 1.0)
+        };
+
+        let shifted = difficulty - w[6] * (grade as f64 - 3.0);
+        let next_difficulty =
+            (w[7] * initial_difficulty(3, w) + (1.0 - w[7]) * shifted).clamp(1.0, 10.0);
+        (next_stability.max(MIN_STABILITY), next_difficulty)
+    }
+
+    pub fn update_fsrs(data: &mut FsrsData, feedback: ReviewFeedback, w: &Weights) {
+        let Some(grade) = grade(&feedback) else {
+            return;
+        };
+        let (memory, elapsed) = match data.last_reviewed_at {
+            Some(last_reviewed) => (
+                Some((data.stability, data.difficulty)),
+                elapsed_days(last_reviewed),
+            ),
+            None => (None, 0.0),
+        };
+        let (stability, difficulty) = next_memory(memory, grade, elapsed, w);
+
+        data.stability = stability;
+        data.difficulty = difficulty;
+        data.last_reviewed_at = Some(Utc::now());
+    }
+
+    pub fn next_review_date(data: &FsrsData, retention: f64) -> Option<DateTime<Utc>> {
+        let last_reviewed = data.last_reviewed_at?;
+        let days = interval_days(data.stability, retention)
+            .round()
+            .clamp(1.0, MAX_INTERVAL_DAYS);
+        Some(last_reviewed + Duration::days(days as i64))
+    }
+
+    /// FSRS state matching an SM-2 card: its interval is taken as the
+    /// stability, and the difficulty is the one that would grow that
+    /// stability by its ease factor on a "good" review at 90% recall.
+    pub fn from_sm2(
+        data: &SM2Data,
+        last_reviewed_at: Option<DateTime<Utc>>,
+        w: &Weights,
+    ) -> FsrsData {
+        let last_reviewed_at = data.last_reviewed_at.or(last_reviewed_at);
+        if data.interval <= 0 || last_reviewed_at.is_none() {
+            return FsrsData::default();
+        }
+
+        let stability = data.interval as f64;
+        let growth =
+            w[8].exp() * stability.powf(-w[9]) * ((w[10] * (1.0 - DEFAULT_RETENTION)).exp() - 1.0);
+        let difficulty = (11.0 - (data.ease_factor as f64 - 1.0) / growth).clamp(1.0, 10.0);
+        FsrsData {
+            stability,
+            difficulty,
+            last_reviewed_at,
+        }
+    }
+
+    /// FSRS state matching a half-life card, with the two curves agreeing on
+    /// when recall reaches 90%. Difficulty starts at that of a "good" first
+    /// review.
+    pub fn from_halflife(data: &HalfLifeData, w: &Weights) -> FsrsData {
+        let Some(last_reviewed_at) = data.last_reviewed_at else {
+            return FsrsData::default();
+        };
+        let stability = data.half_life_days as f64 * DEFAULT_RETENTION.ln() / 0.5_f64.ln();
+        FsrsData {
+            stability: stability.max(MIN_STABILITY),
+            difficulty: initial_difficulty(3, w),
+            last_reviewed_at: Some(last_reviewed_at),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -209,6 +410,7 @@ mod tests {
             ease_factor: 2.5,
             interval: 10,
             repetitions: 3,
+            last_reviewed_at: None,
         };
         sm2::update_sm2(&mut data, ReviewFeedback::Again);
         assert_eq!(data.repetitions, 0);
@@ -228,6 +430,7 @@ mod tests {
             ease_factor: 1.3,
             interval: 1,
             repetitions: 1,
+            last_reviewed_at: None,
         };
         sm2::update_sm2(&mut data, ReviewFeedback::Hard);
         assert!(data.ease_factor >= 1.3);
@@ -293,4 +496,112 @@ mod tests {
         assert!((halflife::get_initial_half_life(ReviewFeedback::Later) - 14.0).abs() < 0.001);
         assert!((halflife::get_initial_half_life(ReviewFeedback::Someday) - 28.0).abs() < 0.001);
     }
+
+    #[test]
+    fn test_fsrs_recall_is_90_percent_at_stability() {
+        assert!((fsrs::retrievability(12.0, 12.0) - 0.9).abs() < 1e-9);
+        assert!((fsrs::interval_days(12.0, 0.9) - 12.0).abs() < 1e-9);
+        // A higher target retention means shorter intervals
+        assert!(fsrs::interval_days(12.0, 0.95) < 12.0);
+    }
+
+    #[test]
+    fn test_fsrs_first_review() {
+        let w = &fsrs::DEFAULT_WEIGHTS;
+        let (again, again_d) = fsrs::next_memory(None, 1, 0.0, w);
+        let (easy, easy_d) = fsrs::next_memory(None, 4, 0.0, w);
+        assert!((again - w[0]).abs() < 1e-9);
+        assert!((easy - w[3]).abs() < 1e-9);
+        assert!(again_d > easy_d);
+    }
+
+    #[test]
+    fn test_fsrs_success_and_lapse() {
+        let w = &fsrs::DEFAULT_WEIGHTS;
+        let memory = Some((10.0, 5.0));
+
+        let (good, good_d) = fsrs::next_memory(memory, 3, 10.0, w);
+        let (easy, _) = fsrs::next_memory(memory, 4, 10.0, w);
+        let (hard, hard_d) = fsrs::next_memory(memory, 2, 10.0, w);
+        let (again, again_d) = fsrs::next_memory(memory, 1, 10.0, w);
+        assert!(easy > good && good > hard && hard > 10.0);
+        assert!(again < 10.0);
+        assert!(again_d > hard_d && hard_d > good_d);
+
+        // Reviewing later, when more has been forgotten, grows stability more
+        let (late, _) = fsrs::next_memory(memory, 3, 30.0, w);
+        assert!(late > good);
+    }
+
+    #[test]
+    fn test_fsrs_update_and_schedule() {
+        let mut data = FsrsData::default();
+        assert_eq!(fsrs::recall_probability(&data), 0.0);
+
+        fsrs::update_fsrs(&mut data, ReviewFeedback::Good, &fsrs::DEFAULT_WEIGHTS);
+        assert!((data.stability - fsrs::DEFAULT_WEIGHTS[2]).abs() < 1e-9);
+        assert!(fsrs::recall_probability(&data) > 0.99);
+
+        let next = fsrs::next_review_date(&data, 0.9).unwrap();
+        let days = (next - data.last_reviewed_at.unwrap()).num_days();
+        assert_eq!(days, fsrs::DEFAULT_WEIGHTS[2].round() as i64);
+
+        let before = data.clone();
+        fsrs::update_fsrs(&mut data, ReviewFeedback::Never, &fsrs::DEFAULT_WEIGHTS);
+        assert_eq!(data.stability, before.stability);
+    }
+
+    #[test]
+    fn test_sm2_recall_probability() {
+        let mut data = SM2Data::default();
+        assert_eq!(data.get_recall_probability(), 1.0);
+
+        sm2::update_sm2(&mut data, ReviewFeedback::Good);
+        assert!(data.get_recall_probability() > 0.99);
+
+        data.last_reviewed_at = Some(Utc::now() - Duration::days(6));
+        data.interval = 6;
+        assert!((data.get_recall_probability() - 0.9).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_fsrs_migration() {
+        let w = &fsrs::DEFAULT_WEIGHTS;
+        let reviewed = Some(Utc::now() - Duration::days(3));
+        let sm2 = SM2Data {
+            ease_factor: 2.5,
+            interval: 20,
+            repetitions: 4,
+            last_reviewed_at: None,
+        };
+        let migrated = fsrs::from_sm2(&sm2, reviewed, w);
+        assert_eq!(migrated.stability, 20.0);
+        assert!((1.0..=10.0).contains(&migrated.difficulty));
+        assert_eq!(migrated.last_reviewed_at, reviewed);
+
+        // A harder card (lower ease) maps to a higher difficulty
+        let hard = fsrs::from_sm2(
+            &SM2Data {
+                ease_factor: 1.5,
+                ..sm2
+            },
+            reviewed,
+            w,
+        );
+        assert!(hard.difficulty > migrated.difficulty);
+        assert!(fsrs::from_sm2(&SM2Data::default(), reviewed, w)
+            .last_reviewed_at
+            .is_none());
+
+        let half_life = HalfLifeData {
+            half_life_days: 14.0,
+            last_reviewed_at: reviewed,
+        };
+        let migrated = fsrs::from_halflife(&half_life, w);
+        let halflife_days_to_90 = 14.0 * 0.9_f64.ln() / 0.5_f64.ln();
+        assert!((migrated.stability - halflife_days_to_90).abs() < 1e-6);
+        assert!(fsrs::from_halflife(&HalfLifeData::default(), w)
+            .last_reviewed_at
+            .is_none());
+    }
 }
@@ -0,0 +1,137 @@
+//! Anki-style cloze syntax: `{{c1::answer}}` or `{{c1::answer::hint}}`.
+//!
+//! Deletions sharing a number belong to the same card, so a text with `c1`
+//! and `c2` makes two sibling cards.
+
+use std::collections::BTreeMap;
+
+use super::mastery::ClozeDeletion;
+use super::SpacedRepetitionError;
+
+const OPEN: &str = "{{c";
+const CLOSE: &str = "}}";
+const SEPARATOR: &str = "::";
+
+/// Cloze text with its markers taken out.
+#[derive(Debug, Clone)]
+pub struct Cloze {
+    /// The text as read, answers in place
+    pub text: String,
+    /// Deletions in `text` by cloze number, in ascending order
+    pub groups: BTreeMap<u32, Vec<ClozeDeletion>>,
+}
+
+fn invalid(message: impl Into<String>) -> SpacedRepetitionError {
+    SpacedRepetitionError::InvalidCloze(message.into())
+}
+
+/// Parses the cloze markers in `input`, or `None` if it has none. Braces
+/// that don't start a `{{cN::` marker are kept as written.
+pub fn parse(input: &str) -> Result<Option<Cloze>, SpacedRepetitionError> {
+    let mut text = String::with_capacity(input.len());
+    let mut groups: BTreeMap<u32, Vec<ClozeDeletion>> = BTreeMap::new();
+    let mut rest = input;
+
+    while let Some(at) = rest.find(OPEN) {
+        let after = &rest[at + OPEN.len()..];
+        let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let body = match after[digits..].strip_prefix(SEPARATOR) {
+            Some(body) if digits > 0 => body,
+            _ => {
+                text.push_str(&rest[..at + OPEN.len()]);
+                rest = after;
+                continue;
+            }
+        };
+        let number: u32 = after[..digits]
+            .parse()
+            .map_err(|_| invalid(format!("cloze number c{} is too large", &after[..digits])))?;
+
+        let end = body
+            .find(CLOSE)
+            .ok_or_else(|| invalid(format!("c{} is not closed", number)))?;
+        let (answer, hint) = match body[..end].split_once(SEPARATOR) {
+            Some((answer, hint)) => (answer, Some(hint.trim())),
+            None => (&body[..end], None),
+        };
+        if answer.contains("{{") {
+            return Err(invalid(format!(
+                "c{} is not closed before the next",
+                number
+            )));
+        }
+        if answer.trim().is_empty() {
+            return Err(invalid(format!("c{} has no answer", number)));
+        }
+
+        text.push_str(&rest[..at]);
+        let start = text.len();
+        text.push_str(answer);
+        groups.entry(number).or_default().push(ClozeDeletion {
+            start,
+            end: text.len(),
+            hint: hint.filter(|h| !h.is_empty()).map(String::from),
+        });
+        rest = &body[end + CLOSE.len()..];
+    }
+
+    if groups.is_empty() {
+        return Ok(None);
+    }
+    text.push_str(rest);
+    Ok(Some(Cloze { text, groups }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hidden(cloze: &Cloze, number: u32) -> Vec<&str> {
+        cloze.groups[&number]
+            .iter()
+            .map(|d| &cloze.text[d.start..d.end])
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_groups_and_hints() {
+        let cloze =
+            parse("{{c1::Paris}} is the capital of {{c2::France::country}}, on the {{c1::Seine}}.")
+                .unwrap()
+                .unwrap();
+
+        assert_eq!(cloze.text, "Paris is the capital of France, on the Seine.");
+        assert_eq!(cloze.groups.len(), 2);
+        assert_eq!(hidden(&cloze, 1), vec!["Paris", "Seine"]);
+        assert_eq!(hidden(&cloze, 2), vec!["France"]);
+        assert_eq!(cloze.groups[&2][0].hint.as_deref(), Some("country"));
+        assert!(cloze.groups[&1][0].hint.is_none());
+    }
+
+    #[test]
+    fn test_parse_multibyte_offsets() {
+        let cloze = parse("서울은 {{c1::한국}}의 수도이다.").unwrap().unwrap();
+        assert_eq!(cloze.text, "서울은 한국의 수도이다.");
+        assert_eq!(hidden(&cloze, 1), vec!["한국"]);
+    }
+
+    #[test]
+    fn test_parse_without_markers() {
+        assert!(parse("Plain text").unwrap().is_none());
+        assert!(parse("A {{cat}} and {{c::x}}").unwrap().is_none());
+
+        let cloze = parse("{{cat}} {{c1::dog}}").unwrap().unwrap();
+        assert_eq!(cloze.text, "{{cat}} dog");
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_markers() {
+        for input in ["{{c1::open", "{{c1::open {{c2::shut}}", "{{c1::::hint}}"] {
+            assert!(
+                matches!(parse(input), Err(SpacedRepetitionError::InvalidCloze(_))),
+                "{}",
+                input
+            );
+        }
+    }
+}
@@ -2,7 +2,8 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::algorithm::{AlgorithmType, HalfLifeData, SM2Data};
+use super::algorithm::{self, AlgorithmType, FsrsData, HalfLifeData, SM2Data};
+use super::{cloze, SpacedRepetitionError};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
@@ -22,10 +23,18 @@ pub struct MasteryCard {
     pub cloze_deletions: Vec<ClozeDeletion>,
     pub sm2: Option<SM2Data>,
     pub half_life: Option<HalfLifeData>,
+    #[serde(default)]
+    pub fsrs: Option<FsrsData>,
     pub review_count: u32,
     pub last_reviewed_at: Option<DateTime<Utc>>,
     pub next_review_at: Option<DateTime<Utc>>,
     pub is_suspended: bool,
+    /// Shared by the cards made from one cloze text, one per cloze number
+    #[serde(default)]
+    pub sibling_group: Option<String>,
+    /// The `cN` this card hides, for cards made from cloze syntax
+    #[serde(default)]
+    pub cloze_number: Option<u32>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -47,6 +56,18 @@ pub struct CreateMasteryCardRequest {
     pub cloze_deletions: Option<Vec<ClozeDeletion>>,
 }
 
+impl CreateMasteryCardRequest {
+    /// Number of cards the request makes: one per cloze number when the cloze
+    /// text has `{{cN::...}}` markers, otherwise one.
+    pub fn card_count(&self) -> Result<usize, SpacedRepetitionError> {
+        let parsed = match (&self.card_type, self.cloze_text.as_deref()) {
+            (MasteryCardType::Cloze, Some(text)) => cloze::parse(text)?,
+            _ => None,
+        };
+        Ok(parsed.map_or(1, |c| c.groups.len()))
+    }
+}
+
 impl MasteryCard {
     pub fn new(req: CreateMasteryCardRequest, algorithm_type: AlgorithmType) -> Self {
         let now = Utc::now();
@@ -68,15 +89,31 @@ impl MasteryCard {
             } else {
                 None
             },
+            fsrs: if algorithm_type == AlgorithmType::Fsrs {
+                Some(FsrsData::default())
+            } else {
+                None
+            },
             review_count: 0,
             last_reviewed_at: None,
             next_review_at: Some(now),
             is_suspended: false,
+            sibling_group: None,
+            cloze_number: None,
             created_at: now,
             updated_at: now,
         }
     }
 
+    pub fn recall_probability(&self, algorithm_type: &AlgorithmType) -> Option<f64> {
+        algorithm::recall_probability(
+            algorithm_type,
+            self.sm2.as_ref(),
+            self.half_life.as_ref(),
+            self.fsrs.as_ref(),
+        )
+    }
+
     pub fn get_display_text(&self) -> String {
         match self.card_type {
             MasteryCardType::QA => self.question.clone().unwrap_or_default(),
@@ -167,6 +204,20 @@ mod tests {
         assert!(card.half_life.is_none());
     }
 
+    #[test]
+    fn test_card_count() {
+        assert_eq!(create_qa_request().card_count().unwrap(), 1);
+        assert_eq!(create_cloze_request().card_count().unwrap(), 1);
+
+        let mut request = create_cloze_request();
+        request.cloze_deletions = None;
+        request.cloze_text =
+            Some("{{c1::Paris}} is in {{c2::France}}, on the {{c1::Seine}}.".into());
+        assert_eq!(request.card_count().unwrap(), 2);
+        request.cloze_text = Some("{{c1::open".to_string());
+        assert!(request.card_count().is_err());
+    }
+
     #[test]
     fn test_create_cloze_card() {
         let card = MasteryCard::new(create_cloze_request(), AlgorithmType::HalfLife);
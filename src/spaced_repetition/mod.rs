@@ -1,8 +1,9 @@
 //! Spaced Repetition System for Naidis
 //!
-//! Supports two algorithms:
+//! Supports three algorithms:
 //! - SM-2 (SuperMemo 2) - Traditional ease factor based algorithm
 //! - Half-life decay (Readwise style) - Probability based algorithm
+//! - FSRS - Stability/difficulty model scheduled to a target retention
 //!
 //! Features:
 //! - Mastery Cards (Q&A, Cloze deletion)
@@ -11,8 +12,11 @@
 //! - Streak & Stats tracking
 
 pub mod algorithm;
+pub mod cloze;
 pub mod frequency;
 pub mod mastery;
+pub mod optimizer;
+pub mod review_log;
 pub mod session;
 pub mod stats;
 
@@ -22,10 +26,13 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use thiserror::Error;
+use uuid::Uuid;
 
-pub use algorithm::{AlgorithmType, HalfLifeData, ReviewFeedback, SM2Data};
+pub use algorithm::{AlgorithmType, FsrsData, HalfLifeData, ReviewFeedback, SM2Data};
 pub use frequency::FrequencyTuning;
 pub use mastery::{CreateMasteryCardRequest, MasteryCard};
+pub use optimizer::OptimizeReport;
+pub use review_log::{PreviousReview, ReviewLogEntry};
 #[allow(unused_imports)]
 pub use session::{ReviewItem, ReviewItemType, ReviewSession};
 pub use stats::{ReviewStats, StreakData};
@@ -42,8 +49,26 @@ pub enum SpacedRepetitionError {
     HighlightNotFound(String),
     #[error("Invalid algorithm: {0}")]
     InvalidAlgorithm(String),
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
     #[error("No items to review")]
     NoItemsToReview,
+    #[error("Session not found: {0}")]
+    SessionNotFound(String),
+    #[error("Session is already complete")]
+    SessionComplete,
+    #[error("Nothing to undo")]
+    NothingToUndo,
+    #[error("Invalid answer: {0}")]
+    InvalidAnswer(String),
+    #[error("Invalid cloze: {0}")]
+    InvalidCloze(String),
+    #[error("Highlight store error: {0}")]
+    Highlights(#[from] crate::highlights::HighlightError),
+    #[error("Reading store error: {0}")]
+    Reading(#[from] crate::reading::ReadingError),
+    #[error("Not enough reviews to optimize: {found} usable, {needed} needed")]
+    NotEnoughReviews { found: usize, needed: usize },
 }
 
 /// Main configuration for the spaced repetition system
@@ -59,6 +84,25 @@ pub struct SpacedRepetitionConfig {
     pub themed_reviews_enabled: bool,
     /// Enable streak tracking
     pub streak_enabled: bool,
+    /// Recall probability FSRS schedules reviews at, between 0.7 and 0.99
+    #[serde(default = "default_target_retention")]
+    pub target_retention: f64,
+    /// FSRS weights fitted to the review log; the published defaults when unset.
+    /// Config updates that leave it out keep the fitted weights.
+    #[serde(default)]
+    pub fsrs_weights: Option<algorithm::fsrs::Weights>,
+}
+
+fn default_target_retention() -> f64 {
+    algorithm::fsrs::DEFAULT_RETENTION
+}
+
+impl SpacedRepetitionConfig {
+    pub fn weights(&self) -> &algorithm::fsrs::Weights {
+        self.fsrs_weights
+            .as_ref()
+            .unwrap_or(&algorithm::fsrs::DEFAULT_WEIGHTS)
+    }
 }
 
 impl Default for SpacedRepetitionConfig {
@@ -69,10 +113,28 @@ impl Default for SpacedRepetitionConfig {
             mastery_cards_per_day: 10,
             themed_reviews_enabled: true,
             streak_enabled: true,
+            target_retention: default_target_retention(),
+            fsrs_weights: None,
         }
     }
 }
 
+/// Whether a card has no FSRS state yet, or was reviewed with SM-2 or
+/// half-life after its FSRS state was last updated.
+fn fsrs_is_stale(
+    fsrs: Option<&FsrsData>,
+    sm2: Option<&SM2Data>,
+    half_life: Option<&HalfLifeData>,
+) -> bool {
+    let Some(fsrs) = fsrs else {
+        return true;
+    };
+    let reviewed = sm2
+        .and_then(|s| s.last_reviewed_at)
+        .max(half_life.and_then(|h| h.last_reviewed_at));
+    reviewed > fsrs.last_reviewed_at
+}
+
 /// Spaced repetition data for a highlight
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HighlightSRData {
@@ -81,6 +143,9 @@ pub struct HighlightSRData {
     pub sm2: Option<SM2Data>,
     /// Half-life specific data (if using half-life)
     pub half_life: Option<HalfLifeData>,
+    /// FSRS specific data (if using FSRS)
+    #[serde(default)]
+    pub fsrs: Option<FsrsData>,
     /// Review status
     pub status: HighlightReviewStatus,
     /// Number of times reviewed
@@ -110,6 +175,11 @@ impl HighlightSRData {
             } else {
                 None
             },
+            fsrs: if algorithm_type == AlgorithmType::Fsrs {
+                Some(FsrsData::default())
+            } else {
+                None
+            },
             status: HighlightReviewStatus::Active,
             review_count: 0,
             last_reviewed_at: None,
@@ -118,6 +188,15 @@ impl HighlightSRData {
             updated_at: now,
         }
     }
+
+    pub fn recall_probability(&self, algorithm_type: &AlgorithmType) -> Option<f64> {
+        algorithm::recall_probability(
+            algorithm_type,
+            self.sm2.as_ref(),
+            self.half_life.as_ref(),
+            self.fsrs.as_ref(),
+        )
+    }
 }
 
 /// Review status for a highlight
@@ -130,6 +209,8 @@ pub enum HighlightReviewStatus {
     Discarded,
     /// Converted to mastery card
     Mastered,
+    /// Its highlight was deleted
+    Orphaned,
 }
 
 /// Main store for spaced repetition data
@@ -140,6 +221,7 @@ pub struct SpacedRepetitionStore {
     mastery_cards: HashMap<String, MasteryCard>,
     frequency_tuning: FrequencyTuning,
     stats: ReviewStats,
+    sessions: HashMap<String, ReviewSession>,
 }
 
 impl SpacedRepetitionStore {
@@ -154,6 +236,7 @@ impl SpacedRepetitionStore {
             mastery_cards: HashMap::new(),
             frequency_tuning: FrequencyTuning::default(),
             stats: ReviewStats::default(),
+            sessions: HashMap::new(),
         };
         store.load_all()?;
         Ok(store)
@@ -195,6 +278,25 @@ impl SpacedRepetitionStore {
             self.stats = serde_json::from_str(&data)?;
         }
 
+        // Load review sessions
+        let sessions_path = self.data_dir.join("sessions.json");
+        if sessions_path.exists() {
+            let data = fs::read_to_string(&sessions_path)?;
+            self.sessions = serde_json::from_str(&data)?;
+        }
+
+        // SM-2 state saved before it tracked its own review time
+        for h in self.highlight_data.values_mut() {
+            if let Some(sm2) = h.sm2.as_mut().filter(|s| s.last_reviewed_at.is_none()) {
+                sm2.last_reviewed_at = h.last_reviewed_at;
+            }
+        }
+        for c in self.mastery_cards.values_mut() {
+            if let Some(sm2) = c.sm2.as_mut().filter(|s| s.last_reviewed_at.is_none()) {
+                sm2.last_reviewed_at = c.last_reviewed_at;
+            }
+        }
+
         Ok(())
     }
 
@@ -233,20 +335,99 @@ impl SpacedRepetitionStore {
         Ok(())
     }
 
+    fn save_sessions(&self) -> Result<(), SpacedRepetitionError> {
+        let path = self.data_dir.join("sessions.json");
+        let data = serde_json::to_string_pretty(&self.sessions)?;
+        fs::write(&path, data)?;
+        Ok(())
+    }
+
     // ========== Config Methods ==========
 
     pub fn get_config(&self) -> &SpacedRepetitionConfig {
         &self.config
     }
 
+    /// Saves `config`; switching to FSRS migrates existing cards to it.
     pub fn update_config(
         &mut self,
-        config: SpacedRepetitionConfig,
+        mut config: SpacedRepetitionConfig,
     ) -> Result<(), SpacedRepetitionError> {
+        if !(0.7..=0.99).contains(&config.target_retention) {
+            return Err(SpacedRepetitionError::InvalidConfig(format!(
+                "target_retention must be between 0.7 and 0.99, got {}",
+                config.target_retention
+            )));
+        }
+
+        if config.fsrs_weights.is_none() {
+            config.fsrs_weights = self.config.fsrs_weights.take();
+        }
         self.config = config;
+        if self.config.algorithm_type == AlgorithmType::Fsrs {
+            self.migrate_to_fsrs()?;
+        }
         self.save_config()
     }
 
+    /// Gives every card without FSRS state, or whose SM-2 or half-life state
+    /// was reviewed since, FSRS state derived from it, rescheduling those
+    /// already reviewed. Returns how many cards were migrated.
+    fn migrate_to_fsrs(&mut self) -> Result<usize, SpacedRepetitionError> {
+        let weights = self.config.weights();
+        let retention = self.config.target_retention;
+        let migrate = |sm2: Option<&SM2Data>,
+                       half_life: Option<&HalfLifeData>,
+                       last_reviewed_at: Option<DateTime<Utc>>| {
+            match (sm2.filter(|s| s.interval > 0), half_life) {
+                (Some(sm2), Some(half_life))
+                    if half_life.last_reviewed_at > sm2.last_reviewed_at =>
+                {
+                    algorithm::fsrs::from_halflife(half_life, weights)
+                }
+                (Some(sm2), _) => algorithm::fsrs::from_sm2(sm2, last_reviewed_at, weights),
+                (_, Some(half_life)) => algorithm::fsrs::from_halflife(half_life, weights),
+                _ => FsrsData::default(),
+            }
+        };
+
+        let mut highlights = 0;
+        for h in self
+            .highlight_data
+            .values_mut()
+            .filter(|h| fsrs_is_stale(h.fsrs.as_ref(), h.sm2.as_ref(), h.half_life.as_ref()))
+        {
+            let fsrs = migrate(h.sm2.as_ref(), h.half_life.as_ref(), h.last_reviewed_at);
+            if h.status == HighlightReviewStatus::Active && fsrs.last_reviewed_at.is_some() {
+                h.next_review_at = algorithm::fsrs::next_review_date(&fsrs, retention);
+            }
+            h.fsrs = Some(fsrs);
+            highlights += 1;
+        }
+
+        let mut cards = 0;
+        for c in self
+            .mastery_cards
+            .values_mut()
+            .filter(|c| fsrs_is_stale(c.fsrs.as_ref(), c.sm2.as_ref(), c.half_life.as_ref()))
+        {
+            let fsrs = migrate(c.sm2.as_ref(), c.half_life.as_ref(), c.last_reviewed_at);
+            if fsrs.last_reviewed_at.is_some() {
+                c.next_review_at = algorithm::fsrs::next_review_date(&fsrs, retention);
+            }
+            c.fsrs = Some(fsrs);
+            cards += 1;
+        }
+
+        if highlights > 0 {
+            self.save_highlight_data()?;
+        }
+        if cards > 0 {
+            self.save_mastery_cards()?;
+        }
+        Ok(highlights + cards)
+    }
+
     // ========== Highlight SR Methods ==========
 
     pub fn register_highlight(
@@ -279,6 +460,12 @@ impl SpacedRepetitionStore {
             .ok_or_else(|| SpacedRepetitionError::HighlightNotFound(highlight_id.to_string()))?;
 
         let now = Utc::now();
+        let previous = PreviousReview::new(
+            sr_data.last_reviewed_at,
+            sr_data.next_review_at,
+            sr_data.recall_probability(&self.config.algorithm_type),
+            now,
+        );
         sr_data.last_reviewed_at = Some(now);
         sr_data.review_count += 1;
         sr_data.updated_at = now;
@@ -287,20 +474,26 @@ impl SpacedRepetitionStore {
             HighlightReviewAction::Keep => {
                 // Update next review based on algorithm
                 match self.config.algorithm_type {
+                    // Items created under another algorithm start fresh state
                     AlgorithmType::SM2 => {
-                        if let Some(ref mut sm2) = sr_data.sm2 {
-                            let feedback = ReviewFeedback::Good;
-                            algorithm::sm2::update_sm2(sm2, feedback);
-                            sr_data.next_review_at =
-                                Some(now + chrono::Duration::days(sm2.interval as i64));
-                        }
+                        let sm2 = sr_data.sm2.get_or_insert_with(SM2Data::default);
+                        let feedback = ReviewFeedback::Good;
+                        algorithm::sm2::update_sm2(sm2, feedback);
+                        sr_data.next_review_at =
+                            Some(now + chrono::Duration::days(sm2.interval as i64));
                     }
                     AlgorithmType::HalfLife => {
-                        if let Some(ref mut hl) = sr_data.half_life {
-                            let feedback = ReviewFeedback::Later;
-                            algorithm::halflife::update_halflife(hl, feedback);
-                            sr_data.next_review_at = algorithm::halflife::next_review_date(hl);
-                        }
+                        let hl = sr_data.half_life.get_or_insert_with(HalfLifeData::default);
+                        let feedback = ReviewFeedback::Later;
+                        algorithm::halflife::update_halflife(hl, feedback);
+                        sr_data.next_review_at = algorithm::halflife::next_review_date(hl);
+                    }
+                    AlgorithmType::Fsrs => {
+                        let fsrs = sr_data.fsrs.get_or_insert_with(FsrsData::default);
+                        let weights = self.config.weights();
+                        algorithm::fsrs::update_fsrs(fsrs, ReviewFeedback::Good, weights);
+                        sr_data.next_review_at =
+                            algorithm::fsrs::next_review_date(fsrs, self.config.target_retention);
                     }
                 }
             }
@@ -316,6 +509,16 @@ impl SpacedRepetitionStore {
 
         let updated = sr_data.clone();
         self.save_highlight_data()?;
+        self.log_review(&ReviewLogEntry {
+            item_id: highlight_id.to_string(),
+            item_type: ReviewItemType::Highlight,
+            reviewed_at: now,
+            feedback: None,
+            action: Some(action),
+            algorithm: self.config.algorithm_type.clone(),
+            next_review_at: updated.next_review_at,
+            previous,
+        })?;
 
         // Update stats
         self.stats.record_review(now);
@@ -326,14 +529,52 @@ impl SpacedRepetitionStore {
 
     // ========== Mastery Card Methods ==========
 
+    /// Creates the card `req` describes or, for cloze text written with
+    /// `{{c1::...}}` markers, one sibling card per cloze number.
     pub fn create_mastery_card(
         &mut self,
         req: CreateMasteryCardRequest,
-    ) -> Result<MasteryCard, SpacedRepetitionError> {
-        let card = MasteryCard::new(req, self.config.algorithm_type.clone());
-        self.mastery_cards.insert(card.id.clone(), card.clone());
+    ) -> Result<Vec<MasteryCard>, SpacedRepetitionError> {
+        let algorithm_type = self.config.algorithm_type.clone();
+        let parsed = match (&req.card_type, req.cloze_text.as_deref()) {
+            (mastery::MasteryCardType::Cloze, Some(text)) => cloze::parse(text)?,
+            _ => None,
+        };
+
+        let cards = match parsed {
+            Some(parsed) => {
+                if req.cloze_deletions.as_ref().is_some_and(|d| !d.is_empty()) {
+                    return Err(SpacedRepetitionError::InvalidCloze(
+                        "give either cloze markers or deletions, not both".to_string(),
+                    ));
+                }
+                let sibling_group = Uuid::new_v4().to_string();
+                parsed
+                    .groups
+                    .into_iter()
+                    .map(|(number, deletions)| {
+                        let mut card = MasteryCard::new(
+                            CreateMasteryCardRequest {
+                                cloze_text: Some(parsed.text.clone()),
+                                cloze_deletions: Some(deletions),
+                                ..req.clone()
+                            },
+                            algorithm_type.clone(),
+                        );
+                        card.sibling_group = Some(sibling_group.clone());
+                        card.cloze_number = Some(number);
+                        card
+                    })
+                    .collect()
+            }
+            None => vec![MasteryCard::new(req, algorithm_type)],
+        };
+
+        for card in &cards {
+            self.mastery_cards.insert(card.id.clone(), card.clone());
+        }
         self.save_mastery_cards()?;
-        Ok(card)
+        Ok(cards)
     }
 
     pub fn get_mastery_card(&self, card_id: &str) -> Option<&MasteryCard> {
@@ -351,27 +592,49 @@ impl SpacedRepetitionStore {
             .ok_or_else(|| SpacedRepetitionError::CardNotFound(card_id.to_string()))?;
 
         let now = Utc::now();
+        let previous = PreviousReview::new(
+            card.last_reviewed_at,
+            card.next_review_at,
+            card.recall_probability(&self.config.algorithm_type),
+            now,
+        );
         card.last_reviewed_at = Some(now);
         card.review_count += 1;
         card.updated_at = now;
+        let logged_feedback = feedback.clone();
 
         match self.config.algorithm_type {
+            // Cards created under another algorithm start fresh state
             AlgorithmType::SM2 => {
-                if let Some(ref mut sm2) = card.sm2 {
-                    algorithm::sm2::update_sm2(sm2, feedback);
-                    card.next_review_at = Some(now + chrono::Duration::days(sm2.interval as i64));
-                }
+                let sm2 = card.sm2.get_or_insert_with(SM2Data::default);
+                algorithm::sm2::update_sm2(sm2, feedback);
+                card.next_review_at = Some(now + chrono::Duration::days(sm2.interval as i64));
             }
             AlgorithmType::HalfLife => {
-                if let Some(ref mut hl) = card.half_life {
-                    algorithm::halflife::update_halflife(hl, feedback);
-                    card.next_review_at = algorithm::halflife::next_review_date(hl);
-                }
+                let hl = card.half_life.get_or_insert_with(HalfLifeData::default);
+                algorithm::halflife::update_halflife(hl, feedback);
+                card.next_review_at = algorithm::halflife::next_review_date(hl);
+            }
+            AlgorithmType::Fsrs => {
+                let fsrs = card.fsrs.get_or_insert_with(FsrsData::default);
+                algorithm::fsrs::update_fsrs(fsrs, feedback, self.config.weights());
+                card.next_review_at =
+                    algorithm::fsrs::next_review_date(fsrs, self.config.target_retention);
             }
         }
 
         let updated = card.clone();
         self.save_mastery_cards()?;
+        self.log_review(&ReviewLogEntry {
+            item_id: card_id.to_string(),
+            item_type: ReviewItemType::MasteryCard,
+            reviewed_at: now,
+            feedback: Some(logged_feedback),
+            action: None,
+            algorithm: self.config.algorithm_type.clone(),
+            next_review_at: updated.next_review_at,
+            previous,
+        })?;
 
         // Update stats
         self.stats.record_review(now);
@@ -424,7 +687,7 @@ impl SpacedRepetitionStore {
 }
 
 /// Action to take on a highlight during review
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum HighlightReviewAction {
     Keep,
@@ -467,7 +730,7 @@ pub fn review_highlight(
 pub fn create_mastery_card(
     data_dir: PathBuf,
     req: CreateMasteryCardRequest,
-) -> Result<MasteryCard, SpacedRepetitionError> {
+) -> Result<Vec<MasteryCard>, SpacedRepetitionError> {
     let mut store = SpacedRepetitionStore::new(data_dir)?;
     store.create_mastery_card(req)
 }
@@ -489,6 +752,41 @@ pub fn delete_mastery_card(
     store.delete_mastery_card(&card_id)
 }
 
+pub fn get_review_log(
+    data_dir: PathBuf,
+    item_id: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Vec<ReviewLogEntry>, SpacedRepetitionError> {
+    let store = SpacedRepetitionStore::new(data_dir)?;
+    let mut log = store.review_log()?;
+    if let Some(item_id) = item_id {
+        log.retain(|entry| entry.item_id == item_id);
+    }
+    // Newest first
+    log.reverse();
+    log.truncate(limit.unwrap_or(usize::MAX));
+    Ok(log)
+}
+
+/// Fits FSRS weights to the review log, saving them to the config when
+/// `apply` is set and they predict the log better than the current ones.
+pub fn optimize_fsrs(
+    data_dir: PathBuf,
+    apply: bool,
+    progress: &crate::jobs::Progress,
+) -> Result<OptimizeReport, SpacedRepetitionError> {
+    let store = SpacedRepetitionStore::new(data_dir.clone())?;
+    let mut report = optimizer::optimize(&store.review_log()?, store.config.weights(), progress)?;
+    if apply && report.log_loss <= report.previous_log_loss {
+        // The fit takes a while; keep config changes saved in the meantime
+        let mut store = SpacedRepetitionStore::new(data_dir)?;
+        store.config.fsrs_weights = Some(report.weights);
+        store.save_config()?;
+        report.applied = true;
+    }
+    Ok(report)
+}
+
 pub fn get_stats(data_dir: PathBuf) -> Result<ReviewStats, SpacedRepetitionError> {
     let store = SpacedRepetitionStore::new(data_dir)?;
     Ok(store.get_stats().clone())
@@ -544,6 +842,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_config_update_keeps_fitted_weights() {
+        let dir = tempdir().unwrap();
+        let mut store = SpacedRepetitionStore::new(dir.path().to_path_buf()).unwrap();
+        let mut weights = algorithm::fsrs::DEFAULT_WEIGHTS;
+        weights[0] = 0.5;
+        store.config.fsrs_weights = Some(weights);
+
+        // A client that doesn't know about the weights sends none back
+        let mut update: SpacedRepetitionConfig = serde_json::from_value(serde_json::json!({
+            "algorithm_type": "fsrs",
+            "highlights_per_day": 5,
+            "mastery_cards_per_day": 5,
+            "themed_reviews_enabled": true,
+            "streak_enabled": true,
+        }))
+        .unwrap();
+        store.update_config(update.clone()).unwrap();
+        assert_eq!(store.config.fsrs_weights, Some(weights));
+
+        weights[0] = 0.7;
+        update.fsrs_weights = Some(weights);
+        store.update_config(update).unwrap();
+        assert_eq!(store.config.fsrs_weights, Some(weights));
+    }
+
     #[test]
     fn test_register_highlight() {
         let dir = tempdir().unwrap();
@@ -570,6 +894,128 @@ mod tests {
         assert!(updated.next_review_at.is_some());
     }
 
+    #[test]
+    fn test_switch_to_fsrs_migrates_cards() {
+        let dir = tempdir().unwrap();
+        let mut store = SpacedRepetitionStore::new(dir.path().to_path_buf()).unwrap();
+
+        store.register_highlight("h1".to_string()).unwrap();
+        store.register_highlight("h2".to_string()).unwrap();
+        store
+            .review_highlight("h1", HighlightReviewAction::Keep)
+            .unwrap();
+
+        let mut config = store.get_config().clone();
+        config.algorithm_type = AlgorithmType::Fsrs;
+        config.target_retention = 0.5;
+        assert!(store.update_config(config.clone()).is_err());
+
+        config.target_retention = 0.9;
+        store.update_config(config).unwrap();
+
+        let reviewed = store.get_highlight_sr("h1").unwrap();
+        let fsrs = reviewed.fsrs.as_ref().unwrap();
+        assert!(fsrs.stability > 0.0);
+        assert!(reviewed.next_review_at.unwrap() > Utc::now());
+        let recall = reviewed.recall_probability(&AlgorithmType::Fsrs).unwrap();
+        assert!(recall > 0.99);
+
+        let new = store.get_highlight_sr("h2").unwrap();
+        assert!(new.fsrs.as_ref().unwrap().last_reviewed_at.is_none());
+
+        let updated = store
+            .review_highlight("h2", HighlightReviewAction::Keep)
+            .unwrap();
+        let fsrs = updated.fsrs.unwrap();
+        assert!((fsrs.stability - algorithm::fsrs::DEFAULT_WEIGHTS[2]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_switching_back_to_fsrs_picks_up_later_reviews() {
+        let dir = tempdir().unwrap();
+        let mut store = SpacedRepetitionStore::new(dir.path().to_path_buf()).unwrap();
+        store.register_highlight("h1".to_string()).unwrap();
+        store
+            .review_highlight("h1", HighlightReviewAction::Keep)
+            .unwrap();
+
+        let mut config = store.get_config().clone();
+        config.algorithm_type = AlgorithmType::Fsrs;
+        store.update_config(config.clone()).unwrap();
+        let migrated = store.get_highlight_sr("h1").unwrap().fsrs.clone().unwrap();
+
+        // Reviews under half-life make the FSRS state stale
+        config.algorithm_type = AlgorithmType::HalfLife;
+        store.update_config(config.clone()).unwrap();
+        let reviewed = store
+            .review_highlight("h1", HighlightReviewAction::Keep)
+            .unwrap();
+        let half_life = reviewed.half_life.unwrap();
+        assert!(half_life.last_reviewed_at > migrated.last_reviewed_at);
+
+        config.algorithm_type = AlgorithmType::Fsrs;
+        store.update_config(config.clone()).unwrap();
+        let h1 = store.get_highlight_sr("h1").unwrap();
+        let fsrs = h1.fsrs.clone().unwrap();
+        assert_eq!(fsrs.last_reviewed_at, half_life.last_reviewed_at);
+        let derived = algorithm::fsrs::from_halflife(&half_life, config.weights());
+        assert_eq!(fsrs.stability, derived.stability);
+        assert_eq!(
+            h1.next_review_at,
+            algorithm::fsrs::next_review_date(&fsrs, config.target_retention)
+        );
+
+        // FSRS state reviewed last is kept through another round trip
+        let reviewed = store
+            .review_highlight("h1", HighlightReviewAction::Keep)
+            .unwrap();
+        let fsrs = reviewed.fsrs.unwrap();
+        config.algorithm_type = AlgorithmType::HalfLife;
+        store.update_config(config.clone()).unwrap();
+        config.algorithm_type = AlgorithmType::Fsrs;
+        store.update_config(config).unwrap();
+        let kept = store.get_highlight_sr("h1").unwrap().fsrs.clone().unwrap();
+        assert_eq!(kept.last_reviewed_at, fsrs.last_reviewed_at);
+        assert_eq!(kept.stability, fsrs.stability);
+    }
+
+    #[test]
+    fn test_cards_created_under_fsrs_schedule_after_switching_back() {
+        let dir = tempdir().unwrap();
+        let mut store = SpacedRepetitionStore::new(dir.path().to_path_buf()).unwrap();
+        let mut config = store.get_config().clone();
+        config.algorithm_type = AlgorithmType::Fsrs;
+        store.update_config(config.clone()).unwrap();
+
+        store.register_highlight("h1".to_string()).unwrap();
+        let card = store
+            .create_mastery_card(CreateMasteryCardRequest {
+                highlight_id: "h1".to_string(),
+                card_type: mastery::MasteryCardType::QA,
+                question: Some("What is focus?".to_string()),
+                answer: Some("A skill".to_string()),
+                cloze_text: None,
+                cloze_deletions: None,
+            })
+            .unwrap()
+            .remove(0);
+        assert!(card.sm2.is_none() && card.half_life.is_none());
+
+        for algorithm_type in [AlgorithmType::SM2, AlgorithmType::HalfLife] {
+            config.algorithm_type = algorithm_type;
+            store.update_config(config.clone()).unwrap();
+
+            let reviewed = store
+                .review_mastery_card(&card.id, ReviewFeedback::Good)
+                .unwrap();
+            assert!(reviewed.next_review_at.unwrap() > Utc::now());
+            let highlight = store
+                .review_highlight("h1", HighlightReviewAction::Keep)
+                .unwrap();
+            assert!(highlight.next_review_at.unwrap() > Utc::now());
+        }
+    }
+
     #[test]
     fn test_review_highlight_discard() {
         let dir = tempdir().unwrap();
@@ -0,0 +1,363 @@
+//! Fits FSRS weights to the review log.
+//!
+//! Each mastery card's graded reviews are replayed in order; every review
+//! after the first is a prediction of whether the card would be recalled.
+//! Weights are fitted by minimising the log loss of those predictions with
+//! Adam on finite-difference gradients, kept within the ranges FSRS allows.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::algorithm::fsrs::{self, Weights};
+use super::review_log::ReviewLogEntry;
+use super::session::ReviewItemType;
+use super::SpacedRepetitionError;
+use crate::jobs::Progress;
+
+/// Predicted reviews needed before fitting is worth trying
+pub const MIN_REVIEWS: usize = 50;
+const ITERATIONS: usize = 150;
+const LEARNING_RATE: f64 = 0.04;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const CALIBRATION_BINS: usize = 10;
+
+const BOUNDS: [(f64, f64); 17] = [
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (1.0, 10.0),
+    (0.1, 4.0),
+    (0.1, 4.0),
+    (0.0, 0.75),
+    (0.0, 4.5),
+    (0.0, 0.8),
+    (0.001, 3.5),
+    (0.001, 5.0),
+    (0.001, 0.25),
+    (0.001, 0.9),
+    (0.0, 4.0),
+    (0.0, 1.0),
+    (1.0, 6.0),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizeReport {
+    pub weights: Weights,
+    pub cards: usize,
+    /// Reviews with a prediction: every graded review after a card's first
+    pub reviews: usize,
+    /// Share of those reviews that were recalled
+    pub actual_retention: f64,
+    /// Mean predicted recall with the fitted weights
+    pub predicted_retention: f64,
+    /// Mean predicted recall with the weights in use before fitting
+    pub previous_predicted_retention: f64,
+    pub log_loss: f64,
+    pub previous_log_loss: f64,
+    /// Fitted predictions grouped by predicted recall
+    pub calibration: Vec<CalibrationBin>,
+    /// Whether the fitted weights were saved to the config
+    pub applied: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationBin {
+    pub predicted: f64,
+    pub actual: f64,
+    pub count: usize,
+}
+
+/// Each card's graded reviews, oldest first, as days since the previous
+/// review and grade.
+type History = Vec<(f64, u8)>;
+
+fn histories(log: &[ReviewLogEntry]) -> Vec<History> {
+    let mut by_card: HashMap<&str, Vec<(DateTime<Utc>, u8)>> = HashMap::new();
+    for entry in log {
+        if entry.item_type != ReviewItemType::MasteryCard {
+            continue;
+        }
+        if let Some(grade) = entry.feedback.as_ref().and_then(fsrs::grade) {
+            by_card
+                .entry(entry.item_id.as_str())
+                .or_default()
+                .push((entry.reviewed_at, grade));
+        }
+    }
+
+    by_card
+        .into_values()
+        .map(|mut reviews| {
+            reviews.sort_by_key(|(at, _)| *at);
+            let mut previous: Option<DateTime<Utc>> = None;
+            reviews
+                .into_iter()
+                .map(|(at, grade)| {
+                    let elapsed = previous.map_or(0.0, |p| {
+                        at.signed_duration_since(p).num_seconds() as f64 / 86400.0
+                    });
+                    previous = Some(at);
+                    (elapsed, grade)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Predicted recall and whether it happened, for every review after a
+/// card's first.
+fn predictions(histories: &[History], w: &Weights) -> Vec<(f64, bool)> {
+    let mut out = Vec::new();
+    for history in histories {
+        let mut memory = None;
+        for &(elapsed, grade) in history {
+            if let Some((stability, _)) = memory {
+                out.push((fsrs::retrievability(elapsed, stability), grade > 1));
+            }
+            memory = Some(fsrs::next_memory(memory, grade, elapsed, w));
+        }
+    }
+    out
+}
+
+fn log_loss(histories: &[History], w: &Weights) -> f64 {
+    let predictions = predictions(histories, w);
+    let total: f64 = predictions
+        .iter()
+        .map(|&(p, recalled)| {
+            let p = p.clamp(1e-6, 1.0 - 1e-6);
+            if recalled {
+                -p.ln()
+            } else {
+                -(1.0 - p).ln()
+            }
+        })
+        .sum();
+    total / predictions.len().max(1) as f64
+}
+
+fn gradient(histories: &[History], w: &Weights) -> Weights {
+    let mut gradient = [0.0; 17];
+    for (i, g) in gradient.iter_mut().enumerate() {
+        let h = 1e-4 * w[i].abs().max(1.0);
+        let (mut up, mut down) = (*w, *w);
+        up[i] += h;
+        down[i] -= h;
+        *g = (log_loss(histories, &up) - log_loss(histories, &down)) / (2.0 * h);
+    }
+    gradient
+}
+
+/// Weights minimising the log loss, starting from `start`.
+fn fit(histories: &[History], start: &Weights, progress: &Progress) -> Weights {
+    let mut w = *start;
+    let mut best = (log_loss(histories, &w), w);
+    let (mut m, mut v) = ([0.0; 17], [0.0; 17]);
+
+    for t in 1..=ITERATIONS {
+        let gradient = gradient(histories, &w);
+        for (i, g) in gradient.iter().enumerate() {
+            m[i] = BETA1 * m[i] + (1.0 - BETA1) * g;
+            v[i] = BETA2 * v[i] + (1.0 - BETA2) * g * g;
+            let m_hat = m[i] / (1.0 - BETA1.powi(t as i32));
+            let v_hat = v[i] / (1.0 - BETA2.powi(t as i32));
+            let (low, high) = BOUNDS[i];
+            w[i] = (w[i] - LEARNING_RATE * m_hat / (v_hat.sqrt() + 1e-8)).clamp(low, high);
+        }
+
+        let loss = log_loss(histories, &w);
+        if loss < best.0 {
+            best = (loss, w);
+        }
+        progress.step(t, ITERATIONS, "Fitting FSRS weights");
+    }
+    best.1
+}
+
+fn mean_prediction(predictions: &[(f64, bool)]) -> f64 {
+    predictions.iter().map(|(p, _)| p).sum::<f64>() / predictions.len().max(1) as f64
+}
+
+fn calibration(predictions: &[(f64, bool)]) -> Vec<CalibrationBin> {
+    let mut bins = vec![(0.0, 0usize, 0usize); CALIBRATION_BINS];
+    for &(p, recalled) in predictions {
+        let bin = &mut bins[((p * CALIBRATION_BINS as f64) as usize).min(CALIBRATION_BINS - 1)];
+        bin.0 += p;
+        bin.1 += recalled as usize;
+        bin.2 += 1;
+    }
+    bins.into_iter()
+        .filter(|&(_, _, count)| count > 0)
+        .map(|(predicted, recalled, count)| CalibrationBin {
+            predicted: predicted / count as f64,
+            actual: recalled as f64 / count as f64,
+            count,
+        })
+        .collect()
+}
+
+/// Fits weights to `log`, comparing them with `current`, the weights in use.
+pub fn optimize(
+    log: &[ReviewLogEntry],
+    current: &Weights,
+    progress: &Progress,
+) -> Result<OptimizeReport, SpacedRepetitionError> {
+    let histories = histories(log);
+    let previous = predictions(&histories, current);
+    if previous.len() < MIN_REVIEWS {
+        return Err(SpacedRepetitionError::NotEnoughReviews {
+            found: previous.len(),
+            needed: MIN_REVIEWS,
+        });
+    }
+
+    let weights = fit(&histories, current, progress);
+    let fitted = predictions(&histories, &weights);
+    let recalled = fitted.iter().filter(|(_, recalled)| *recalled).count();
+
+    Ok(OptimizeReport {
+        weights,
+        cards: histories.len(),
+        reviews: fitted.len(),
+        actual_retention: recalled as f64 / fitted.len() as f64,
+        predicted_retention: mean_prediction(&fitted),
+        previous_predicted_retention: mean_prediction(&previous),
+        log_loss: log_loss(&histories, &weights),
+        previous_log_loss: log_loss(&histories, current),
+        calibration: calibration(&fitted),
+        applied: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spaced_repetition::algorithm::{AlgorithmType, ReviewFeedback};
+    use crate::spaced_repetition::review_log::PreviousReview;
+    use chrono::Duration;
+
+    /// Deterministic uniform numbers in [0, 1)
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn uniform(&mut self) -> f64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+
+    fn entry(card: usize, at: DateTime<Utc>, feedback: ReviewFeedback) -> ReviewLogEntry {
+        ReviewLogEntry {
+            item_id: format!("c{}", card),
+            item_type: ReviewItemType::MasteryCard,
+            reviewed_at: at,
+            feedback: Some(feedback),
+            action: None,
+            algorithm: AlgorithmType::Fsrs,
+            previous: PreviousReview::default(),
+            next_review_at: None,
+        }
+    }
+
+    /// A log from someone who forgets much faster than the default weights
+    /// expect, reviewing on the default schedule.
+    fn simulated_log(cards: usize, reviews: usize) -> Vec<ReviewLogEntry> {
+        let mut truth = fsrs::DEFAULT_WEIGHTS;
+        for w in truth.iter_mut().take(4) {
+            *w *= 0.2;
+        }
+        truth[8] -= 1.0;
+
+        let mut rng = Lcg(7);
+        let start = Utc::now() - Duration::days(3650);
+        let mut log = Vec::new();
+        for card in 0..cards {
+            let mut at = start;
+            let mut true_memory = None;
+            let mut scheduled = None;
+            for _ in 0..reviews {
+                let elapsed = match scheduled {
+                    Some((stability, _)) => fsrs::interval_days(stability, 0.9).max(1.0),
+                    None => 0.0,
+                };
+                at += Duration::seconds((elapsed * 86400.0) as i64);
+                let recalled = match true_memory {
+                    Some((stability, _)) => {
+                        rng.uniform() < fsrs::retrievability(elapsed, stability)
+                    }
+                    None => true,
+                };
+                let grade = if recalled { 3 } else { 1 };
+                log.push(entry(
+                    card,
+                    at,
+                    if recalled {
+                        ReviewFeedback::Good
+                    } else {
+                        ReviewFeedback::Again
+                    },
+                ));
+                true_memory = Some(fsrs::next_memory(true_memory, grade, elapsed, &truth));
+                scheduled = Some(fsrs::next_memory(
+                    scheduled,
+                    grade,
+                    elapsed,
+                    &fsrs::DEFAULT_WEIGHTS,
+                ));
+            }
+        }
+        log
+    }
+
+    #[test]
+    fn test_histories_group_graded_card_reviews() {
+        let at = Utc::now();
+        let mut log = vec![
+            entry(1, at + Duration::days(2), ReviewFeedback::Again),
+            entry(1, at, ReviewFeedback::Good),
+            entry(2, at, ReviewFeedback::Never),
+        ];
+        let mut highlight = entry(3, at, ReviewFeedback::Good);
+        highlight.item_type = ReviewItemType::Highlight;
+        log.push(highlight);
+
+        let histories = histories(&log);
+        assert_eq!(histories, vec![vec![(0.0, 3), (2.0, 1)]]);
+    }
+
+    #[test]
+    fn test_optimize_needs_enough_reviews() {
+        let log = simulated_log(5, 3);
+        let result = optimize(&log, &fsrs::DEFAULT_WEIGHTS, &Progress::none());
+        assert!(matches!(
+            result,
+            Err(SpacedRepetitionError::NotEnoughReviews { found: 10, .. })
+        ));
+    }
+
+    #[test]
+    fn test_optimize_fits_faster_forgetting() {
+        let log = simulated_log(60, 5);
+        let report = optimize(&log, &fsrs::DEFAULT_WEIGHTS, &Progress::none()).unwrap();
+
+        assert_eq!(report.cards, 60);
+        assert_eq!(report.reviews, 240);
+        assert!(report.log_loss < report.previous_log_loss);
+        let error = (report.predicted_retention - report.actual_retention).abs();
+        let previous_error = (report.previous_predicted_retention - report.actual_retention).abs();
+        assert!(error < previous_error);
+        assert_eq!(
+            report.calibration.iter().map(|b| b.count).sum::<usize>(),
+            report.reviews
+        );
+        for (w, (low, high)) in report.weights.iter().zip(BOUNDS) {
+            assert!((low..=high).contains(w));
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Append-only log of individual reviews, kept so scheduling can be audited
+//! and fitted to how the user actually remembers.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use super::algorithm::{AlgorithmType, ReviewFeedback};
+use super::session::ReviewItemType;
+use super::{HighlightReviewAction, SpacedRepetitionError, SpacedRepetitionStore};
+
+const REVIEW_LOG_FILE: &str = "review_log.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewLogEntry {
+    /// Highlight id or mastery card id
+    pub item_id: String,
+    pub item_type: ReviewItemType,
+    pub reviewed_at: DateTime<Utc>,
+    /// Rating given to a mastery card
+    pub feedback: Option<ReviewFeedback>,
+    /// Action taken on a highlight
+    pub action: Option<HighlightReviewAction>,
+    /// Algorithm that scheduled the next review
+    pub algorithm: AlgorithmType,
+    #[serde(flatten)]
+    pub previous: PreviousReview,
+    pub next_review_at: Option<DateTime<Utc>>,
+}
+
+/// How the item stood just before the review; all unset on its first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreviousReview {
+    /// Days since the previous review
+    pub elapsed_days: Option<f64>,
+    /// Days the previous review scheduled this one for
+    pub previous_interval_days: Option<f64>,
+    /// Predicted recall when reviewed
+    pub recall_probability: Option<f64>,
+}
+
+fn days_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    to.signed_duration_since(from).num_seconds() as f64 / 86400.0
+}
+
+impl PreviousReview {
+    pub fn new(
+        last_reviewed_at: Option<DateTime<Utc>>,
+        next_review_at: Option<DateTime<Utc>>,
+        recall_probability: Option<f64>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let Some(last_reviewed_at) = last_reviewed_at else {
+            return Self::default();
+        };
+        Self {
+            elapsed_days: Some(days_between(last_reviewed_at, now)),
+            previous_interval_days: next_review_at.map(|next| days_between(last_reviewed_at, next)),
+            recall_probability,
+        }
+    }
+}
+
+impl SpacedRepetitionStore {
+    pub(super) fn log_review(&self, entry: &ReviewLogEntry) -> Result<(), SpacedRepetitionError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.data_dir.join(REVIEW_LOG_FILE))?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    /// Drops the logged review of `item_id` at `reviewed_at`, for undo.
+    pub(super) fn unlog_review(
+        &self,
+        item_id: &str,
+        reviewed_at: DateTime<Utc>,
+    ) -> Result<(), SpacedRepetitionError> {
+        let path = self.data_dir.join(REVIEW_LOG_FILE);
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let data = fs::read_to_string(&path)?;
+        let mut lines: Vec<&str> = data.lines().collect();
+        let logged = lines.iter().rposition(|line| {
+            serde_json::from_str::<ReviewLogEntry>(line)
+                .is_ok_and(|e| e.item_id == item_id && e.reviewed_at == reviewed_at)
+        });
+        if let Some(index) = logged {
+            lines.remove(index);
+            let data: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+            fs::write(&path, data)?;
+        }
+        Ok(())
+    }
+
+    /// Every logged review, oldest first. Lines that don't parse, such as
+    /// one cut short by a crash, are skipped.
+    pub fn review_log(&self) -> Result<Vec<ReviewLogEntry>, SpacedRepetitionError> {
+        let path = self.data_dir.join(REVIEW_LOG_FILE);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let data = fs::read_to_string(&path)?;
+        Ok(data
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    tracing::warn!("Skipping review log line: {}", e);
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spaced_repetition::mastery::MasteryCardType;
+    use crate::spaced_repetition::CreateMasteryCardRequest;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_reviews_are_logged() {
+        let dir = tempdir().unwrap();
+        let mut store = SpacedRepetitionStore::new(dir.path().to_path_buf()).unwrap();
+        let card = store
+            .create_mastery_card(CreateMasteryCardRequest {
+                highlight_id: "h1".to_string(),
+                card_type: MasteryCardType::QA,
+                question: Some("Q".to_string()),
+                answer: Some("A".to_string()),
+                cloze_text: None,
+                cloze_deletions: None,
+            })
+            .unwrap()
+            .remove(0);
+        store.register_highlight("h1".to_string()).unwrap();
+
+        store
+            .review_mastery_card(&card.id, ReviewFeedback::Good)
+            .unwrap();
+        store
+            .review_mastery_card(&card.id, ReviewFeedback::Again)
+            .unwrap();
+        store
+            .review_highlight("h1", HighlightReviewAction::Discard)
+            .unwrap();
+
+        let log = SpacedRepetitionStore::new(dir.path().to_path_buf())
+            .unwrap()
+            .review_log()
+            .unwrap();
+        assert_eq!(log.len(), 3);
+
+        assert_eq!(log[0].item_id, card.id);
+        assert!(log[0].previous.elapsed_days.is_none());
+        assert!(matches!(log[1].feedback, Some(ReviewFeedback::Again)));
+        assert!(log[1].previous.elapsed_days.unwrap() < 0.01);
+        assert!(log[1].previous.previous_interval_days.unwrap() > 0.0);
+        assert!(log[1].previous.recall_probability.is_some());
+
+        assert_eq!(log[2].item_type, ReviewItemType::Highlight);
+        assert_eq!(log[2].action, Some(HighlightReviewAction::Discard));
+        assert!(log[2].next_review_at.is_none());
+    }
+
+    #[test]
+    fn test_review_log_skips_torn_lines() {
+        let dir = tempdir().unwrap();
+        let store = SpacedRepetitionStore::new(dir.path().to_path_buf()).unwrap();
+        let entry = ReviewLogEntry {
+            item_id: "c1".to_string(),
+            item_type: ReviewItemType::MasteryCard,
+            reviewed_at: Utc::now(),
+            feedback: Some(ReviewFeedback::Hard),
+            action: None,
+            algorithm: AlgorithmType::Fsrs,
+            previous: PreviousReview::default(),
+            next_review_at: None,
+        };
+        fs::write(
+            store.data_dir.join(REVIEW_LOG_FILE),
+            format!(
+                "{}\n{{\"item_id\": \"c2\"",
+                serde_json::to_string(&entry).unwrap()
+            ),
+        )
+        .unwrap();
+
+        let log = store.review_log().unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].item_id, "c1");
+    }
+}
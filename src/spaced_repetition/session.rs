@@ -1,9 +1,14 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use uuid::Uuid;
 
-use super::algorithm::halflife;
-use super::{HighlightReviewStatus, SpacedRepetitionError, SpacedRepetitionStore};
+use super::{
+    HighlightReviewAction, HighlightReviewStatus, HighlightSRData, MasteryCard, ReviewFeedback,
+    SpacedRepetitionError, SpacedRepetitionStore,
+};
+use crate::highlights::{Highlight, HighlightStore};
+use crate::reading::ReadingStore;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
@@ -37,6 +42,25 @@ pub struct ReviewSession {
     pub started_at: DateTime<Utc>,
     pub completed_at: Option<DateTime<Utc>>,
     pub session_type: ReviewSessionType,
+    /// Answers given so far, oldest first, kept so they can be undone
+    #[serde(default)]
+    pub answers: Vec<SessionAnswer>,
+}
+
+/// An answer given during a session and the state it replaced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionAnswer {
+    pub item_index: usize,
+    pub answered_at: DateTime<Utc>,
+    pub before: ItemSnapshot,
+}
+
+/// Scheduling state of a review item before it was answered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "item_type", content = "state", rename_all = "snake_case")]
+pub enum ItemSnapshot {
+    Highlight(HighlightSRData),
+    MasteryCard(Box<MasteryCard>),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -56,6 +80,18 @@ pub struct CreateSessionRequest {
     pub document_ids: Option<Vec<String>>,
 }
 
+/// Answer to the current item of a session: an action for a highlight,
+/// feedback for a mastery card.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionAnswerRequest {
+    pub id: String,
+    pub feedback: Option<ReviewFeedback>,
+    pub action: Option<HighlightReviewAction>,
+}
+
+/// Sessions are kept this long after they start, finished or not
+const SESSION_RETENTION_DAYS: i64 = 30;
+
 impl ReviewSession {
     pub fn new(items: Vec<ReviewItem>, session_type: ReviewSessionType) -> Self {
         Self {
@@ -66,6 +102,7 @@ impl ReviewSession {
             started_at: Utc::now(),
             completed_at: None,
             session_type,
+            answers: Vec::new(),
         }
     }
 
@@ -101,10 +138,69 @@ impl ReviewSession {
     }
 }
 
+/// Highlights and articles that review items are filled in from.
+pub struct ItemSources<'a> {
+    pub highlights: &'a HighlightStore,
+    pub articles: &'a ReadingStore,
+}
+
+impl ItemSources<'_> {
+    /// Title and author of the article `highlight` was made in.
+    fn source(&self, highlight: Option<&Highlight>) -> (Option<String>, Option<String>) {
+        match highlight.and_then(|h| self.articles.get(&h.article_id)) {
+            Some(article) => (Some(article.title.clone()), article.author.clone()),
+            None => (None, None),
+        }
+    }
+}
+
 impl SpacedRepetitionStore {
+    /// Takes active highlights that were deleted out of review, marking them
+    /// orphaned, and puts orphaned ones whose highlight is back into review,
+    /// due right away. Returns how many were flagged.
+    pub fn flag_orphaned_highlights(
+        &mut self,
+        highlights: &HighlightStore,
+    ) -> Result<usize, SpacedRepetitionError> {
+        let now = Utc::now();
+        let mut flagged = 0;
+        let mut restored = 0;
+        for h in self.highlight_data.values_mut() {
+            let exists = highlights.get(&h.highlight_id).is_some();
+            match h.status {
+                HighlightReviewStatus::Active if !exists => {
+                    h.status = HighlightReviewStatus::Orphaned;
+                    h.next_review_at = None;
+                    flagged += 1;
+                }
+                HighlightReviewStatus::Orphaned if exists => {
+                    h.status = HighlightReviewStatus::Active;
+                    h.next_review_at = Some(now);
+                    restored += 1;
+                }
+                _ => continue,
+            }
+            h.updated_at = now;
+        }
+
+        if flagged + restored > 0 {
+            tracing::info!(
+                "Flagged {} orphaned highlights, restored {}",
+                flagged,
+                restored
+            );
+            self.save_highlight_data()?;
+        }
+        Ok(flagged)
+    }
+
+    /// Builds a session of due items with their text, note and source filled
+    /// in from `sources`. Highlights missing from it are left out; mastery
+    /// cards carry their own text, so only lose their source.
     pub fn create_review_session(
         &self,
         req: CreateSessionRequest,
+        sources: &ItemSources,
     ) -> Result<ReviewSession, SpacedRepetitionError> {
         let mut items = Vec::new();
 
@@ -123,36 +219,30 @@ impl SpacedRepetitionStore {
                 h.status == HighlightReviewStatus::Active
                     && h.next_review_at.map(|d| d <= now).unwrap_or(true)
             })
+            .filter_map(|h| Some((h, sources.highlights.get(&h.highlight_id)?)))
             .collect();
 
-        highlights_due.sort_by(|a, b| {
-            let prob_a = a
-                .half_life
-                .as_ref()
-                .map(halflife::recall_probability)
-                .unwrap_or(0.0);
-            let prob_b = b
-                .half_life
-                .as_ref()
-                .map(halflife::recall_probability)
-                .unwrap_or(0.0);
-            prob_a
-                .partial_cmp(&prob_b)
-                .unwrap_or(std::cmp::Ordering::Equal)
+        // Least likely to be recalled first
+        let algorithm = &self.config.algorithm_type;
+        highlights_due.sort_by(|(a, _), (b, _)| {
+            let prob_a = a.recall_probability(algorithm).unwrap_or(0.0);
+            let prob_b = b.recall_probability(algorithm).unwrap_or(0.0);
+            prob_a.total_cmp(&prob_b)
         });
 
-        for h in highlights_due.into_iter().take(highlight_limit) {
+        for (h, highlight) in highlights_due.into_iter().take(highlight_limit) {
+            let (source_title, source_author) = sources.source(Some(highlight));
             items.push(ReviewItem {
                 id: h.highlight_id.clone(),
                 item_type: ReviewItemType::Highlight,
                 highlight_id: h.highlight_id.clone(),
-                text: String::new(),
-                source_title: None,
-                source_author: None,
-                note: None,
+                text: highlight.text.clone(),
+                source_title,
+                source_author,
+                note: highlight.note.clone(),
                 question: None,
                 answer: None,
-                recall_probability: h.half_life.as_ref().map(halflife::recall_probability),
+                recall_probability: h.recall_probability(algorithm),
                 last_reviewed_at: h.last_reviewed_at,
                 review_count: h.review_count,
             });
@@ -165,33 +255,33 @@ impl SpacedRepetitionStore {
             .collect();
 
         mastery_due.sort_by(|a, b| {
-            let prob_a = a
-                .half_life
-                .as_ref()
-                .map(halflife::recall_probability)
-                .unwrap_or(0.0);
-            let prob_b = b
-                .half_life
-                .as_ref()
-                .map(halflife::recall_probability)
-                .unwrap_or(0.0);
-            prob_a
-                .partial_cmp(&prob_b)
-                .unwrap_or(std::cmp::Ordering::Equal)
+            let prob_a = a.recall_probability(algorithm).unwrap_or(0.0);
+            let prob_b = b.recall_probability(algorithm).unwrap_or(0.0);
+            prob_a.total_cmp(&prob_b)
+        });
+
+        // One card per sibling group, so a card doesn't give away the
+        // answers its siblings hide; the rest wait for a later session
+        let mut sibling_groups = HashSet::new();
+        let mastery_due = mastery_due.into_iter().filter(|&c| match &c.sibling_group {
+            Some(group) => sibling_groups.insert(group.as_str()),
+            None => true,
         });
 
-        for c in mastery_due.into_iter().take(mastery_limit) {
+        for c in mastery_due.take(mastery_limit) {
+            let (source_title, source_author) =
+                sources.source(sources.highlights.get(&c.highlight_id));
             items.push(ReviewItem {
                 id: c.id.clone(),
                 item_type: ReviewItemType::MasteryCard,
                 highlight_id: c.highlight_id.clone(),
                 text: c.get_display_text(),
-                source_title: None,
-                source_author: None,
+                source_title,
+                source_author,
                 note: None,
                 question: c.question.clone(),
                 answer: Some(c.get_answer_text()),
-                recall_probability: c.half_life.as_ref().map(halflife::recall_probability),
+                recall_probability: c.recall_probability(algorithm),
                 last_reviewed_at: c.last_reviewed_at,
                 review_count: c.review_count,
             });
@@ -204,6 +294,169 @@ impl SpacedRepetitionStore {
         Ok(ReviewSession::new(items, req.session_type))
     }
 
+    /// Stores a newly created session, dropping ones past retention.
+    pub fn start_session(&mut self, session: &ReviewSession) -> Result<(), SpacedRepetitionError> {
+        let cutoff = Utc::now() - Duration::days(SESSION_RETENTION_DAYS);
+        self.sessions.retain(|_, s| s.started_at >= cutoff);
+        self.sessions.insert(session.id.clone(), session.clone());
+        self.save_sessions()?;
+
+        self.stats.record_session_start(session.started_at);
+        self.save_stats()
+    }
+
+    pub fn get_session(&self, session_id: &str) -> Option<&ReviewSession> {
+        self.sessions.get(session_id)
+    }
+
+    /// The most recently started session that hasn't been finished.
+    pub fn active_session(&self) -> Option<&ReviewSession> {
+        self.sessions
+            .values()
+            .filter(|s| s.completed_at.is_none())
+            .max_by_key(|s| s.started_at)
+    }
+
+    fn session_mut(
+        &mut self,
+        session_id: &str,
+    ) -> Result<&mut ReviewSession, SpacedRepetitionError> {
+        self.sessions
+            .get_mut(session_id)
+            .ok_or_else(|| SpacedRepetitionError::SessionNotFound(session_id.to_string()))
+    }
+
+    /// Reviews the session's current item and moves on to the next,
+    /// completing the session after its last item. An item whose highlight
+    /// or card was deleted since the session started is skipped unanswered.
+    pub fn answer_session_item(
+        &mut self,
+        req: SessionAnswerRequest,
+    ) -> Result<ReviewSession, SpacedRepetitionError> {
+        let session = self.session_mut(&req.id)?;
+        let item_index = session.current_index;
+        let item = session
+            .current_item()
+            .cloned()
+            .ok_or(SpacedRepetitionError::SessionComplete)?;
+
+        let exists = match item.item_type {
+            ReviewItemType::Highlight => self.highlight_data.contains_key(&item.id),
+            ReviewItemType::MasteryCard => self.mastery_cards.contains_key(&item.id),
+        };
+        if !exists {
+            tracing::info!("Skipping deleted review item {}", item.id);
+            return self.advance_session(&req.id, None);
+        }
+
+        let (before, answered_at) = match item.item_type {
+            ReviewItemType::Highlight => {
+                let action = req.action.ok_or_else(|| {
+                    SpacedRepetitionError::InvalidAnswer("a highlight needs an action".to_string())
+                })?;
+                let before = self
+                    .highlight_data
+                    .get(&item.id)
+                    .cloned()
+                    .ok_or_else(|| SpacedRepetitionError::HighlightNotFound(item.id.clone()))?;
+                let updated = self.review_highlight(&item.id, action)?;
+                (ItemSnapshot::Highlight(before), updated.last_reviewed_at)
+            }
+            ReviewItemType::MasteryCard => {
+                let feedback = req.feedback.ok_or_else(|| {
+                    SpacedRepetitionError::InvalidAnswer(
+                        "a mastery card needs feedback".to_string(),
+                    )
+                })?;
+                let before = self
+                    .mastery_cards
+                    .get(&item.id)
+                    .cloned()
+                    .ok_or_else(|| SpacedRepetitionError::CardNotFound(item.id.clone()))?;
+                let updated = self.review_mastery_card(&item.id, feedback)?;
+                (
+                    ItemSnapshot::MasteryCard(Box::new(before)),
+                    updated.last_reviewed_at,
+                )
+            }
+        };
+        let answer = SessionAnswer {
+            item_index,
+            answered_at: answered_at.unwrap_or_else(Utc::now),
+            before,
+        };
+        self.advance_session(&req.id, Some(answer))
+    }
+
+    /// Moves the session past its current item, recording `answer` for it.
+    fn advance_session(
+        &mut self,
+        session_id: &str,
+        answer: Option<SessionAnswer>,
+    ) -> Result<ReviewSession, SpacedRepetitionError> {
+        let session = self.session_mut(session_id)?;
+        session.answers.extend(answer);
+        session.next();
+        if session.is_complete() {
+            session.complete();
+        }
+        let session = session.clone();
+        self.save_sessions()?;
+
+        if let Some(completed_at) = session.completed_at {
+            self.stats.record_session_complete(completed_at);
+            self.save_stats()?;
+        }
+        Ok(session)
+    }
+
+    /// Takes back the session's last answer, restoring the item's scheduling
+    /// state and making it the current item again. A deleted item is not
+    /// brought back.
+    pub fn undo_session_answer(
+        &mut self,
+        session_id: &str,
+    ) -> Result<ReviewSession, SpacedRepetitionError> {
+        let session = self.session_mut(session_id)?;
+        let answer = session
+            .answers
+            .pop()
+            .ok_or(SpacedRepetitionError::NothingToUndo)?;
+        let completed_at = session.completed_at.take();
+        session.current_index = answer.item_index;
+        session.completed_count = session.completed_count.saturating_sub(1);
+        let session = session.clone();
+
+        // An item deleted since it was answered stays deleted
+        let item_id = match answer.before {
+            ItemSnapshot::Highlight(data) => {
+                let item_id = data.highlight_id.clone();
+                if let Some(current) = self.highlight_data.get_mut(&item_id) {
+                    *current = data;
+                    self.save_highlight_data()?;
+                }
+                item_id
+            }
+            ItemSnapshot::MasteryCard(card) => {
+                let item_id = card.id.clone();
+                if let Some(current) = self.mastery_cards.get_mut(&item_id) {
+                    *current = *card;
+                    self.save_mastery_cards()?;
+                }
+                item_id
+            }
+        };
+        self.unlog_review(&item_id, answer.answered_at)?;
+
+        self.stats.remove_review(answer.answered_at);
+        if let Some(completed_at) = completed_at {
+            self.stats.remove_session_complete(completed_at);
+        }
+        self.save_stats()?;
+        self.save_sessions()?;
+        Ok(session)
+    }
+
     pub fn get_due_counts(&self) -> (usize, usize) {
         let now = Utc::now();
 
@@ -230,8 +483,53 @@ pub fn create_review_session(
     data_dir: std::path::PathBuf,
     req: CreateSessionRequest,
 ) -> Result<ReviewSession, SpacedRepetitionError> {
+    let mut store = SpacedRepetitionStore::new(data_dir.clone())?;
+    let highlights = HighlightStore::new(data_dir.clone())?;
+    let articles = ReadingStore::new(data_dir)?;
+    store.flag_orphaned_highlights(&highlights)?;
+    let session = store.create_review_session(
+        req,
+        &ItemSources {
+            highlights: &highlights,
+            articles: &articles,
+        },
+    )?;
+    store.start_session(&session)?;
+    Ok(session)
+}
+
+pub fn answer_session_item(
+    data_dir: std::path::PathBuf,
+    req: SessionAnswerRequest,
+) -> Result<ReviewSession, SpacedRepetitionError> {
+    let mut store = SpacedRepetitionStore::new(data_dir)?;
+    store.answer_session_item(req)
+}
+
+pub fn undo_session_answer(
+    data_dir: std::path::PathBuf,
+    session_id: &str,
+) -> Result<ReviewSession, SpacedRepetitionError> {
+    let mut store = SpacedRepetitionStore::new(data_dir)?;
+    store.undo_session_answer(session_id)
+}
+
+pub fn resume_session(
+    data_dir: std::path::PathBuf,
+    session_id: &str,
+) -> Result<ReviewSession, SpacedRepetitionError> {
+    let store = SpacedRepetitionStore::new(data_dir)?;
+    store
+        .get_session(session_id)
+        .cloned()
+        .ok_or_else(|| SpacedRepetitionError::SessionNotFound(session_id.to_string()))
+}
+
+pub fn get_active_session(
+    data_dir: std::path::PathBuf,
+) -> Result<Option<ReviewSession>, SpacedRepetitionError> {
     let store = SpacedRepetitionStore::new(data_dir)?;
-    store.create_review_session(req)
+    Ok(store.active_session().cloned())
 }
 
 pub fn get_due_counts(
@@ -244,6 +542,349 @@ pub fn get_due_counts(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::highlights::{CreateHighlightRequest, HighlightPosition};
+    use crate::reading::SaveArticleRequest;
+    use crate::spaced_repetition::mastery::MasteryCardType;
+    use crate::spaced_repetition::CreateMasteryCardRequest;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_session_items_are_hydrated() {
+        let dir = tempdir().unwrap();
+        let data_dir = dir.path().to_path_buf();
+        let article = ReadingStore::new(data_dir.clone())
+            .unwrap()
+            .save(SaveArticleRequest {
+                url: None,
+                title: "Deep Work".to_string(),
+                author: Some("Cal Newport".to_string()),
+                content: "Focus is a skill.".to_string(),
+                excerpt: None,
+                site_name: None,
+                article_type: None,
+                labels: None,
+                thumbnail_url: None,
+            })
+            .unwrap();
+        let mut highlights = HighlightStore::new(data_dir.clone()).unwrap();
+        let highlight = highlights
+            .create(CreateHighlightRequest {
+                article_id: article.id.clone(),
+                text: "Focus is a skill.".to_string(),
+                note: Some("Practice daily".to_string()),
+                color: None,
+                position: HighlightPosition {
+                    start_offset: 0,
+                    end_offset: 17,
+                    paragraph_index: None,
+                    page_number: None,
+                },
+            })
+            .unwrap();
+
+        let mut store = SpacedRepetitionStore::new(data_dir.clone()).unwrap();
+        store.register_highlight(highlight.id.clone()).unwrap();
+        store.register_highlight("deleted".to_string()).unwrap();
+        store
+            .create_mastery_card(CreateMasteryCardRequest {
+                highlight_id: highlight.id.clone(),
+                card_type: MasteryCardType::QA,
+                question: Some("What is focus?".to_string()),
+                answer: Some("A skill".to_string()),
+                cloze_text: None,
+                cloze_deletions: None,
+            })
+            .unwrap();
+
+        let request = CreateSessionRequest {
+            session_type: ReviewSessionType::Daily,
+            highlight_limit: None,
+            mastery_limit: None,
+            tags: None,
+            document_ids: None,
+        };
+        let session = create_review_session(data_dir.clone(), request).unwrap();
+        assert_eq!(session.items.len(), 2);
+
+        let item = &session.items[0];
+        assert_eq!(item.highlight_id, highlight.id);
+        assert_eq!(item.text, "Focus is a skill.");
+        assert_eq!(item.note.as_deref(), Some("Practice daily"));
+        assert_eq!(item.source_title.as_deref(), Some("Deep Work"));
+        assert_eq!(item.source_author.as_deref(), Some("Cal Newport"));
+
+        let card = &session.items[1];
+        assert_eq!(card.item_type, ReviewItemType::MasteryCard);
+        assert_eq!(card.source_title.as_deref(), Some("Deep Work"));
+
+        let store = SpacedRepetitionStore::new(data_dir.clone()).unwrap();
+        let orphan = store.get_highlight_sr("deleted").unwrap();
+        assert_eq!(orphan.status, HighlightReviewStatus::Orphaned);
+        assert_eq!(get_due_counts(data_dir).unwrap(), (1, 1));
+    }
+
+    #[test]
+    fn test_orphan_flags_follow_the_highlights() {
+        let dir = tempdir().unwrap();
+        let data_dir = dir.path().to_path_buf();
+        let mut highlights = HighlightStore::new(data_dir.clone()).unwrap();
+        let mut create = |text: &str| {
+            highlights
+                .create(CreateHighlightRequest {
+                    article_id: "a1".to_string(),
+                    text: text.to_string(),
+                    note: None,
+                    color: None,
+                    position: HighlightPosition {
+                        start_offset: 0,
+                        end_offset: text.len(),
+                        paragraph_index: None,
+                        page_number: None,
+                    },
+                })
+                .unwrap()
+                .id
+        };
+        let active = create("Focus is a skill.");
+        let discarded = create("Rest is part of work.");
+
+        let mut store = SpacedRepetitionStore::new(data_dir.clone()).unwrap();
+        store.register_highlight(active.clone()).unwrap();
+        store.register_highlight(discarded.clone()).unwrap();
+        store
+            .review_highlight(&discarded, HighlightReviewAction::Discard)
+            .unwrap();
+
+        // Counting due items leaves the file alone, deleted highlight and all
+        store.register_highlight("deleted".to_string()).unwrap();
+        let path = data_dir
+            .join("spaced_repetition")
+            .join("highlights_sr.json");
+        let saved = fs::read(&path).unwrap();
+        get_due_counts(data_dir.clone()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), saved);
+
+        // All go missing: only the active ones are orphaned
+        let empty_dir = tempdir().unwrap();
+        let missing = HighlightStore::new(empty_dir.path().to_path_buf()).unwrap();
+        assert_eq!(store.flag_orphaned_highlights(&missing).unwrap(), 2);
+        let status = |store: &SpacedRepetitionStore, id: &str| {
+            store.get_highlight_sr(id).unwrap().status.clone()
+        };
+        assert_eq!(status(&store, &active), HighlightReviewStatus::Orphaned);
+        assert_eq!(status(&store, &discarded), HighlightReviewStatus::Discarded);
+        assert_eq!(store.get_due_counts(), (0, 0));
+
+        // Two come back
+        let highlights = HighlightStore::new(data_dir).unwrap();
+        assert_eq!(store.flag_orphaned_highlights(&highlights).unwrap(), 0);
+        assert_eq!(status(&store, &active), HighlightReviewStatus::Active);
+        assert_eq!(status(&store, &discarded), HighlightReviewStatus::Discarded);
+        assert_eq!(status(&store, "deleted"), HighlightReviewStatus::Orphaned);
+        assert_eq!(store.get_due_counts(), (1, 0));
+    }
+
+    #[test]
+    fn test_answer_undo_and_resume() {
+        let dir = tempdir().unwrap();
+        let data_dir = dir.path().to_path_buf();
+        let mut highlights = HighlightStore::new(data_dir.clone()).unwrap();
+        let highlight = highlights
+            .create(CreateHighlightRequest {
+                article_id: "a1".to_string(),
+                text: "Focus is a skill.".to_string(),
+                note: None,
+                color: None,
+                position: HighlightPosition {
+                    start_offset: 0,
+                    end_offset: 17,
+                    paragraph_index: None,
+                    page_number: None,
+                },
+            })
+            .unwrap();
+        let mut store = SpacedRepetitionStore::new(data_dir.clone()).unwrap();
+        store.register_highlight(highlight.id.clone()).unwrap();
+        let card = store
+            .create_mastery_card(CreateMasteryCardRequest {
+                highlight_id: highlight.id.clone(),
+                card_type: MasteryCardType::QA,
+                question: Some("What is focus?".to_string()),
+                answer: Some("A skill".to_string()),
+                cloze_text: None,
+                cloze_deletions: None,
+            })
+            .unwrap()
+            .remove(0);
+
+        let request = CreateSessionRequest {
+            session_type: ReviewSessionType::Daily,
+            highlight_limit: None,
+            mastery_limit: None,
+            tags: None,
+            document_ids: None,
+        };
+        let session = create_review_session(data_dir.clone(), request).unwrap();
+        let answer = |feedback, action| SessionAnswerRequest {
+            id: session.id.clone(),
+            feedback,
+            action,
+        };
+
+        let wrong = answer_session_item(data_dir.clone(), answer(Some(ReviewFeedback::Good), None));
+        assert!(matches!(
+            wrong,
+            Err(SpacedRepetitionError::InvalidAnswer(_))
+        ));
+
+        // Restarting the app between answers picks the session back up
+        answer_session_item(
+            data_dir.clone(),
+            answer(None, Some(HighlightReviewAction::Keep)),
+        )
+        .unwrap();
+        let active = get_active_session(data_dir.clone()).unwrap().unwrap();
+        assert_eq!(active.id, session.id);
+        assert_eq!(active.current_item().unwrap().id, card.id);
+
+        let done = answer_session_item(data_dir.clone(), answer(Some(ReviewFeedback::Easy), None))
+            .unwrap();
+        assert!(done.completed_at.is_some());
+        assert!(get_active_session(data_dir.clone()).unwrap().is_none());
+
+        let undone = undo_session_answer(data_dir.clone(), &session.id).unwrap();
+        assert!(undone.completed_at.is_none());
+        assert_eq!(undone.current_item().unwrap().id, card.id);
+        assert_eq!(undone.completed_count, 1);
+
+        let store = SpacedRepetitionStore::new(data_dir.clone()).unwrap();
+        let restored = store.get_mastery_card(&card.id).unwrap();
+        assert_eq!(restored.review_count, 0);
+        assert!(restored.last_reviewed_at.is_none());
+        assert_eq!(restored.next_review_at, card.next_review_at);
+        assert_eq!(store.review_log().unwrap().len(), 1);
+        assert_eq!(store.get_stats().total_reviews, 1);
+
+        let resumed = resume_session(data_dir.clone(), &session.id).unwrap();
+        assert_eq!(resumed.answers.len(), 1);
+        assert_eq!(resumed.current_index, 1);
+
+        undo_session_answer(data_dir.clone(), &session.id).unwrap();
+        let store = SpacedRepetitionStore::new(data_dir.clone()).unwrap();
+        assert_eq!(
+            store.get_highlight_sr(&highlight.id).unwrap().review_count,
+            0
+        );
+        assert!(matches!(
+            undo_session_answer(data_dir, &session.id),
+            Err(SpacedRepetitionError::NothingToUndo)
+        ));
+    }
+
+    #[test]
+    fn test_deleted_items_are_skipped() {
+        let dir = tempdir().unwrap();
+        let data_dir = dir.path().to_path_buf();
+        let mut store = SpacedRepetitionStore::new(data_dir.clone()).unwrap();
+        let mut create = |question: &str| {
+            store
+                .create_mastery_card(CreateMasteryCardRequest {
+                    highlight_id: "h1".to_string(),
+                    card_type: MasteryCardType::QA,
+                    question: Some(question.to_string()),
+                    answer: Some("A skill".to_string()),
+                    cloze_text: None,
+                    cloze_deletions: None,
+                })
+                .unwrap()
+                .remove(0)
+        };
+        let kept = create("What is focus?");
+        let deleted = create("What is rest?");
+
+        let request = CreateSessionRequest {
+            session_type: ReviewSessionType::Daily,
+            highlight_limit: None,
+            mastery_limit: None,
+            tags: None,
+            document_ids: None,
+        };
+        let session = create_review_session(data_dir.clone(), request).unwrap();
+        assert_eq!(session.items.len(), 2);
+        crate::spaced_repetition::delete_mastery_card(data_dir.clone(), deleted.id).unwrap();
+
+        let answer = || SessionAnswerRequest {
+            id: session.id.clone(),
+            feedback: Some(ReviewFeedback::Good),
+            action: None,
+        };
+        // Whichever comes first, the deleted card is passed over unanswered
+        answer_session_item(data_dir.clone(), answer()).unwrap();
+        let done = answer_session_item(data_dir.clone(), answer()).unwrap();
+        assert!(done.completed_at.is_some());
+        assert_eq!(done.answers.len(), 1);
+
+        let store = SpacedRepetitionStore::new(data_dir.clone()).unwrap();
+        assert_eq!(store.get_mastery_card(&kept.id).unwrap().review_count, 1);
+        assert_eq!(store.review_log().unwrap().len(), 1);
+        assert!(matches!(
+            answer_session_item(data_dir.clone(), answer()),
+            Err(SpacedRepetitionError::SessionComplete)
+        ));
+
+        // Undoing an answer to a card deleted since leaves it deleted
+        crate::spaced_repetition::delete_mastery_card(data_dir.clone(), kept.id.clone()).unwrap();
+        undo_session_answer(data_dir.clone(), &session.id).unwrap();
+        let store = SpacedRepetitionStore::new(data_dir).unwrap();
+        assert!(store.get_mastery_card(&kept.id).is_none());
+        assert_eq!(store.review_log().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_cloze_siblings_are_buried() {
+        let dir = tempdir().unwrap();
+        let data_dir = dir.path().to_path_buf();
+        let mut store = SpacedRepetitionStore::new(data_dir.clone()).unwrap();
+        let cards = store
+            .create_mastery_card(CreateMasteryCardRequest {
+                highlight_id: "h1".to_string(),
+                card_type: MasteryCardType::Cloze,
+                question: None,
+                answer: None,
+                cloze_text: Some("{{c1::Paris}} is the capital of {{c2::France}}.".to_string()),
+                cloze_deletions: None,
+            })
+            .unwrap();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].sibling_group, cards[1].sibling_group);
+        assert_eq!(cards[1].cloze_number, Some(2));
+        assert_eq!(
+            cards[1].get_display_text(),
+            "Paris is the capital of [...1...]."
+        );
+
+        let highlights = HighlightStore::new(data_dir.clone()).unwrap();
+        let articles = ReadingStore::new(data_dir).unwrap();
+        let request = CreateSessionRequest {
+            session_type: ReviewSessionType::Daily,
+            highlight_limit: None,
+            mastery_limit: None,
+            tags: None,
+            document_ids: None,
+        };
+        let session = store
+            .create_review_session(
+                request,
+                &ItemSources {
+                    highlights: &highlights,
+                    articles: &articles,
+                },
+            )
+            .unwrap();
+        assert_eq!(session.items.len(), 1);
+        assert_eq!(store.get_due_counts(), (0, 2));
+    }
 
     #[test]
     fn test_session_creation() {
@@ -96,6 +96,16 @@ impl ReviewStats {
         daily.session_count += 1;
     }
 
+    /// Takes back a review recorded with `record_review`, as on undo. The
+    /// streak is left as it is.
+    pub fn remove_review(&mut self, at: DateTime<Utc>) {
+        self.total_reviews = self.total_reviews.saturating_sub(1);
+        let date_str = at.format("%Y-%m-%d").to_string();
+        if let Some(daily) = self.reviews_by_date.get_mut(&date_str) {
+            daily.highlights_reviewed = daily.highlights_reviewed.saturating_sub(1);
+        }
+    }
+
     pub fn record_session_complete(&mut self, at: DateTime<Utc>) {
         let date_str = at.format("%Y-%m-%d").to_string();
         let daily = self
@@ -105,6 +115,13 @@ impl ReviewStats {
         daily.completed_sessions += 1;
     }
 
+    pub fn remove_session_complete(&mut self, at: DateTime<Utc>) {
+        let date_str = at.format("%Y-%m-%d").to_string();
+        if let Some(daily) = self.reviews_by_date.get_mut(&date_str) {
+            daily.completed_sessions = daily.completed_sessions.saturating_sub(1);
+        }
+    }
+
     fn update_streak(&mut self, date_str: &str) {
         let today = date_str.to_string();
 
@@ -301,6 +318,20 @@ mod tests {
         assert_eq!(daily.completed_sessions, 1);
     }
 
+    #[test]
+    fn test_remove_review() {
+        let mut stats = ReviewStats::default();
+        let now = Utc::now();
+        stats.record_review(now);
+        stats.record_review(now);
+        stats.remove_review(now);
+
+        let date_str = now.format("%Y-%m-%d").to_string();
+        assert_eq!(stats.total_reviews, 1);
+        assert_eq!(stats.reviews_by_date[&date_str].highlights_reviewed, 1);
+        assert_eq!(stats.streak.current_streak, 1);
+    }
+
     #[test]
     fn test_stats_response() {
         let mut stats = ReviewStats::default();
@@ -189,17 +189,34 @@ pub async fn check_rss_limit(
 pub async fn check_sr_limit(
     tier: &TierType,
     usage: &Arc<RwLock<UsageTracker>>,
+) -> Result<(), TierErrorResponse> {
+    check_sr_cards_limit(tier, usage, 1).await
+}
+
+/// Like [`check_sr_limit`], for a request that adds `count` cards at once.
+pub async fn check_sr_cards_limit(
+    tier: &TierType,
+    usage: &Arc<RwLock<UsageTracker>>,
+    count: u32,
 ) -> Result<(), TierErrorResponse> {
     if tier.is_pro() {
         return Ok(());
     }
 
     let tracker = usage.read().await;
-    let gate = tracker.check_sr_card();
+    let gate = tracker.check_sr_cards(count);
 
     TierErrorResponse::from_gate(gate).map_or(Ok(()), Err)
 }
 
+/// SR cards the tier can still add; `None` when it has no limit.
+pub async fn sr_cards_remaining(tier: &TierType, usage: &Arc<RwLock<UsageTracker>>) -> Option<u32> {
+    if tier.is_pro() {
+        return None;
+    }
+    Some(usage.read().await.sr_cards_remaining())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -4,7 +4,7 @@ pub mod usage;
 
 pub use limits::ProFeature;
 pub use middleware::{
-    check_ai_limit, check_pro_feature, check_rag_limit, check_rss_limit, check_sr_limit,
-    extract_tier_from_headers,
+    check_ai_limit, check_pro_feature, check_rag_limit, check_rss_limit, check_sr_cards_limit,
+    check_sr_limit, extract_tier_from_headers, sr_cards_remaining,
 };
 pub use usage::{create_shared_tracker, SharedUsageTracker};
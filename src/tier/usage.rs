@@ -213,12 +213,18 @@ impl UsageTracker {
 
     /// Check if adding a new SR card is allowed
     pub fn check_sr_card(&self) -> FeatureGate {
+        self.check_sr_cards(1)
+    }
+
+    /// Check if adding `count` new SR cards at once is allowed
+    pub fn check_sr_cards(&self, count: u32) -> FeatureGate {
         let limits = FreeLimits::get();
 
-        if self.data.persistent.sr_card_count >= limits.sr_active_cards {
+        let current = self.data.persistent.sr_card_count;
+        if current.saturating_add(count) > limits.sr_active_cards {
             FeatureGate::MaxLimitReached {
                 feature: "spaced_repetition".to_string(),
-                current: self.data.persistent.sr_card_count,
+                current,
                 limit: limits.sr_active_cards,
             }
         } else {
@@ -226,6 +232,13 @@ impl UsageTracker {
         }
     }
 
+    /// Number of SR cards that can still be added
+    pub fn sr_cards_remaining(&self) -> u32 {
+        FreeLimits::get()
+            .sr_active_cards
+            .saturating_sub(self.data.persistent.sr_card_count)
+    }
+
     /// Update SR card count (called when cards change)
     pub fn set_sr_card_count(&mut self, count: u32) -> Result<()> {
         self.data.persistent.sr_card_count = count;
@@ -362,6 +375,22 @@ mod tests {
             .is_allowed());
     }
 
+    #[test]
+    fn test_sr_card_limit_counts_batches() {
+        let dir = tempdir().unwrap();
+        let mut tracker = UsageTracker::new(dir.path().to_path_buf()).unwrap();
+        tracker.set_sr_card_count(47).unwrap();
+
+        assert_eq!(tracker.sr_cards_remaining(), 3);
+        assert!(tracker.check_sr_cards(3).is_allowed());
+        assert!(!tracker.check_sr_cards(4).is_allowed());
+        assert!(tracker.check_sr_card().is_allowed());
+
+        tracker.set_sr_card_count(50).unwrap();
+        assert_eq!(tracker.sr_cards_remaining(), 0);
+        assert!(!tracker.check_sr_card().is_allowed());
+    }
+
     #[test]
     fn test_usage_persistence() {
         let dir = tempdir().unwrap();
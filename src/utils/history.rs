@@ -144,7 +144,7 @@ pub fn get_frequent_commands(limit: usize) -> Result<Vec<(String, usize)>> {
     }
 
     let mut sorted: Vec<(String, usize)> = counts.into_iter().collect();
-    sorted.sort_by(|a, b| b.1.cmp(&a.1));
+    sorted.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
     sorted.truncate(limit);
 
     Ok(sorted)
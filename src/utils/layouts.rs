@@ -133,7 +133,7 @@ pub fn get_layout(id: &str) -> Result<Option<LayoutPreset>> {
 pub fn list_layouts() -> Result<LayoutListResponse> {
     let store = load_store()?;
     let mut presets: Vec<LayoutPreset> = store.presets.values().cloned().collect();
-    presets.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    presets.sort_by_key(|p| std::cmp::Reverse(p.updated_at));
     let total = presets.len();
     Ok(LayoutListResponse { presets, total })
 }
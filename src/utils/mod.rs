@@ -6,4 +6,5 @@ pub mod history;
 pub mod layouts;
 pub mod links;
 pub mod snippets;
+pub mod text;
 pub mod vault;
@@ -176,7 +176,7 @@ pub fn list_snippets(request: &SnippetListRequest) -> Result<SnippetListResponse
         });
     }
 
-    snippets.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    snippets.sort_by_key(|s| std::cmp::Reverse(s.updated_at));
     let total = snippets.len();
 
     Ok(SnippetListResponse { snippets, total })
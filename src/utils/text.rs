@@ -0,0 +1,36 @@
+/// Longest prefix of `s` that is at most `max_bytes` long and ends on a
+/// character boundary.
+pub fn prefix(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Cuts `s` to at most `max_bytes`, without splitting a character, and marks
+/// the cut with `...`.
+pub fn truncate(s: &str, max_bytes: usize) -> String {
+    if s.len() <= max_bytes {
+        s.to_string()
+    } else {
+        format!("{}...", prefix(s, max_bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_respects_char_boundaries() {
+        assert_eq!(truncate("hello", 10), "hello");
+        assert_eq!(truncate("hello", 4), "hell...");
+        // Each Hangul syllable is three bytes
+        assert_eq!(truncate("안녕하세요", 7), "안녕...");
+        assert_eq!(prefix("안녕", 2), "");
+    }
+}
@@ -312,11 +312,7 @@ pub fn search_vault(request: &VaultSearchRequest) -> Result<VaultSearchResponse>
                                 .unwrap_or("")
                                 .to_string();
 
-                            let snippet = if line.len() > 100 {
-                                format!("{}...", &line[..100])
-                            } else {
-                                line.to_string()
-                            };
+                            let snippet = super::text::truncate(line, 100);
 
                             results.push(VaultSearchResult {
                                 path: relative_path,
@@ -6,10 +6,10 @@ use std::process::Command;
 use std::sync::OnceLock;
 use tokio::fs;
 
-use crate::ai::providers::{create_provider, ChatMessage, LlmConfig, LlmProvider};
+use crate::jobs::Progress;
 use crate::rpc::{
-    Chapter, TranscriptSegment, YouTubeBatchItem, YouTubeBatchRequest, YouTubeBatchResponse,
-    YouTubeRequest, YouTubeResponse,
+    Chapter, LlmSelection, TranscriptSegment, YouTubeBatchItem, YouTubeBatchRequest,
+    YouTubeBatchResponse, YouTubeRequest, YouTubeResponse,
 };
 
 static YT_DLP_PATH: OnceLock<PathBuf> = OnceLock::new();
@@ -60,9 +60,7 @@ pub async fn extract(request: &YouTubeRequest) -> Result<YouTubeResponse> {
             chapters = generate_ai_chapters(
                 transcript_segments,
                 info.duration.unwrap_or(0.0),
-                request.provider.as_deref(),
-                request.api_key.as_deref(),
-                request.model.as_deref(),
+                &request.llm,
             )
             .await
             .ok();
@@ -84,8 +82,16 @@ pub async fn extract(request: &YouTubeRequest) -> Result<YouTubeResponse> {
     })
 }
 
-pub async fn extract_batch(request: &YouTubeBatchRequest) -> YouTubeBatchResponse {
+pub async fn extract_batch(
+    request: &YouTubeBatchRequest,
+    progress: &Progress,
+) -> YouTubeBatchResponse {
     use futures::future::join_all;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let total = request.urls.len();
+    let finished = AtomicUsize::new(0);
+    progress.step(0, total, format!("Extracted 0/{} videos", total));
 
     let futures: Vec<_> = request
         .urls
@@ -97,13 +103,12 @@ pub async fn extract_batch(request: &YouTubeBatchRequest) -> YouTubeBatchRespons
                 include_chapters: request.include_chapters,
                 generate_ai_chapters: request.generate_ai_chapters,
                 language: request.language.clone(),
-                provider: request.provider.clone(),
-                api_key: request.api_key.clone(),
-                model: request.model.clone(),
+                llm: request.llm.clone(),
             };
+            let finished = &finished;
             async move {
                 let url_clone = url.clone();
-                match extract(&single_request).await {
+                let item = match extract(&single_request).await {
                     Ok(response) => YouTubeBatchItem {
                         url: url_clone,
                         result: Some(response),
@@ -114,7 +119,10 @@ pub async fn extract_batch(request: &YouTubeBatchRequest) -> YouTubeBatchRespons
                         result: None,
                         error: Some(e.to_string()),
                     },
-                }
+                };
+                let done = finished.fetch_add(1, Ordering::Relaxed) + 1;
+                progress.step(done, total, format!("Extracted {}/{} videos", done, total));
+                item
             }
         })
         .collect();
@@ -442,9 +450,7 @@ fn parse_vtt_transcript(content: &str) -> Result<Vec<TranscriptSegment>> {
 async fn generate_ai_chapters(
     transcript: &[TranscriptSegment],
     duration: f64,
-    provider: Option<&str>,
-    api_key: Option<&str>,
-    model: Option<&str>,
+    llm: &LlmSelection,
 ) -> Result<Vec<Chapter>> {
     let transcript_text = transcript
         .iter()
@@ -452,78 +458,12 @@ async fn generate_ai_chapters(
         .collect::<Vec<_>>()
         .join("\n");
 
-    let prompt = format!(
-        r#"Analyze this video transcript and identify 5-10 logical chapters/sections.
-For each chapter, provide:
-1. The start timestamp (in seconds)
-2. A concise title (under 50 characters)
-
-Video duration: {:.0} seconds
-
-Transcript:
-{}
-
-Respond ONLY with valid JSON array:
-[{{"start": 0, "title": "Introduction"}}, {{"start": 120, "title": "Main Topic"}}]"#,
-        duration, transcript_text
-    );
-
-    let llm_provider = match provider.unwrap_or("local") {
-        "openai" => LlmProvider::OpenAI,
-        "anthropic" => LlmProvider::Anthropic,
-        "groq" => LlmProvider::Groq,
-        "zai" | "z.ai" => LlmProvider::Zai,
-        "ollama" => LlmProvider::Ollama,
-        _ => LlmProvider::Local,
-    };
-
-    let response = match llm_provider {
-        LlmProvider::Local => {
-            let model_name = model.unwrap_or("llama3.2");
-            crate::ai::ollama::generate(model_name, &prompt, None).await?
-        }
-        LlmProvider::Ollama => {
-            let model_name = model.unwrap_or("llama3.2");
-            crate::ai::ollama::generate(model_name, &prompt, None).await?
-        }
-        _ => {
-            let config = LlmConfig {
-                provider: llm_provider,
-                api_key: api_key.map(|s| s.to_string()),
-                model: model.map(|s| s.to_string()),
-                base_url: None,
-            };
-            let llm = create_provider(&config)?;
-            let messages = vec![ChatMessage {
-                role: "user".to_string(),
-                content: prompt,
-            }];
-            llm.chat(messages, 1024).await?
-        }
-    };
-
-    parse_chapters_json(&response)
-}
-
-fn parse_chapters_json(response: &str) -> Result<Vec<Chapter>> {
-    let json_start = response.find('[').context("No JSON array found")?;
-    let json_end = response.rfind(']').context("No JSON array end found")?;
-    let json_str = &response[json_start..=json_end];
-
-    #[derive(Deserialize)]
-    struct ChapterJson {
-        start: f64,
-        title: String,
+    // There is no in-process model here, so "local" means the local Ollama
+    let mut llm = llm.clone();
+    if llm.provider.as_deref().is_none_or(|p| p == "local") {
+        llm.provider = Some("ollama".to_string());
     }
-
-    let chapters: Vec<ChapterJson> =
-        serde_json::from_str(json_str).context("Failed to parse chapters JSON")?;
-
-    Ok(chapters
-        .into_iter()
-        .map(|c| Chapter {
-            start: c.start,
-            title: c.title,
-        })
-        .collect())
+    let chain =
+        crate::ai::provider_chain(&llm)?.context("No LLM provider available for chapters")?;
+    crate::ai::extract_chapters(&chain, &transcript_text, Some(duration)).await
 }